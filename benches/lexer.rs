@@ -0,0 +1,60 @@
+//! Throughput of `Lexer` against `ByteLexer` on a generated multi-megabyte script.
+//!
+//! Run with `cargo bench --bench lexer`.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use ironmonkey::byte_lexer::ByteLexer;
+use ironmonkey::lexer::Lexer;
+use ironmonkey::token::TokenType;
+use std::hint::black_box;
+
+const SNIPPET: &str = "
+let five = 5;
+let ten = 10;
+
+let add = fn(x, y) {
+    x + y;
+};
+
+let result = add(five, ten);
+!-/*5;
+5 < 10 > 5;
+
+if (5 < 10) {
+    return true;
+} else {
+    return false;
+}
+
+10 == 10;
+10 != 9;
+";
+
+fn generated_script() -> String {
+    // 約4MB
+    SNIPPET.repeat(4 * 1024 * 1024 / SNIPPET.len())
+}
+
+fn lexers(c: &mut Criterion) {
+    let input = generated_script();
+    let mut group = c.benchmark_group("lexer");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.sample_size(10);
+
+    group.bench_function("Lexer", |b| {
+        b.iter(|| {
+            let mut lexer = Lexer::new(black_box(&input));
+            while lexer.next_token().token_type != TokenType::Eof {}
+        })
+    });
+    group.bench_function("ByteLexer", |b| {
+        b.iter(|| {
+            let mut lexer = ByteLexer::new(black_box(&input));
+            while lexer.next_token().token_type != TokenType::Eof {}
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, lexers);
+criterion_main!(benches);
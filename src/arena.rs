@@ -0,0 +1,681 @@
+//! Arena representation of the AST for semantic analysis.
+//!
+//! All nodes of a program live in one `Ast`, addressed by `NodeId`s, and child
+//! lists are ranges of a shared vector. Passes attach their results (types,
+//! resolved bindings, ...) to nodes with a `SideTable` instead of changing the
+//! tree. `Ast::parse` runs `Parser` and copies its tree into an arena, so the
+//! grammar exists only once.
+//!
+//! Type annotations are nodes too, but no node kind refers to them; they hang
+//! off the annotated node and are found with `Ast::annotation`, so passes that
+//! do not care about types never see them.
+
+use super::ast::{
+    self, BlockStatement, ExpressionType, Identifer, Node, Program, StatementType, TypeExpression,
+};
+use super::diagnostic::Diagnostic;
+use super::lexer::Lexer;
+use super::parser::Parser;
+use super::token::{Span, TokenType};
+use num_bigint::BigInt;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::ops::Index;
+
+/// Index of a node in its `Ast`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(u32);
+
+impl NodeId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// An interned identifier name. Equal names have equal symbols.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+/// Children of a node, stored contiguously in the `Ast`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NodeList {
+    start: u32,
+    len: u32,
+}
+
+impl NodeList {
+    pub fn len(self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Operators are kept as the token type that spelled them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeKind {
+    Let {
+        name: NodeId,
+        value: NodeId,
+    },
+    Return {
+        value: NodeId,
+    },
+    Expression {
+        expression: NodeId,
+    },
+    Block {
+        statements: NodeList,
+    },
+    Identifier(Symbol),
+    Integer(i64),
+    BigInteger(Box<BigInt>),
+    Float(f64),
+    Boolean(bool),
+    String(String),
+    Array {
+        elements: NodeList,
+    },
+    /// Keys and values alternate: key, value, key, value, ...
+    Hash {
+        pairs: NodeList,
+    },
+    Prefix {
+        operator: TokenType,
+        right: NodeId,
+    },
+    Infix {
+        operator: TokenType,
+        left: NodeId,
+        right: NodeId,
+    },
+    If {
+        condition: NodeId,
+        consequence: NodeId,
+        alternative: Option<NodeId>,
+    },
+    Function {
+        parameters: NodeList,
+        body: NodeId,
+    },
+    Call {
+        function: NodeId,
+        arguments: NodeList,
+    },
+    Index {
+        left: NodeId,
+        index: NodeId,
+    },
+    NamedType(Symbol),
+    FunctionType {
+        parameters: NodeList,
+        result: NodeId,
+    },
+    ArrayType {
+        element: NodeId,
+    },
+    HashType {
+        key: NodeId,
+        value: NodeId,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Ast {
+    kinds: Vec<NodeKind>,
+    spans: Vec<Span>,
+    lists: Vec<NodeId>,
+    names: Vec<String>,
+    symbols: HashMap<String, Symbol>,
+    statements: NodeList,
+    annotations: SideTable<NodeId>,
+}
+
+impl Ast {
+    /// Parse a program. If the source has errors they are the same
+    /// diagnostics `Parser` reports.
+    pub fn parse(source: &str, big_integers: bool) -> Result<Self, Vec<Diagnostic>> {
+        let mut parser = Parser::new(Lexer::new(source)).with_big_integers(big_integers);
+        let program = parser.parse_program();
+        let errors = parser.errors();
+        if errors.is_empty() {
+            Ok(Ast::from_program(&program))
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Copy a boxed tree, e.g. one loaded from JSON, into an arena.
+    pub fn from_program(program: &Program) -> Self {
+        let mut ast = Ast::default();
+        let statements: Vec<NodeId> = program
+            .iter()
+            .map(|statement| ast.lower_statement(statement))
+            .collect();
+        ast.statements = ast.list(statements);
+        ast
+    }
+
+    /// Top-level statements.
+    pub fn statements(&self) -> &[NodeId] {
+        self.nodes(self.statements)
+    }
+
+    pub fn nodes(&self, list: NodeList) -> &[NodeId] {
+        let start = list.start as usize;
+        &self.lists[start..start + list.len()]
+    }
+
+    pub fn kind(&self, id: NodeId) -> &NodeKind {
+        &self.kinds[id.index()]
+    }
+
+    pub fn span(&self, id: NodeId) -> Span {
+        self.spans[id.index()]
+    }
+
+    /// The type annotation of a let name or a parameter, or the return type of
+    /// a function.
+    pub fn annotation(&self, id: NodeId) -> Option<NodeId> {
+        self.annotations.get(id).copied()
+    }
+
+    pub fn name(&self, symbol: Symbol) -> &str {
+        &self.names[symbol.0 as usize]
+    }
+
+    /// The symbol of `name` if it occurs in the program.
+    pub fn symbol(&self, name: &str) -> Option<Symbol> {
+        self.symbols.get(name).copied()
+    }
+
+    /// Number of nodes. Ids are `0..len()` in the order nodes were finished,
+    /// so children always come before their parent.
+    pub fn len(&self) -> usize {
+        self.kinds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kinds.is_empty()
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = NodeId> {
+        (0..self.kinds.len() as u32).map(NodeId)
+    }
+
+    /// Print a node in the same form as `Node::string` of the boxed AST,
+    /// except that integers are printed in decimal.
+    pub fn string(&self, id: NodeId) -> String {
+        match self.kind(id) {
+            NodeKind::Let { name, value } => format!(
+                "let {} = {};",
+                self.annotated_string(*name),
+                self.string(*value)
+            ),
+            NodeKind::Return { value } => format!("return {};", self.string(*value)),
+            NodeKind::Expression { expression } => self.string(*expression),
+            NodeKind::Block { statements } => self
+                .nodes(*statements)
+                .iter()
+                .map(|statement| self.string(*statement))
+                .collect(),
+            NodeKind::Identifier(symbol) => String::from(self.name(*symbol)),
+            NodeKind::Integer(value) => value.to_string(),
+            NodeKind::BigInteger(value) => value.to_string(),
+            NodeKind::Float(value) => format!("{:?}", value),
+            NodeKind::Boolean(value) => value.to_string(),
+            NodeKind::String(value) => ast::quote(value),
+            NodeKind::Array { elements } => {
+                let elements: Vec<String> = self
+                    .nodes(*elements)
+                    .iter()
+                    .map(|element| self.string(*element))
+                    .collect();
+                format!("[{}]", elements.join(", "))
+            }
+            NodeKind::Hash { pairs } => {
+                let pairs: Vec<String> = self
+                    .nodes(*pairs)
+                    .chunks(2)
+                    .map(|pair| format!("{}: {}", self.string(pair[0]), self.string(pair[1])))
+                    .collect();
+                format!("{{{}}}", pairs.join(", "))
+            }
+            NodeKind::Prefix { operator, right } => {
+                format!("({}{})", operator.value(), self.string(*right))
+            }
+            NodeKind::Infix {
+                operator,
+                left,
+                right,
+            } => format!(
+                "({} {} {})",
+                self.string(*left),
+                operator.value(),
+                self.string(*right)
+            ),
+            NodeKind::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                let mut buf = format!(
+                    "if{} {}",
+                    self.string(*condition),
+                    self.string(*consequence)
+                );
+                if let Some(alternative) = alternative {
+                    buf.push_str("else ");
+                    buf.push_str(&self.string(*alternative));
+                }
+                buf
+            }
+            NodeKind::Function { parameters, body } => {
+                let parameters: Vec<String> = self
+                    .nodes(*parameters)
+                    .iter()
+                    .map(|parameter| self.annotated_string(*parameter))
+                    .collect();
+                let return_type = self
+                    .annotation(id)
+                    .map_or(String::new(), |ty| format!(" -> {}", self.string(ty)));
+                format!(
+                    "fn({}){} {}",
+                    parameters.join(", "),
+                    return_type,
+                    self.string(*body)
+                )
+            }
+            NodeKind::Call {
+                function,
+                arguments,
+            } => {
+                let arguments: Vec<String> = self
+                    .nodes(*arguments)
+                    .iter()
+                    .map(|argument| self.string(*argument))
+                    .collect();
+                format!("{}({})", self.string(*function), arguments.join(", "))
+            }
+            NodeKind::Index { left, index } => {
+                format!("({}[{}])", self.string(*left), self.string(*index))
+            }
+            NodeKind::NamedType(symbol) => String::from(self.name(*symbol)),
+            NodeKind::FunctionType { parameters, result } => {
+                let parameters: Vec<String> = self
+                    .nodes(*parameters)
+                    .iter()
+                    .map(|parameter| self.string(*parameter))
+                    .collect();
+                format!("fn({}) -> {}", parameters.join(", "), self.string(*result))
+            }
+            NodeKind::ArrayType { element } => format!("[{}]", self.string(*element)),
+            NodeKind::HashType { key, value } => {
+                format!("{{{}: {}}}", self.string(*key), self.string(*value))
+            }
+        }
+    }
+
+    // x または x: int
+    fn annotated_string(&self, id: NodeId) -> String {
+        match self.annotation(id) {
+            Some(ty) => format!("{}: {}", self.string(id), self.string(ty)),
+            None => self.string(id),
+        }
+    }
+
+    fn push(&mut self, kind: NodeKind, span: Span) -> NodeId {
+        let id = NodeId(u32::try_from(self.kinds.len()).expect("too many nodes"));
+        self.kinds.push(kind);
+        self.spans.push(span);
+        id
+    }
+
+    fn list(&mut self, nodes: impl IntoIterator<Item = NodeId>) -> NodeList {
+        let start = self.lists.len();
+        self.lists.extend(nodes);
+        NodeList {
+            start: start as u32,
+            len: (self.lists.len() - start) as u32,
+        }
+    }
+
+    fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(name) {
+            return *symbol;
+        }
+        let symbol = Symbol(self.names.len() as u32);
+        self.names.push(String::from(name));
+        self.symbols.insert(String::from(name), symbol);
+        symbol
+    }
+
+    fn identifier(&mut self, name: &str, span: Span) -> NodeId {
+        let symbol = self.intern(name);
+        self.push(NodeKind::Identifier(symbol), span)
+    }
+
+    fn lower_statement(&mut self, statement: &StatementType) -> NodeId {
+        let kind = match statement {
+            StatementType::LetStatement(statement) => NodeKind::Let {
+                name: self.lower_annotated(&statement.name, statement.annotation.as_ref()),
+                value: self.lower_expression(&statement.value),
+            },
+            StatementType::ReturnStatement(statement) => NodeKind::Return {
+                value: self.lower_expression(&statement.value),
+            },
+            StatementType::ExpressionStatement(statement) => NodeKind::Expression {
+                expression: self.lower_expression(&statement.expression),
+            },
+        };
+        self.push(kind, statement.span())
+    }
+
+    fn lower_identifer(&mut self, identifer: &Identifer) -> NodeId {
+        self.identifier(&identifer.value, identifer.span())
+    }
+
+    fn lower_annotated(
+        &mut self,
+        identifer: &Identifer,
+        annotation: Option<&TypeExpression>,
+    ) -> NodeId {
+        let id = self.lower_identifer(identifer);
+        if let Some(annotation) = annotation {
+            let ty = self.lower_type(annotation);
+            self.annotations.insert(id, ty);
+        }
+        id
+    }
+
+    fn lower_type(&mut self, ty: &TypeExpression) -> NodeId {
+        let kind = match ty {
+            TypeExpression::Named(name) => NodeKind::NamedType(self.intern(&name.value)),
+            TypeExpression::Function(function) => {
+                let parameters: Vec<NodeId> = function
+                    .parameters
+                    .iter()
+                    .map(|parameter| self.lower_type(parameter))
+                    .collect();
+                NodeKind::FunctionType {
+                    parameters: self.list(parameters),
+                    result: self.lower_type(&function.result),
+                }
+            }
+            TypeExpression::Array(array) => NodeKind::ArrayType {
+                element: self.lower_type(&array.element),
+            },
+            TypeExpression::Hash(hash) => NodeKind::HashType {
+                key: self.lower_type(&hash.key),
+                value: self.lower_type(&hash.value),
+            },
+        };
+        self.push(kind, ty.span())
+    }
+
+    fn lower_block(&mut self, block: &BlockStatement) -> NodeId {
+        let statements: Vec<NodeId> = block
+            .statements
+            .iter()
+            .map(|statement| self.lower_statement(statement))
+            .collect();
+        let statements = self.list(statements);
+        self.push(NodeKind::Block { statements }, block.span)
+    }
+
+    fn lower_expression(&mut self, expression: &ExpressionType) -> NodeId {
+        let kind = match expression {
+            ExpressionType::Identifer(identifer) => return self.lower_identifer(identifer),
+            ExpressionType::IntegerLiteral(literal) => NodeKind::Integer(literal.value),
+            ExpressionType::BigIntegerLiteral(literal) => {
+                NodeKind::BigInteger(Box::new(literal.value.clone()))
+            }
+            ExpressionType::FloatLiteral(literal) => NodeKind::Float(literal.value),
+            ExpressionType::Boolean(boolean) => NodeKind::Boolean(boolean.value),
+            ExpressionType::StringLiteral(literal) => NodeKind::String(literal.value.clone()),
+            ExpressionType::ArrayLiteral(literal) => {
+                let elements: Vec<NodeId> = literal
+                    .elements
+                    .iter()
+                    .map(|element| self.lower_expression(element))
+                    .collect();
+                NodeKind::Array {
+                    elements: self.list(elements),
+                }
+            }
+            ExpressionType::HashLiteral(literal) => {
+                let mut pairs = Vec::with_capacity(literal.pairs.len() * 2);
+                for (key, value) in &literal.pairs {
+                    pairs.push(self.lower_expression(key));
+                    pairs.push(self.lower_expression(value));
+                }
+                NodeKind::Hash {
+                    pairs: self.list(pairs),
+                }
+            }
+            ExpressionType::PrefixExpression(expression) => NodeKind::Prefix {
+                operator: expression.token.token_type,
+                right: self.lower_expression(&expression.right),
+            },
+            ExpressionType::InfixExpression(expression) => NodeKind::Infix {
+                operator: expression.token.token_type,
+                left: self.lower_expression(&expression.left),
+                right: self.lower_expression(&expression.right),
+            },
+            ExpressionType::IfExpression(expression) => NodeKind::If {
+                condition: self.lower_expression(&expression.condition),
+                consequence: self.lower_block(&expression.consequence),
+                alternative: expression
+                    .alternative
+                    .as_ref()
+                    .map(|alternative| self.lower_block(alternative)),
+            },
+            ExpressionType::FunctionLiteral(literal) => {
+                let parameters: Vec<NodeId> = literal
+                    .parameters
+                    .iter()
+                    .zip(&literal.parameter_types)
+                    .map(|(parameter, annotation)| {
+                        self.lower_annotated(parameter, annotation.as_ref())
+                    })
+                    .collect();
+                let return_type = literal.return_type.as_ref().map(|ty| self.lower_type(ty));
+                let kind = NodeKind::Function {
+                    parameters: self.list(parameters),
+                    body: self.lower_block(&literal.body),
+                };
+                let id = self.push(kind, expression.span());
+                if let Some(return_type) = return_type {
+                    self.annotations.insert(id, return_type);
+                }
+                return id;
+            }
+            ExpressionType::CallExpression(expression) => {
+                let function = self.lower_expression(&expression.function);
+                let arguments: Vec<NodeId> = expression
+                    .arguments
+                    .iter()
+                    .map(|argument| self.lower_expression(argument))
+                    .collect();
+                NodeKind::Call {
+                    function,
+                    arguments: self.list(arguments),
+                }
+            }
+            ExpressionType::IndexExpression(expression) => NodeKind::Index {
+                left: self.lower_expression(&expression.left),
+                index: self.lower_expression(&expression.index),
+            },
+            ExpressionType::Illegal => panic!("cannot lower an Illegal expression"),
+        };
+        self.push(kind, expression.span())
+    }
+}
+
+impl Index<NodeId> for Ast {
+    type Output = NodeKind;
+
+    fn index(&self, id: NodeId) -> &NodeKind {
+        self.kind(id)
+    }
+}
+
+/// Per-node data computed by a pass, such as the type of each expression.
+#[derive(Debug, Clone)]
+pub struct SideTable<T> {
+    values: Vec<Option<T>>,
+}
+
+impl<T> Default for SideTable<T> {
+    fn default() -> Self {
+        Self { values: Vec::new() }
+    }
+}
+
+impl<T> SideTable<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, id: NodeId, value: T) -> Option<T> {
+        if self.values.len() <= id.index() {
+            self.values.resize_with(id.index() + 1, || None);
+        }
+        self.values[id.index()].replace(value)
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&T> {
+        self.values.get(id.index()).and_then(Option::as_ref)
+    }
+
+    pub fn get_mut(&mut self, id: NodeId) -> Option<&mut T> {
+        self.values.get_mut(id.index()).and_then(Option::as_mut)
+    }
+
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.get(id).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &T)> {
+        self.values
+            .iter()
+            .enumerate()
+            .filter_map(|(i, value)| Some((NodeId(i as u32), value.as_ref()?)))
+    }
+}
+
+impl<T> Index<NodeId> for SideTable<T> {
+    type Output = T;
+
+    fn index(&self, id: NodeId) -> &T {
+        self.get(id).expect("no entry for node")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = "let add = fn(a, b) { a + b; };
+let f = fn(n) { if (n < 2) { return n; } else { add(f(n - 1), -n) } };
+f(16) * -9223372036854775808 / 2.5 == !true;
+fn() {}();";
+
+    #[test]
+    fn prints_like_parser() {
+        let inputs = [
+            PROGRAM,
+            "",
+            "a; b; (c)",
+            "if (x) { 1 } ",
+            "18446744073709551616 + 1",
+            "let f: fn(int, fn() -> bool) -> int = fn(a: int, b) -> int { a }; f",
+            "let h: {string: [int]} = {\"a\\n\": [1, 2][0], true: {}}; h[\"a\"][1 + 1]",
+        ];
+        for input in inputs.iter() {
+            let mut parser = Parser::new(Lexer::new(input)).with_big_integers(true);
+            let program = parser.parse_program();
+            assert_eq!(parser.errors(), vec![], "input: {}", input);
+
+            let ast = Ast::parse(input, true).unwrap();
+            let strings: String = ast.statements().iter().map(|id| ast.string(*id)).collect();
+            assert_eq!(strings, program.string(), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn errors_match_parser() {
+        let inputs = [
+            "let x 5;",
+            "let = 1; @",
+            "fn(x { x }",
+            "if (x) { 1",
+            "9223372036854775808",
+            "0b102",
+            "1e400",
+            "f(1, 2",
+            "let x: = 1;",
+            "fn(a: fn(int) int) { a }",
+            "[1, 2",
+            "{1: 2, 3}",
+            "\"a\\q\"",
+            "x[1",
+        ];
+        for input in inputs.iter() {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            let errors = Ast::parse(input, false).unwrap_err();
+            assert_eq!(errors, parser.errors(), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn nodes_and_symbols() {
+        let ast = Ast::parse("let x = 1; fn(x, y) { x }", false).unwrap();
+        assert_eq!(ast.statements().len(), 2);
+
+        let x = ast.symbol("x").unwrap();
+        let uses = ast
+            .ids()
+            .filter(|id| ast[*id] == NodeKind::Identifier(x))
+            .count();
+        assert_eq!(uses, 3);
+        assert_eq!(ast.name(x), "x");
+        assert_eq!(ast.symbol("z"), None);
+
+        let function = match &ast[ast.statements()[1]] {
+            NodeKind::Expression { expression } => *expression,
+            kind => panic!("not an expression statement: {:?}", kind),
+        };
+        match &ast[function] {
+            NodeKind::Function { parameters, body } => {
+                assert_eq!(parameters.len(), 2);
+                assert_eq!(ast.string(ast.nodes(*parameters)[1]), "y");
+                assert_eq!(ast.span(*body), Span::new(20, 25));
+                // 子は親より先に作られる
+                assert!(ast.nodes(*parameters).iter().all(|id| id < &function));
+            }
+            kind => panic!("not a function: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn side_table() {
+        let ast = Ast::parse("1 + 2", false).unwrap();
+        let mut table = SideTable::new();
+        for id in ast.ids() {
+            if let NodeKind::Integer(value) = ast[id] {
+                table.insert(id, value * 10);
+            }
+        }
+        let values: Vec<i64> = table.iter().map(|(_, value)| *value).collect();
+        assert_eq!(values, vec![10, 20]);
+
+        let root = ast.statements()[0];
+        assert!(!table.contains(root));
+        assert_eq!(table.insert(NodeId(0), 5), Some(10));
+        assert_eq!(table[NodeId(0)], 5);
+    }
+}
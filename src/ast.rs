@@ -1,7 +1,7 @@
-use super::token::Token;
-use std::io;
-use std::io::Write;
+use super::token::{Span, Token};
+use num_bigint::BigInt;
 
+#[derive(Debug, Clone)]
 pub enum StatementType {
     LetStatement(LetStatement),
     ReturnStatement(ReturnStatement),
@@ -17,7 +17,18 @@ impl Node for StatementType {
         }
     }
     fn string(&self) -> String {
-        unimplemented!();
+        match self {
+            StatementType::LetStatement(statement) => statement.string(),
+            StatementType::ReturnStatement(statement) => statement.string(),
+            StatementType::ExpressionStatement(statement) => statement.string(),
+        }
+    }
+    fn span(&self) -> Span {
+        match self {
+            StatementType::LetStatement(statement) => statement.span(),
+            StatementType::ReturnStatement(statement) => statement.span(),
+            StatementType::ExpressionStatement(statement) => statement.span(),
+        }
     }
 }
 
@@ -25,29 +36,93 @@ impl Node for ExpressionType {
     fn token_literal(&self) -> String {
         match self {
             ExpressionType::Identifer(expression) => expression.token_literal(),
+            ExpressionType::IntegerLiteral(expression) => expression.token_literal(),
+            ExpressionType::BigIntegerLiteral(expression) => expression.token_literal(),
+            ExpressionType::FloatLiteral(expression) => expression.token_literal(),
+            ExpressionType::Boolean(expression) => expression.token_literal(),
+            ExpressionType::StringLiteral(expression) => expression.token_literal(),
+            ExpressionType::ArrayLiteral(expression) => expression.token_literal(),
+            ExpressionType::HashLiteral(expression) => expression.token_literal(),
+            ExpressionType::PrefixExpression(expression) => expression.token_literal(),
+            ExpressionType::InfixExpression(expression) => expression.token_literal(),
+            ExpressionType::IfExpression(expression) => expression.token_literal(),
+            ExpressionType::FunctionLiteral(expression) => expression.token_literal(),
+            ExpressionType::CallExpression(expression) => expression.token_literal(),
+            ExpressionType::IndexExpression(expression) => expression.token_literal(),
             ExpressionType::Illegal => String::from("Illegal"),
         }
     }
     fn string(&self) -> String {
-        unimplemented!();
+        match self {
+            ExpressionType::Identifer(expression) => expression.string(),
+            ExpressionType::IntegerLiteral(expression) => expression.string(),
+            ExpressionType::BigIntegerLiteral(expression) => expression.string(),
+            ExpressionType::FloatLiteral(expression) => expression.string(),
+            ExpressionType::Boolean(expression) => expression.string(),
+            ExpressionType::StringLiteral(expression) => expression.string(),
+            ExpressionType::ArrayLiteral(expression) => expression.string(),
+            ExpressionType::HashLiteral(expression) => expression.string(),
+            ExpressionType::PrefixExpression(expression) => expression.string(),
+            ExpressionType::InfixExpression(expression) => expression.string(),
+            ExpressionType::IfExpression(expression) => expression.string(),
+            ExpressionType::FunctionLiteral(expression) => expression.string(),
+            ExpressionType::CallExpression(expression) => expression.string(),
+            ExpressionType::IndexExpression(expression) => expression.string(),
+            ExpressionType::Illegal => String::from("Illegal"),
+        }
+    }
+    fn span(&self) -> Span {
+        match self {
+            ExpressionType::Identifer(expression) => expression.span(),
+            ExpressionType::IntegerLiteral(expression) => expression.span(),
+            ExpressionType::BigIntegerLiteral(expression) => expression.span(),
+            ExpressionType::FloatLiteral(expression) => expression.span(),
+            ExpressionType::Boolean(expression) => expression.span(),
+            ExpressionType::StringLiteral(expression) => expression.span(),
+            ExpressionType::ArrayLiteral(expression) => expression.span(),
+            ExpressionType::HashLiteral(expression) => expression.span(),
+            ExpressionType::PrefixExpression(expression) => expression.span(),
+            ExpressionType::InfixExpression(expression) => expression.span(),
+            ExpressionType::IfExpression(expression) => expression.span(),
+            ExpressionType::FunctionLiteral(expression) => expression.span(),
+            ExpressionType::CallExpression(expression) => expression.span(),
+            ExpressionType::IndexExpression(expression) => expression.span(),
+            ExpressionType::Illegal => Span::default(),
+        }
     }
 }
 
+#[derive(Debug, Clone)]
 pub enum ExpressionType {
     Identifer(Identifer),
+    IntegerLiteral(IntegerLiteral),
+    BigIntegerLiteral(BigIntegerLiteral),
+    FloatLiteral(FloatLiteral),
+    Boolean(Boolean),
+    StringLiteral(StringLiteral),
+    ArrayLiteral(ArrayLiteral),
+    HashLiteral(HashLiteral),
+    PrefixExpression(PrefixExpression),
+    InfixExpression(InfixExpression),
+    IfExpression(IfExpression),
+    FunctionLiteral(FunctionLiteral),
+    CallExpression(CallExpression),
+    IndexExpression(IndexExpression),
     Illegal,
 }
 
 pub trait Node {
     fn token_literal(&self) -> String;
     fn string(&self) -> String;
+    /// Byte range of the node in the source it was parsed from.
+    fn span(&self) -> Span;
 }
 
 pub type Program = Vec<StatementType>;
 
 impl Node for Program {
     fn token_literal(&self) -> String {
-        self.get(0).map_or(String::new(), Node::token_literal)
+        self.first().map_or(String::new(), Node::token_literal)
     }
     fn string(&self) -> String {
         let mut buf = String::new();
@@ -56,11 +131,19 @@ impl Node for Program {
         }
         buf
     }
+    fn span(&self) -> Span {
+        match (self.first(), self.last()) {
+            (Some(first), Some(last)) => first.span().to(last.span()),
+            _ => Span::default(),
+        }
+    }
 }
 
+#[derive(Debug, Clone)]
 pub struct LetStatement {
     pub token: Box<Token>, // LET token
     pub name: Identifer,
+    pub annotation: Option<TypeExpression>, // let x: int = ...
     pub value: ExpressionType,
 }
 
@@ -73,13 +156,21 @@ impl Node for LetStatement {
 
         buf.push_str(&format!("{} ", self.token_literal()));
         buf.push_str(&self.name.string());
+        if let Some(annotation) = &self.annotation {
+            buf.push_str(": ");
+            buf.push_str(&annotation.string());
+        }
         buf.push_str(" = ");
         buf.push_str(&self.value.string());
-        buf.push_str(";");
+        buf.push(';');
         buf
     }
+    fn span(&self) -> Span {
+        self.token.span.to(self.value.span())
+    }
 }
 
+#[derive(Debug, Clone)]
 pub struct ReturnStatement {
     pub token: Box<Token>,
     pub value: ExpressionType,
@@ -94,12 +185,16 @@ impl Node for ReturnStatement {
 
         buf.push_str(&format!("{} ", self.token_literal()));
         buf.push_str(&self.value.string());
-        buf.push_str(";");
+        buf.push(';');
         buf
     }
+    fn span(&self) -> Span {
+        self.token.span.to(self.value.span())
+    }
 }
 
 // x + 10; is valid in monkey
+#[derive(Debug, Clone)]
 pub struct ExpressionStatement {
     pub token: Box<Token>,
     pub expression: ExpressionType,
@@ -112,9 +207,36 @@ impl Node for ExpressionStatement {
     fn string(&self) -> String {
         self.expression.string()
     }
+    fn span(&self) -> Span {
+        self.expression.span()
+    }
+}
+
+// { x; y; } used as the body of if and fn
+#[derive(Debug, Clone)]
+pub struct BlockStatement {
+    pub token: Box<Token>, // { token
+    pub statements: Vec<StatementType>,
+    pub span: Span, // { から } まで
+}
+
+impl Node for BlockStatement {
+    fn token_literal(&self) -> String {
+        String::from(&self.token.literal)
+    }
+    fn string(&self) -> String {
+        let mut buf = String::new();
+        for statement in &self.statements {
+            buf.push_str(&statement.string());
+        }
+        buf
+    }
+    fn span(&self) -> Span {
+        self.span
+    }
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct Identifer {
     pub token: Box<Token>, // Ident token
     pub value: String,
@@ -134,4 +256,431 @@ impl Node for Identifer {
     fn string(&self) -> String {
         self.value.clone()
     }
+    fn span(&self) -> Span {
+        self.token.span
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IntegerLiteral {
+    pub token: Box<Token>, // Int token
+    pub value: i64,
+}
+
+impl Node for IntegerLiteral {
+    fn token_literal(&self) -> String {
+        String::from(&self.token.literal)
+    }
+    fn string(&self) -> String {
+        self.token.literal.clone()
+    }
+    fn span(&self) -> Span {
+        self.token.span
+    }
+}
+
+// big integerモードでだけ作られる、i64に収まらない整数
+#[derive(Debug, Clone)]
+pub struct BigIntegerLiteral {
+    pub token: Box<Token>, // Int token
+    pub value: BigInt,
+}
+
+impl Node for BigIntegerLiteral {
+    fn token_literal(&self) -> String {
+        String::from(&self.token.literal)
+    }
+    fn string(&self) -> String {
+        self.token.literal.clone()
+    }
+    fn span(&self) -> Span {
+        self.token.span
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FloatLiteral {
+    pub token: Box<Token>, // Float token
+    pub value: f64,
+}
+
+impl Node for FloatLiteral {
+    fn token_literal(&self) -> String {
+        String::from(&self.token.literal)
+    }
+    /// Shortest form that parses back to exactly the same value. It always
+    /// contains `.` or `e`, so it is lexed as a Float again.
+    fn string(&self) -> String {
+        format!("{:?}", self.value)
+    }
+    fn span(&self) -> Span {
+        self.token.span
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Boolean {
+    pub token: Box<Token>, // True or False token
+    pub value: bool,
+}
+
+impl Node for Boolean {
+    fn token_literal(&self) -> String {
+        String::from(&self.token.literal)
+    }
+    fn string(&self) -> String {
+        self.token.literal.clone()
+    }
+    fn span(&self) -> Span {
+        self.token.span
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StringLiteral {
+    pub token: Box<Token>, // String token
+    pub value: String,     // エスケープを解釈した後の文字列
+}
+
+impl Node for StringLiteral {
+    fn token_literal(&self) -> String {
+        String::from(&self.token.literal)
+    }
+    fn string(&self) -> String {
+        quote(&self.value)
+    }
+    fn span(&self) -> Span {
+        self.token.span
+    }
+}
+
+/// `value` as a string literal that lexes and parses back to it.
+pub fn quote(value: &str) -> String {
+    let mut buf = String::with_capacity(value.len() + 2);
+    buf.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            ch if ch.is_control() => buf.push_str(&format!("\\u{{{:x}}}", ch as u32)),
+            ch => buf.push(ch),
+        }
+    }
+    buf.push('"');
+    buf
+}
+
+// [<elements>]
+#[derive(Debug, Clone)]
+pub struct ArrayLiteral {
+    pub token: Box<Token>, // [ token
+    pub elements: Vec<ExpressionType>,
+    pub span: Span, // [ から ] まで
+}
+
+impl Node for ArrayLiteral {
+    fn token_literal(&self) -> String {
+        String::from(&self.token.literal)
+    }
+    fn string(&self) -> String {
+        let elements: Vec<String> = self.elements.iter().map(Node::string).collect();
+        format!("[{}]", elements.join(", "))
+    }
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+// {<key>: <value>, ...}
+#[derive(Debug, Clone)]
+pub struct HashLiteral {
+    pub token: Box<Token>, // { token
+    pub pairs: Vec<(ExpressionType, ExpressionType)>,
+    pub span: Span, // { から } まで
+}
+
+impl Node for HashLiteral {
+    fn token_literal(&self) -> String {
+        String::from(&self.token.literal)
+    }
+    fn string(&self) -> String {
+        let pairs: Vec<String> = self
+            .pairs
+            .iter()
+            .map(|(key, value)| format!("{}: {}", key.string(), value.string()))
+            .collect();
+        format!("{{{}}}", pairs.join(", "))
+    }
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+// -x, !x
+#[derive(Debug, Clone)]
+pub struct PrefixExpression {
+    pub token: Box<Token>, // operator token
+    pub operator: String,
+    pub right: Box<ExpressionType>,
+}
+
+impl Node for PrefixExpression {
+    fn token_literal(&self) -> String {
+        String::from(&self.token.literal)
+    }
+    fn string(&self) -> String {
+        format!("({}{})", self.operator, self.right.string())
+    }
+    fn span(&self) -> Span {
+        self.token.span.to(self.right.span())
+    }
+}
+
+// x + y, x == y, ...
+#[derive(Debug, Clone)]
+pub struct InfixExpression {
+    pub token: Box<Token>, // operator token
+    pub left: Box<ExpressionType>,
+    pub operator: String,
+    pub right: Box<ExpressionType>,
+}
+
+impl Node for InfixExpression {
+    fn token_literal(&self) -> String {
+        String::from(&self.token.literal)
+    }
+    fn string(&self) -> String {
+        format!(
+            "({} {} {})",
+            self.left.string(),
+            self.operator,
+            self.right.string()
+        )
+    }
+    fn span(&self) -> Span {
+        self.left.span().to(self.right.span())
+    }
+}
+
+// if (<condition>) <consequence> else <alternative>
+#[derive(Debug, Clone)]
+pub struct IfExpression {
+    pub token: Box<Token>, // IF token
+    pub condition: Box<ExpressionType>,
+    pub consequence: BlockStatement,
+    pub alternative: Option<BlockStatement>,
+}
+
+impl Node for IfExpression {
+    fn token_literal(&self) -> String {
+        String::from(&self.token.literal)
+    }
+    fn string(&self) -> String {
+        let mut buf = String::new();
+
+        buf.push_str("if");
+        buf.push_str(&self.condition.string());
+        buf.push(' ');
+        buf.push_str(&self.consequence.string());
+        if let Some(alternative) = &self.alternative {
+            buf.push_str("else ");
+            buf.push_str(&alternative.string());
+        }
+        buf
+    }
+    fn span(&self) -> Span {
+        let last = self.alternative.as_ref().unwrap_or(&self.consequence);
+        self.token.span.to(last.span())
+    }
+}
+
+// fn(<parameters>) -> <return type> <body>
+#[derive(Debug, Clone)]
+pub struct FunctionLiteral {
+    pub token: Box<Token>, // FUNCTION token
+    pub parameters: Vec<Identifer>,
+    pub parameter_types: Vec<Option<TypeExpression>>, // parametersと同じ長さ
+    pub return_type: Option<TypeExpression>,
+    pub body: BlockStatement,
+}
+
+impl Node for FunctionLiteral {
+    fn token_literal(&self) -> String {
+        String::from(&self.token.literal)
+    }
+    fn string(&self) -> String {
+        let parameters: Vec<String> = self
+            .parameters
+            .iter()
+            .zip(&self.parameter_types)
+            .map(|(parameter, annotation)| match annotation {
+                Some(annotation) => format!("{}: {}", parameter.string(), annotation.string()),
+                None => parameter.string(),
+            })
+            .collect();
+        let return_type = self
+            .return_type
+            .as_ref()
+            .map_or(String::new(), |ty| format!(" -> {}", ty.string()));
+        format!(
+            "{}({}){} {}",
+            self.token_literal(),
+            parameters.join(", "),
+            return_type,
+            self.body.string()
+        )
+    }
+    fn span(&self) -> Span {
+        self.token.span.to(self.body.span())
+    }
+}
+
+// <function>(<arguments>)
+#[derive(Debug, Clone)]
+pub struct CallExpression {
+    pub token: Box<Token>, // ( token
+    pub function: Box<ExpressionType>,
+    pub arguments: Vec<ExpressionType>,
+    pub span: Span, // 関数から ) まで
+}
+
+impl Node for CallExpression {
+    fn token_literal(&self) -> String {
+        String::from(&self.token.literal)
+    }
+    fn string(&self) -> String {
+        let arguments: Vec<String> = self.arguments.iter().map(Node::string).collect();
+        format!("{}({})", self.function.string(), arguments.join(", "))
+    }
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+// <left>[<index>]
+#[derive(Debug, Clone)]
+pub struct IndexExpression {
+    pub token: Box<Token>, // [ token
+    pub left: Box<ExpressionType>,
+    pub index: Box<ExpressionType>,
+    pub span: Span, // 左辺から ] まで
+}
+
+impl Node for IndexExpression {
+    fn token_literal(&self) -> String {
+        String::from(&self.token.literal)
+    }
+    fn string(&self) -> String {
+        format!("({}[{}])", self.left.string(), self.index.string())
+    }
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// A type annotation. Annotations are only used by the type checker; the
+/// evaluator ignores them.
+#[derive(Debug, Clone)]
+pub enum TypeExpression {
+    Named(Identifer), // int, float, bool, ...
+    Function(FunctionType),
+    Array(ArrayType),
+    Hash(HashType),
+}
+
+impl Node for TypeExpression {
+    fn token_literal(&self) -> String {
+        match self {
+            TypeExpression::Named(name) => name.token_literal(),
+            TypeExpression::Function(ty) => ty.token_literal(),
+            TypeExpression::Array(ty) => ty.token_literal(),
+            TypeExpression::Hash(ty) => ty.token_literal(),
+        }
+    }
+    fn string(&self) -> String {
+        match self {
+            TypeExpression::Named(name) => name.string(),
+            TypeExpression::Function(ty) => ty.string(),
+            TypeExpression::Array(ty) => ty.string(),
+            TypeExpression::Hash(ty) => ty.string(),
+        }
+    }
+    fn span(&self) -> Span {
+        match self {
+            TypeExpression::Named(name) => name.span(),
+            TypeExpression::Function(ty) => ty.span(),
+            TypeExpression::Array(ty) => ty.span(),
+            TypeExpression::Hash(ty) => ty.span(),
+        }
+    }
+}
+
+// fn(<parameter types>) -> <result type>
+#[derive(Debug, Clone)]
+pub struct FunctionType {
+    pub token: Box<Token>, // FUNCTION token
+    pub parameters: Vec<TypeExpression>,
+    pub result: Box<TypeExpression>,
+}
+
+impl Node for FunctionType {
+    fn token_literal(&self) -> String {
+        String::from(&self.token.literal)
+    }
+    fn string(&self) -> String {
+        let parameters: Vec<String> = self.parameters.iter().map(Node::string).collect();
+        format!(
+            "{}({}) -> {}",
+            self.token_literal(),
+            parameters.join(", "),
+            self.result.string()
+        )
+    }
+    fn span(&self) -> Span {
+        self.token.span.to(self.result.span())
+    }
+}
+
+// [<element type>]
+#[derive(Debug, Clone)]
+pub struct ArrayType {
+    pub token: Box<Token>, // [ token
+    pub element: Box<TypeExpression>,
+    pub span: Span, // [ から ] まで
+}
+
+impl Node for ArrayType {
+    fn token_literal(&self) -> String {
+        String::from(&self.token.literal)
+    }
+    fn string(&self) -> String {
+        format!("[{}]", self.element.string())
+    }
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+// {<key type>: <value type>}
+#[derive(Debug, Clone)]
+pub struct HashType {
+    pub token: Box<Token>, // { token
+    pub key: Box<TypeExpression>,
+    pub value: Box<TypeExpression>,
+    pub span: Span, // { から } まで
+}
+
+impl Node for HashType {
+    fn token_literal(&self) -> String {
+        String::from(&self.token.literal)
+    }
+    fn string(&self) -> String {
+        format!("{{{}: {}}}", self.key.string(), self.value.string())
+    }
+    fn span(&self) -> Span {
+        self.span
+    }
 }
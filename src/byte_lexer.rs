@@ -0,0 +1,319 @@
+//! Zero-copy lexer for large inputs.
+//!
+//! `Lexer` collects its input into a `Vec<char>` and allocates a `String` for
+//! every token. `ByteLexer` scans the UTF-8 bytes of the source in place and
+//! hands out tokens whose literal borrows from it. Both lexers produce the same
+//! token stream and spans.
+
+use super::diagnostic::Diagnostic;
+use super::lexer::illegal_token_error;
+use super::token::{Span, Token, TokenType};
+use unicode_xid::UnicodeXID;
+
+/// A token whose literal is a slice of the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BorrowedToken<'src> {
+    pub token_type: TokenType,
+    pub literal: &'src str,
+    pub span: Span,
+}
+
+impl BorrowedToken<'_> {
+    pub fn to_token(&self) -> Token {
+        Token {
+            token_type: self.token_type,
+            literal: String::from(self.literal),
+            span: self.span,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ByteLexer<'src> {
+    input: &'src str,
+    position: usize, // 次に読むバイトの位置
+    errors: Vec<Diagnostic>,
+    finished: bool, // Eofを返し終えたか (Iterator用)
+}
+
+impl<'src> ByteLexer<'src> {
+    pub fn new(input: &'src str) -> Self {
+        Self {
+            input,
+            position: 0,
+            errors: Vec::new(),
+            finished: false,
+        }
+    }
+
+    /// Errors for the Illegal tokens produced so far.
+    pub fn errors(&self) -> Vec<Diagnostic> {
+        self.errors.clone()
+    }
+
+    pub fn next_token(&mut self) -> BorrowedToken<'src> {
+        use TokenType::*;
+
+        self.skip_trivia();
+        let start = self.position;
+        let bytes = self.input.as_bytes();
+
+        let token_type = match bytes.get(start) {
+            None => Eof,
+            Some(b'=') if bytes.get(start + 1) == Some(&b'=') => {
+                self.position += 2;
+                Equal
+            }
+            Some(b'!') if bytes.get(start + 1) == Some(&b'=') => {
+                self.position += 2;
+                NotEqual
+            }
+            Some(b'-') if bytes.get(start + 1) == Some(&b'>') => {
+                self.position += 2;
+                Arrow
+            }
+            Some(b) if b.is_ascii_digit() => self.read_number(),
+            Some(b'.') if bytes.get(start + 1).is_some_and(u8::is_ascii_digit) => {
+                self.read_number()
+            }
+            Some(b'"') => self.read_string(),
+            Some(b) if b.is_ascii() => {
+                self.position += 1;
+                match b {
+                    b'=' => Assign,
+                    b';' => Semicolon,
+                    b':' => Colon,
+                    b'(' => Lparen,
+                    b')' => Rparen,
+                    b',' => Comma,
+                    b'+' => Plus,
+                    b'-' => Minus,
+                    b'!' => Bang,
+                    b'*' => Asterisk,
+                    b'/' => Slash,
+                    b'<' => Lt,
+                    b'>' => Gt,
+                    b'{' => Lbrace,
+                    b'}' => Rbrace,
+                    b'[' => Lbracket,
+                    b']' => Rbracket,
+                    b'_' => self.read_identifer(start),
+                    b if b.is_ascii_alphabetic() => self.read_identifer(start),
+                    _ => Illegal,
+                }
+            }
+            Some(_) => {
+                let ch = self.peek_char().expect("non-ASCII byte starts a character");
+                self.position += ch.len_utf8();
+                if ch.is_xid_start() {
+                    self.read_identifer(start)
+                } else {
+                    Illegal
+                }
+            }
+        };
+
+        let span = Span::new(start, self.position);
+        let literal = &self.input[start..self.position];
+        if token_type == Illegal {
+            self.errors.push(illegal_token_error(literal, span));
+        }
+        BorrowedToken {
+            token_type,
+            literal,
+            span,
+        }
+    }
+
+    fn skip_trivia(&mut self) {
+        while let Some(ch) = self.peek_char() {
+            if ch.is_whitespace() {
+                self.position += ch.len_utf8();
+            } else if self.input[self.position..].starts_with("//") {
+                self.position += self.input[self.position..]
+                    .find('\n')
+                    .unwrap_or(self.input.len() - self.position);
+            } else {
+                break;
+            }
+        }
+    }
+
+    // 先頭の1文字は読み終わっている前提
+    fn read_identifer(&mut self, start: usize) -> TokenType {
+        let bytes = self.input.as_bytes();
+        while let Some(&b) = bytes.get(self.position) {
+            if b.is_ascii_alphanumeric() || b == b'_' {
+                self.position += 1;
+                continue;
+            }
+            match self.peek_char() {
+                Some(ch) if !ch.is_ascii() && ch.is_xid_continue() => {
+                    self.position += ch.len_utf8()
+                }
+                _ => break,
+            }
+        }
+        TokenType::lookup_iden(&self.input[start..self.position])
+    }
+
+    // 基数の接頭辞や区切りの`_`、小数部と指数部も含めて読む (Lexer::read_numberと同じ)
+    fn read_number(&mut self) -> TokenType {
+        let bytes = self.input.as_bytes();
+        let peek = |position: usize| bytes.get(position).copied();
+        let mut token_type = TokenType::Int;
+        let radix_prefix = peek(self.position) == Some(b'0')
+            && matches!(
+                peek(self.position + 1),
+                Some(b'x') | Some(b'o') | Some(b'b')
+            );
+
+        if !radix_prefix {
+            self.read_digits();
+            if peek(self.position) == Some(b'.')
+                && peek(self.position + 1).is_some_and(|b| b.is_ascii_digit())
+            {
+                token_type = TokenType::Float;
+                self.position += 1;
+                self.read_digits();
+            }
+            if matches!(peek(self.position), Some(b'e') | Some(b'E')) {
+                token_type = TokenType::Float;
+                self.position += 1;
+                if matches!(peek(self.position), Some(b'+') | Some(b'-')) {
+                    self.position += 1;
+                }
+                self.read_digits();
+            }
+        }
+
+        while peek(self.position).is_some_and(|b| b.is_ascii_alphanumeric() || b == b'_') {
+            self.position += 1;
+        }
+        token_type
+    }
+
+    // 閉じる `"` までを引用符ごと読む (Lexer::read_stringと同じ)
+    fn read_string(&mut self) -> TokenType {
+        let bytes = self.input.as_bytes();
+        self.position += 1;
+        while let Some(&b) = bytes.get(self.position) {
+            match b {
+                b'"' => {
+                    self.position += 1;
+                    return TokenType::String;
+                }
+                // エスケープされた文字は1バイトとは限らない
+                b'\\' => {
+                    self.position += 1;
+                    if let Some(ch) = self.peek_char() {
+                        self.position += ch.len_utf8();
+                    }
+                }
+                _ => self.position += 1,
+            }
+        }
+        TokenType::Illegal
+    }
+
+    fn read_digits(&mut self) {
+        let bytes = self.input.as_bytes();
+        while bytes
+            .get(self.position)
+            .is_some_and(|b| b.is_ascii_digit() || *b == b'_')
+        {
+            self.position += 1;
+        }
+    }
+
+    fn peek_char(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+}
+
+impl<'src> Iterator for ByteLexer<'src> {
+    type Item = BorrowedToken<'src>;
+
+    fn next(&mut self) -> Option<BorrowedToken<'src>> {
+        if self.finished {
+            return None;
+        }
+        let tok = self.next_token();
+        self.finished = tok.token_type == TokenType::Eof;
+        Some(tok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::lexer::Lexer;
+    use super::*;
+
+    // ByteLexer must agree with Lexer token for token
+    fn assert_same_tokens(input: &str) {
+        let mut lexer = Lexer::new(input);
+        let mut byte_lexer = ByteLexer::new(input);
+        loop {
+            let expected = lexer.next_token();
+            let tok = byte_lexer.next_token();
+            assert_eq!(tok.token_type, expected.token_type, "input: {:?}", input);
+            assert_eq!(tok.literal, expected.literal, "input: {:?}", input);
+            assert_eq!(tok.span, expected.span, "input: {:?}", input);
+            if expected.token_type == TokenType::Eof {
+                break;
+            }
+        }
+        assert_eq!(byte_lexer.errors(), lexer.errors());
+    }
+
+    #[test]
+    fn same_as_lexer() {
+        let inputs = [
+            "
+let five = 5;
+let ten = 10;
+
+let add = fn(x, y) {
+    x + y;
+};
+
+let result = add(five, ten);
+!-/*5;
+5 < 10 > 5;
+
+if (5 < 10) {
+    return true;
+} else {
+    return false;
+}
+
+10 == 10;
+10 != 9;
+",
+            "let 変数 = x1 + café_2;\u{3000}Ñu;",
+            "let x = 5 @ 3; # $ 😀",
+            "// comment\nx / y // 終わり\n//\n1 //",
+            "!===!=!",
+            "let x: int = 1; fn(a: int) -> bool { a->b - -1 -> - > }:",
+            "0x1F 0o17 0b1010 1_000_000 0b102 12ab 0x 99999999999999999999",
+            "3.14 1e-9 .5 2.5E+3 1_000.000_1 1. 0.x 1e 0x1e-5 1..2",
+            r#""a\"b" "\\" "😀\q" [1]["x"] {"k": []} "unterminated"#,
+            "\"\\",
+            "",
+        ];
+        for input in inputs.iter() {
+            assert_same_tokens(input);
+        }
+    }
+
+    #[test]
+    fn literal_borrows_input() {
+        let input = String::from("let answer = 42;");
+        let mut l = ByteLexer::new(&input);
+        l.next_token();
+        let tok = l.next_token();
+
+        assert_eq!(tok.literal, "answer");
+        assert_eq!(tok.literal.as_ptr(), input[4..].as_ptr());
+    }
+}
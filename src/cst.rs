@@ -0,0 +1,1206 @@
+//! Lossless concrete syntax tree.
+//!
+//! Unlike the AST, the CST keeps every byte of the source. Each token carries
+//! the whitespace and comments in front of it, and the trivia at the end of the
+//! file hangs off the final `Eof` token, so printing a tree gives back the
+//! input exactly. Malformed input still produces a tree; the tokens that do not
+//! fit the grammar are kept in `Error` nodes.
+//!
+//! Integer literals are kept as text whatever their size. Whether a literal
+//! beyond i64 is an error depends on the integer mode, so it is checked only
+//! when the tree is lowered.
+
+use super::ast::{
+    ArrayLiteral, ArrayType, BigIntegerLiteral, BlockStatement, Boolean, CallExpression,
+    ExpressionStatement, ExpressionType, FunctionLiteral, FunctionType, HashLiteral, HashType,
+    Identifer, IfExpression, IndexExpression, InfixExpression, LetStatement, PrefixExpression,
+    Program, ReturnStatement, StatementType, TypeExpression,
+};
+use super::byte_lexer::ByteLexer;
+use super::diagnostic::Diagnostic;
+use super::error_code::ErrorCode;
+use super::parser::{
+    float_literal, integer_literal, min_integer, precedence_of, string_literal, too_deep_error,
+    OperatorPriority, MAX_NESTING_DEPTH,
+};
+use super::token::{Span, Token, TokenType};
+use super::visit::Visitor;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    Comment, // // から行末まで (改行は含まない)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span,
+}
+
+/// A token together with the trivia that precedes it.
+#[derive(Debug, Clone, Default)]
+pub struct SyntaxToken {
+    pub leading: Vec<Trivia>,
+    pub token: Token,
+}
+
+impl SyntaxToken {
+    pub fn token_type(&self) -> TokenType {
+        self.token.token_type
+    }
+
+    fn write(&self, buf: &mut String) {
+        for trivia in &self.leading {
+            buf.push_str(&trivia.text);
+        }
+        buf.push_str(&self.token.literal);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxKind {
+    Program,
+    LetStatement,
+    ReturnStatement,
+    ExpressionStatement,
+    Block,
+    Identifier,
+    IntegerLiteral,
+    FloatLiteral,
+    BooleanLiteral,
+    StringLiteral,
+    ArrayLiteral, // 要素を直接持つ
+    HashLiteral,  // キーと値を交互に持つ
+    PrefixExpression,
+    InfixExpression,
+    GroupedExpression,
+    IfExpression,
+    FunctionLiteral,
+    ParameterList,
+    CallExpression,
+    ArgumentList,
+    IndexExpression,
+    NamedType,    // int
+    FunctionType, // fn(int) -> int
+    ArrayType,    // [int]
+    HashType,     // {string: int}
+    Error,        // 文法に合わないトークン
+}
+
+#[derive(Debug, Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+#[derive(Debug, Clone)]
+pub struct SyntaxNode {
+    pub kind: SyntaxKind,
+    pub children: Vec<SyntaxElement>,
+}
+
+impl SyntaxNode {
+    fn new(kind: SyntaxKind) -> Self {
+        Self {
+            kind,
+            children: Vec::new(),
+        }
+    }
+
+    /// Source text of the node, including the trivia in front of each token.
+    pub fn text(&self) -> String {
+        let mut buf = String::new();
+        self.write(&mut buf);
+        buf
+    }
+
+    fn write(&self, buf: &mut String) {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.write(buf),
+                SyntaxElement::Token(token) => token.write(buf),
+            }
+        }
+    }
+
+    /// All tokens of the node in source order.
+    pub fn tokens(&self) -> Vec<&SyntaxToken> {
+        let mut tokens = Vec::new();
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a SyntaxToken>) {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.collect_tokens(tokens),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+    }
+
+    /// Direct child nodes.
+    pub fn nodes(&self) -> impl Iterator<Item = &SyntaxNode> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    /// Direct child tokens.
+    pub fn child_tokens(&self) -> impl Iterator<Item = &SyntaxToken> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(_) => None,
+            SyntaxElement::Token(token) => Some(token),
+        })
+    }
+
+    /// Byte range from the first to the last token, without leading trivia.
+    pub fn span(&self) -> Span {
+        let tokens = self.tokens();
+        match (tokens.first(), tokens.last()) {
+            (Some(first), Some(last)) => first.token.span.to(last.token.span),
+            _ => Span::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SyntaxTree {
+    root: SyntaxNode,
+    errors: Vec<Diagnostic>,
+}
+
+impl SyntaxTree {
+    pub fn parse(source: &str) -> Self {
+        let mut lexer = ByteLexer::new(source);
+        let mut tokens = Vec::new();
+        let mut end = 0;
+        for tok in lexer.by_ref() {
+            let leading = split_trivia(&source[end..tok.span.start], end);
+            end = tok.span.end;
+            tokens.push(SyntaxToken {
+                leading,
+                token: tok.to_token(),
+            });
+        }
+
+        let mut parser = CstParser {
+            tokens,
+            position: 0,
+            errors: lexer.errors(),
+            depth: 0,
+            too_deep: false,
+        };
+        let root = parser.parse_program();
+        let mut errors = parser.errors;
+        errors.sort_by_key(|diagnostic| diagnostic.span.start);
+        Self { root, errors }
+    }
+
+    pub fn root(&self) -> &SyntaxNode {
+        &self.root
+    }
+
+    /// Lexer and parser errors, in source order.
+    pub fn errors(&self) -> Vec<Diagnostic> {
+        self.errors.clone()
+    }
+
+    /// Convert to the AST. The trivia is dropped; a tree with errors is not
+    /// lowered. Without `big_integers` a literal beyond i64 is an error, as in
+    /// `Parser::with_big_integers`.
+    pub fn lower(&self, big_integers: bool) -> Result<Program, Vec<Diagnostic>> {
+        if !self.errors.is_empty() {
+            return Err(self.errors());
+        }
+        let program: Program = self.root.nodes().map(lower_statement).collect();
+        if !big_integers {
+            let mut range = IntegerRange(Vec::new());
+            range.visit_program(&program);
+            if !range.0.is_empty() {
+                return Err(range.0);
+            }
+        }
+        Ok(program)
+    }
+}
+
+impl fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.root.text())
+    }
+}
+
+// i64に収まらないリテラルのエラーを集める
+struct IntegerRange(Vec<Diagnostic>);
+
+impl Visitor for IntegerRange {
+    fn visit_big_integer_literal(&mut self, literal: &BigIntegerLiteral) {
+        if let Err(err) = integer_literal(literal.token.clone(), false) {
+            self.0.push(err);
+        }
+    }
+}
+
+// トークン間の文字列は空白とコメントだけからなる
+fn split_trivia(text: &str, offset: usize) -> Vec<Trivia> {
+    let mut trivia = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let (kind, len) = if rest.starts_with("//") {
+            (TriviaKind::Comment, rest.find('\n').unwrap_or(rest.len()))
+        } else {
+            let len = rest
+                .find(|c: char| !c.is_whitespace())
+                .unwrap_or(rest.len());
+            (TriviaKind::Whitespace, len)
+        };
+        let start = offset + text.len() - rest.len();
+        trivia.push(Trivia {
+            kind,
+            text: String::from(&rest[..len]),
+            span: Span::new(start, start + len),
+        });
+        rest = &rest[len..];
+    }
+    trivia
+}
+
+// Parserと同じ文法を、トークンを1つも捨てずに木にする
+struct CstParser {
+    tokens: Vec<SyntaxToken>, // 最後は必ずEof
+    position: usize,
+    errors: Vec<Diagnostic>,
+    depth: usize,
+    too_deep: bool, // 入れ子が深すぎて残りを1つのErrorにした
+}
+
+impl CstParser {
+    fn current(&self) -> TokenType {
+        self.tokens[self.position].token_type()
+    }
+
+    fn current_token(&self) -> &Token {
+        &self.tokens[self.position].token
+    }
+
+    fn at(&self, token_type: TokenType) -> bool {
+        self.current() == token_type
+    }
+
+    // Eofはparse_programが最後に1度だけ木に入れる
+    fn bump(&mut self, node: &mut SyntaxNode) {
+        debug_assert!(!self.at(TokenType::Eof), "Eof must not be consumed");
+        let token = std::mem::take(&mut self.tokens[self.position]);
+        self.position += 1;
+        node.children.push(SyntaxElement::Token(token));
+    }
+
+    fn eat(&mut self, node: &mut SyntaxNode, token_type: TokenType) -> bool {
+        if self.at(token_type) {
+            self.bump(node);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, node: &mut SyntaxNode, token_type: TokenType) -> bool {
+        if self.eat(node, token_type) {
+            return true;
+        }
+        // illegal tokens are already reported by the lexer
+        if !self.at(TokenType::Illegal) && !self.too_deep {
+            let message = format!(
+                "expected next token to be {}, got {} instead",
+                token_type,
+                self.current()
+            );
+            self.errors.push(
+                Diagnostic::error(message, self.current_token().span)
+                    .with_code(ErrorCode::UnexpectedToken),
+            );
+        }
+        false
+    }
+
+    fn parse_program(&mut self) -> SyntaxNode {
+        let mut program = SyntaxNode::new(SyntaxKind::Program);
+        while !self.at(TokenType::Eof) {
+            let statement = self.parse_statement();
+            program.children.push(SyntaxElement::Node(statement));
+        }
+        // 末尾のtriviaはEofが持つ
+        let eof = self.tokens[self.position].clone();
+        program.children.push(SyntaxElement::Token(eof));
+        program
+    }
+
+    fn parse_statement(&mut self) -> SyntaxNode {
+        let start = self.position;
+        let mut statement = match self.current() {
+            TokenType::Let => self.parse_let_statement(),
+            TokenType::Return => self.parse_return_statement(),
+            _ => self.parse_expression_statement(),
+        };
+        // 文として読めなかったトークンも捨てずに進む
+        if self.position == start && !self.at(TokenType::Eof) {
+            let mut error = SyntaxNode::new(SyntaxKind::Error);
+            self.bump(&mut error);
+            statement.children.push(SyntaxElement::Node(error));
+        }
+        statement
+    }
+
+    fn parse_let_statement(&mut self) -> SyntaxNode {
+        let mut node = SyntaxNode::new(SyntaxKind::LetStatement);
+        self.bump(&mut node);
+        if !self.expect(&mut node, TokenType::Ident) {
+            self.skip_statement(&mut node);
+            return node;
+        }
+        self.parse_annotation(&mut node);
+        if !self.expect(&mut node, TokenType::Assign) {
+            self.skip_statement(&mut node);
+            return node;
+        }
+        let value = self.parse_expression(OperatorPriority::Lowest);
+        node.children.push(SyntaxElement::Node(value));
+        self.eat(&mut node, TokenType::Semicolon);
+        node
+    }
+
+    fn parse_return_statement(&mut self) -> SyntaxNode {
+        let mut node = SyntaxNode::new(SyntaxKind::ReturnStatement);
+        self.bump(&mut node);
+        let value = self.parse_expression(OperatorPriority::Lowest);
+        node.children.push(SyntaxElement::Node(value));
+        self.eat(&mut node, TokenType::Semicolon);
+        node
+    }
+
+    // Parser::skip_statementと同じく ; まで読み飛ばす。ブロックの } は残す
+    fn skip_statement(&mut self, node: &mut SyntaxNode) {
+        let mut error = SyntaxNode::new(SyntaxKind::Error);
+        while !matches!(self.current(), TokenType::Eof | TokenType::Rbrace) {
+            let semicolon = self.at(TokenType::Semicolon);
+            self.bump(&mut error);
+            if semicolon {
+                break;
+            }
+        }
+        node.children.push(SyntaxElement::Node(error));
+    }
+
+    fn parse_expression_statement(&mut self) -> SyntaxNode {
+        let mut node = SyntaxNode::new(SyntaxKind::ExpressionStatement);
+        let expression = self.parse_expression(OperatorPriority::Lowest);
+        node.children.push(SyntaxElement::Node(expression));
+        self.eat(&mut node, TokenType::Semicolon);
+        node
+    }
+
+    fn parse_expression(&mut self, precedence: OperatorPriority) -> SyntaxNode {
+        let depth = self.depth;
+        let expression = self.parse_nested_expression(precedence);
+        self.depth = depth;
+        expression
+    }
+
+    // Parserと同じく、左へ深くなる演算子の列も1段ずつ数える
+    fn parse_nested_expression(&mut self, precedence: OperatorPriority) -> SyntaxNode {
+        if let Some(rest) = self.enter() {
+            return rest;
+        }
+        let mut left = self.parse_prefix();
+        while precedence < precedence_of(&self.current()) {
+            if let Some(rest) = self.enter() {
+                let mut node = SyntaxNode::new(SyntaxKind::Error);
+                node.children.push(SyntaxElement::Node(left));
+                node.children.extend(rest.children);
+                return node;
+            }
+            left = match self.current() {
+                TokenType::Lparen => self.parse_call_expression(left),
+                TokenType::Lbracket => self.parse_index_expression(left),
+                _ => self.parse_infix_expression(left),
+            };
+        }
+        left
+    }
+
+    // 入れ子を1段深くする。上限を超えたら報告し、Eofまでの残りをErrorにして返す
+    fn enter(&mut self) -> Option<SyntaxNode> {
+        if self.depth < MAX_NESTING_DEPTH {
+            self.depth += 1;
+            return None;
+        }
+        self.errors.push(too_deep_error(self.current_token().span));
+        self.too_deep = true;
+        let mut rest = SyntaxNode::new(SyntaxKind::Error);
+        while !self.at(TokenType::Eof) {
+            self.bump(&mut rest);
+        }
+        Some(rest)
+    }
+
+    fn parse_prefix(&mut self) -> SyntaxNode {
+        use TokenType::*;
+
+        let kind = match self.current() {
+            Ident => SyntaxKind::Identifier,
+            Int => SyntaxKind::IntegerLiteral,
+            Float => SyntaxKind::FloatLiteral,
+            True | False => SyntaxKind::BooleanLiteral,
+            String => SyntaxKind::StringLiteral,
+            Bang | Minus => return self.parse_prefix_expression(),
+            Lbracket => return self.parse_array_literal(),
+            Lbrace => return self.parse_hash_literal(),
+            Lparen => return self.parse_grouped_expression(),
+            If => return self.parse_if_expression(),
+            Function => return self.parse_function_literal(),
+            _ => return self.expression_error(),
+        };
+        let literal = match kind {
+            SyntaxKind::IntegerLiteral => {
+                integer_literal(Box::new(self.current_token().clone()), true)
+            }
+            SyntaxKind::FloatLiteral => float_literal(Box::new(self.current_token().clone())),
+            SyntaxKind::StringLiteral => string_literal(Box::new(self.current_token().clone())),
+            _ => Ok(ExpressionType::Illegal),
+        };
+        if let Err(err) = literal {
+            self.errors.push(err);
+        }
+        let mut node = SyntaxNode::new(kind);
+        self.bump(&mut node);
+        node
+    }
+
+    fn expression_error(&mut self) -> SyntaxNode {
+        let mut node = SyntaxNode::new(SyntaxKind::Error);
+        if !self.at(TokenType::Illegal) && !self.too_deep {
+            let message = format!("expected an expression, got {} instead", self.current());
+            self.errors.push(
+                Diagnostic::error(message, self.current_token().span)
+                    .with_code(ErrorCode::ExpectedExpression),
+            );
+        }
+        // 区切りのトークンは外側の構文のために残す
+        if !matches!(
+            self.current(),
+            TokenType::Eof
+                | TokenType::Semicolon
+                | TokenType::Rparen
+                | TokenType::Rbrace
+                | TokenType::Rbracket
+                | TokenType::Comma
+        ) {
+            self.bump(&mut node);
+        }
+        node
+    }
+
+    fn parse_array_literal(&mut self) -> SyntaxNode {
+        let mut node = SyntaxNode::new(SyntaxKind::ArrayLiteral);
+        self.bump(&mut node);
+        if !self.eat(&mut node, TokenType::Rbracket) {
+            loop {
+                let element = self.parse_expression(OperatorPriority::Lowest);
+                node.children.push(SyntaxElement::Node(element));
+                if !self.eat(&mut node, TokenType::Comma) {
+                    break;
+                }
+            }
+            self.expect(&mut node, TokenType::Rbracket);
+        }
+        node
+    }
+
+    fn parse_hash_literal(&mut self) -> SyntaxNode {
+        let mut node = SyntaxNode::new(SyntaxKind::HashLiteral);
+        self.bump(&mut node);
+        if !self.eat(&mut node, TokenType::Rbrace) {
+            loop {
+                let key = self.parse_expression(OperatorPriority::Lowest);
+                node.children.push(SyntaxElement::Node(key));
+                // 値のない最後のキーの後の } は、このハッシュを閉じるものとみなす
+                if !self.expect(&mut node, TokenType::Colon) {
+                    self.eat(&mut node, TokenType::Rbrace);
+                    return node;
+                }
+                let value = self.parse_expression(OperatorPriority::Lowest);
+                node.children.push(SyntaxElement::Node(value));
+                if !self.eat(&mut node, TokenType::Comma) {
+                    break;
+                }
+            }
+            self.expect(&mut node, TokenType::Rbrace);
+        }
+        node
+    }
+
+    fn parse_prefix_expression(&mut self) -> SyntaxNode {
+        let mut node = SyntaxNode::new(SyntaxKind::PrefixExpression);
+        let minus = self.current_token().clone();
+        self.bump(&mut node);
+        // -9223372036854775808 はそれだけで正しいリテラル
+        if minus.token_type == TokenType::Minus
+            && self.at(TokenType::Int)
+            && min_integer(&minus, self.current_token()).is_some()
+        {
+            let mut literal = SyntaxNode::new(SyntaxKind::IntegerLiteral);
+            self.bump(&mut literal);
+            node.children.push(SyntaxElement::Node(literal));
+            return node;
+        }
+        let right = self.parse_expression(OperatorPriority::Prefix);
+        node.children.push(SyntaxElement::Node(right));
+        node
+    }
+
+    fn parse_infix_expression(&mut self, left: SyntaxNode) -> SyntaxNode {
+        let mut node = SyntaxNode::new(SyntaxKind::InfixExpression);
+        let precedence = precedence_of(&self.current());
+        node.children.push(SyntaxElement::Node(left));
+        self.bump(&mut node);
+        let right = self.parse_expression(precedence);
+        node.children.push(SyntaxElement::Node(right));
+        node
+    }
+
+    fn parse_grouped_expression(&mut self) -> SyntaxNode {
+        let mut node = SyntaxNode::new(SyntaxKind::GroupedExpression);
+        self.bump(&mut node);
+        let expression = self.parse_expression(OperatorPriority::Lowest);
+        node.children.push(SyntaxElement::Node(expression));
+        self.expect(&mut node, TokenType::Rparen);
+        node
+    }
+
+    fn parse_if_expression(&mut self) -> SyntaxNode {
+        let mut node = SyntaxNode::new(SyntaxKind::IfExpression);
+        self.bump(&mut node);
+        if !self.expect(&mut node, TokenType::Lparen) {
+            return node;
+        }
+        let condition = self.parse_expression(OperatorPriority::Lowest);
+        node.children.push(SyntaxElement::Node(condition));
+        if !self.expect(&mut node, TokenType::Rparen) || !self.at_block(&mut node) {
+            return node;
+        }
+        let consequence = self.parse_block();
+        node.children.push(SyntaxElement::Node(consequence));
+        if self.eat(&mut node, TokenType::Else) && self.at_block(&mut node) {
+            let alternative = self.parse_block();
+            node.children.push(SyntaxElement::Node(alternative));
+        }
+        node
+    }
+
+    fn at_block(&mut self, node: &mut SyntaxNode) -> bool {
+        if self.at(TokenType::Lbrace) {
+            return true;
+        }
+        // 期待したトークンがないことだけ報告する
+        let mut probe = SyntaxNode::new(SyntaxKind::Error);
+        self.expect(&mut probe, TokenType::Lbrace);
+        node.children.extend(probe.children);
+        false
+    }
+
+    fn parse_block(&mut self) -> SyntaxNode {
+        let mut node = SyntaxNode::new(SyntaxKind::Block);
+        let open = self.current_token().span;
+        self.bump(&mut node);
+        while !self.at(TokenType::Rbrace) {
+            if self.at(TokenType::Eof) {
+                if !self.too_deep {
+                    self.errors.push(
+                        Diagnostic::error("unclosed block: expected `}` before end of input", open)
+                            .with_code(ErrorCode::UnexpectedToken),
+                    );
+                }
+                return node;
+            }
+            let statement = self.parse_statement();
+            node.children.push(SyntaxElement::Node(statement));
+        }
+        self.bump(&mut node);
+        node
+    }
+
+    fn parse_function_literal(&mut self) -> SyntaxNode {
+        let mut node = SyntaxNode::new(SyntaxKind::FunctionLiteral);
+        self.bump(&mut node);
+        if !self.at(TokenType::Lparen) {
+            self.expect(&mut node, TokenType::Lparen);
+            return node;
+        }
+        let mut parameters = SyntaxNode::new(SyntaxKind::ParameterList);
+        self.bump(&mut parameters);
+        let mut closed = self.eat(&mut parameters, TokenType::Rparen);
+        if !closed && self.expect(&mut parameters, TokenType::Ident) {
+            self.parse_annotation(&mut parameters);
+            while self.eat(&mut parameters, TokenType::Comma) {
+                if !self.expect(&mut parameters, TokenType::Ident) {
+                    break;
+                }
+                self.parse_annotation(&mut parameters);
+            }
+            closed = self.expect(&mut parameters, TokenType::Rparen);
+        }
+        node.children.push(SyntaxElement::Node(parameters));
+        if closed && self.eat(&mut node, TokenType::Arrow) {
+            let return_type = self.parse_type();
+            node.children.push(SyntaxElement::Node(return_type));
+        }
+        if closed && self.at_block(&mut node) {
+            let body = self.parse_block();
+            node.children.push(SyntaxElement::Node(body));
+        }
+        node
+    }
+
+    // 名前の後の : <type>
+    fn parse_annotation(&mut self, node: &mut SyntaxNode) {
+        if self.eat(node, TokenType::Colon) {
+            let annotation = self.parse_type();
+            node.children.push(SyntaxElement::Node(annotation));
+        }
+    }
+
+    fn parse_type(&mut self) -> SyntaxNode {
+        let depth = self.depth;
+        let ty = self.parse_nested_type();
+        self.depth = depth;
+        ty
+    }
+
+    fn parse_nested_type(&mut self) -> SyntaxNode {
+        if let Some(rest) = self.enter() {
+            return rest;
+        }
+        match self.current() {
+            TokenType::Ident => {
+                let mut node = SyntaxNode::new(SyntaxKind::NamedType);
+                self.bump(&mut node);
+                node
+            }
+            TokenType::Function => self.parse_function_type(),
+            TokenType::Lbracket => {
+                let mut node = SyntaxNode::new(SyntaxKind::ArrayType);
+                self.bump(&mut node);
+                let element = self.parse_type();
+                node.children.push(SyntaxElement::Node(element));
+                self.expect(&mut node, TokenType::Rbracket);
+                node
+            }
+            TokenType::Lbrace => {
+                let mut node = SyntaxNode::new(SyntaxKind::HashType);
+                self.bump(&mut node);
+                let key = self.parse_type();
+                node.children.push(SyntaxElement::Node(key));
+                if self.expect(&mut node, TokenType::Colon) {
+                    let value = self.parse_type();
+                    node.children.push(SyntaxElement::Node(value));
+                    self.expect(&mut node, TokenType::Rbrace);
+                }
+                node
+            }
+            _ => self.type_error(),
+        }
+    }
+
+    fn parse_function_type(&mut self) -> SyntaxNode {
+        let mut node = SyntaxNode::new(SyntaxKind::FunctionType);
+        self.bump(&mut node);
+        if !self.expect(&mut node, TokenType::Lparen) {
+            return node;
+        }
+        if !self.eat(&mut node, TokenType::Rparen) {
+            loop {
+                let parameter = self.parse_type();
+                node.children.push(SyntaxElement::Node(parameter));
+                if !self.eat(&mut node, TokenType::Comma) {
+                    break;
+                }
+            }
+            if !self.expect(&mut node, TokenType::Rparen) {
+                return node;
+            }
+        }
+        if self.expect(&mut node, TokenType::Arrow) {
+            let result = self.parse_type();
+            node.children.push(SyntaxElement::Node(result));
+        }
+        node
+    }
+
+    fn type_error(&mut self) -> SyntaxNode {
+        let mut node = SyntaxNode::new(SyntaxKind::Error);
+        if !self.at(TokenType::Illegal) && !self.too_deep {
+            let message = format!("expected a type, got {} instead", self.current());
+            self.errors.push(
+                Diagnostic::error(message, self.current_token().span)
+                    .with_code(ErrorCode::UnexpectedToken),
+            );
+        }
+        if !matches!(
+            self.current(),
+            TokenType::Eof
+                | TokenType::Semicolon
+                | TokenType::Assign
+                | TokenType::Rparen
+                | TokenType::Lbrace
+                | TokenType::Rbrace
+                | TokenType::Rbracket
+                | TokenType::Comma
+        ) {
+            self.bump(&mut node);
+        }
+        node
+    }
+
+    fn parse_call_expression(&mut self, function: SyntaxNode) -> SyntaxNode {
+        let mut node = SyntaxNode::new(SyntaxKind::CallExpression);
+        node.children.push(SyntaxElement::Node(function));
+        let mut arguments = SyntaxNode::new(SyntaxKind::ArgumentList);
+        self.bump(&mut arguments);
+        if !self.eat(&mut arguments, TokenType::Rparen) {
+            loop {
+                let argument = self.parse_expression(OperatorPriority::Lowest);
+                arguments.children.push(SyntaxElement::Node(argument));
+                if !self.eat(&mut arguments, TokenType::Comma) {
+                    break;
+                }
+            }
+            self.expect(&mut arguments, TokenType::Rparen);
+        }
+        node.children.push(SyntaxElement::Node(arguments));
+        node
+    }
+
+    fn parse_index_expression(&mut self, left: SyntaxNode) -> SyntaxNode {
+        let mut node = SyntaxNode::new(SyntaxKind::IndexExpression);
+        node.children.push(SyntaxElement::Node(left));
+        self.bump(&mut node);
+        let index = self.parse_expression(OperatorPriority::Lowest);
+        node.children.push(SyntaxElement::Node(index));
+        self.expect(&mut node, TokenType::Rbracket);
+        node
+    }
+}
+
+// 以下はエラーのない木だけを受け取る
+
+fn boxed(token: &SyntaxToken) -> Box<Token> {
+    Box::new(token.token.clone())
+}
+
+fn lower_statement(node: &SyntaxNode) -> StatementType {
+    let tokens: Vec<&SyntaxToken> = node.child_tokens().collect();
+    let value = || lower_expression(node.nodes().last().expect("statement has a value"));
+    match node.kind {
+        SyntaxKind::LetStatement => StatementType::LetStatement(LetStatement {
+            token: boxed(tokens[0]),
+            name: Identifer::new(boxed(tokens[1])),
+            annotation: node.nodes().find(|node| is_type(node.kind)).map(lower_type),
+            value: value(),
+        }),
+        SyntaxKind::ReturnStatement => StatementType::ReturnStatement(ReturnStatement {
+            token: boxed(tokens[0]),
+            value: value(),
+        }),
+        _ => {
+            let expression = node.nodes().next().expect("statement has an expression");
+            StatementType::ExpressionStatement(ExpressionStatement {
+                token: boxed(expression.tokens()[0]),
+                expression: lower_expression(expression),
+            })
+        }
+    }
+}
+
+fn lower_expression(node: &SyntaxNode) -> ExpressionType {
+    let tokens: Vec<&SyntaxToken> = node.child_tokens().collect();
+    let mut nodes = node.nodes();
+    match node.kind {
+        SyntaxKind::Identifier => ExpressionType::Identifer(Identifer::new(boxed(tokens[0]))),
+        SyntaxKind::IntegerLiteral => {
+            integer_literal(boxed(tokens[0]), true).expect("checked by the parser")
+        }
+        SyntaxKind::FloatLiteral => float_literal(boxed(tokens[0])).expect("checked by the parser"),
+        SyntaxKind::BooleanLiteral => ExpressionType::Boolean(Boolean {
+            token: boxed(tokens[0]),
+            value: tokens[0].token_type() == TokenType::True,
+        }),
+        SyntaxKind::StringLiteral => {
+            string_literal(boxed(tokens[0])).expect("checked by the parser")
+        }
+        SyntaxKind::ArrayLiteral => ExpressionType::ArrayLiteral(ArrayLiteral {
+            token: boxed(tokens[0]),
+            elements: nodes.map(lower_expression).collect(),
+            span: node.span(),
+        }),
+        SyntaxKind::HashLiteral => {
+            let elements: Vec<ExpressionType> = nodes.map(lower_expression).collect();
+            let mut pairs = Vec::with_capacity(elements.len() / 2);
+            let mut elements = elements.into_iter();
+            while let (Some(key), Some(value)) = (elements.next(), elements.next()) {
+                pairs.push((key, value));
+            }
+            ExpressionType::HashLiteral(HashLiteral {
+                token: boxed(tokens[0]),
+                pairs,
+                span: node.span(),
+            })
+        }
+        SyntaxKind::PrefixExpression => {
+            let right = nodes.next().expect("prefix expression has an operand");
+            if right.kind == SyntaxKind::IntegerLiteral {
+                if let Some(literal) = min_integer(&tokens[0].token, &right.tokens()[0].token) {
+                    return literal;
+                }
+            }
+            ExpressionType::PrefixExpression(PrefixExpression {
+                token: boxed(tokens[0]),
+                operator: tokens[0].token.literal.clone(),
+                right: Box::new(lower_expression(right)),
+            })
+        }
+        SyntaxKind::InfixExpression => {
+            let left = nodes.next().expect("infix expression has a left operand");
+            let right = nodes.next().expect("infix expression has a right operand");
+            ExpressionType::InfixExpression(InfixExpression {
+                token: boxed(tokens[0]),
+                left: Box::new(lower_expression(left)),
+                operator: tokens[0].token.literal.clone(),
+                right: Box::new(lower_expression(right)),
+            })
+        }
+        SyntaxKind::GroupedExpression => {
+            lower_expression(nodes.next().expect("grouped expression has an expression"))
+        }
+        SyntaxKind::IfExpression => {
+            let condition = nodes.next().expect("if expression has a condition");
+            let consequence = nodes.next().expect("if expression has a consequence");
+            ExpressionType::IfExpression(IfExpression {
+                token: boxed(tokens[0]),
+                condition: Box::new(lower_expression(condition)),
+                consequence: lower_block(consequence),
+                alternative: nodes.next().map(lower_block),
+            })
+        }
+        SyntaxKind::FunctionLiteral => {
+            let mut parameters = Vec::new();
+            let mut parameter_types = Vec::new();
+            let list = nodes.next().expect("function has parameters");
+            for child in &list.children {
+                match child {
+                    SyntaxElement::Token(token) if token.token_type() == TokenType::Ident => {
+                        parameters.push(Identifer::new(boxed(token)));
+                        parameter_types.push(None);
+                    }
+                    SyntaxElement::Node(annotation) => {
+                        *parameter_types
+                            .last_mut()
+                            .expect("annotation follows a parameter") =
+                            Some(lower_type(annotation));
+                    }
+                    SyntaxElement::Token(_) => {}
+                }
+            }
+            let return_type = node.nodes().find(|node| is_type(node.kind)).map(lower_type);
+            let body = node.nodes().last().expect("function has a body");
+            ExpressionType::FunctionLiteral(FunctionLiteral {
+                token: boxed(tokens[0]),
+                parameters,
+                parameter_types,
+                return_type,
+                body: lower_block(body),
+            })
+        }
+        SyntaxKind::CallExpression => {
+            let function = nodes.next().expect("call has a function");
+            let arguments = nodes.next().expect("call has arguments");
+            let open = arguments
+                .child_tokens()
+                .next()
+                .expect("arguments start with (");
+            ExpressionType::CallExpression(CallExpression {
+                token: boxed(open),
+                function: Box::new(lower_expression(function)),
+                arguments: arguments.nodes().map(lower_expression).collect(),
+                span: node.span(),
+            })
+        }
+        SyntaxKind::IndexExpression => {
+            let left = nodes.next().expect("index expression has a left operand");
+            let index = nodes.next().expect("index expression has an index");
+            ExpressionType::IndexExpression(IndexExpression {
+                token: boxed(tokens[0]),
+                left: Box::new(lower_expression(left)),
+                index: Box::new(lower_expression(index)),
+                span: node.span(),
+            })
+        }
+        _ => ExpressionType::Illegal,
+    }
+}
+
+fn is_type(kind: SyntaxKind) -> bool {
+    matches!(
+        kind,
+        SyntaxKind::NamedType
+            | SyntaxKind::FunctionType
+            | SyntaxKind::ArrayType
+            | SyntaxKind::HashType
+    )
+}
+
+fn lower_type(node: &SyntaxNode) -> TypeExpression {
+    let token = boxed(
+        node.child_tokens()
+            .next()
+            .expect("type starts with a token"),
+    );
+    match node.kind {
+        SyntaxKind::FunctionType => {
+            let mut types: Vec<TypeExpression> = node.nodes().map(lower_type).collect();
+            let result = types.pop().expect("function type has a result");
+            TypeExpression::Function(FunctionType {
+                token,
+                parameters: types,
+                result: Box::new(result),
+            })
+        }
+        SyntaxKind::ArrayType => TypeExpression::Array(ArrayType {
+            token,
+            element: Box::new(lower_type(
+                node.nodes().next().expect("array type has an element"),
+            )),
+            span: node.span(),
+        }),
+        SyntaxKind::HashType => {
+            let mut types = node.nodes().map(lower_type);
+            TypeExpression::Hash(HashType {
+                token,
+                key: Box::new(types.next().expect("hash type has a key")),
+                value: Box::new(types.next().expect("hash type has a value")),
+                span: node.span(),
+            })
+        }
+        _ => TypeExpression::Named(Identifer::new(token)),
+    }
+}
+
+fn lower_block(node: &SyntaxNode) -> BlockStatement {
+    let open = node.child_tokens().next().expect("block starts with {");
+    BlockStatement {
+        token: boxed(open),
+        statements: node.nodes().map(lower_statement).collect(),
+        span: node.span(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ast::Node;
+    use super::super::lexer::Lexer;
+    use super::super::parser::Parser;
+    use super::*;
+
+    const PROGRAM: &str = "// adder
+let add = fn(x, y) {
+    x + y; // sum
+};
+
+let result = add(five, ten)  ;
+!-5 * 5;
+if (5 < 10) { return true; } else { return false; }
+let min = -9223372036854775808;\t// smallest
+1.5e3 * (2 + .5)
+";
+
+    #[test]
+    fn print_is_lossless() {
+        let inputs = [
+            PROGRAM,
+            "",
+            "   \n// only a comment",
+            "let 変数 = café;\u{3000}// 全角スペース\r\n",
+            // 壊れた入力も1バイトも失わない
+            "let x 5; let = 10; @ } ) ;",
+            "if (x { fn(a,, b) }",
+            "add(1, 2",
+            "let big = 99999999999999999999;",
+            "fn(x) { x",
+            "let f: fn(int, = fn(a: ) -> { };",
+            r#"let s = "a\q" + "open"#,
+            "[1, {2: ] x[",
+            "let h: {int [bool = {};",
+        ];
+        for input in inputs.iter() {
+            let tree = SyntaxTree::parse(input);
+            assert_eq!(tree.to_string(), *input);
+        }
+    }
+
+    #[test]
+    fn nesting_too_deep() {
+        // 上限を超えた所から後ろは、1つのErrorにまとめて残す
+        let input = String::from("let x = ") + &"(".repeat(1000) + "1" + &")".repeat(1000) + "; x";
+        let tree = SyntaxTree::parse(&input);
+        assert_eq!(tree.to_string(), input);
+        let errors = tree.errors();
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(errors[0].code, Some(ErrorCode::NestingTooDeep));
+        assert_eq!(errors[0].span, Span::new(136, 137));
+        let mut parser = Parser::new(Lexer::new(&input));
+        parser.parse_program();
+        assert_eq!(parser.errors(), errors);
+
+        let input = vec!["1"; 1000].join("+");
+        let errors = SyntaxTree::parse(&input).errors();
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(errors[0].code, Some(ErrorCode::NestingTooDeep));
+        assert_eq!(errors[0].span, Span::new(254, 255));
+        let mut parser = Parser::new(Lexer::new(&input));
+        parser.parse_program();
+        assert_eq!(parser.errors(), errors);
+    }
+
+    #[test]
+    fn trivia_attaches_to_the_next_token() {
+        let tree = SyntaxTree::parse("let x = 1; // one\n");
+        let tokens = tree.root().tokens();
+
+        let semicolon = tokens[4];
+        assert_eq!(semicolon.token.literal, ";");
+        assert!(semicolon.leading.is_empty());
+
+        let eof = tokens[5];
+        assert_eq!(eof.token_type(), TokenType::Eof);
+        assert_eq!(
+            eof.leading,
+            vec![
+                Trivia {
+                    kind: TriviaKind::Whitespace,
+                    text: String::from(" "),
+                    span: Span::new(10, 11),
+                },
+                Trivia {
+                    kind: TriviaKind::Comment,
+                    text: String::from("// one"),
+                    span: Span::new(11, 17),
+                },
+                Trivia {
+                    kind: TriviaKind::Whitespace,
+                    text: String::from("\n"),
+                    span: Span::new(17, 18),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn tree_shape() {
+        let tree = SyntaxTree::parse("f(1 + 2);");
+        let statement = tree.root().nodes().next().unwrap();
+        assert_eq!(statement.kind, SyntaxKind::ExpressionStatement);
+        let call = statement.nodes().next().unwrap();
+        assert_eq!(call.kind, SyntaxKind::CallExpression);
+        let kinds: Vec<SyntaxKind> = call.nodes().map(|node| node.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![SyntaxKind::Identifier, SyntaxKind::ArgumentList]
+        );
+        assert_eq!(call.span(), Span::new(0, 8));
+        assert_eq!(call.text(), "f(1 + 2)");
+    }
+
+    #[test]
+    fn lower_matches_parser() {
+        let inputs = [
+            PROGRAM,
+            "let newAdder = fn(x) { fn(y) { x + y } }; newAdder(2)(3);",
+            "fn() { } ()",
+            "a + b * c == -d / (e - f) != !g",
+            "let twice: fn(fn(int) -> int, int) -> int = fn(f: fn(int) -> int, x) -> int { f(f(x)) };",
+            "fn(a:int,b : bool)->bool { a }",
+            r#"let h: {string: [int]} = {"a\n": [1, 2][0], true: {}}; h["a"][1 + 1]"#,
+            r#"fn(xs: [fn() -> int]) -> {bool: string} { xs[0]()["\u{1F600}\t"] }"#,
+            "",
+        ];
+        for input in inputs.iter() {
+            let lowered = SyntaxTree::parse(input).lower(false).unwrap();
+            let mut parser = Parser::new(Lexer::new(input));
+            let expected = parser.parse_program();
+            assert!(parser.errors().is_empty());
+
+            assert_eq!(lowered.string(), expected.string(), "input: {:?}", input);
+            assert_eq!(lowered.len(), expected.len());
+            for (lowered, expected) in lowered.iter().zip(expected.iter()) {
+                assert_eq!(lowered.span(), expected.span(), "input: {:?}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let tests = [
+            ("let x 5;", ErrorCode::UnexpectedToken, Span::new(6, 7)),
+            ("let x = ;", ErrorCode::ExpectedExpression, Span::new(8, 9)),
+            ("1 + @", ErrorCode::UnknownCharacter, Span::new(4, 5)),
+            ("fn(x) { x", ErrorCode::UnexpectedToken, Span::new(6, 7)),
+            ("let x: = 1;", ErrorCode::UnexpectedToken, Span::new(7, 8)),
+            (
+                "fn(x) -> fn(int) { x }",
+                ErrorCode::UnexpectedToken,
+                Span::new(17, 18),
+            ),
+            (
+                "99999999999999999999",
+                ErrorCode::IntegerOverflow,
+                Span::new(0, 20),
+            ),
+            ("[1, 2", ErrorCode::UnexpectedToken, Span::new(5, 5)),
+            ("{1: 2, 3}", ErrorCode::UnexpectedToken, Span::new(8, 9)),
+            (r#""a\q""#, ErrorCode::InvalidEscape, Span::new(2, 4)),
+            (
+                r#"x + "open"#,
+                ErrorCode::UnterminatedString,
+                Span::new(4, 5),
+            ),
+        ];
+        for (input, code, span) in tests.iter() {
+            let tree = SyntaxTree::parse(input);
+            let errors = tree.lower(false).unwrap_err();
+            assert_eq!(errors.len(), 1, "input: {:?}, errors: {:?}", input, errors);
+            assert_eq!(errors[0].code, Some(*code), "input: {:?}", input);
+            assert_eq!(errors[0].span, *span, "input: {:?}", input);
+        }
+    }
+
+    #[test]
+    fn integers_beyond_i64() {
+        let input = "let x = 18446744073709551616; -9223372036854775808 + x";
+        let tree = SyntaxTree::parse(input);
+        assert!(tree.errors().is_empty());
+        assert_eq!(tree.to_string(), input);
+
+        let errors = tree.lower(false).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, Some(ErrorCode::IntegerOverflow));
+        assert_eq!(errors[0].span, Span::new(8, 28));
+
+        let lowered = tree.lower(true).unwrap();
+        let mut parser = Parser::new(Lexer::new(input)).with_big_integers(true);
+        assert_eq!(lowered.string(), parser.parse_program().string());
+    }
+}
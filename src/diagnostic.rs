@@ -0,0 +1,418 @@
+//! Compiler style rendering of problems found in Monkey source.
+//!
+//! A `Diagnostic` only knows the byte `Span` it points at. Line and column
+//! numbers are resolved against a `SourceFile` when the diagnostic is rendered,
+//! either for humans by a `Renderer` or for tools by `Diagnostic::to_json`.
+
+use super::error_code::ErrorCode;
+use super::token::Span;
+use serde::Serialize;
+use std::fmt;
+use unicode_width::UnicodeWidthChar;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const BOLD_RED: &str = "\x1b[1;31m";
+const BOLD_YELLOW: &str = "\x1b[1;33m";
+const BOLD_BLUE: &str = "\x1b[1;34m";
+const BOLD_CYAN: &str = "\x1b[1;36m";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteKind {
+    Note,
+    Help,
+}
+
+impl fmt::Display for NoteKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NoteKind::Note => write!(f, "note"),
+            NoteKind::Help => write!(f, "help"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Note {
+    pub kind: NoteKind,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<ErrorCode>,
+    pub message: String,
+    pub span: Span,
+    pub notes: Vec<Note>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity: Severity::Error,
+            code: None,
+            message: message.into(),
+            span,
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn warning(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(message, span)
+        }
+    }
+
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_note(mut self, message: impl Into<String>) -> Self {
+        self.notes.push(Note {
+            kind: NoteKind::Note,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_help(mut self, message: impl Into<String>) -> Self {
+        self.notes.push(Note {
+            kind: NoteKind::Help,
+            message: message.into(),
+        });
+        self
+    }
+
+    /// Serialize as a single line JSON object:
+    ///
+    /// ```text
+    /// {"severity":"error","code":"E0001","message":"...","file":"main.mk",
+    ///  "spans":[{"byte_start":6,"byte_end":7,"line_start":1,"column_start":7,
+    ///            "line_end":1,"column_end":8,"is_primary":true}],
+    ///  "notes":[{"kind":"help","message":"..."}]}
+    /// ```
+    pub fn to_json(&self, source: &SourceFile) -> String {
+        let json = JsonDiagnostic {
+            severity: self.severity,
+            code: self.code.map(|code| code.code()),
+            message: &self.message,
+            file: &source.name,
+            spans: vec![source.resolve(self.span)],
+            notes: &self.notes,
+        };
+        serde_json::to_string(&json).expect("diagnostic is always serializable")
+    }
+}
+
+#[derive(Serialize)]
+struct JsonDiagnostic<'a> {
+    severity: Severity,
+    code: Option<&'static str>,
+    message: &'a str,
+    file: &'a str,
+    spans: Vec<SpanLocation>,
+    notes: &'a [Note],
+}
+
+/// A span with its 1-based line and column numbers resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SpanLocation {
+    pub byte_start: usize,
+    pub byte_end: usize,
+    pub line_start: usize,
+    pub column_start: usize,
+    pub line_end: usize,
+    pub column_end: usize,
+    pub is_primary: bool,
+}
+
+/// Source text together with the name it is reported under.
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub name: String,
+    pub text: String,
+}
+
+impl SourceFile {
+    pub fn new(name: &str, text: &str) -> Self {
+        Self {
+            name: String::from(name),
+            text: String::from(text),
+        }
+    }
+
+    /// 1-based line and column of a byte offset. Columns count characters, not bytes.
+    pub fn location(&self, offset: usize) -> (usize, usize) {
+        let offset = self.clamp(offset);
+        let before = &self.text[..offset];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let column = self.text[line_start..offset].chars().count() + 1;
+        (line, column)
+    }
+
+    pub fn resolve(&self, span: Span) -> SpanLocation {
+        let (line_start, column_start) = self.location(span.start);
+        let (line_end, column_end) = self.location(span.end);
+        SpanLocation {
+            byte_start: span.start,
+            byte_end: span.end,
+            line_start,
+            column_start,
+            line_end,
+            column_end,
+            is_primary: true,
+        }
+    }
+
+    /// Text of a 1-based line without its line terminator.
+    pub fn line(&self, line: usize) -> &str {
+        self.text
+            .split('\n')
+            .nth(line - 1)
+            .map_or("", |l| l.trim_end_matches('\r'))
+    }
+
+    // 入力末尾の改行の後ろ (EOF) は最終行の行末として扱う
+    fn clamp(&self, offset: usize) -> usize {
+        let mut offset = offset.min(self.text.len());
+        while !self.text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        if offset == self.text.len() && self.text.ends_with('\n') {
+            offset -= 1;
+        }
+        offset
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Renderer {
+    color: bool,
+}
+
+impl Renderer {
+    /// Renderer for logs and other non-terminal output.
+    pub fn plain() -> Self {
+        Self { color: false }
+    }
+
+    /// Renderer that decorates its output with ANSI escape sequences.
+    pub fn colored() -> Self {
+        Self { color: true }
+    }
+
+    /// Render a diagnostic as
+    ///
+    /// ```text
+    /// error[E0001]: expected next token to be `=`, got integer literal instead
+    ///  --> main.mk:1:7
+    ///   |
+    /// 1 | let x 5;
+    ///   |       ^
+    /// ```
+    pub fn render(&self, diagnostic: &Diagnostic, source: &SourceFile) -> String {
+        let (line, column) = source.location(diagnostic.span.start);
+        let text = source.line(line);
+        let gutter = " ".repeat(line.to_string().len());
+        let level_style = match diagnostic.severity {
+            Severity::Error => BOLD_RED,
+            Severity::Warning => BOLD_YELLOW,
+        };
+
+        let level = match diagnostic.code {
+            Some(code) => format!("{}[{}]", diagnostic.severity, code),
+            None => diagnostic.severity.to_string(),
+        };
+
+        let mut buf = String::new();
+        buf.push_str(&format!(
+            "{}{}",
+            self.paint(level_style, &level),
+            self.paint(BOLD, &format!(": {}", diagnostic.message))
+        ));
+        buf.push('\n');
+        buf.push_str(&format!(
+            "{}{} {}:{}:{}\n",
+            gutter,
+            self.paint(BOLD_BLUE, "-->"),
+            source.name,
+            line,
+            column
+        ));
+        buf.push_str(&format!("{} {}\n", gutter, self.paint(BOLD_BLUE, "|")));
+        buf.push_str(&format!(
+            "{} {} {}\n",
+            self.paint(BOLD_BLUE, &line.to_string()),
+            self.paint(BOLD_BLUE, "|"),
+            text
+        ));
+
+        let len = source
+            .text
+            .get(diagnostic.span.start..diagnostic.span.end)
+            .map_or(0, |s| s.chars().count());
+        let width = underline_width(text, column, len);
+        let padding = padding(text, column);
+        let underline = format!("^{}", "~".repeat(width - 1));
+        buf.push_str(&format!(
+            "{} {} {}{}\n",
+            gutter,
+            self.paint(BOLD_BLUE, "|"),
+            padding,
+            self.paint(level_style, &underline)
+        ));
+
+        for note in &diagnostic.notes {
+            buf.push_str(&format!(
+                "{} {} {} {}\n",
+                gutter,
+                self.paint(BOLD_BLUE, "="),
+                self.paint(BOLD_CYAN, &format!("{}:", note.kind)),
+                note.message
+            ));
+        }
+        buf
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            String::from(text)
+        }
+    }
+}
+
+/// Whitespace that puts the caret under `column`. Wide characters such as CJK
+/// take two terminal cells, and tabs are kept so that the terminal expands them alike.
+fn padding(text: &str, column: usize) -> String {
+    text.chars()
+        .take(column - 1)
+        .map(|c| match c {
+            '\t' => String::from("\t"),
+            _ => " ".repeat(c.width().unwrap_or(0)),
+        })
+        .collect()
+}
+
+/// Number of terminal cells under `len` characters from `column`, clipped to
+/// the end of the line.
+fn underline_width(text: &str, column: usize, len: usize) -> usize {
+    let width: usize = text
+        .chars()
+        .skip(column - 1)
+        .take(len)
+        .map(|c| c.width().unwrap_or(1))
+        .sum();
+    width.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn location() {
+        let source = SourceFile::new("test.mk", "let x = 5;\nlet y = 10;\n");
+
+        assert_eq!(source.location(0), (1, 1));
+        assert_eq!(source.location(4), (1, 5));
+        assert_eq!(source.location(15), (2, 5));
+        // EOF after the trailing newline points at the end of the last line
+        assert_eq!(source.location(22), (2, 12));
+    }
+
+    #[test]
+    fn render_plain() {
+        let source = SourceFile::new("test.mk", "let x = 5;\nlet foobar 10;\n");
+        let diagnostic = Diagnostic::error("expected next token to be `=`", Span::new(15, 21))
+            .with_code(ErrorCode::UnexpectedToken)
+            .with_help("add `=` after the name");
+
+        let expected = "\
+error[E0001]: expected next token to be `=`
+ --> test.mk:2:5
+  |
+2 | let foobar 10;
+  |     ^~~~~~
+  = help: add `=` after the name
+";
+        assert_eq!(Renderer::plain().render(&diagnostic, &source), expected);
+    }
+
+    #[test]
+    fn render_wide_characters() {
+        let source = SourceFile::new("test.mk", "let 名前 = café @;");
+        let diagnostic = Diagnostic::error("unknown character `@`", Span::new(19, 20));
+
+        let expected = "\
+error: unknown character `@`
+ --> test.mk:1:15
+  |
+1 | let 名前 = café @;
+  |                 ^
+";
+        assert_eq!(Renderer::plain().render(&diagnostic, &source), expected);
+
+        let diagnostic = Diagnostic::error("unused variable", Span::new(4, 10));
+        let rendered = Renderer::plain().render(&diagnostic, &source);
+        assert!(rendered.contains(" --> test.mk:1:5\n"));
+        assert!(rendered.ends_with("  |     ^~~~\n"));
+    }
+
+    #[test]
+    fn render_empty_span() {
+        let source = SourceFile::new("test.mk", "let x");
+        let diagnostic = Diagnostic::warning("unexpected end of input", Span::new(5, 5));
+
+        let expected = "\
+warning: unexpected end of input
+ --> test.mk:1:6
+  |
+1 | let x
+  |      ^
+";
+        assert_eq!(Renderer::plain().render(&diagnostic, &source), expected);
+    }
+
+    #[test]
+    fn to_json() {
+        let source = SourceFile::new("test.mk", "let x 5;\n");
+        let diagnostic = Diagnostic::error("expected next token to be `=`", Span::new(6, 7))
+            .with_help("add `=` after the name");
+
+        let expected = r#"{"severity":"error","code":null,"message":"expected next token to be `=`","file":"test.mk","spans":[{"byte_start":6,"byte_end":7,"line_start":1,"column_start":7,"line_end":1,"column_end":8,"is_primary":true}],"notes":[{"kind":"help","message":"add `=` after the name"}]}"#;
+        assert_eq!(diagnostic.to_json(&source), expected);
+    }
+
+    #[test]
+    fn render_colored() {
+        let source = SourceFile::new("test.mk", "let");
+        let diagnostic = Diagnostic::error("oops", Span::new(0, 3));
+
+        let rendered = Renderer::colored().render(&diagnostic, &source);
+        assert!(rendered.starts_with("\x1b[1;31merror\x1b[0m"));
+        assert!(rendered.contains("\x1b[1;31m^~~\x1b[0m"));
+    }
+}
@@ -0,0 +1,535 @@
+//! Stable codes for every diagnostic the lexer, parser, resolver, type checker,
+//! evaluator and linter can produce.
+//!
+//! A code never changes meaning once published, so docs and suppression lists
+//! can refer to it instead of the message text. New codes are appended.
+
+use serde::{Serialize, Serializer};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    UnexpectedToken,
+    ExpectedExpression,
+    UnknownCharacter,
+    IntegerOverflow,
+    InvalidIntegerLiteral,
+    InvalidFloatLiteral,
+    FloatOverflow,
+    TypeMismatch,
+    UnknownOperator,
+    UnboundIdentifier,
+    NotAFunction,
+    WrongArgumentCount,
+    DivisionByZero,
+    ArithmeticOverflow,
+    UndefinedVariable,
+    UseBeforeDefinition,
+    UnusedVariable,
+    ShadowedBuiltin,
+    InfiniteType,
+    UnknownType,
+    UnreachableCode,
+    ConstantCondition,
+    SelfComparison,
+    EmptyBlock,
+    DoubleNegation,
+    UnusedParameter,
+    CallDepthExceeded,
+    StepLimitExceeded,
+    SizeLimitExceeded,
+    TimeLimitExceeded,
+    NativeFunctionFailed,
+    NestingTooDeep,
+    UnterminatedString,
+    InvalidEscape,
+    UnusableHashKey,
+    ValueTooDeep,
+}
+
+impl ErrorCode {
+    pub const ALL: &'static [ErrorCode] = &[
+        ErrorCode::UnexpectedToken,
+        ErrorCode::ExpectedExpression,
+        ErrorCode::UnknownCharacter,
+        ErrorCode::IntegerOverflow,
+        ErrorCode::InvalidIntegerLiteral,
+        ErrorCode::InvalidFloatLiteral,
+        ErrorCode::FloatOverflow,
+        ErrorCode::TypeMismatch,
+        ErrorCode::UnknownOperator,
+        ErrorCode::UnboundIdentifier,
+        ErrorCode::NotAFunction,
+        ErrorCode::WrongArgumentCount,
+        ErrorCode::DivisionByZero,
+        ErrorCode::ArithmeticOverflow,
+        ErrorCode::UndefinedVariable,
+        ErrorCode::UseBeforeDefinition,
+        ErrorCode::UnusedVariable,
+        ErrorCode::ShadowedBuiltin,
+        ErrorCode::InfiniteType,
+        ErrorCode::UnknownType,
+        ErrorCode::UnreachableCode,
+        ErrorCode::ConstantCondition,
+        ErrorCode::SelfComparison,
+        ErrorCode::EmptyBlock,
+        ErrorCode::DoubleNegation,
+        ErrorCode::UnusedParameter,
+        ErrorCode::CallDepthExceeded,
+        ErrorCode::StepLimitExceeded,
+        ErrorCode::SizeLimitExceeded,
+        ErrorCode::TimeLimitExceeded,
+        ErrorCode::NativeFunctionFailed,
+        ErrorCode::NestingTooDeep,
+        ErrorCode::UnterminatedString,
+        ErrorCode::InvalidEscape,
+        ErrorCode::UnusableHashKey,
+        ErrorCode::ValueTooDeep,
+    ];
+
+    pub fn code(&self) -> &'static str {
+        use ErrorCode::*;
+        match self {
+            UnexpectedToken => "E0001",
+            ExpectedExpression => "E0002",
+            UnknownCharacter => "E0003",
+            IntegerOverflow => "E0004",
+            InvalidIntegerLiteral => "E0005",
+            InvalidFloatLiteral => "E0006",
+            FloatOverflow => "E0007",
+            NestingTooDeep => "E0008",
+            UnterminatedString => "E0009",
+            InvalidEscape => "E0010",
+            // 実行時エラーはE01xx
+            TypeMismatch => "E0100",
+            UnknownOperator => "E0101",
+            UnboundIdentifier => "E0102",
+            NotAFunction => "E0103",
+            WrongArgumentCount => "E0104",
+            DivisionByZero => "E0105",
+            ArithmeticOverflow => "E0106",
+            CallDepthExceeded => "E0107",
+            StepLimitExceeded => "E0108",
+            SizeLimitExceeded => "E0109",
+            TimeLimitExceeded => "E0110",
+            NativeFunctionFailed => "E0111",
+            UnusableHashKey => "E0112",
+            ValueTooDeep => "E0113",
+            // 実行前の静的解析はE02xx
+            UndefinedVariable => "E0200",
+            UseBeforeDefinition => "E0201",
+            UnusedVariable => "E0202",
+            ShadowedBuiltin => "E0203",
+            InfiniteType => "E0204",
+            UnknownType => "E0205",
+            // lint はE03xx
+            UnreachableCode => "E0300",
+            ConstantCondition => "E0301",
+            SelfComparison => "E0302",
+            EmptyBlock => "E0303",
+            DoubleNegation => "E0304",
+            UnusedParameter => "E0305",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        use ErrorCode::*;
+        match self {
+            UnexpectedToken => "unexpected token",
+            ExpectedExpression => "expected expression",
+            UnknownCharacter => "unknown character",
+            IntegerOverflow => "integer literal out of range",
+            InvalidIntegerLiteral => "invalid integer literal",
+            InvalidFloatLiteral => "invalid float literal",
+            FloatOverflow => "float literal out of range",
+            TypeMismatch => "type mismatch",
+            UnknownOperator => "unknown operator",
+            UnboundIdentifier => "identifier not found",
+            NotAFunction => "not a function",
+            WrongArgumentCount => "wrong number of arguments",
+            DivisionByZero => "division by zero",
+            ArithmeticOverflow => "arithmetic overflow",
+            UndefinedVariable => "undefined variable",
+            UseBeforeDefinition => "variable used before its definition",
+            UnusedVariable => "unused variable",
+            ShadowedBuiltin => "builtin shadowed",
+            InfiniteType => "infinite type",
+            UnknownType => "unknown type",
+            UnreachableCode => "unreachable code",
+            ConstantCondition => "constant condition",
+            SelfComparison => "comparison with itself",
+            EmptyBlock => "empty block",
+            DoubleNegation => "double negation",
+            UnusedParameter => "unused parameter",
+            CallDepthExceeded => "call depth limit exceeded",
+            StepLimitExceeded => "step limit exceeded",
+            SizeLimitExceeded => "value size limit exceeded",
+            TimeLimitExceeded => "time limit exceeded",
+            NativeFunctionFailed => "native function failed",
+            NestingTooDeep => "expression nested too deeply",
+            UnterminatedString => "unterminated string literal",
+            InvalidEscape => "invalid escape in string literal",
+            UnusableHashKey => "unusable hash key",
+            ValueTooDeep => "value nested too deeply",
+        }
+    }
+
+    /// Whether the error stops a program for exceeding one of the evaluator's
+    /// `Limits` rather than for a mistake in it.
+    pub fn is_resource_limit(&self) -> bool {
+        use ErrorCode::*;
+        matches!(
+            self,
+            CallDepthExceeded | StepLimitExceeded | SizeLimitExceeded | TimeLimitExceeded
+        )
+    }
+
+    /// Look up a code such as `E0001`. The `E` prefix is optional and case insensitive.
+    pub fn lookup(code: &str) -> Option<ErrorCode> {
+        let digits = code.strip_prefix(|c| c == 'E' || c == 'e').unwrap_or(code);
+        ErrorCode::ALL
+            .iter()
+            .find(|v| &v.code()[1..] == digits)
+            .cloned()
+    }
+
+    /// Long description printed by `ironmonkey explain`.
+    pub fn explain(&self) -> String {
+        let (description, wrong, right) = self.explanation();
+        format!(
+            "{}: {}\n\n{}\n\nErroneous code example:\n\n{}\n\nCorrected:\n\n{}\n",
+            self.code(),
+            self.title(),
+            description,
+            indent(wrong),
+            indent(right)
+        )
+    }
+
+    // (description, wrong example, right example)
+    fn explanation(&self) -> (&'static str, &'static str, &'static str) {
+        use ErrorCode::*;
+        match self {
+            UnexpectedToken => (
+                "The parser found a token other than the one the grammar requires at this\n\
+                 point, for example a `let` statement missing its `=`.",
+                "let x 5;",
+                "let x = 5;",
+            ),
+            ExpectedExpression => (
+                "An expression was expected, but the token found cannot start one.\n\
+                 This usually means an operand is missing or a statement is incomplete.",
+                "let x = ;",
+                "let x = 5;",
+            ),
+            UnknownCharacter => (
+                "The lexer found a character that is not part of the Monkey language.\n\
+                 Only the operators, delimiters, identifiers and literals listed in the\n\
+                 grammar are allowed outside of whitespace.",
+                "let price = 5 @ 3;",
+                "let price = 5 * 3;",
+            ),
+            IntegerOverflow => (
+                "Integers are 64-bit signed values. A literal larger than\n\
+                 9223372036854775807 cannot be represented and is rejected when the\n\
+                 program is parsed.",
+                "let big = 99999999999999999999;",
+                "let big = 9223372036854775807;",
+            ),
+            InvalidIntegerLiteral => (
+                "An integer literal contains a digit that is not valid for its radix, or\n\
+                 a radix prefix is not followed by any digits. `0x` literals take the\n\
+                 digits 0-9 and a-f, `0o` literals 0-7 and `0b` literals 0 and 1.\n\
+                 Digits may be separated by `_`.",
+                "let mask = 0b1021;",
+                "let mask = 0b1011;",
+            ),
+            InvalidFloatLiteral => (
+                "A float literal is malformed. Floats are written as decimal digits with\n\
+                 a fractional part (`3.14`, `.5`), an exponent (`1e-9`, `2.5E+3`), or\n\
+                 both. An exponent needs at least one digit and radix prefixes are not\n\
+                 allowed.",
+                "let epsilon = 1e-;",
+                "let epsilon = 1e-9;",
+            ),
+            FloatOverflow => (
+                "Floats are 64-bit IEEE 754 values. A literal whose magnitude exceeds\n\
+                 about 1.8e308 would become infinity and is rejected when the program\n\
+                 is parsed.",
+                "let huge = 1e400;",
+                "let huge = 1e300;",
+            ),
+            TypeMismatch => (
+                "The operands of a binary operator have types it cannot combine.\n\
+                 Integers and floats mix freely (the integer is converted to a float),\n\
+                 but booleans and functions cannot be used in arithmetic, and `+` joins\n\
+                 two strings but not a string and a number.",
+                "let total = 1 + true;",
+                "let total = 1 + 1;",
+            ),
+            UnknownOperator => (
+                "The operator is not defined for the type of its operand, for example\n\
+                 negating a boolean or adding two booleans. Indexing with `[]` works on\n\
+                 arrays, with an integer, and on hashes.",
+                "let flag = -true;",
+                "let flag = !true;",
+            ),
+            UnboundIdentifier => (
+                "A name was used that is not bound by a `let` statement or a function\n\
+                 parameter in any enclosing scope.",
+                "let y = x + 1;",
+                "let x = 1;\nlet y = x + 1;",
+            ),
+            NotAFunction => (
+                "Only functions can be called. The value on the left of `(` evaluated\n\
+                 to something else.",
+                "let five = 5;\nfive(1);",
+                "let five = fn(x) { 5 };\nfive(1);",
+            ),
+            WrongArgumentCount => (
+                "A function was called with a different number of arguments than it\n\
+                 declares parameters.",
+                "let add = fn(x, y) { x + y };\nadd(1);",
+                "let add = fn(x, y) { x + y };\nadd(1, 2);",
+            ),
+            DivisionByZero => (
+                "An integer was divided by zero. Integer division has no result for a\n\
+                 zero divisor; float division by zero yields infinity instead.",
+                "let ratio = 10 / 0;",
+                "let ratio = 10.0 / 0;",
+            ),
+            ArithmeticOverflow => (
+                "The result of integer arithmetic does not fit in a 64-bit signed\n\
+                 integer. Rather than wrapping around, the program stops with this\n\
+                 error. Enable big integers (`--bignum`) to compute with integers of\n\
+                 any size, or use floats when an approximate result is enough. With\n\
+                 big integers, the error also reports a value too large for a native\n\
+                 function that takes a 64-bit integer argument.",
+                "let big = 9223372036854775807 + 1;",
+                "let big = 9223372036854775807.0 + 1;",
+            ),
+            UndefinedVariable => (
+                "A name is used that no `let` statement, function parameter or builtin\n\
+                 defines. `ironmonkey check` reports this before the program runs, even\n\
+                 in branches that are rarely executed. It is often a typo.",
+                "let count = 1;\nif (count > 0) { cuont + 1 }",
+                "let count = 1;\nif (count > 0) { count + 1 }",
+            ),
+            UseBeforeDefinition => (
+                "A name is used before the `let` statement that defines it in the same\n\
+                 scope has run. Inside a function body, names of the enclosing scope may\n\
+                 be defined later, because the body runs only when the function is called.",
+                "let total = price * 2;\nlet price = 5;",
+                "let price = 5;\nlet total = price * 2;",
+            ),
+            UnusedVariable => (
+                "A `let` binding is never read. This is a warning: the binding may be\n\
+                 left over from an edit. Prefix the name with `_` to mark it as\n\
+                 intentionally unused. Unused function parameters are reported by\n\
+                 `ironmonkey lint` instead, as E0305.",
+                "let f = fn(x) { let y = x * 2; x };",
+                "let f = fn(x) { let y = x * 2; y };",
+            ),
+            ShadowedBuiltin => (
+                "A `let` binding or function parameter has the name of a builtin, which\n\
+                 makes the builtin unreachable in its scope. This is a warning.",
+                "let len = fn(items) { 0 };",
+                "let size = fn(items) { 0 };",
+            ),
+            InfiniteType => (
+                "`ironmonkey check --types` found a value whose type would have to contain\n\
+                 itself, such as a function applied to itself. No finite type describes\n\
+                 it, so the program is rejected even if it might run.",
+                "let twice = fn(f) { f(f) };",
+                "let twice = fn(f, x) { f(f(x)) };",
+            ),
+            UnknownType => (
+                "A type annotation names a type that does not exist. The types are `int`,\n\
+                 `float`, `bool`, `string`, `null`, array types written `[int]`, hash\n\
+                 types written `{string: int}` and function types written\n\
+                 `fn(int) -> bool`.",
+                "let count: integer = 1;",
+                "let count: int = 1;",
+            ),
+            UnreachableCode => (
+                "`ironmonkey lint` found statements after a `return` in the same block.\n\
+                 They are never evaluated, so they are either dead code or the `return`\n\
+                 is misplaced.",
+                "let f = fn(x) { return x; x + 1 };",
+                "let f = fn(x) { x + 1 };",
+            ),
+            ConstantCondition => (
+                "The condition of an `if` is a literal, so the same branch is always\n\
+                 taken. Every value other than `false` and `null` is truthy, including\n\
+                 `0`.",
+                "if (0) { puts(1) }",
+                "puts(1)",
+            ),
+            SelfComparison => (
+                "A variable is compared with itself with `==`, `!=`, `<` or `>`. The\n\
+                 result does not depend on the value, which usually means the other\n\
+                 operand was mistyped.",
+                "let same = fn(a, b) { a == a };",
+                "let same = fn(a, b) { a == b };",
+            ),
+            EmptyBlock => (
+                "An `if` branch, an `else` branch or a function body is empty. An empty\n\
+                 block evaluates to `null`; it is often left over from an edit.",
+                "if (x > 0) { } else { puts(x) }",
+                "if (!(x > 0)) { puts(x) }",
+            ),
+            DoubleNegation => (
+                "`!!x` converts `x` to a boolean. Conditions already test truthiness, so\n\
+                 the negations only make the code harder to read.",
+                "if (!!x) { 1 }",
+                "if (x) { 1 }",
+            ),
+            UnusedParameter => (
+                "A function parameter is never read in the body. Prefix the name with\n\
+                 `_` to mark it as intentionally unused.",
+                "let first = fn(a, b) { a };",
+                "let first = fn(a, _b) { a };",
+            ),
+            CallDepthExceeded => (
+                "The host limits how many function calls may be in progress at once,\n\
+                 and a call went past the limit. Deep recursion usually causes this.\n\
+                 A call whose value is returned directly, a tail call, replaces its\n\
+                 caller and does not add to the depth.",
+                "let sum = fn(n) { if (n == 0) { 0 } else { n + sum(n - 1) } };\nsum(100000);",
+                "let sum = fn(n, acc) { if (n == 0) { acc } else { sum(n - 1, acc + n) } };\n\
+                 sum(100000, 0);",
+            ),
+            StepLimitExceeded => (
+                "The host limits how many expressions a program may evaluate, and the\n\
+                 program went past the limit. An infinite loop never finishes within\n\
+                 any limit; otherwise the program has to do less work.",
+                "let spin = fn() { spin() };\nspin();",
+                "let spin = fn(n) { if (n > 0) { spin(n - 1) } };\nspin(10);",
+            ),
+            SizeLimitExceeded => (
+                "The host limits how many bytes a single value may take, and a result\n\
+                 went past the limit. With `--bignum` integers grow without bound, for\n\
+                 example when a value is squared repeatedly.",
+                "let square = fn(x, n) { if (n == 0) { x } else { square(x * x, n - 1) } };\n\
+                 square(2, 64);",
+                "let square = fn(x, n) { if (n == 0) { x } else { square(x * x, n - 1) } };\n\
+                 square(2, 6);",
+            ),
+            TimeLimitExceeded => (
+                "The host limits how long a program may run, and it ran past the\n\
+                 deadline.",
+                "let spin = fn() { spin() };\nspin();",
+                "let spin = fn(n) { if (n > 0) { spin(n - 1) } };\nspin(10);",
+            ),
+            NativeFunctionFailed => (
+                "A function written in Rust and registered by the program embedding\n\
+                 Monkey returned an error. The message after the function name comes\n\
+                 from the function itself. In the example, the host has registered a\n\
+                 `checked_sqrt` that rejects negative numbers.",
+                "let root = checked_sqrt(-1);",
+                "let root = checked_sqrt(1);",
+            ),
+            NestingTooDeep => (
+                "Expressions and type annotations may be nested at most 128 levels deep.\n\
+                 Each parenthesis, operand, call and block adds a level, and so does each\n\
+                 operator in a chain like `1 + 2 + 3`. Deeper input is rejected when it is\n\
+                 parsed, and the rest of the file is not read. Generated code usually\n\
+                 causes this; give the inner parts names with `let`.",
+                "let x = 1 + (2 + (3 + (4 + (5 + ...))));  // hundreds of levels",
+                "let e = 4 + (5 + 6);\nlet x = 1 + (2 + (3 + e));",
+            ),
+            UnterminatedString => (
+                "A string literal has no closing `\"`, so it runs to the end of the\n\
+                 input. A `\"` preceded by a backslash does not close the string.",
+                "let greeting = \"hello;",
+                "let greeting = \"hello\";",
+            ),
+            InvalidEscape => (
+                "A backslash in a string literal starts an escape. The escapes are\n\
+                 `\\\"`, `\\\\`, `\\n`, `\\r`, `\\t`, and `\\u{...}` with one to six hex\n\
+                 digits naming a Unicode scalar value. Write `\\\\` for a backslash.",
+                "let path = \"C:\\data\";",
+                "let path = \"C:\\\\data\";",
+            ),
+            UnusableHashKey => (
+                "Hash keys must be integers, booleans or strings. Floats, arrays,\n\
+                 hashes, functions and `null` cannot be used as keys, either in a hash\n\
+                 literal or to index a hash.",
+                "let prices = {1.5: \"small\"};",
+                "let prices = {\"1.5\": \"small\"};",
+            ),
+            ValueTooDeep => (
+                "Arrays and hashes may be nested at most 128 levels deep. A value nested\n\
+                 deeper than that is rejected when it is made, whether by a literal, by\n\
+                 a function like `json_parse`, or by a program that wraps a value in a\n\
+                 new array again and again.",
+                "let wrap = fn(x, n) { if (n == 0) { x } else { wrap([x], n - 1) } };\n\
+                 wrap(1, 1000);",
+                "let wrap = fn(x, n) { if (n == 0) { x } else { wrap([x], n - 1) } };\n\
+                 wrap(1, 100);",
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+impl Serialize for ErrorCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
+
+fn indent(example: &str) -> String {
+    example
+        .lines()
+        .map(|line| format!("    {}", line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn codes_are_unique() {
+        let codes: HashSet<&str> = ErrorCode::ALL.iter().map(ErrorCode::code).collect();
+        assert_eq!(codes.len(), ErrorCode::ALL.len());
+    }
+
+    #[test]
+    fn lookup() {
+        assert_eq!(ErrorCode::lookup("E0001"), Some(ErrorCode::UnexpectedToken));
+        assert_eq!(
+            ErrorCode::lookup("e0002"),
+            Some(ErrorCode::ExpectedExpression)
+        );
+        assert_eq!(ErrorCode::lookup("0003"), Some(ErrorCode::UnknownCharacter));
+        assert_eq!(ErrorCode::lookup("E9999"), None);
+    }
+
+    #[test]
+    fn explain() {
+        let expected = "\
+E0001: unexpected token
+
+The parser found a token other than the one the grammar requires at this
+point, for example a `let` statement missing its `=`.
+
+Erroneous code example:
+
+    let x 5;
+
+Corrected:
+
+    let x = 5;
+";
+        assert_eq!(ErrorCode::UnexpectedToken.explain(), expected);
+    }
+}
@@ -0,0 +1,1284 @@
+//! Tree-walking evaluator.
+//!
+//! Calls in tail position, the value of a function body or of a `return`, are
+//! made by a loop in the caller instead of a nested call, so tail recursion
+//! runs in constant host stack.
+//!
+//! Other calls nest on the host stack, which is grown on the heap as needed,
+//! so deep recursion is bounded by memory or `Limits::max_depth` rather than
+//! crashing the process. How deeply the tree itself is nested is bounded by
+//! the parser's `MAX_NESTING_DEPTH`.
+//!
+//! `Limits` bound the resources a program may use, for running untrusted
+//! code. Each limit has its own error code, so a host can tell them apart.
+//!
+//! Runtime errors are returned as `Diagnostic`s pointing at the expression that
+//! failed, so they are reported the same way as parse errors.
+
+use super::ast::{
+    ArrayLiteral, BlockStatement, CallExpression, ExpressionStatement, ExpressionType, HashLiteral,
+    IfExpression, IndexExpression, InfixExpression, Node, PrefixExpression, Program, StatementType,
+};
+use super::diagnostic::Diagnostic;
+use super::error_code::ErrorCode;
+use super::native::{Native, NativeError};
+use super::object::{Array, Env, Environment, Function, Hash, HashKey, Object, MAX_VALUE_DEPTH};
+use super::token::Span;
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, ToPrimitive, Zero};
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+pub type EvalResult = Result<Object, Diagnostic>;
+
+/// How integer arithmetic behaves when a result does not fit in i64.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IntegerMode {
+    /// Overflow is a runtime error.
+    #[default]
+    Checked,
+    /// Integers grow as needed. Values that fit in i64 stay `Object::Integer`.
+    Big,
+}
+
+/// Resources one `eval_program` may use. Every limit is off by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Limits {
+    /// Function calls in progress at once. A tail call replaces its caller
+    /// and does not count.
+    pub max_depth: Option<usize>,
+    /// Expressions evaluated.
+    pub max_steps: Option<u64>,
+    /// Bytes of a single value, as `Object::size` counts them.
+    pub max_value_size: Option<usize>,
+    /// Wall-clock time.
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Evaluator {
+    pub integer_mode: IntegerMode,
+    pub limits: Limits,
+    depth: Cell<usize>,
+    steps: Cell<u64>,
+    deadline: Cell<Option<Instant>>,
+}
+
+// 時刻の取得は式の評価より重いので、この回数ごとに確かめる
+const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+// 式を一段評価するのに足りるスタックの余裕と、足りないときに確保する大きさ
+const STACK_RED_ZONE: usize = 256 * 1024;
+const STACK_SEGMENT_SIZE: usize = 4 * 1024 * 1024;
+
+impl Evaluator {
+    pub fn new(integer_mode: IntegerMode) -> Self {
+        Self {
+            integer_mode,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Evaluate a program and return the value of its last statement.
+    pub fn eval_program(&self, program: &Program, env: &Env) -> EvalResult {
+        self.depth.set(0);
+        self.steps.set(0);
+        self.deadline
+            .set(self.limits.timeout.map(|timeout| Instant::now() + timeout));
+        let mut result = Object::Null;
+        for statement in program {
+            result = self.eval_statement(statement, env)?;
+            if let Object::ReturnValue(value) = result {
+                return Ok(*value);
+            }
+        }
+        Ok(result)
+    }
+
+    // ReturnValueは包んだまま返し、外側のブロックも抜けさせる
+    fn eval_block_statement(&self, block: &BlockStatement, env: &Env) -> EvalResult {
+        let mut result = Object::Null;
+        for statement in &block.statements {
+            result = self.eval_statement(statement, env)?;
+            if let Object::ReturnValue(_) = result {
+                return Ok(result);
+            }
+        }
+        Ok(result)
+    }
+
+    fn eval_statement(&self, statement: &StatementType, env: &Env) -> EvalResult {
+        match statement {
+            StatementType::LetStatement(statement) => {
+                let value = self.eval_expression(&statement.value, env)?;
+                env.borrow_mut().set(&statement.name.value, value);
+                Ok(Object::Null)
+            }
+            StatementType::ReturnStatement(statement) => {
+                let value = self.eval_expression(&statement.value, env)?;
+                Ok(Object::ReturnValue(Box::new(value)))
+            }
+            StatementType::ExpressionStatement(statement) => {
+                self.eval_expression(&statement.expression, env)
+            }
+        }
+    }
+
+    // 再帰の深さはMonkeyのプログラム次第なので、ホストのスタックの残りが
+    // 少なくなったらヒープに新しいスタックを確保して続ける
+    fn eval_expression(&self, expression: &ExpressionType, env: &Env) -> EvalResult {
+        stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT_SIZE, || {
+            self.eval_expression_unguarded(expression, env)
+        })
+    }
+
+    fn eval_expression_unguarded(&self, expression: &ExpressionType, env: &Env) -> EvalResult {
+        self.step(expression)?;
+        match expression {
+            ExpressionType::Identifer(identifer) => {
+                env.borrow().get(&identifer.value).ok_or_else(|| {
+                    Diagnostic::error(
+                        format!("identifier not found: {}", identifer.value),
+                        identifer.span(),
+                    )
+                    .with_code(ErrorCode::UnboundIdentifier)
+                })
+            }
+            ExpressionType::IntegerLiteral(literal) => Ok(Object::Integer(literal.value)),
+            // 大きな整数を許して作った木でも、Checked では評価しない
+            ExpressionType::BigIntegerLiteral(_) if self.integer_mode == IntegerMode::Checked => {
+                Err(overflow_error(
+                    "evaluate an integer literal",
+                    expression.span(),
+                ))
+            }
+            ExpressionType::BigIntegerLiteral(literal) => {
+                self.check_size(big_integer(literal.value.clone()), expression.span())
+            }
+            ExpressionType::FloatLiteral(literal) => Ok(Object::Float(literal.value)),
+            ExpressionType::Boolean(boolean) => Ok(Object::Boolean(boolean.value)),
+            ExpressionType::StringLiteral(literal) => self.check_size(
+                Object::String(Rc::from(literal.value.as_str())),
+                literal.span(),
+            ),
+            ExpressionType::ArrayLiteral(literal) => {
+                let value = self.eval_array_literal(literal, env)?;
+                self.check_size(value, literal.span)
+            }
+            ExpressionType::HashLiteral(literal) => {
+                let value = self.eval_hash_literal(literal, env)?;
+                self.check_size(value, literal.span)
+            }
+            ExpressionType::PrefixExpression(prefix) => {
+                let value = self.eval_prefix_expression(prefix, env)?;
+                self.check_size(value, expression.span())
+            }
+            ExpressionType::InfixExpression(infix) => {
+                let value = self.eval_infix_expression(infix, env)?;
+                self.check_size(value, expression.span())
+            }
+            ExpressionType::IfExpression(expression) => self.eval_if_expression(expression, env),
+            ExpressionType::FunctionLiteral(literal) => Ok(Object::Function(Rc::new(Function {
+                parameters: literal.parameters.clone(),
+                body: literal.body.clone(),
+                env: Rc::clone(env),
+            }))),
+            ExpressionType::CallExpression(expression) => {
+                self.eval_call_expression(expression, env)
+            }
+            ExpressionType::IndexExpression(expression) => {
+                self.eval_index_expression(expression, env)
+            }
+            // パースエラーのあるプログラムは評価しない
+            ExpressionType::Illegal => Ok(Object::Null),
+        }
+    }
+
+    fn eval_array_literal(&self, literal: &ArrayLiteral, env: &Env) -> EvalResult {
+        let mut elements = Vec::with_capacity(literal.elements.len());
+        for element in &literal.elements {
+            elements.push(self.eval_expression(element, env)?);
+        }
+        check_depth(Object::Array(Rc::new(Array::new(elements))), literal.span)
+    }
+
+    // 同じキーが複数あれば後のものを使う
+    fn eval_hash_literal(&self, literal: &HashLiteral, env: &Env) -> EvalResult {
+        let mut pairs = BTreeMap::new();
+        for (key, value) in &literal.pairs {
+            let key_object = self.eval_expression(key, env)?;
+            let key = HashKey::from_object(&key_object)
+                .ok_or_else(|| unusable_hash_key(&key_object, key.span()))?;
+            pairs.insert(key, self.eval_expression(value, env)?);
+        }
+        check_depth(Object::Hash(Rc::new(Hash::new(pairs))), literal.span)
+    }
+
+    // 範囲外の添字と無いキーは null
+    fn eval_index_expression(&self, expression: &IndexExpression, env: &Env) -> EvalResult {
+        let left = self.eval_expression(&expression.left, env)?;
+        let index = self.eval_expression(&expression.index, env)?;
+        match (&left, &index) {
+            (Object::Array(array), Object::Integer(i)) => Ok(i
+                .to_usize()
+                .and_then(|i| array.elements().get(i))
+                .cloned()
+                .unwrap_or(Object::Null)),
+            (Object::Array(_), Object::BigInteger(_)) => Ok(Object::Null),
+            (Object::Hash(hash), _) => {
+                let key = HashKey::from_object(&index)
+                    .ok_or_else(|| unusable_hash_key(&index, expression.index.span()))?;
+                Ok(hash.get(&key).cloned().unwrap_or(Object::Null))
+            }
+            _ => Err(Diagnostic::error(
+                format!(
+                    "index operator not supported: {}[{}]",
+                    left.type_name(),
+                    index.type_name()
+                ),
+                expression.span(),
+            )
+            .with_code(ErrorCode::UnknownOperator)),
+        }
+    }
+
+    fn eval_prefix_expression(&self, expression: &PrefixExpression, env: &Env) -> EvalResult {
+        let right = self.eval_expression(&expression.right, env)?;
+        match (expression.operator.as_str(), right) {
+            ("!", right) => Ok(Object::Boolean(!is_truthy(&right))),
+            ("-", Object::Integer(value)) => match value.checked_neg() {
+                Some(value) => Ok(Object::Integer(value)),
+                None if self.integer_mode == IntegerMode::Big => {
+                    Ok(big_integer(-BigInt::from(value)))
+                }
+                None => Err(overflow_error("negate", expression.span())),
+            },
+            ("-", Object::BigInteger(value)) => Ok(big_integer(-value)),
+            ("-", Object::Float(value)) => Ok(Object::Float(-value)),
+            (operator, right) => Err(Diagnostic::error(
+                format!("unknown operator: {}{}", operator, right.type_name()),
+                expression.span(),
+            )
+            .with_code(ErrorCode::UnknownOperator)),
+        }
+    }
+
+    fn eval_infix_expression(&self, expression: &InfixExpression, env: &Env) -> EvalResult {
+        let left = self.eval_expression(&expression.left, env)?;
+        let right = self.eval_expression(&expression.right, env)?;
+        let operator = expression.operator.as_str();
+        let span = expression.span();
+
+        // 整数とfloatの比較は丸めずに行う
+        if let Some(ordering) = compare_integer_float(&left, &right) {
+            match operator {
+                "<" => return Ok(Object::Boolean(ordering == Some(Ordering::Less))),
+                ">" => return Ok(Object::Boolean(ordering == Some(Ordering::Greater))),
+                "==" => return Ok(Object::Boolean(ordering == Some(Ordering::Equal))),
+                "!=" => return Ok(Object::Boolean(ordering != Some(Ordering::Equal))),
+                _ => {}
+            }
+        }
+        // 片方がfloatならもう片方もfloatに揃える
+        if let (Some(l), Some(r)) = (as_float(&left), as_float(&right)) {
+            if matches!(left, Object::Float(_)) || matches!(right, Object::Float(_)) {
+                return eval_float_infix(operator, l, r, span);
+            }
+        }
+        match (&left, &right) {
+            (Object::Integer(l), Object::Integer(r)) => {
+                self.eval_integer_infix(operator, *l, *r, span)
+            }
+            (Object::Integer(_), Object::BigInteger(_))
+            | (Object::BigInteger(_), Object::Integer(_))
+            | (Object::BigInteger(_), Object::BigInteger(_)) => eval_big_integer_infix(
+                operator,
+                as_big_integer(&left),
+                as_big_integer(&right),
+                span,
+            ),
+            (Object::String(l), Object::String(r)) if operator == "+" => {
+                Ok(Object::String(Rc::from(format!("{}{}", l, r))))
+            }
+            _ if operator == "==" => Ok(Object::Boolean(equal(&left, &right))),
+            _ if operator == "!=" => Ok(Object::Boolean(!equal(&left, &right))),
+            _ if left.type_name() != right.type_name() => Err(Diagnostic::error(
+                format!(
+                    "type mismatch: {} {} {}",
+                    left.type_name(),
+                    operator,
+                    right.type_name()
+                ),
+                span,
+            )
+            .with_code(ErrorCode::TypeMismatch)),
+            _ => Err(unknown_infix_operator(operator, &left, &right, span)),
+        }
+    }
+
+    fn eval_integer_infix(&self, operator: &str, left: i64, right: i64, span: Span) -> EvalResult {
+        let (value, action) = match operator {
+            "+" => (left.checked_add(right), "add"),
+            "-" => (left.checked_sub(right), "subtract"),
+            "*" => (left.checked_mul(right), "multiply"),
+            "/" if right == 0 => return Err(division_by_zero(span)),
+            "/" => (left.checked_div(right), "divide"),
+            "<" => return Ok(Object::Boolean(left < right)),
+            ">" => return Ok(Object::Boolean(left > right)),
+            "==" => return Ok(Object::Boolean(left == right)),
+            "!=" => return Ok(Object::Boolean(left != right)),
+            _ => {
+                let (left, right) = (Object::Integer(left), Object::Integer(right));
+                return Err(unknown_infix_operator(operator, &left, &right, span));
+            }
+        };
+        match value {
+            Some(value) => Ok(Object::Integer(value)),
+            None if self.integer_mode == IntegerMode::Big => {
+                eval_big_integer_infix(operator, BigInt::from(left), BigInt::from(right), span)
+            }
+            None => Err(overflow_error(action, span)),
+        }
+    }
+
+    fn eval_if_expression(&self, expression: &IfExpression, env: &Env) -> EvalResult {
+        let condition = self.eval_expression(&expression.condition, env)?;
+        if is_truthy(&condition) {
+            self.eval_block_statement(&expression.consequence, env)
+        } else if let Some(alternative) = &expression.alternative {
+            self.eval_block_statement(alternative, env)
+        } else {
+            Ok(Object::Null)
+        }
+    }
+
+    // 末尾位置の呼び出しは Tail::Call として返ってくるので、ここでループして
+    // ホストのスタックを伸ばさずに実行する
+    fn eval_call_expression(&self, expression: &CallExpression, env: &Env) -> EvalResult {
+        let (function, arguments) = match self.eval_call_target(expression, env)? {
+            Tail::Value(value) => return Ok(value),
+            Tail::Call(function, arguments) => (function, arguments),
+        };
+        let depth = self.depth.get() + 1;
+        if let Some(max_depth) = self.limits.max_depth {
+            if depth > max_depth {
+                return Err(Diagnostic::error(
+                    format!("call depth limit of {} exceeded", max_depth),
+                    expression.span(),
+                )
+                .with_code(ErrorCode::CallDepthExceeded));
+            }
+        }
+        self.depth.set(depth);
+        let result = self.call(function, arguments);
+        self.depth.set(depth - 1);
+        result
+    }
+
+    fn call(&self, mut function: Rc<Function>, mut arguments: Vec<Object>) -> EvalResult {
+        loop {
+            let scope = Environment::new_enclosed(&function.env);
+            for (parameter, argument) in function.parameters.iter().zip(arguments) {
+                scope.borrow_mut().set(&parameter.value, argument);
+            }
+            match self.eval_body(&function.body, &scope, true)? {
+                Tail::Value(Object::ReturnValue(value)) => return Ok(*value),
+                Tail::Value(value) => return Ok(value),
+                Tail::Call(next, next_arguments) => {
+                    function = next;
+                    arguments = next_arguments;
+                }
+            }
+        }
+    }
+
+    fn step(&self, expression: &ExpressionType) -> Result<(), Diagnostic> {
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+        if let Some(max_steps) = self.limits.max_steps {
+            if steps > max_steps {
+                return Err(Diagnostic::error(
+                    format!("step limit of {} exceeded", max_steps),
+                    expression.span(),
+                )
+                .with_code(ErrorCode::StepLimitExceeded));
+            }
+        }
+        if steps.is_multiple_of(DEADLINE_CHECK_INTERVAL) {
+            if let (Some(deadline), Some(timeout)) = (self.deadline.get(), self.limits.timeout) {
+                if Instant::now() >= deadline {
+                    return Err(Diagnostic::error(
+                        format!("time limit of {} ms exceeded", timeout.as_millis()),
+                        expression.span(),
+                    )
+                    .with_code(ErrorCode::TimeLimitExceeded));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 大きさの決まっている値は測らない
+    fn check_size(&self, value: Object, span: Span) -> EvalResult {
+        if let (
+            Some(max_size),
+            Object::BigInteger(_) | Object::String(_) | Object::Array(_) | Object::Hash(_),
+        ) = (self.limits.max_value_size, &value)
+        {
+            let size = value.size();
+            if size > max_size {
+                return Err(Diagnostic::error(
+                    format!(
+                        "value size limit of {} bytes exceeded: the result needs {} bytes",
+                        max_size, size
+                    ),
+                    span,
+                )
+                .with_code(ErrorCode::SizeLimitExceeded));
+            }
+        }
+        Ok(value)
+    }
+
+    // Rust の関数の結果も整数モードと値の大きさの制限に従わせる
+    fn call_native(
+        &self,
+        native: &Native,
+        arguments: Vec<Object>,
+        expression: &CallExpression,
+    ) -> Result<Tail, Diagnostic> {
+        let value = match native.call(&arguments) {
+            Ok(value) => value,
+            Err(NativeError::ArgumentCount { expected, found }) => {
+                return Err(wrong_argument_count(expected, found, expression))
+            }
+            Err(NativeError::Argument {
+                index,
+                expected,
+                found,
+            }) => {
+                return Err(Diagnostic::error(
+                    format!(
+                        "type mismatch: argument {} of `{}` must be {}, found {}",
+                        index + 1,
+                        native.name,
+                        expected,
+                        found
+                    ),
+                    expression.arguments[index].span(),
+                )
+                .with_code(ErrorCode::TypeMismatch))
+            }
+            Err(NativeError::OutOfRange { index }) => {
+                return Err(Diagnostic::error(
+                    format!(
+                        "argument {} of `{}` is out of range: {} does not fit in a 64-bit integer",
+                        index + 1,
+                        native.name,
+                        arguments[index].inspect()
+                    ),
+                    expression.arguments[index].span(),
+                )
+                .with_code(ErrorCode::ArithmeticOverflow))
+            }
+            Err(NativeError::Failed(message)) => {
+                return Err(Diagnostic::error(
+                    format!("`{}` failed: {}", native.name, message),
+                    expression.span(),
+                )
+                .with_code(ErrorCode::NativeFunctionFailed))
+            }
+        };
+        if self.integer_mode == IntegerMode::Checked && contains_big_integer(&value) {
+            let action = format!("return an integer from `{}`", native.name);
+            return Err(overflow_error(&action, expression.span()));
+        }
+        let value = check_depth(value, expression.span())?;
+        self.check_size(value, expression.span()).map(Tail::Value)
+    }
+
+    // 呼び出す関数と引数を評価し、呼び出せるか確かめる。
+    // Rust の関数はその場で呼び、値を返す
+    fn eval_call_target(&self, expression: &CallExpression, env: &Env) -> Result<Tail, Diagnostic> {
+        let function = self.eval_expression(&expression.function, env)?;
+        let mut arguments = Vec::with_capacity(expression.arguments.len());
+        for argument in &expression.arguments {
+            arguments.push(self.eval_expression(argument, env)?);
+        }
+
+        let function = match function {
+            Object::Function(function) => function,
+            Object::Native(native) => return self.call_native(&native, arguments, expression),
+            other => {
+                return Err(Diagnostic::error(
+                    format!("not a function: {}", other.type_name()),
+                    expression.function.span(),
+                )
+                .with_code(ErrorCode::NotAFunction))
+            }
+        };
+        if arguments.len() != function.parameters.len() {
+            return Err(wrong_argument_count(
+                function.parameters.len(),
+                arguments.len(),
+                expression,
+            ));
+        }
+        Ok(Tail::Call(function, arguments))
+    }
+
+    // 関数本体のブロック。tail が真なら最後の文の値が関数の値になる。
+    // return の値と、関数の値になる式は末尾位置として評価する
+    fn eval_body(&self, block: &BlockStatement, env: &Env, tail: bool) -> Result<Tail, Diagnostic> {
+        let mut result = Object::Null;
+        for (i, statement) in block.statements.iter().enumerate() {
+            let last = tail && i + 1 == block.statements.len();
+            match statement {
+                StatementType::ReturnStatement(statement) => {
+                    return match self.eval_tail(&statement.value, env)? {
+                        Tail::Value(value) => Ok(Tail::Value(Object::ReturnValue(Box::new(value)))),
+                        call => Ok(call),
+                    };
+                }
+                StatementType::ExpressionStatement(statement) if last => {
+                    return self.eval_tail(&statement.expression, env);
+                }
+                // 途中の if の中の return も末尾位置
+                StatementType::ExpressionStatement(ExpressionStatement {
+                    expression: ExpressionType::IfExpression(expression),
+                    ..
+                }) => match self.eval_if_body(expression, env, false)? {
+                    Tail::Value(Object::ReturnValue(value)) => {
+                        return Ok(Tail::Value(Object::ReturnValue(value)))
+                    }
+                    Tail::Value(value) => result = value,
+                    call => return Ok(call),
+                },
+                statement => {
+                    result = self.eval_statement(statement, env)?;
+                    if let Object::ReturnValue(_) = result {
+                        return Ok(Tail::Value(result));
+                    }
+                }
+            }
+        }
+        Ok(Tail::Value(result))
+    }
+
+    fn eval_if_body(
+        &self,
+        expression: &IfExpression,
+        env: &Env,
+        tail: bool,
+    ) -> Result<Tail, Diagnostic> {
+        let condition = self.eval_expression(&expression.condition, env)?;
+        if is_truthy(&condition) {
+            self.eval_body(&expression.consequence, env, tail)
+        } else if let Some(alternative) = &expression.alternative {
+            self.eval_body(alternative, env, tail)
+        } else {
+            Ok(Tail::Value(Object::Null))
+        }
+    }
+
+    // 値が関数の値になる式
+    fn eval_tail(&self, expression: &ExpressionType, env: &Env) -> Result<Tail, Diagnostic> {
+        match expression {
+            ExpressionType::CallExpression(expression) => self.eval_call_target(expression, env),
+            ExpressionType::IfExpression(expression) => self.eval_if_body(expression, env, true),
+            expression => self.eval_expression(expression, env).map(Tail::Value),
+        }
+    }
+}
+
+/// The result of evaluating part of a function body, or of a call target.
+enum Tail {
+    /// A value, wrapped in `ReturnValue` if a `return` produced it.
+    Value(Object),
+    /// A call in tail position, left to the caller to make.
+    Call(Rc<Function>, Vec<Object>),
+}
+
+fn wrong_argument_count(expected: usize, found: usize, expression: &CallExpression) -> Diagnostic {
+    Diagnostic::error(
+        format!(
+            "wrong number of arguments: expected {}, found {}",
+            expected, found
+        ),
+        expression.span(),
+    )
+    .with_code(ErrorCode::WrongArgumentCount)
+}
+
+fn eval_big_integer_infix(operator: &str, left: BigInt, right: BigInt, span: Span) -> EvalResult {
+    let value = match operator {
+        "+" => left + right,
+        "-" => left - right,
+        "*" => left * right,
+        "/" if right.is_zero() => return Err(division_by_zero(span)),
+        "/" => left / right, // i64と同じく0に向かって丸める
+        "<" => return Ok(Object::Boolean(left < right)),
+        ">" => return Ok(Object::Boolean(left > right)),
+        "==" => return Ok(Object::Boolean(left == right)),
+        "!=" => return Ok(Object::Boolean(left != right)),
+        _ => {
+            let (left, right) = (Object::BigInteger(left), Object::BigInteger(right));
+            return Err(unknown_infix_operator(operator, &left, &right, span));
+        }
+    };
+    Ok(big_integer(value))
+}
+
+fn eval_float_infix(operator: &str, left: f64, right: f64, span: Span) -> EvalResult {
+    let value = match operator {
+        "+" => Object::Float(left + right),
+        "-" => Object::Float(left - right),
+        "*" => Object::Float(left * right),
+        "/" => Object::Float(left / right),
+        "<" => Object::Boolean(left < right),
+        ">" => Object::Boolean(left > right),
+        "==" => Object::Boolean(left == right),
+        "!=" => Object::Boolean(left != right),
+        _ => {
+            let (left, right) = (Object::Float(left), Object::Float(right));
+            return Err(unknown_infix_operator(operator, &left, &right, span));
+        }
+    };
+    Ok(value)
+}
+
+// i64に収まる値は常にIntegerで表す
+fn big_integer(value: BigInt) -> Object {
+    match value.to_i64() {
+        Some(value) => Object::Integer(value),
+        None => Object::BigInteger(value),
+    }
+}
+
+fn as_big_integer(object: &Object) -> BigInt {
+    match object {
+        Object::Integer(value) => BigInt::from(*value),
+        Object::BigInteger(value) => value.clone(),
+        _ => unreachable!("not an integer: {}", object.type_name()),
+    }
+}
+
+fn as_float(object: &Object) -> Option<f64> {
+    match object {
+        Object::Integer(value) => Some(*value as f64),
+        Object::BigInteger(value) => value.to_f64(),
+        Object::Float(value) => Some(*value),
+        _ => None,
+    }
+}
+
+// 整数とfloatの組ならその順序 (NaNとは順序がないのでNone) を返す。
+// f64に変換すると2^53を超える整数が丸められるので、floatを整数部と小数部に
+// 分けて比べる
+fn compare_integer_float(left: &Object, right: &Object) -> Option<Option<Ordering>> {
+    let (integer, float, reversed) = match (left, right) {
+        (Object::Integer(_) | Object::BigInteger(_), Object::Float(float)) => (left, *float, false),
+        (Object::Float(float), Object::Integer(_) | Object::BigInteger(_)) => (right, *float, true),
+        _ => return None,
+    };
+    let ordering = if float.is_nan() {
+        None
+    } else if float.is_infinite() {
+        Some(if float > 0.0 {
+            Ordering::Less
+        } else {
+            Ordering::Greater
+        })
+    } else {
+        let whole = float.trunc();
+        let fraction = 0.0.partial_cmp(&(float - whole))?;
+        BigInt::from_f64(whole).map(|whole| as_big_integer(integer).cmp(&whole).then(fraction))
+    };
+    Some(if reversed {
+        ordering.map(Ordering::reverse)
+    } else {
+        ordering
+    })
+}
+
+// == と != の比較。配列とハッシュの中でも整数とfloatは値で比べる
+fn equal(left: &Object, right: &Object) -> bool {
+    if let Some(ordering) = compare_integer_float(left, right) {
+        return ordering == Some(Ordering::Equal);
+    }
+    match (left, right) {
+        (Object::Array(l), Object::Array(r)) => {
+            l.elements().len() == r.elements().len()
+                && l.elements()
+                    .iter()
+                    .zip(r.elements())
+                    .all(|(l, r)| equal(l, r))
+        }
+        (Object::Hash(l), Object::Hash(r)) => {
+            l.pairs().len() == r.pairs().len()
+                && l.pairs()
+                    .iter()
+                    .zip(r.pairs())
+                    .all(|((lk, lv), (rk, rv))| lk == rk && equal(lv, rv))
+        }
+        _ => left == right,
+    }
+}
+
+// 深さは作るときに数えてあるので、中の要素は見なくてよい
+fn check_depth(value: Object, span: Span) -> EvalResult {
+    if value.depth() > MAX_VALUE_DEPTH {
+        return Err(Diagnostic::error(
+            format!(
+                "value nesting limit of {} exceeded: arrays and hashes are nested {} deep",
+                MAX_VALUE_DEPTH,
+                value.depth()
+            ),
+            span,
+        )
+        .with_code(ErrorCode::ValueTooDeep));
+    }
+    Ok(value)
+}
+
+fn contains_big_integer(value: &Object) -> bool {
+    match value {
+        Object::BigInteger(_) => true,
+        Object::Array(array) => array.elements().iter().any(contains_big_integer),
+        Object::Hash(hash) => hash.pairs().iter().any(|(key, value)| {
+            matches!(key, HashKey::BigInteger(_)) || contains_big_integer(value)
+        }),
+        _ => false,
+    }
+}
+
+fn unusable_hash_key(key: &Object, span: Span) -> Diagnostic {
+    Diagnostic::error(format!("unusable as hash key: {}", key.type_name()), span)
+        .with_code(ErrorCode::UnusableHashKey)
+        .with_help("hash keys are integers, booleans and strings")
+}
+
+fn unknown_infix_operator(operator: &str, left: &Object, right: &Object, span: Span) -> Diagnostic {
+    Diagnostic::error(
+        format!(
+            "unknown operator: {} {} {}",
+            left.type_name(),
+            operator,
+            right.type_name()
+        ),
+        span,
+    )
+    .with_code(ErrorCode::UnknownOperator)
+}
+
+fn division_by_zero(span: Span) -> Diagnostic {
+    Diagnostic::error("attempt to divide by zero", span)
+        .with_code(ErrorCode::DivisionByZero)
+        .with_help("divide a float instead to get infinity")
+}
+
+fn overflow_error(action: &str, span: Span) -> Diagnostic {
+    Diagnostic::error(format!("attempt to {} with overflow", action), span)
+        .with_code(ErrorCode::ArithmeticOverflow)
+        .with_help(format!(
+            "integers are 64-bit and range from {} to {} unless big integers are enabled",
+            i64::MIN,
+            i64::MAX
+        ))
+}
+
+// null と false 以外は真
+fn is_truthy(object: &Object) -> bool {
+    !matches!(object, Object::Null | Object::Boolean(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::lexer::Lexer;
+    use super::super::parser::Parser;
+    use super::*;
+
+    fn eval(input: &str) -> EvalResult {
+        eval_with(input, IntegerMode::Checked)
+    }
+
+    fn eval_with(input: &str, integer_mode: IntegerMode) -> EvalResult {
+        let mut parser =
+            Parser::new(Lexer::new(input)).with_big_integers(integer_mode == IntegerMode::Big);
+        let program = parser.parse_program();
+        assert_eq!(parser.errors(), vec![], "input: {:?}", input);
+        Evaluator::new(integer_mode).eval_program(&program, &Environment::new())
+    }
+
+    #[test]
+    fn integer_expression() {
+        let tests = [
+            ("5", 5),
+            ("-10", -10),
+            ("5 + 5 + 5 + 5 - 10", 10),
+            ("2 * (5 + 10)", 30),
+            ("-50 + 100 + -50", 0),
+            ("50 / 2 * 2 + 10", 60),
+            ("7 / 2", 3),
+        ];
+        for (input, expected) in tests.iter() {
+            assert_eq!(eval(input), Ok(Object::Integer(*expected)), "{}", input);
+        }
+    }
+
+    #[test]
+    fn float_expression() {
+        let tests = [
+            ("3.5", 3.5),
+            ("-.5", -0.5),
+            ("1 + 0.5", 1.5),
+            ("0.5 * 4", 2.0),
+            ("7 / 2.0", 3.5),
+            ("1e3 - 1", 999.0),
+            ("0.1 + 0.2", 0.1 + 0.2),
+        ];
+        for (input, expected) in tests.iter() {
+            assert_eq!(eval(input), Ok(Object::Float(*expected)), "{}", input);
+        }
+        assert_eq!(eval("1 / 0.0"), Ok(Object::Float(f64::INFINITY)));
+        assert_eq!(eval("0.1 + 0.2").unwrap().inspect(), "0.30000000000000004");
+        assert_eq!(eval("2.0 * 3").unwrap().inspect(), "6.0");
+    }
+
+    #[test]
+    fn boolean_expression() {
+        let tests = [
+            ("true", true),
+            ("!5", false),
+            ("!!true", true),
+            ("1 < 2", true),
+            ("1 == 1.0", true),
+            ("2.5 > 2", true),
+            ("1 != 1.5", true),
+            ("(1 < 2) == true", true),
+            ("true != false", true),
+            ("1 == true", false),
+            // 2^53を超える整数もfloatと正確に比べる
+            ("9007199254740993 == 9007199254740992.0", false),
+            ("9007199254740993 > 9007199254740992.0", true),
+            ("9007199254740992.0 < 9007199254740993", true),
+            ("9007199254740992 == 9007199254740992.0", true),
+            ("-3 < -2.5", true),
+            ("-2 > -2.5", true),
+            ("3 != 3.0", false),
+            ("0.0 / 0 == 0", false),
+            ("0.0 / 0 != 0", true),
+            ("1 / 0.0 > 9223372036854775807", true),
+        ];
+        for (input, expected) in tests.iter() {
+            assert_eq!(eval(input), Ok(Object::Boolean(*expected)), "{}", input);
+        }
+    }
+
+    #[test]
+    fn if_and_return() {
+        let tests = [
+            ("if (true) { 10 }", Object::Integer(10)),
+            ("if (false) { 10 }", Object::Null),
+            ("if (1 < 2.5) { 10 } else { 20 }", Object::Integer(10)),
+            ("9; return 2 * 5; 9;", Object::Integer(10)),
+            (
+                "if (10 > 1) { if (10 > 1) { return 10; } return 1; }",
+                Object::Integer(10),
+            ),
+        ];
+        for (input, expected) in tests.iter() {
+            assert_eq!(eval(input).as_ref(), Ok(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn functions_and_closures() {
+        let tests = [
+            ("let identity = fn(x) { x; }; identity(5);", 5),
+            ("let double = fn(x) { return x * 2; }; double(5);", 10),
+            ("let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));", 20),
+            ("fn(x) { x; }(5)", 5),
+            (
+                "let newAdder = fn(x) { fn(y) { x + y } }; let addTwo = newAdder(2); addTwo(2);",
+                4,
+            ),
+        ];
+        for (input, expected) in tests.iter() {
+            assert_eq!(eval(input), Ok(Object::Integer(*expected)), "{}", input);
+        }
+    }
+
+    #[test]
+    fn strings_arrays_and_hashes() {
+        let tests = [
+            (r#""Hello" + ", " + "World!""#, r#""Hello, World!""#),
+            (r#""a\tb\u{1F600}""#, r#""a\tb😀""#),
+            ("[1, 2 * 2, 3 + 3]", "[1, 4, 6]"),
+            ("[1, 2, 3][0] + [1, 2, 3][2]", "4"),
+            ("let i = 0; [1][i]", "1"),
+            ("[1, 2, 3][3]", "null"),
+            ("[1, 2, 3][-1]", "null"),
+            (r#"{"one": 1, "two": 2}["two"]"#, "2"),
+            (r#"{"one": 1}["three"]"#, "null"),
+            ("{true: 5, 1: 6}[1 == 1]", "5"),
+            (r#"{1: "a", 1: "b"}"#, r#"{1: "b"}"#),
+            (r#"{"b": 1, true: 2, -3: 3}"#, r#"{-3: 3, true: 2, "b": 1}"#),
+            (
+                "let add = fn(a, b) { a + b }; [add(1, 2), add(0.5, 1)]",
+                "[3, 1.5]",
+            ),
+            ("[[1, 2], [3]][0][1]", "2"),
+            // 配列とハッシュの中でも整数とfloatは値で比べる
+            ("[1, [2]] == [1.0, [2.0]]", "true"),
+            (r#"{"a": [1]} == {"a": [1]}"#, "true"),
+            ("[1, 2] != [1, 2, 3]", "true"),
+            (r#""a" == "a""#, "true"),
+            (r#""a" == 1"#, "false"),
+        ];
+        for (input, expected) in tests.iter() {
+            assert_eq!(eval(input).unwrap().inspect(), *expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn tail_calls() {
+        let tests = [
+            (
+                "let loop = fn(n) { if (n == 0) { 0 } else { loop(n - 1) } }; loop(1000000);",
+                0,
+            ),
+            (
+                "let sum = fn(n, acc) { if (n == 0) { return acc; } return sum(n - 1, acc + n); }; \
+                 sum(100000, 0);",
+                5000050000,
+            ),
+            (
+                "let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } }; \
+                 let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } }; \
+                 if (even(1000001)) { 1 } else { 2 };",
+                2,
+            ),
+            (
+                "let count = fn(n) { if (n > 0) { if (n > 1) { return count(n - 1); } } n }; \
+                 count(100000);",
+                1,
+            ),
+            // 末尾位置でない呼び出しの結果はそのまま使われる
+            (
+                "let fact = fn(n) { if (n == 0) { 1 } else { n * fact(n - 1) } }; fact(20);",
+                2432902008176640000,
+            ),
+        ];
+        for (input, expected) in tests.iter() {
+            assert_eq!(eval(input), Ok(Object::Integer(*expected)), "{}", input);
+        }
+
+        let err = eval(
+            "let f = fn(n) { if (n == 0) { g(1) } else { f(n - 1) } }; let g = fn() { 0 }; f(3)",
+        )
+        .unwrap_err();
+        assert_eq!(err.code, Some(ErrorCode::WrongArgumentCount));
+        assert_eq!(err.span, Span::new(30, 34));
+    }
+
+    #[test]
+    fn limits() {
+        let eval_limited = |input: &str, limits: Limits| {
+            let program = Parser::new(Lexer::new(input))
+                .with_big_integers(true)
+                .parse_program();
+            Evaluator::new(IntegerMode::Big)
+                .with_limits(limits)
+                .eval_program(&program, &Environment::new())
+        };
+        let spin = "let spin = fn() { spin() }; spin();";
+
+        let sum = "let sum = fn(n) { if (n == 0) { 0 } else { n + sum(n - 1) } }; sum(100)";
+        let depth = Limits {
+            max_depth: Some(50),
+            ..Limits::default()
+        };
+        let err = eval_limited(sum, depth).unwrap_err();
+        assert_eq!(err.message, "call depth limit of 50 exceeded");
+        assert_eq!(err.code, Some(ErrorCode::CallDepthExceeded));
+        assert_eq!(err.span, Span::new(47, 57));
+        // 末尾呼び出しは深さに数えない
+        assert_eq!(
+            eval_limited(
+                "let loop = fn(n) { if (n > 0) { loop(n - 1) } else { n } }; loop(1000)",
+                depth
+            ),
+            Ok(Object::Integer(0))
+        );
+        // 深さの上限がスタックに収まらないほど大きくてもプロセスは落ちない
+        let deep = Limits {
+            max_depth: Some(100000),
+            ..Limits::default()
+        };
+        let err = eval_limited(&sum.replace("sum(100)", "sum(200000)"), deep).unwrap_err();
+        assert_eq!(err.code, Some(ErrorCode::CallDepthExceeded));
+
+        let steps = Limits {
+            max_steps: Some(10000),
+            ..Limits::default()
+        };
+        let err = eval_limited(spin, steps).unwrap_err();
+        assert_eq!(err.message, "step limit of 10000 exceeded");
+        assert_eq!(err.code, Some(ErrorCode::StepLimitExceeded));
+        assert_eq!(eval_limited("1 + 2 * 3", steps), Ok(Object::Integer(7)));
+
+        let square =
+            "let square = fn(x, n) { if (n == 0) { x } else { square(x * x, n - 1) } }; square(2, ";
+        let size = Limits {
+            max_value_size: Some(64),
+            ..Limits::default()
+        };
+        let err = eval_limited(&format!("{}64)", square), size).unwrap_err();
+        assert_eq!(
+            err.message,
+            "value size limit of 64 bytes exceeded: the result needs 65 bytes"
+        );
+        assert_eq!(err.code, Some(ErrorCode::SizeLimitExceeded));
+        assert_eq!(err.span, Span::new(56, 61));
+        let err = eval_limited(
+            "1 + 0x1_0000_0000_0000_0000_0000",
+            Limits {
+                max_value_size: Some(8),
+                ..Limits::default()
+            },
+        )
+        .unwrap_err();
+        assert_eq!(err.code, Some(ErrorCode::SizeLimitExceeded));
+        assert_eq!(err.span, Span::new(4, 32));
+        assert_eq!(
+            eval_limited(&format!("{}6)", square), size)
+                .unwrap()
+                .inspect(),
+            "18446744073709551616"
+        );
+        // 文字列と配列は中身の大きさで数える
+        let double = "let double = fn(s, n) { if (n == 0) { s } else { double(s + s, n - 1) } }; ";
+        let err = eval_limited(&format!(r#"{}double("a", 7)"#, double), size).unwrap_err();
+        assert_eq!(
+            err.message,
+            "value size limit of 64 bytes exceeded: the result needs 128 bytes"
+        );
+        assert_eq!(err.code, Some(ErrorCode::SizeLimitExceeded));
+        let err = eval_limited(
+            r#"let s = "0123456789abcdef0123456789abcdef"; [s, [s], 1]"#,
+            size,
+        )
+        .unwrap_err();
+        assert_eq!(err.span, Span::new(44, 55));
+
+        let time = Limits {
+            timeout: Some(Duration::from_millis(10)),
+            ..Limits::default()
+        };
+        let err = eval_limited(spin, time).unwrap_err();
+        assert_eq!(err.message, "time limit of 10 ms exceeded");
+        assert_eq!(err.code, Some(ErrorCode::TimeLimitExceeded));
+    }
+
+    #[test]
+    fn runtime_errors() {
+        let tests = [
+            (
+                "5 + true;",
+                "type mismatch: INTEGER + BOOLEAN",
+                ErrorCode::TypeMismatch,
+                (0, 8),
+            ),
+            (
+                "1.5 * false",
+                "type mismatch: FLOAT * BOOLEAN",
+                ErrorCode::TypeMismatch,
+                (0, 11),
+            ),
+            (
+                "-true",
+                "unknown operator: -BOOLEAN",
+                ErrorCode::UnknownOperator,
+                (0, 5),
+            ),
+            (
+                "if (1) { true + false; }",
+                "unknown operator: BOOLEAN + BOOLEAN",
+                ErrorCode::UnknownOperator,
+                (9, 21),
+            ),
+            (
+                "foobar",
+                "identifier not found: foobar",
+                ErrorCode::UnboundIdentifier,
+                (0, 6),
+            ),
+            (
+                "let x = 1; x(2)",
+                "not a function: INTEGER",
+                ErrorCode::NotAFunction,
+                (11, 12),
+            ),
+            (
+                "fn(a, b) { a }(1)",
+                "wrong number of arguments: expected 2, found 1",
+                ErrorCode::WrongArgumentCount,
+                (0, 17),
+            ),
+            (
+                "10 / (5 - 5)",
+                "attempt to divide by zero",
+                ErrorCode::DivisionByZero,
+                (0, 11),
+            ),
+            (
+                r#""a" - "b""#,
+                "unknown operator: STRING - STRING",
+                ErrorCode::UnknownOperator,
+                (0, 9),
+            ),
+            (
+                r#""a" + 1"#,
+                "type mismatch: STRING + INTEGER",
+                ErrorCode::TypeMismatch,
+                (0, 7),
+            ),
+            (
+                "{[1]: 2}",
+                "unusable as hash key: ARRAY",
+                ErrorCode::UnusableHashKey,
+                (1, 4),
+            ),
+            (
+                r#"{"a": 1}[fn(x) { x }]"#,
+                "unusable as hash key: FUNCTION",
+                ErrorCode::UnusableHashKey,
+                (9, 20),
+            ),
+            (
+                "1[0]",
+                "index operator not supported: INTEGER[INTEGER]",
+                ErrorCode::UnknownOperator,
+                (0, 4),
+            ),
+            (
+                r#"[1]["0"]"#,
+                "index operator not supported: ARRAY[STRING]",
+                ErrorCode::UnknownOperator,
+                (0, 8),
+            ),
+            (
+                "let wrap = fn(x, n) { if (n == 0) { x } else { wrap([x], n - 1) } }; wrap(1, 200)",
+                "value nesting limit of 128 exceeded: arrays and hashes are nested 129 deep",
+                ErrorCode::ValueTooDeep,
+                (52, 55),
+            ),
+        ];
+        for (input, message, code, (start, end)) in tests.iter() {
+            let err = eval(input).unwrap_err();
+            assert_eq!(err.message, *message, "{}", input);
+            assert_eq!(err.code, Some(*code), "{}", input);
+            assert_eq!(err.span, Span::new(*start, *end), "{}", input);
+        }
+    }
+
+    #[test]
+    fn checked_overflow() {
+        let tests = [
+            (
+                "9223372036854775807 + 1",
+                "attempt to add with overflow",
+                (0, 23),
+            ),
+            (
+                "-9223372036854775808 - 1",
+                "attempt to subtract with overflow",
+                (0, 24),
+            ),
+            (
+                "4611686018427387904 * 2",
+                "attempt to multiply with overflow",
+                (0, 23),
+            ),
+            (
+                "-9223372036854775808 / -1",
+                "attempt to divide with overflow",
+                (0, 25),
+            ),
+            (
+                "let min = -9223372036854775808; -min",
+                "attempt to negate with overflow",
+                (32, 36),
+            ),
+        ];
+        for (input, message, (start, end)) in tests.iter() {
+            let err = eval(input).unwrap_err();
+            assert_eq!(err.message, *message, "{}", input);
+            assert_eq!(err.code, Some(ErrorCode::ArithmeticOverflow), "{}", input);
+            assert_eq!(err.span, Span::new(*start, *end), "{}", input);
+        }
+
+        // 大きな整数を許してパースした木でも、Checked では評価しない
+        let input = "1 + 18446744073709551616";
+        let mut parser = Parser::new(Lexer::new(input)).with_big_integers(true);
+        let program = parser.parse_program();
+        let err = Evaluator::new(IntegerMode::Checked)
+            .eval_program(&program, &Environment::new())
+            .unwrap_err();
+        assert_eq!(
+            err.message,
+            "attempt to evaluate an integer literal with overflow"
+        );
+        assert_eq!(err.code, Some(ErrorCode::ArithmeticOverflow));
+        assert_eq!(err.span, Span::new(4, 24));
+    }
+
+    #[test]
+    fn big_integers() {
+        let big = |digits: &str| Object::BigInteger(digits.parse().unwrap());
+        let tests = [
+            ("9223372036854775807 + 1", big("9223372036854775808")),
+            ("-(-9223372036854775808)", big("9223372036854775808")),
+            ("-9223372036854775808 / -1", big("9223372036854775808")),
+            (
+                "99999999999999999999 * 99999999999999999999",
+                big("9999999999999999999800000000000000000001"),
+            ),
+            ("0xffff_ffff_ffff_ffff_ff", big("4722366482869645213695")),
+            // i64に戻る値はIntegerになる
+            (
+                "99999999999999999999 - 99999999999999999998",
+                Object::Integer(1),
+            ),
+            (
+                "-99999999999999999999 / 10000000000",
+                Object::Integer(-9999999999),
+            ),
+            ("99999999999999999999 > 1", Object::Boolean(true)),
+            (
+                "99999999999999999999 == 99999999999999999999",
+                Object::Boolean(true),
+            ),
+            ("99999999999999999999 * 0.5", Object::Float(5e19)),
+        ];
+        for (input, expected) in tests.iter() {
+            assert_eq!(
+                eval_with(input, IntegerMode::Big).as_ref(),
+                Ok(expected),
+                "{}",
+                input
+            );
+        }
+
+        let err = eval_with("99999999999999999999 / 0", IntegerMode::Big).unwrap_err();
+        assert_eq!(err.code, Some(ErrorCode::DivisionByZero));
+    }
+}
@@ -0,0 +1,513 @@
+//! Canonical source formatter behind `ironmonkey fmt`.
+//!
+//! The formatter works on the lossless CST so that comments survive. Blocks are
+//! indented by four spaces, binary operators are surrounded by spaces, every
+//! statement ends with `;`, at most one blank line is kept between statements,
+//! and call arguments that do not fit in `MAX_WIDTH` columns go one per line.
+//! Array and hash literals are written on one line as `[1, 2]` and
+//! `{"a": 1}`. Type annotations are written as `x: int`, `fn(int) -> int`,
+//! `[int]` and `{string: int}`. When a
+//! comment breaks a statement, the rest of it is indented one more level.
+
+use super::cst::{SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken, SyntaxTree, TriviaKind};
+use super::diagnostic::Diagnostic;
+use super::token::TokenType;
+
+pub const MAX_WIDTH: usize = 100;
+const INDENT: &str = "    ";
+
+/// Format a whole source file. Sources with syntax errors are not formatted.
+pub fn format(source: &str) -> Result<String, Vec<Diagnostic>> {
+    let tree = SyntaxTree::parse(source);
+    let errors = tree.errors();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let mut formatter = Formatter::new(0, false);
+    formatter.program(tree.root());
+    let mut out = formatter.out;
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+struct Formatter {
+    out: String,
+    indent: usize,
+    line_pending: bool,  // 次に書く前に改行する
+    blank_pending: bool, // その改行を空行にする
+    block_start: bool,   // { の直後 (空行を入れない)
+    flat: bool,          // 引数を折り返さない (幅を測るための試し書き)
+    start_column: usize, // outの1行目が始まる桁
+    saw_comment: bool,
+    continued: bool, // 文の途中がコメントで改行され、1段深くしている
+}
+
+impl Formatter {
+    fn new(indent: usize, flat: bool) -> Self {
+        Self {
+            out: String::new(),
+            indent,
+            line_pending: false,
+            blank_pending: false,
+            block_start: true,
+            flat,
+            start_column: 0,
+            saw_comment: false,
+            continued: false,
+        }
+    }
+
+    fn push(&mut self, text: &str) {
+        if self.line_pending {
+            self.out.push('\n');
+            if self.blank_pending {
+                self.out.push('\n');
+            }
+            for _ in 0..self.indent {
+                self.out.push_str(INDENT);
+            }
+            self.line_pending = false;
+            self.blank_pending = false;
+        }
+        self.out.push_str(text);
+        self.block_start = false;
+    }
+
+    fn newline(&mut self) {
+        if !self.out.is_empty() {
+            self.line_pending = true;
+        }
+    }
+
+    fn blank_line(&mut self) {
+        if self.line_pending && !self.block_start {
+            self.blank_pending = true;
+        }
+    }
+
+    fn space(&mut self) {
+        if !self.line_pending {
+            self.out.push(' ');
+        }
+    }
+
+    fn column(&self) -> usize {
+        if self.line_pending {
+            return self.indent * INDENT.len();
+        }
+        match self.out.rfind('\n') {
+            Some(newline) => self.out[newline + 1..].chars().count(),
+            None => self.start_column + self.out.chars().count(),
+        }
+    }
+
+    // トークンの前にあるコメントを書き出す。空行は1つまで残す
+    fn comments(&mut self, token: &SyntaxToken) -> usize {
+        let mut newlines = 0;
+        for trivia in &token.leading {
+            match trivia.kind {
+                TriviaKind::Whitespace => newlines += trivia.text.matches('\n').count(),
+                TriviaKind::Comment => {
+                    self.saw_comment = true;
+                    if newlines == 0 && !self.out.is_empty() {
+                        // 行末のコメントはその行に残す
+                        if !self.out.ends_with(' ') {
+                            self.out.push(' ');
+                        }
+                        self.out.push_str(&trivia.text);
+                    } else {
+                        // 演算子の後に置いた空白を行末に残さない
+                        if !self.line_pending {
+                            let len = self.out.trim_end_matches(' ').len();
+                            self.out.truncate(len);
+                        }
+                        self.newline();
+                        if newlines >= 2 {
+                            self.blank_line();
+                        }
+                        self.push(&trivia.text);
+                    }
+                    self.newline();
+                    newlines = 0;
+                }
+            }
+        }
+        newlines
+    }
+
+    fn token(&mut self, token: &SyntaxToken) {
+        // 文の途中でコメントが行を切ったら、文の終わりまで1段深くする
+        let mid_line = !self.line_pending && !self.out.is_empty();
+        let has_comment = token
+            .leading
+            .iter()
+            .any(|trivia| trivia.kind == TriviaKind::Comment);
+        if mid_line && has_comment && !self.continued {
+            self.indent += 1;
+            self.continued = true;
+        }
+        let newlines = self.comments(token);
+        if newlines >= 2 {
+            self.blank_line();
+        }
+        self.push(&token.token.literal);
+    }
+
+    fn element(&mut self, element: &SyntaxElement) {
+        match element {
+            SyntaxElement::Node(node) => self.node(node),
+            SyntaxElement::Token(token) => self.token(token),
+        }
+    }
+
+    fn program(&mut self, node: &SyntaxNode) {
+        for child in &node.children {
+            match child {
+                SyntaxElement::Node(statement) => {
+                    self.newline();
+                    self.statement(statement);
+                }
+                // Eofの前にあるファイル末尾のコメント
+                SyntaxElement::Token(eof) => {
+                    self.comments(eof);
+                }
+            }
+        }
+    }
+
+    fn statement(&mut self, node: &SyntaxNode) {
+        let continued = std::mem::replace(&mut self.continued, false);
+        let mut semicolon = false;
+        for (i, child) in node.children.iter().enumerate() {
+            match child {
+                SyntaxElement::Token(token) if token.token_type() == TokenType::Semicolon => {
+                    semicolon = true;
+                }
+                // let x: int = ...
+                _ if i > 0 && !is_token(child, TokenType::Colon) => self.space(),
+                _ => {}
+            }
+            self.element(child);
+        }
+        if !semicolon {
+            self.push(";");
+        }
+        if self.continued {
+            self.indent -= 1;
+        }
+        self.continued = continued;
+    }
+
+    fn node(&mut self, node: &SyntaxNode) {
+        match node.kind {
+            SyntaxKind::LetStatement
+            | SyntaxKind::ReturnStatement
+            | SyntaxKind::ExpressionStatement => self.statement(node),
+            SyntaxKind::Block => self.block(node),
+            SyntaxKind::InfixExpression => {
+                for (i, child) in node.children.iter().enumerate() {
+                    if i > 0 {
+                        self.space();
+                    }
+                    self.element(child);
+                }
+            }
+            // if (cond) { ... } else { ... }
+            // fn(x: int) -> int { ... }
+            SyntaxKind::IfExpression | SyntaxKind::FunctionLiteral => {
+                for child in &node.children {
+                    let spaced = match child {
+                        SyntaxElement::Token(token) => match token.token_type() {
+                            TokenType::Lparen | TokenType::Else => {
+                                node.kind == SyntaxKind::IfExpression
+                            }
+                            TokenType::Arrow => true,
+                            _ => false,
+                        },
+                        SyntaxElement::Node(child) => matches!(
+                            child.kind,
+                            SyntaxKind::Block
+                                | SyntaxKind::NamedType
+                                | SyntaxKind::FunctionType
+                                | SyntaxKind::ArrayType
+                                | SyntaxKind::HashType
+                        ),
+                    };
+                    if spaced {
+                        self.space();
+                    }
+                    self.element(child);
+                }
+            }
+            // [1, 2], {"a": 1}, {string: int}
+            SyntaxKind::ParameterList
+            | SyntaxKind::ArrayLiteral
+            | SyntaxKind::HashLiteral
+            | SyntaxKind::HashType => self.list(node),
+            // fn(int, int) -> int
+            SyntaxKind::FunctionType => {
+                for child in &node.children {
+                    if is_token(child, TokenType::Arrow) {
+                        self.space();
+                    }
+                    self.element(child);
+                    if is_token(child, TokenType::Comma) || is_token(child, TokenType::Arrow) {
+                        self.space();
+                    }
+                }
+            }
+            SyntaxKind::ArgumentList => self.arguments(node),
+            // identifiers, literals, prefix, grouped, call and index
+            // expressions, and array types
+            _ => {
+                for child in &node.children {
+                    self.element(child);
+                }
+            }
+        }
+    }
+
+    fn block(&mut self, node: &SyntaxNode) {
+        let mut tokens = node.child_tokens();
+        let open = tokens.next().expect("block starts with {");
+        let close = tokens.next().expect("block ends with }");
+
+        self.token(open);
+        self.indent += 1;
+        self.block_start = true;
+        for statement in node.nodes() {
+            self.newline();
+            self.statement(statement);
+        }
+        // } の前のコメントはブロックの中に書く
+        let has_comments = close
+            .leading
+            .iter()
+            .any(|trivia| trivia.kind == TriviaKind::Comment);
+        if has_comments {
+            self.newline();
+            self.comments(close);
+        }
+        self.indent -= 1;
+        if node.nodes().next().is_some() || has_comments {
+            self.newline();
+        }
+        self.push(&close.token.literal);
+    }
+
+    // (a, b: int, c) を1行で書く
+    fn list(&mut self, node: &SyntaxNode) {
+        self.items(&node.children);
+    }
+
+    fn items(&mut self, children: &[SyntaxElement]) {
+        for child in children {
+            self.element(child);
+            if is_token(child, TokenType::Comma) || is_token(child, TokenType::Colon) {
+                self.space();
+            }
+        }
+    }
+
+    fn arguments(&mut self, node: &SyntaxNode) {
+        if self.flat {
+            return self.list(node);
+        }
+        // ( より前のコメントは引数の折り返しに関係しない
+        let open = node.child_tokens().next().expect("arguments start with (");
+        let close = node.child_tokens().last().expect("arguments end with )");
+        self.token(open);
+        let mut flat = Formatter::new(self.indent, true);
+        flat.start_column = self.column();
+        flat.block_start = false;
+        flat.items(&node.children[1..]);
+        let first_line = flat.out.lines().next().unwrap_or("");
+        let fits = flat.start_column + first_line.chars().count() <= MAX_WIDTH;
+        if fits && !flat.saw_comment {
+            self.out.push_str(&flat.out);
+            self.block_start = false;
+            return;
+        }
+
+        // 1行に収まらなければ引数を1つずつ改行する
+        self.indent += 1;
+        for child in &node.children[1..node.children.len() - 1] {
+            if let SyntaxElement::Node(_) = child {
+                self.newline();
+            }
+            self.element(child);
+        }
+        self.comments(close);
+        self.indent -= 1;
+        self.newline();
+        self.push(&close.token.literal);
+    }
+}
+
+fn is_token(element: &SyntaxElement, token_type: TokenType) -> bool {
+    match element {
+        SyntaxElement::Token(token) => token.token_type() == token_type,
+        SyntaxElement::Node(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ast::Node;
+    use super::*;
+
+    #[test]
+    fn canonical_style() {
+        let input = "let add=fn(x,y){x+y};
+// double a number
+let double = fn(x) {   return x*2   }
+
+
+let result=add( 1,double(-2) ) ;if(result>2){result}else{!true}
+fn(){}";
+        let expected = "let add = fn(x, y) {
+    x + y;
+};
+// double a number
+let double = fn(x) {
+    return x * 2;
+};
+
+let result = add(1, double(-2));
+if (result > 2) {
+    result;
+} else {
+    !true;
+};
+fn() {};
+";
+        assert_eq!(format(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn comments_are_preserved() {
+        let input = "// header
+
+let x = 1; // one
+let f = fn(a) {
+    // inside
+    a // trailing a
+
+    // before end
+};
+// footer
+";
+        let expected = "// header
+
+let x = 1; // one
+let f = fn(a) {
+    // inside
+    a; // trailing a
+
+    // before end
+};
+// footer
+";
+        assert_eq!(format(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn comments_inside_statements() {
+        // コメントで切れた文の続きは1段深くする
+        let input = "let x = 1 + // c
+2;
+let f = fn(a) { a } // c
+(1)
+let y = 1 +
+// own line
+2 * 3;
+";
+        let expected = "let x = 1 + // c
+    2;
+let f = fn(a) {
+    a;
+} // c
+    (1);
+let y = 1 +
+    // own line
+    2 * 3;
+";
+        assert_eq!(format(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn long_arguments_are_wrapped() {
+        let input =
+            "let total = sum(first_argument_with_a_long_name, second_argument_with_a_long_name, third_argument_value, 4);
+f(1, g(2, 3));";
+        let expected = "let total = sum(
+    first_argument_with_a_long_name,
+    second_argument_with_a_long_name,
+    third_argument_value,
+    4
+);
+f(1, g(2, 3));
+";
+        assert_eq!(format(input).unwrap(), expected);
+    }
+
+    #[test]
+    fn idempotent_and_preserves_meaning() {
+        let inputs = [
+            "let a = -9223372036854775808; a / (2 - 1.5e3)",
+            "let x=18446744073709551616;x",
+            "let f = fn(x) { if (x < 1) { return 0; } x + f(x - 1) }; f(10)",
+            "map(numbers, fn(n) { n * 2 }, aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa, bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb)",
+            "",
+            "let x = 1 + // c\n2;",
+            "let f = fn(a) { a } // c\n(1)",
+            "let y = 1 +\n// own line\n2 * 3;",
+            "f(1, // one\n2)",
+            r#"let h={"a\n":[1,2][0],true:{}};h["a"][1+1]"#,
+            "[1, // one\n2]",
+        ];
+        for input in inputs.iter() {
+            let formatted = format(input).unwrap();
+            assert_eq!(format(&formatted).unwrap(), formatted, "input: {:?}", input);
+
+            let before = SyntaxTree::parse(input).lower(true).unwrap();
+            let after = SyntaxTree::parse(&formatted).lower(true).unwrap();
+            assert_eq!(before.string(), after.string(), "input: {:?}", input);
+        }
+    }
+
+    #[test]
+    fn type_annotations() {
+        let input = "let f:fn(int,bool)->int=fn(a : int,b:bool)->int{a};let g = fn(h: fn() -> int) { h() };";
+        let expected = "let f: fn(int, bool) -> int = fn(a: int, b: bool) -> int {
+    a;
+};
+let g = fn(h: fn() -> int) {
+    h();
+};
+";
+        assert_eq!(format(input).unwrap(), expected);
+        assert_eq!(format(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn arrays_and_hashes() {
+        let input = r#"let xs:[int]=[ 1,2 ,3 ];let h : {string:[int]}={"a" :xs,"b":[]};fn(h:{bool:int})->[int]{[h[true]]}(h)[0]"#;
+        let expected = r#"let xs: [int] = [1, 2, 3];
+let h: {string: [int]} = {"a": xs, "b": []};
+fn(h: {bool: int}) -> [int] {
+    [h[true]];
+}(h)[0];
+"#;
+        assert_eq!(format(input).unwrap(), expected);
+        assert_eq!(format(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn syntax_errors_are_reported() {
+        let errors = format("let x 5;").unwrap_err();
+        assert_eq!(errors.len(), 1);
+    }
+}
@@ -0,0 +1,338 @@
+//! Classification of source text for syntax highlighting.
+//!
+//! Tokens are classified by their `TokenType`. When the source parses, the
+//! identifiers are refined with the resolver: names bound to a function
+//! literal and called names become `Function`, parameters and their uses
+//! become `Parameter`. Comments are not tokens; they are found in the gaps
+//! between tokens. Whitespace and delimiters such as `(` and `;` are left
+//! unclassified.
+
+use super::arena::{Ast, NodeId, NodeKind};
+use super::lexer::Lexer;
+use super::resolver::{self, BindingKind, Resolution};
+use super::token::{Span, TokenType};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenClass {
+    Keyword,
+    Identifier,
+    /// A name bound to a function literal, or any name that is called.
+    Function,
+    /// A function parameter, where it is declared and where it is used.
+    Parameter,
+    Number,
+    /// A string literal, quotes included.
+    String,
+    Operator,
+    Comment,
+    /// A character the lexer does not know.
+    Invalid,
+}
+
+impl TokenClass {
+    /// The CSS class of the class, without the `mk-` prefix.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenClass::Keyword => "keyword",
+            TokenClass::Identifier => "identifier",
+            TokenClass::Function => "function",
+            TokenClass::Parameter => "parameter",
+            TokenClass::Number => "number",
+            TokenClass::String => "string",
+            TokenClass::Operator => "operator",
+            TokenClass::Comment => "comment",
+            TokenClass::Invalid => "invalid",
+        }
+    }
+
+    fn ansi(&self) -> Option<&'static str> {
+        match self {
+            TokenClass::Keyword => Some("\x1b[35m"),
+            TokenClass::Function => Some("\x1b[34m"),
+            TokenClass::Parameter => Some("\x1b[36m"),
+            TokenClass::Number => Some("\x1b[33m"),
+            TokenClass::String => Some("\x1b[32m"),
+            TokenClass::Comment => Some("\x1b[90m"),
+            TokenClass::Invalid => Some("\x1b[1;31m"),
+            TokenClass::Identifier | TokenClass::Operator => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Highlight {
+    pub span: Span,
+    pub class: TokenClass,
+}
+
+/// Classify a source string. The highlights are in source order and do not
+/// overlap. Sources with syntax errors are classified by tokens alone.
+pub fn classify(source: &str) -> Vec<Highlight> {
+    let identifiers = match Ast::parse(source, false) {
+        Ok(ast) => identifier_classes(&ast, &resolver::resolve(&ast, &[])),
+        Err(_) => HashMap::new(),
+    };
+    let mut highlights = Vec::new();
+    let mut end = 0;
+    for token in Lexer::new(source) {
+        comments(source, Span::new(end, token.span.start), &mut highlights);
+        end = token.span.end;
+        let class = match token.token_type {
+            TokenType::Eof => break,
+            TokenType::Ident => identifiers
+                .get(&token.span.start)
+                .copied()
+                .unwrap_or(TokenClass::Identifier),
+            TokenType::Int | TokenType::Float => TokenClass::Number,
+            TokenType::String => TokenClass::String,
+            TokenType::Illegal => TokenClass::Invalid,
+            TokenType::Function
+            | TokenType::Let
+            | TokenType::True
+            | TokenType::False
+            | TokenType::If
+            | TokenType::Else
+            | TokenType::Return => TokenClass::Keyword,
+            TokenType::Assign
+            | TokenType::Plus
+            | TokenType::Minus
+            | TokenType::Bang
+            | TokenType::Asterisk
+            | TokenType::Slash
+            | TokenType::Lt
+            | TokenType::Gt
+            | TokenType::Equal
+            | TokenType::NotEqual
+            | TokenType::Arrow => TokenClass::Operator,
+            TokenType::Comma
+            | TokenType::Semicolon
+            | TokenType::Colon
+            | TokenType::Lparen
+            | TokenType::Rparen
+            | TokenType::Lbrace
+            | TokenType::Rbrace
+            | TokenType::Lbracket
+            | TokenType::Rbracket => continue,
+        };
+        highlights.push(Highlight {
+            span: token.span,
+            class,
+        });
+    }
+    highlights
+}
+
+// トークンの間には空白とコメントしかない
+fn comments(source: &str, gap: Span, highlights: &mut Vec<Highlight>) {
+    let mut start = gap.start;
+    while let Some(i) = source[start..gap.end].find("//") {
+        let comment_start = start + i;
+        let comment_end = source[comment_start..gap.end]
+            .find('\n')
+            .map_or(gap.end, |i| comment_start + i);
+        let text = &source[comment_start..comment_end];
+        highlights.push(Highlight {
+            span: Span::new(comment_start, comment_start + text.trim_end().len()),
+            class: TokenClass::Comment,
+        });
+        start = comment_end;
+    }
+}
+
+// 識別子の開始位置ごとの分類。子は親より先に来るので、呼び出しの分類が優先される
+fn identifier_classes(ast: &Ast, resolution: &Resolution) -> HashMap<usize, TokenClass> {
+    let mut classes = HashMap::new();
+    for id in ast.ids() {
+        match ast.kind(id) {
+            NodeKind::Identifier(_) => {
+                let definition = match resolution.definitions.get(id) {
+                    Some(_) => Some(id),
+                    None => resolution.bindings.get(id).copied(),
+                };
+                if let Some(class) = definition.and_then(|d| binding_class(ast, resolution, d)) {
+                    classes.insert(ast.span(id).start, class);
+                }
+            }
+            NodeKind::Call { function, .. } => {
+                if let NodeKind::Identifier(_) = ast.kind(*function) {
+                    classes.insert(ast.span(*function).start, TokenClass::Function);
+                }
+            }
+            _ => {}
+        }
+    }
+    classes
+}
+
+fn binding_class(ast: &Ast, resolution: &Resolution, definition: NodeId) -> Option<TokenClass> {
+    match resolution.definitions.get(definition)? {
+        BindingKind::Parameter(_) => Some(TokenClass::Parameter),
+        BindingKind::Let(statement) => match ast.kind(*statement) {
+            NodeKind::Let { value, .. } => match ast.kind(*value) {
+                NodeKind::Function { .. } => Some(TokenClass::Function),
+                _ => None,
+            },
+            _ => None,
+        },
+    }
+}
+
+/// Render the source with ANSI colour escapes for a terminal.
+pub fn to_ansi(source: &str, highlights: &[Highlight]) -> String {
+    render(
+        source,
+        highlights,
+        str::to_owned,
+        |text, class| match class.ansi() {
+            Some(colour) => format!("{}{}\x1b[0m", colour, text),
+            None => String::from(text),
+        },
+    )
+}
+
+/// Render the source as an HTML `<pre>` block. Each classified span is a
+/// `<span>` with a CSS class such as `mk-keyword`; styling is left to the page.
+pub fn to_html(source: &str, highlights: &[Highlight]) -> String {
+    let body = render(source, highlights, escape_html, |text, class| {
+        format!(
+            "<span class=\"mk-{}\">{}</span>",
+            class.name(),
+            escape_html(text)
+        )
+    });
+    format!("<pre class=\"monkey\"><code>{}</code></pre>", body)
+}
+
+// 分類されていない部分は plain で、分類された部分は span で出力する
+fn render<P, S>(source: &str, highlights: &[Highlight], plain: P, span: S) -> String
+where
+    P: Fn(&str) -> String,
+    S: Fn(&str, TokenClass) -> String,
+{
+    let mut buf = String::new();
+    let mut end = 0;
+    for highlight in highlights {
+        buf.push_str(&plain(&source[end..highlight.span.start]));
+        buf.push_str(&span(
+            &source[highlight.span.start..highlight.span.end],
+            highlight.class,
+        ));
+        end = highlight.span.end;
+    }
+    buf.push_str(&plain(&source[end..]));
+    buf
+}
+
+fn escape_html(text: &str) -> String {
+    let mut buf = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '<' => buf.push_str("&lt;"),
+            '>' => buf.push_str("&gt;"),
+            '&' => buf.push_str("&amp;"),
+            '"' => buf.push_str("&quot;"),
+            _ => buf.push(c),
+        }
+    }
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classes(source: &str) -> Vec<(&str, TokenClass)> {
+        classify(source)
+            .into_iter()
+            .map(|highlight| {
+                (
+                    &source[highlight.span.start..highlight.span.end],
+                    highlight.class,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn classifies_tokens() {
+        use TokenClass::*;
+        let source = "let add = fn(a, b) { a + b }; // sum\nlet x = add(1, 2.5) != true;";
+        assert_eq!(
+            classes(source),
+            vec![
+                ("let", Keyword),
+                ("add", Function),
+                ("=", Operator),
+                ("fn", Keyword),
+                ("a", Parameter),
+                ("b", Parameter),
+                ("a", Parameter),
+                ("+", Operator),
+                ("b", Parameter),
+                ("// sum", Comment),
+                ("let", Keyword),
+                ("x", Identifier),
+                ("=", Operator),
+                ("add", Function),
+                ("1", Number),
+                ("2.5", Number),
+                ("!=", Operator),
+                ("true", Keyword),
+            ]
+        );
+    }
+
+    #[test]
+    fn called_names_are_functions() {
+        use TokenClass::*;
+        assert_eq!(
+            classes("let apply = fn(f) { f(1) }; puts"),
+            vec![
+                ("let", Keyword),
+                ("apply", Function),
+                ("=", Operator),
+                ("fn", Keyword),
+                ("f", Parameter),
+                ("f", Function),
+                ("1", Number),
+                ("puts", Identifier),
+            ]
+        );
+    }
+
+    #[test]
+    fn sources_with_errors() {
+        use TokenClass::*;
+        assert_eq!(
+            classes("// a\n// b\nlet s = \"hi\" @ \"open"),
+            vec![
+                ("// a", Comment),
+                ("// b", Comment),
+                ("let", Keyword),
+                ("s", Identifier),
+                ("=", Operator),
+                ("\"hi\"", String),
+                ("@", Invalid),
+                ("\"open", Invalid),
+            ]
+        );
+    }
+
+    #[test]
+    fn render() {
+        let source = "if (a < 1) { a } // <ok>\n";
+        let highlights = classify(source);
+        assert_eq!(
+            to_html(source, &highlights),
+            "<pre class=\"monkey\"><code><span class=\"mk-keyword\">if</span> \
+             (<span class=\"mk-identifier\">a</span> <span class=\"mk-operator\">&lt;</span> \
+             <span class=\"mk-number\">1</span>) { <span class=\"mk-identifier\">a</span> } \
+             <span class=\"mk-comment\">// &lt;ok&gt;</span>\n</code></pre>"
+        );
+        assert_eq!(
+            to_ansi(source, &highlights),
+            "\x1b[35mif\x1b[0m (a < \x1b[33m1\x1b[0m) { a } \x1b[90m// <ok>\x1b[0m\n"
+        );
+    }
+}
@@ -0,0 +1,520 @@
+//! Incremental reparsing for editors.
+//!
+//! A `Document` keeps the tokens of its source and the top-level statements
+//! parsed from them. `Document::edit` re-lexes from the last token before the
+//! edit until the new tokens line up with the old ones again, then re-parses
+//! only the top-level statements that saw a changed token. Between top-level
+//! statements the parser has no state besides its position, so statements
+//! before the change are kept as they are and statements after it are kept
+//! with their spans shifted. The result is the same as a full parse of the new
+//! source, errors included.
+
+use super::ast::{
+    ArrayLiteral, BigIntegerLiteral, BlockStatement, Boolean, CallExpression, ExpressionStatement,
+    FloatLiteral, FunctionLiteral, HashLiteral, Identifer, IfExpression, IndexExpression,
+    InfixExpression, IntegerLiteral, LetStatement, PrefixExpression, Program, ReturnStatement,
+    StatementType, StringLiteral, TypeExpression,
+};
+use super::diagnostic::Diagnostic;
+use super::lexer::Lexer;
+use super::parser::Parser;
+use super::token::{Span, Token};
+use super::visit::{self, VisitorMut};
+
+// トークンの終わりを決めるのに、字句解析器はその後ろの2文字まで見る
+// (`1.5` の `.` と数字など)。先読みするのは ASCII の文字だけなので2バイトで足りる
+const LOOKAHEAD: usize = 2;
+
+/// Replace the bytes of `span` in the old source with `text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub span: Span,
+    pub text: String,
+}
+
+impl TextEdit {
+    pub fn new(span: Span, text: &str) -> Self {
+        Self {
+            span,
+            text: String::from(text),
+        }
+    }
+}
+
+/// How much work an edit took.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Reparse {
+    pub relexed_tokens: usize,
+    pub reparsed_statements: usize,
+}
+
+// parse_program の1回の繰り返しの結果
+#[derive(Debug, Clone)]
+struct Unit {
+    start: usize, // 最初のトークンの位置
+    statement: Option<StatementType>,
+    errors: Vec<Diagnostic>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Document {
+    source: String,
+    big_integers: bool,
+    tokens: Vec<Token>,       // 最後は Eof
+    prelude: Vec<Diagnostic>, // パーサーが最初の2トークンを読んだときのエラー
+    units: Vec<Unit>,
+}
+
+// 編集の後ろで再利用できる古い文
+struct Tail {
+    units: Vec<Unit>,
+    old_start: usize, // 再利用する最初の古いトークン
+    new_start: usize, // それの新しい位置
+    delta: isize,
+}
+
+impl Document {
+    pub fn parse(source: &str, big_integers: bool) -> Self {
+        let mut document = Self {
+            source: String::from(source),
+            big_integers,
+            tokens: Lexer::new(source).collect(),
+            prelude: Vec::new(),
+            units: Vec::new(),
+        };
+        document.parse_from(0, None);
+        document
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The tokens of the source, ending with `Eof`.
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    /// The statements that parsed, as `Parser::parse_program` returns them.
+    pub fn program(&self) -> Program {
+        self.units
+            .iter()
+            .filter_map(|unit| unit.statement.clone())
+            .collect()
+    }
+
+    /// The same errors as `Parser::errors` gives for the source.
+    pub fn errors(&self) -> Vec<Diagnostic> {
+        let mut errors = self.prelude.clone();
+        for unit in &self.units {
+            errors.extend(unit.errors.iter().cloned());
+        }
+        errors.sort_by_key(|diagnostic| diagnostic.span.start);
+        errors
+    }
+
+    /// Apply an edit to the source and update the tokens and statements.
+    ///
+    /// # Panics
+    ///
+    /// If the span of the edit is not a range of character boundaries of the
+    /// source.
+    pub fn edit(&mut self, edit: &TextEdit) -> Reparse {
+        let Span { start, end } = edit.span;
+        let mut source = String::with_capacity(self.source.len() - (end - start) + edit.text.len());
+        source.push_str(&self.source[..start]);
+        source.push_str(&edit.text);
+        source.push_str(&self.source[end..]);
+        self.source = source;
+        let delta = edit.text.len() as isize - (end - start) as isize;
+
+        // 編集位置の手前で終わるトークンの直後から字句解析し直し、古いトークンと
+        // 同じ位置で始まるトークンに出会ったら、そこから先は古いトークンと同じ
+        let first = self
+            .tokens
+            .iter()
+            .position(|token| token.span.end + LOOKAHEAD >= start)
+            .expect("the Eof token ends at the end of the source");
+        let restart = if first == 0 {
+            0
+        } else {
+            self.tokens[first - 1].span.end
+        };
+        let mut relexed = Vec::new();
+        let mut resume = None;
+        for mut token in Lexer::new(&self.source[restart..]) {
+            token.span = shift(token.span, restart as isize);
+            if token.span.start >= start + edit.text.len() {
+                let old_start = (token.span.start as isize - delta) as usize;
+                if let Ok(i) =
+                    self.tokens[first..].binary_search_by_key(&old_start, |token| token.span.start)
+                {
+                    resume = Some(first + i);
+                    break;
+                }
+            }
+            relexed.push(token);
+        }
+        // 古い Eof はかならず新しい Eof と同じ位置にある
+        let resume = resume.expect("the Eof tokens line up");
+        let relexed_tokens = relexed.len();
+
+        // 文は次の文の最初の2トークンまで見るので、それより前に変更がなければそのまま使える
+        let eof = self.tokens.len() - 1;
+        let mut units = std::mem::take(&mut self.units);
+        let kept = (0..units.len())
+            .take_while(|k| units.get(k + 1).map_or(eof, |unit| unit.start) + 1 < first)
+            .count();
+        let boundary = if kept == 0 { 0 } else { units[kept].start };
+        let tail = Tail {
+            units: units.split_off(kept),
+            old_start: resume,
+            new_start: first + relexed_tokens,
+            delta,
+        };
+        self.units = units;
+
+        let moved: Vec<Token> = self.tokens[resume..]
+            .iter()
+            .cloned()
+            .map(|mut token| {
+                token.span = shift(token.span, delta);
+                token
+            })
+            .collect();
+        self.tokens.truncate(first);
+        self.tokens.extend(relexed);
+        self.tokens.extend(moved);
+        let reparsed_statements = self.parse_from(boundary, Some(tail));
+        Reparse {
+            relexed_tokens,
+            reparsed_statements,
+        }
+    }
+
+    // boundary の文から解析し、古い文の始まりに戻ったら残りは tail を使う。
+    // 解析し直した文の数を返す
+    fn parse_from(&mut self, boundary: usize, tail: Option<Tail>) -> usize {
+        let tokens = &self.tokens;
+        let mut parser =
+            Parser::new(tokens[boundary..].iter().cloned()).with_big_integers(self.big_integers);
+        let prelude = parser.take_errors();
+        if boundary == 0 {
+            self.prelude = prelude;
+        }
+        let mut reparsed = 0;
+        loop {
+            let at = boundary + index_of(&tokens[boundary..], parser.current_span());
+            // 変更より後ろで古い文の始まりに戻ったら、残りの文は同じになる
+            if let Some(tail) = tail.as_ref().filter(|tail| at >= tail.new_start) {
+                let old = at - tail.new_start + tail.old_start;
+                if let Some(m) = tail.units.iter().position(|unit| unit.start == old) {
+                    for unit in &tail.units[m..] {
+                        self.units.push(shift_unit(unit, tail));
+                    }
+                    return reparsed;
+                }
+            }
+            match parser.parse_top_level_statement() {
+                Some(statement) => {
+                    self.units.push(Unit {
+                        start: at,
+                        statement,
+                        errors: parser.take_errors(),
+                    });
+                    reparsed += 1;
+                }
+                None => return reparsed,
+            }
+        }
+    }
+}
+
+fn index_of(tokens: &[Token], span: Span) -> usize {
+    tokens
+        .binary_search_by_key(&span.start, |token| token.span.start)
+        .unwrap_or(tokens.len() - 1)
+}
+
+fn shift_unit(unit: &Unit, tail: &Tail) -> Unit {
+    let mut statement = unit.statement.clone();
+    if let Some(statement) = &mut statement {
+        Shift(tail.delta).visit_statement_mut(statement);
+    }
+    Unit {
+        start: unit.start - tail.old_start + tail.new_start,
+        statement,
+        errors: unit
+            .errors
+            .iter()
+            .map(|diagnostic| Diagnostic {
+                span: shift(diagnostic.span, tail.delta),
+                ..diagnostic.clone()
+            })
+            .collect(),
+    }
+}
+
+fn shift(span: Span, delta: isize) -> Span {
+    Span::new(
+        (span.start as isize + delta) as usize,
+        (span.end as isize + delta) as usize,
+    )
+}
+
+// 部分木のすべてのトークンと span をずらす
+struct Shift(isize);
+
+impl Shift {
+    fn token(&self, token: &mut Token) {
+        token.span = shift(token.span, self.0);
+    }
+}
+
+impl VisitorMut for Shift {
+    fn visit_let_statement_mut(&mut self, statement: &mut LetStatement) {
+        self.token(&mut statement.token);
+        visit::walk_let_statement_mut(self, statement);
+    }
+    fn visit_return_statement_mut(&mut self, statement: &mut ReturnStatement) {
+        self.token(&mut statement.token);
+        visit::walk_return_statement_mut(self, statement);
+    }
+    fn visit_expression_statement_mut(&mut self, statement: &mut ExpressionStatement) {
+        self.token(&mut statement.token);
+        visit::walk_expression_statement_mut(self, statement);
+    }
+    fn visit_block_statement_mut(&mut self, block: &mut BlockStatement) {
+        self.token(&mut block.token);
+        block.span = shift(block.span, self.0);
+        visit::walk_block_statement_mut(self, block);
+    }
+    fn visit_identifer_mut(&mut self, identifer: &mut Identifer) {
+        self.token(&mut identifer.token);
+    }
+    fn visit_integer_literal_mut(&mut self, literal: &mut IntegerLiteral) {
+        self.token(&mut literal.token);
+    }
+    fn visit_big_integer_literal_mut(&mut self, literal: &mut BigIntegerLiteral) {
+        self.token(&mut literal.token);
+    }
+    fn visit_float_literal_mut(&mut self, literal: &mut FloatLiteral) {
+        self.token(&mut literal.token);
+    }
+    fn visit_boolean_mut(&mut self, boolean: &mut Boolean) {
+        self.token(&mut boolean.token);
+    }
+    fn visit_string_literal_mut(&mut self, literal: &mut StringLiteral) {
+        self.token(&mut literal.token);
+    }
+    fn visit_array_literal_mut(&mut self, literal: &mut ArrayLiteral) {
+        self.token(&mut literal.token);
+        literal.span = shift(literal.span, self.0);
+        visit::walk_array_literal_mut(self, literal);
+    }
+    fn visit_hash_literal_mut(&mut self, literal: &mut HashLiteral) {
+        self.token(&mut literal.token);
+        literal.span = shift(literal.span, self.0);
+        visit::walk_hash_literal_mut(self, literal);
+    }
+    fn visit_type_expression_mut(&mut self, ty: &mut TypeExpression) {
+        match ty {
+            TypeExpression::Named(name) => self.visit_identifer_mut(name),
+            TypeExpression::Function(function) => {
+                self.token(&mut function.token);
+                for parameter in &mut function.parameters {
+                    self.visit_type_expression_mut(parameter);
+                }
+                self.visit_type_expression_mut(&mut function.result);
+            }
+            TypeExpression::Array(array) => {
+                self.token(&mut array.token);
+                array.span = shift(array.span, self.0);
+                self.visit_type_expression_mut(&mut array.element);
+            }
+            TypeExpression::Hash(hash) => {
+                self.token(&mut hash.token);
+                hash.span = shift(hash.span, self.0);
+                self.visit_type_expression_mut(&mut hash.key);
+                self.visit_type_expression_mut(&mut hash.value);
+            }
+        }
+    }
+    fn visit_prefix_expression_mut(&mut self, expression: &mut PrefixExpression) {
+        self.token(&mut expression.token);
+        visit::walk_prefix_expression_mut(self, expression);
+    }
+    fn visit_infix_expression_mut(&mut self, expression: &mut InfixExpression) {
+        self.token(&mut expression.token);
+        visit::walk_infix_expression_mut(self, expression);
+    }
+    fn visit_if_expression_mut(&mut self, expression: &mut IfExpression) {
+        self.token(&mut expression.token);
+        visit::walk_if_expression_mut(self, expression);
+    }
+    fn visit_function_literal_mut(&mut self, literal: &mut FunctionLiteral) {
+        self.token(&mut literal.token);
+        visit::walk_function_literal_mut(self, literal);
+    }
+    fn visit_call_expression_mut(&mut self, expression: &mut CallExpression) {
+        self.token(&mut expression.token);
+        expression.span = shift(expression.span, self.0);
+        visit::walk_call_expression_mut(self, expression);
+    }
+    fn visit_index_expression_mut(&mut self, expression: &mut IndexExpression) {
+        self.token(&mut expression.token);
+        expression.span = shift(expression.span, self.0);
+        visit::walk_index_expression_mut(self, expression);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(tokens: &[Token]) -> Vec<String> {
+        tokens.iter().map(|token| format!("{:?}", token)).collect()
+    }
+
+    // 全体を解析し直した結果と同じか
+    fn assert_same_as_full_parse(document: &Document) {
+        let full = Document::parse(document.source(), document.big_integers);
+        let mut parser = Parser::new(Lexer::new(document.source()));
+        let program = parser.parse_program();
+        assert_eq!(
+            tokens(document.tokens()),
+            tokens(full.tokens()),
+            "source: {:?}",
+            document.source()
+        );
+        assert_eq!(
+            format!("{:?}", document.program()),
+            format!("{:?}", program),
+            "source: {:?}",
+            document.source()
+        );
+        assert_eq!(
+            document.errors(),
+            parser.errors(),
+            "source: {:?}",
+            document.source()
+        );
+    }
+
+    fn edit(source: &str, start: usize, end: usize, text: &str) -> (Document, Reparse) {
+        let mut document = Document::parse(source, false);
+        assert_same_as_full_parse(&document);
+        let reparse = document.edit(&TextEdit::new(Span::new(start, end), text));
+        assert_same_as_full_parse(&document);
+        (document, reparse)
+    }
+
+    #[test]
+    fn reuses_unchanged_statements() {
+        let source = "let a = 1;\nlet b = a + 2;\nlet c = fn(x) { x * b };\nc(a)";
+        let start = source.find("a + 2").unwrap();
+        let (document, reparse) = edit(source, start, start + 1, "b0");
+        assert_eq!(
+            document.source(),
+            "let a = 1;\nlet b = b0 + 2;\nlet c = fn(x) { x * b };\nc(a)"
+        );
+        assert_eq!(
+            reparse,
+            Reparse {
+                relexed_tokens: 2,
+                reparsed_statements: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn tokens_that_grow_across_the_edit() {
+        let tests = vec![
+            // 数字が小数になる
+            ("1. + 2", 2, 2, "5"),
+            ("1 = 2", 2, 3, "=="),
+            ("let x = 1 / 2;", 11, 11, "/"),
+            ("let a = b; c", 9, 10, ""),
+            ("ab + cd", 1, 1, "x"),
+            ("1e5", 1, 2, ""),
+            ("x // y\nlet z = 1;", 6, 7, ""),
+            ("x // y\nlet z = 1;", 2, 4, ""),
+            ("x\nlet z = 1;", 1, 1, " //"),
+        ];
+        for (source, start, end, text) in tests {
+            edit(source, start, end, text);
+        }
+    }
+
+    #[test]
+    fn errors_are_updated() {
+        let source = "let a = 1;\nlet b = ;\nlet c = 3;\n@";
+        let start = source.find(';').unwrap() + 9;
+        let (document, _) = edit(source, start, start, "2");
+        assert_eq!(document.errors().len(), 1);
+        let (document, _) = edit(document.source(), 0, 0, "@ ");
+        assert_eq!(document.errors().len(), 2);
+        let (document, _) = edit(document.source(), 1, 2, "");
+        assert_eq!(document.errors().len(), 2);
+    }
+
+    #[test]
+    fn typing_a_program() {
+        let program = "let add = fn(a: int, b) -> int { return a + b; };\n\
+                       // comment\n\
+                       if (add(1, 2.5e3) > 3) { !true } else { -add(0x10, 2) }\n";
+        let mut document = Document::parse("", false);
+        for (i, c) in program.char_indices() {
+            document.edit(&TextEdit::new(Span::new(i, i), &c.to_string()));
+            assert_same_as_full_parse(&document);
+        }
+        // 途中に挿入する
+        let mut document = Document::parse("let x = 1;\nx", false);
+        for (i, c) in program.char_indices() {
+            let at = 11 + i;
+            document.edit(&TextEdit::new(Span::new(at, at), &c.to_string()));
+            assert_same_as_full_parse(&document);
+        }
+    }
+
+    #[test]
+    fn random_edits() {
+        let pieces = [
+            "let", " ", "x", "y1", "=", "==", ";", "\n", "fn", "(", ")", "{", "}", ",", "1", "2.5",
+            ".", "e", "0x", "//", "!", "-", "+", "*", "/", "<", ">", ":", "->", "int", "if",
+            "else", "return", "true", "@", "é",
+        ];
+        // 線形合同法で再現できる乱数
+        let mut seed: u64 = 42;
+        let mut next = |n: usize| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as usize % n
+        };
+        let mut document = Document::parse("let f = fn(a) { a + 1 };\nf(2)", false);
+        for _ in 0..2000 {
+            let source = document.source();
+            let boundaries: Vec<usize> = (0..=source.len())
+                .filter(|i| source.is_char_boundary(*i))
+                .collect();
+            let start = boundaries[next(boundaries.len())];
+            let end = if next(3) == 0 {
+                let rest: Vec<usize> = boundaries.iter().copied().filter(|i| *i >= start).collect();
+                rest[next(rest.len().min(4))]
+            } else {
+                start
+            };
+            let text = if next(4) == 0 {
+                ""
+            } else {
+                pieces[next(pieces.len())]
+            };
+            document.edit(&TextEdit::new(Span::new(start, end), text));
+            assert_same_as_full_parse(&document);
+            if document.source().len() > 300 {
+                document = Document::parse("", false);
+            }
+        }
+    }
+}
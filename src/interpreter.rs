@@ -0,0 +1,394 @@
+//! Parse and evaluate source text against one persistent environment.
+
+use super::arena::Ast;
+use super::ast::Program;
+use super::diagnostic::Diagnostic;
+use super::evaluator::{EvalResult, Evaluator, IntegerMode, Limits};
+use super::lexer::Lexer;
+use super::native::IntoNative;
+use super::object::{Env, Environment, Object};
+use super::parser::Parser;
+use super::resolver::{self, Resolution};
+use std::rc::Rc;
+
+/// Bindings made by one `eval` are visible to the next, as in the REPL.
+#[derive(Debug)]
+pub struct Interpreter {
+    env: Env,
+    evaluator: Evaluator,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    /// An interpreter with the builtins bound: `json_parse(text)` reads a
+    /// JSON document into a value and `json_stringify(value)` writes one.
+    pub fn new() -> Self {
+        let interpreter = Self {
+            env: Environment::new(),
+            evaluator: Evaluator::default(),
+        };
+        interpreter.register("json_parse", json_parse);
+        interpreter.register("json_stringify", json_stringify);
+        interpreter
+    }
+
+    /// Choose between checked 64-bit and arbitrary-precision integers.
+    pub fn with_integer_mode(mut self, integer_mode: IntegerMode) -> Self {
+        self.evaluator.integer_mode = integer_mode;
+        self
+    }
+
+    /// Bound the resources each `eval` may use.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.evaluator.limits = limits;
+        self
+    }
+
+    pub fn integer_mode(&self) -> IntegerMode {
+        self.evaluator.integer_mode
+    }
+
+    /// Bind a Rust function to a name, for example
+    /// `interpreter.register("max", |a: i64, b: i64| a.max(b))`. Arguments are
+    /// converted to the parameter types when it is called; a closure taking
+    /// `&[Object]` receives them unconverted. Returning `Err` stops the program
+    /// with the error's message.
+    pub fn register<F, Args>(&self, name: &str, function: F)
+    where
+        F: IntoNative<Args>,
+    {
+        let native = function.into_native(name);
+        self.env
+            .borrow_mut()
+            .set(name, Object::Native(Rc::new(native)));
+    }
+
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+
+    /// Parse with the settings of this interpreter. In big integer mode
+    /// literals beyond i64 are accepted.
+    pub fn parse(&self, source: &str) -> Result<Program, Vec<Diagnostic>> {
+        let mut parser = Parser::new(Lexer::new(source))
+            .with_big_integers(self.integer_mode() == IntegerMode::Big);
+        let program = parser.parse_program();
+        let errors = parser.errors();
+        if errors.is_empty() {
+            Ok(program)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Check the names of a program before running it. Names already bound in
+    /// this interpreter are treated as builtins.
+    pub fn resolve(&self, program: &Program) -> Resolution {
+        let names = self.env.borrow().names();
+        let builtins: Vec<&str> = names.iter().map(String::as_str).collect();
+        resolver::resolve(&Ast::from_program(program), &builtins)
+    }
+
+    pub fn eval(&self, program: &Program) -> EvalResult {
+        self.evaluator.eval_program(program, &self.env)
+    }
+}
+
+// オブジェクトのキーは文字列になる。整数は i64 に収まらなければ整数モードに従う
+fn json_parse(text: String) -> Result<Object, serde_json::Error> {
+    serde_json::from_str(&text)
+}
+
+fn json_stringify(value: Object) -> Result<String, serde_json::Error> {
+    serde_json::to_string(&value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ast;
+    use super::super::error_code::ErrorCode;
+    use super::super::parser::MAX_NESTING_DEPTH;
+    use super::super::token::Span;
+    use super::super::{formatter, highlight, lint, serialize, typecheck};
+    use super::*;
+    use num_bigint::BigInt;
+
+    #[test]
+    fn bindings_persist() {
+        let interpreter = Interpreter::new();
+        let program = interpreter.parse("let x = 2;").unwrap();
+        assert_eq!(interpreter.eval(&program), Ok(Object::Null));
+        let program = interpreter.parse("x * 21").unwrap();
+        assert_eq!(interpreter.eval(&program), Ok(Object::Integer(42)));
+    }
+
+    #[test]
+    fn resolve_against_bindings() {
+        let interpreter = Interpreter::new();
+        let program = interpreter.parse("let x = 2;").unwrap();
+        interpreter.eval(&program).unwrap();
+
+        let program = interpreter.parse("x + y").unwrap();
+        let messages: Vec<String> = interpreter
+            .resolve(&program)
+            .diagnostics
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect();
+        assert_eq!(messages, vec!["undefined variable `y`"]);
+    }
+
+    #[test]
+    fn annotations_are_ignored() {
+        let interpreter = Interpreter::new();
+        let program = interpreter
+            .parse(
+                "let add: fn(int, int) -> int = fn(a: int, b: int) -> int { a + b }; add(1.5, 2)",
+            )
+            .unwrap();
+        assert_eq!(interpreter.eval(&program).unwrap().inspect(), "3.5");
+    }
+
+    #[test]
+    fn integer_mode() {
+        let source = "let big = 18446744073709551616; big / 2";
+        let errors = Interpreter::new().parse(source).unwrap_err();
+        assert_eq!(errors[0].code, Some(ErrorCode::IntegerOverflow));
+
+        let interpreter = Interpreter::new().with_integer_mode(IntegerMode::Big);
+        let program = interpreter.parse(source).unwrap();
+        assert_eq!(
+            interpreter.eval(&program).unwrap().inspect(),
+            "9223372036854775808"
+        );
+    }
+
+    #[test]
+    fn limits_apply_to_each_eval() {
+        let interpreter = Interpreter::new().with_limits(Limits {
+            max_steps: Some(20),
+            ..Limits::default()
+        });
+        let program = interpreter.parse("let f = fn(n) { n + 1 };").unwrap();
+        interpreter.eval(&program).unwrap();
+        let program = interpreter.parse("f(f(f(1)))").unwrap();
+        for _ in 0..3 {
+            assert_eq!(interpreter.eval(&program), Ok(Object::Integer(4)));
+        }
+
+        let program = interpreter.parse("let g = fn() { g() }; g()").unwrap();
+        let err = interpreter.eval(&program).unwrap_err();
+        assert!(err.code.is_some_and(|code| code.is_resource_limit()));
+        assert!(!ErrorCode::DivisionByZero.is_resource_limit());
+    }
+
+    #[test]
+    fn native_functions() {
+        let interpreter = Interpreter::new();
+        interpreter.register("max", |a: i64, b: i64| a.max(b));
+        interpreter.register("half", |x: f64| x / 2.0);
+        interpreter.register("count", |arguments: &[Object]| arguments.len() as i64);
+        interpreter.register("checked_sqrt", |x: f64| {
+            if x < 0.0 {
+                Err(format!("{} is negative", x))
+            } else {
+                Ok(x.sqrt())
+            }
+        });
+        let run = |source: &str| interpreter.eval(&interpreter.parse(source).unwrap());
+
+        assert_eq!(run("max(3, 7) + 1"), Ok(Object::Integer(8)));
+        assert_eq!(run("half(3)"), Ok(Object::Float(1.5)));
+        assert_eq!(run("count(1, true, max)"), Ok(Object::Integer(3)));
+        assert_eq!(run("checked_sqrt(16)"), Ok(Object::Float(4.0)));
+        // 末尾位置の呼び出しも、関数として渡された場合も同じ
+        assert_eq!(
+            run("let apply = fn(f, x) { f(x, 1) }; apply(max, 5)"),
+            Ok(Object::Integer(5))
+        );
+        assert_eq!(run("max").unwrap().inspect(), "<native fn max>");
+
+        let err = run("max(1, true)").unwrap_err();
+        assert_eq!(
+            err.message,
+            "type mismatch: argument 2 of `max` must be INTEGER, found BOOLEAN"
+        );
+        assert_eq!(err.code, Some(ErrorCode::TypeMismatch));
+        assert_eq!(err.span, Span::new(7, 11));
+
+        let err = run("max(1)").unwrap_err();
+        assert_eq!(err.code, Some(ErrorCode::WrongArgumentCount));
+
+        let interpreter = Interpreter::new().with_integer_mode(IntegerMode::Big);
+        interpreter.register("max", |a: i64, b: i64| a.max(b));
+        let program = interpreter.parse("max(1, 18446744073709551616)").unwrap();
+        let err = interpreter.eval(&program).unwrap_err();
+        assert_eq!(
+            err.message,
+            "argument 2 of `max` is out of range: 18446744073709551616 does not fit in a 64-bit integer"
+        );
+        assert_eq!(err.code, Some(ErrorCode::ArithmeticOverflow));
+        assert_eq!(err.span, Span::new(7, 27));
+
+        let err = run("1 + checked_sqrt(-4)").unwrap_err();
+        assert_eq!(err.message, "`checked_sqrt` failed: -4 is negative");
+        assert_eq!(err.code, Some(ErrorCode::NativeFunctionFailed));
+        assert_eq!(err.span, Span::new(4, 20));
+
+        // 登録した名前は解決できる
+        let program = interpreter.parse("max(1, 2)").unwrap();
+        assert!(interpreter.resolve(&program).diagnostics.is_empty());
+    }
+
+    #[test]
+    fn native_results_follow_integer_mode() {
+        let register = |interpreter: &Interpreter| {
+            interpreter.register("pow2", |n: i64| BigInt::from(1) << n as usize);
+        };
+
+        let interpreter = Interpreter::new();
+        register(&interpreter);
+        let run = |source: &str| interpreter.eval(&interpreter.parse(source).unwrap());
+        assert_eq!(run("pow2(62)"), Ok(Object::Integer(1 << 62)));
+        let err = run("1 + pow2(64)").unwrap_err();
+        assert_eq!(err.code, Some(ErrorCode::ArithmeticOverflow));
+        assert_eq!(err.span, Span::new(4, 12));
+
+        let interpreter = Interpreter::new()
+            .with_integer_mode(IntegerMode::Big)
+            .with_limits(Limits {
+                max_value_size: Some(16),
+                ..Limits::default()
+            });
+        register(&interpreter);
+        let run = |source: &str| interpreter.eval(&interpreter.parse(source).unwrap());
+        assert_eq!(run("pow2(64)").unwrap().inspect(), "18446744073709551616");
+        let err = run("pow2(128)").unwrap_err();
+        assert_eq!(err.code, Some(ErrorCode::SizeLimitExceeded));
+        assert_eq!(err.span, Span::new(0, 9));
+    }
+
+    #[test]
+    fn json_builtins() {
+        let interpreter = Interpreter::new();
+        let run = |source: &str| interpreter.eval(&interpreter.parse(source).unwrap());
+
+        let value = run(r#"json_parse("{\"a\": [1, 2.5, true, null], \"b\": {\"c\": \"d\"}}")"#);
+        assert_eq!(
+            value.unwrap().inspect(),
+            r#"{"a": [1, 2.5, true, null], "b": {"c": "d"}}"#
+        );
+        let value = run(r#"json_parse("[1, 2]")[1] + json_parse("{\"n\": 3}")["n"]"#);
+        assert_eq!(value, Ok(Object::Integer(5)));
+
+        // 書き出して読み戻すと同じ値になる
+        let tests = [
+            (
+                r#"{"s": "a\"b\n", "xs": [1, -2, 0.5, []], "t": true}"#,
+                r#"{"s":"a\"b\n","t":true,"xs":[1,-2,0.5,[]]}"#,
+            ),
+            ("[[], {}]", "[[],{}]"),
+            (r#""é😀""#, r#""é😀""#),
+        ];
+        for (literal, json) in tests.iter() {
+            let source = format!("json_stringify({})", literal);
+            assert_eq!(run(&source).unwrap().inspect(), ast::quote(json));
+            let source = format!("json_parse(json_stringify({0})) == {0}", literal);
+            assert_eq!(run(&source), Ok(Object::Boolean(true)), "{}", source);
+        }
+        // 整数と真偽値のキーは文字列として書かれる
+        assert_eq!(
+            run(r#"json_stringify({1: "one", true: "yes"})"#),
+            Ok(Object::String(Rc::from(r#"{"1":"one","true":"yes"}"#)))
+        );
+
+        let err = run("json_stringify([1, fn(x) { x }])").unwrap_err();
+        assert_eq!(
+            err.message,
+            "`json_stringify` failed: cannot serialize a function: functions have no data representation"
+        );
+        assert_eq!(err.code, Some(ErrorCode::NativeFunctionFailed));
+        assert_eq!(err.span, Span::new(0, 32));
+
+        let err = run(r#"json_parse("[1,")"#).unwrap_err();
+        assert_eq!(
+            err.message,
+            "`json_parse` failed: EOF while parsing a value at line 1 column 3"
+        );
+        let err = run("json_parse(1)").unwrap_err();
+        assert_eq!(
+            err.message,
+            "type mismatch: argument 1 of `json_parse` must be STRING, found INTEGER"
+        );
+        // i64 に収まらない整数は整数モードに従う
+        let err = run(r#"json_parse("[18446744073709551615]")"#).unwrap_err();
+        assert_eq!(err.code, Some(ErrorCode::ArithmeticOverflow));
+        let interpreter = Interpreter::new().with_integer_mode(IntegerMode::Big);
+        let program = interpreter
+            .parse(r#"json_parse("[18446744073709551615]")[0]"#)
+            .unwrap();
+        assert_eq!(
+            interpreter.eval(&program).unwrap().inspect(),
+            "18446744073709551615"
+        );
+
+        // 組み込みの名前も解決できる
+        let program = interpreter.parse("json_parse").unwrap();
+        assert!(interpreter.resolve(&program).diagnostics.is_empty());
+    }
+
+    #[test]
+    fn nesting_is_limited_before_any_pass_runs() {
+        let shapes: [fn(usize) -> String; 9] = [
+            |n| "-".repeat(n) + "x",
+            |n| "(".repeat(n) + "x" + &")".repeat(n),
+            |n| vec!["x"; n].join(" + "),
+            |n| String::from("f") + &"(x)".repeat(n),
+            |n| "id(".repeat(n) + "x" + &")".repeat(n),
+            |n| "if (true) { ".repeat(n) + "x" + &" }".repeat(n),
+            |n| String::from("let y: ") + &"fn() -> ".repeat(n) + "int = x; y",
+            |n| "[".repeat(n) + "x" + &"]".repeat(n),
+            |n| r#"{"k": "#.repeat(n) + "x" + &"}".repeat(n),
+        ];
+        let prelude = "let x = 1; let id = fn(a) { a }; let f = fn(a) { f };\n";
+        for shape in shapes.iter() {
+            // ホストのスタックより深い入力は、パースの時点で1つのエラーになる
+            let source = String::from(prelude) + &shape(50_000);
+            let errors = Interpreter::new().parse(&source).unwrap_err();
+            assert_eq!(errors.len(), 1, "{:?}", errors);
+            assert_eq!(errors[0].code, Some(ErrorCode::NestingTooDeep));
+            let errors = formatter::format(&source).unwrap_err();
+            assert_eq!(errors.len(), 1, "{:?}", errors);
+            assert_eq!(errors[0].code, Some(ErrorCode::NestingTooDeep));
+
+            // 受け付ける最も深い入力は、どのパスでもスタックを使い切らない
+            let interpreter = Interpreter::new();
+            let (source, program) = (1..=MAX_NESTING_DEPTH)
+                .rev()
+                .find_map(|n| {
+                    let source = String::from(prelude) + &shape(n);
+                    let program = interpreter.parse(&source).ok()?;
+                    Some((source, program))
+                })
+                .unwrap();
+            assert!(source.len() > MAX_NESTING_DEPTH, "{}", source);
+            interpreter.resolve(&program);
+            typecheck::typecheck(&program);
+            lint::lint(&program, &source, &lint::Config::default());
+            formatter::format(&source).unwrap();
+            highlight::classify(&source);
+            serialize::to_sexp(&program);
+            let json = serialize::to_json(&program).to_string();
+            let _ = serialize::from_json(&json, false);
+            // 値の入れ子は式の入れ子より浅く制限される
+            if let Err(err) = interpreter.eval(&program) {
+                assert_eq!(err.code, Some(ErrorCode::ValueTooDeep), "{}", source);
+            }
+        }
+    }
+}
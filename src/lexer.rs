@@ -1,39 +1,98 @@
+use super::diagnostic::Diagnostic;
+use super::error_code::ErrorCode;
 use super::token;
-use std::{mem, fmt};
+use std::io::{self, BufReader, Bytes, Read};
+use std::{fmt, vec};
+use unicode_xid::UnicodeXID;
 
+/// Turns a stream of characters into tokens.
+///
+/// `Lexer::new` lexes a `&str`. `Lexer::from_reader` pulls characters from an
+/// `io::Read` one at a time, so large files or stdin never have to be buffered
+/// as a whole. As an `Iterator` it yields every token up to and including `Eof`.
 #[derive(Default, Debug, Clone)]
-pub struct Lexer {
-    input: Vec<char>,
-    position: usize,      // 現在検査中のchの位置を指し示す
-    read_position: usize, // 入力における「次の」位置を指し示す
-    ch: Option<char>,
+pub struct Lexer<C = vec::IntoIter<char>> {
+    input: C,
+    ch: Option<char>,   // 現在検査中の文字
+    peek: Option<char>, // 入力における「次の」文字
+    ch_width: usize,    // chが入力で占めるバイト数
+    peek_width: usize,
+    offset: usize, // chのバイト単位の位置 (Spanに使う)
+    errors: Vec<Diagnostic>,
+    finished: bool, // Eofを返し終えたか (Iterator用)
 }
 
 impl Lexer {
     pub fn new(input: &str) -> Self {
+        Self::from_chars(input.chars().collect::<Vec<_>>().into_iter())
+    }
+}
+
+impl<R: Read> Lexer<ReadChars<R>> {
+    pub fn from_reader(reader: R) -> Self {
+        Self::from_chars(ReadChars::new(reader))
+    }
+
+    /// The I/O error that ended the input early, if any.
+    pub fn io_error(&self) -> Option<&io::Error> {
+        self.input.error.as_ref()
+    }
+}
+
+/// Characters for the lexer, each with the number of input bytes it took.
+/// The widths are what keep spans byte-accurate when invalid input is
+/// replaced by U+FFFD.
+pub trait CharSource {
+    fn next_char(&mut self) -> Option<(char, usize)>;
+}
+
+impl CharSource for vec::IntoIter<char> {
+    fn next_char(&mut self) -> Option<(char, usize)> {
+        self.next().map(|ch| (ch, ch.len_utf8()))
+    }
+}
+
+impl<C: CharSource> Lexer<C> {
+    pub fn from_chars(mut input: C) -> Self {
+        let (peek, peek_width) = input.next_char().unzip();
         let mut lexer = Self {
-            input: input.chars().collect(),
-            ..Default::default()
+            input,
+            ch: None,
+            peek,
+            ch_width: 0,
+            peek_width: peek_width.unwrap_or(0),
+            offset: 0,
+            errors: Vec::new(),
+            finished: false,
         };
-        lexer.read_char(); // position, read_position, chの初期化
+        lexer.read_char(); // chの初期化
         lexer
     }
 
+    /// Errors for the Illegal tokens produced so far.
+    pub fn errors(&self) -> Vec<Diagnostic> {
+        self.errors.clone()
+    }
+
     fn read_char(&mut self) {
-        self.ch = self.input.get(self.read_position).cloned();
-        self.position = self.read_position;
-        self.read_position += 1;
+        self.offset += self.ch_width;
+        self.ch = self.peek.take();
+        self.ch_width = self.peek_width;
+        let (peek, peek_width) = self.input.next_char().unzip();
+        self.peek = peek;
+        self.peek_width = peek_width.unwrap_or(0);
     }
 
     pub fn next_token(&mut self) -> token::Token {
         use token::TokenType::*;
         use token::*;
 
-        self.skip_white_space();
+        self.skip_trivia();
+        let start = self.offset;
 
         // TODO: early returnのための対応をスマートにする
         let mut ret = false;
-        let tok = match self.ch {
+        let mut tok = match self.ch {
             Some('=') => {
                 if let Some('=') = self.peek_char() {
                     self.read_char();
@@ -43,11 +102,19 @@ impl Lexer {
                 }
             }
             Some(';') => Token::new_token_from_char(Semicolon, self.ch),
+            Some(':') => Token::new_token_from_char(Colon, self.ch),
             Some('(') => Token::new_token_from_char(Lparen, self.ch),
             Some(')') => Token::new_token_from_char(Rparen, self.ch),
             Some(',') => Token::new_token_from_char(Comma, self.ch),
             Some('+') => Token::new_token_from_char(Plus, self.ch),
-            Some('-') => Token::new_token_from_char(Minus, self.ch),
+            Some('-') => {
+                if let Some('>') = self.peek_char() {
+                    self.read_char();
+                    Token::new_token_from_str(Arrow, "->")
+                } else {
+                    Token::new_token_from_char(Minus, self.ch)
+                }
+            }
             Some('!') => {
                 if let Some('=') = self.peek_char() {
                     self.read_char();
@@ -62,65 +129,267 @@ impl Lexer {
             Some('>') => Token::new_token_from_char(Gt, self.ch),
             Some('{') => Token::new_token_from_char(Lbrace, self.ch),
             Some('}') => Token::new_token_from_char(Rbrace, self.ch),
+            Some('[') => Token::new_token_from_char(Lbracket, self.ch),
+            Some(']') => Token::new_token_from_char(Rbracket, self.ch),
+            Some('"') => {
+                ret = true;
+                let (token_type, literal) = self.read_string();
+                Token::new_token_from_str(token_type, &literal)
+            }
             None => Token::new_token_from_char(Eof, self.ch),
             _ => {
-                if self.ch.map_or(false, is_letter) {
+                if self.ch.is_some_and(is_identifer_start) {
                     ret = true;
                     let literal = self.read_identifer();
                     Token::new_token_from_str(TokenType::lookup_iden(&literal), &literal)
-                } else if self.ch.as_ref().map_or(false, char::is_ascii_digit) {
+                } else if self.ch.as_ref().is_some_and(char::is_ascii_digit)
+                    || (self.ch == Some('.')
+                        && self.peek_char().is_some_and(|c| c.is_ascii_digit()))
+                {
                     ret = true;
-                    Token::new_token_from_str(Int, &self.read_number())
+                    let (token_type, literal) = self.read_number();
+                    Token::new_token_from_str(token_type, &literal)
                 } else {
                     Token::new_token_from_char(Illegal, self.ch)
                 }
             }
         };
 
-        if ret {
-            return tok;
-        };
-        self.read_char();
+        if !ret {
+            self.read_char();
+        }
+        tok.span = Span::new(start, self.offset);
+        if tok.token_type == Illegal {
+            self.errors
+                .push(illegal_token_error(&tok.literal, tok.span));
+        }
         tok
     }
 
-    fn skip_white_space(&mut self) {
-        while self.ch.as_ref().map_or(false, char::is_ascii_whitespace) {
-            self.read_char();
+    // 空白と // から行末までのコメントを読み飛ばす
+    fn skip_trivia(&mut self) {
+        loop {
+            match self.ch {
+                Some(c) if c.is_whitespace() => self.read_char(),
+                Some('/') if self.peek_char() == Some('/') => {
+                    while self.ch.is_some_and(|c| c != '\n') {
+                        self.read_char();
+                    }
+                }
+                _ => return,
+            }
         }
     }
 
     fn read_identifer(&mut self) -> String {
-        let position = self.position;
-        while self.ch.map_or(false, is_letter) {
+        let mut literal = String::new();
+        while let Some(ch) = self.ch.filter(|c| is_identifer_continue(*c)) {
+            literal.push(ch);
             self.read_char();
         }
-        self.input[position..self.position].into_iter().collect()
+        literal
     }
 
-    fn read_number(&mut self) -> String {
-        let position = self.position;
-        while self.ch.as_ref().map_or(false, char::is_ascii_digit){
+    // 基数の接頭辞 (0x, 0o, 0b) や区切りの`_`も含めて読む。数値として正しいかはパーサーが検査する
+    // 10進数は小数部 (3.14, .5) と指数部 (1e-9) を持つことができ、その場合はFloatになる
+    fn read_number(&mut self) -> (token::TokenType, String) {
+        let mut token_type = token::TokenType::Int;
+        let mut literal = String::new();
+        let radix_prefix =
+            self.ch == Some('0') && matches!(self.peek_char(), Some('x') | Some('o') | Some('b'));
+
+        if !radix_prefix {
+            self.read_digits(&mut literal);
+            if self.ch == Some('.') && self.peek_char().is_some_and(|c| c.is_ascii_digit()) {
+                token_type = token::TokenType::Float;
+                literal.push('.');
+                self.read_char();
+                self.read_digits(&mut literal);
+            }
+            if let Some(e) = self.ch.filter(|c| *c == 'e' || *c == 'E') {
+                token_type = token::TokenType::Float;
+                literal.push(e);
+                self.read_char();
+                if let Some(sign) = self.ch.filter(|c| *c == '+' || *c == '-') {
+                    literal.push(sign);
+                    self.read_char();
+                }
+                self.read_digits(&mut literal);
+            }
+        }
+
+        // 続く英数字も同じトークンに含め、不正な数字として報告できるようにする
+        while let Some(ch) = self.ch.filter(|c| c.is_ascii_alphanumeric() || *c == '_') {
+            literal.push(ch);
+            self.read_char();
+        }
+        (token_type, literal)
+    }
+
+    // 閉じる `"` までを引用符ごと読む。エスケープの検査と解釈はパーサーがする
+    // 閉じないまま入力が終わったら、そこまでを Illegal にする
+    fn read_string(&mut self) -> (token::TokenType, String) {
+        let mut literal = String::from('"');
+        self.read_char();
+        while let Some(ch) = self.ch {
+            literal.push(ch);
+            self.read_char();
+            match ch {
+                '"' => return (token::TokenType::String, literal),
+                '\\' => {
+                    if let Some(escaped) = self.ch {
+                        literal.push(escaped);
+                        self.read_char();
+                    }
+                }
+                _ => {}
+            }
+        }
+        (token::TokenType::Illegal, literal)
+    }
+
+    fn read_digits(&mut self, literal: &mut String) {
+        while let Some(ch) = self.ch.filter(|c| c.is_ascii_digit() || *c == '_') {
+            literal.push(ch);
             self.read_char();
         }
-        self.input[position..self.position].into_iter().collect()
     }
 
     fn peek_char(&self) -> Option<char> {
-        self.input.get(self.read_position).cloned()
+        self.peek
     }
 }
 
-impl fmt::Display for Lexer {
+impl<C: CharSource> Iterator for Lexer<C> {
+    type Item = token::Token;
+
+    fn next(&mut self) -> Option<token::Token> {
+        if self.finished {
+            return None;
+        }
+        let tok = self.next_token();
+        self.finished = tok.token_type == token::TokenType::Eof;
+        Some(tok)
+    }
+}
+
+impl<C> fmt::Display for Lexer<C> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "input: {}\ncurrent: {}\n", &mut self.input.iter().collect::<String>(), self.ch.unwrap().to_string())
+        writeln!(
+            f,
+            "offset: {}\ncurrent: {}",
+            self.offset,
+            self.ch.map_or(String::new(), String::from)
+        )
+    }
+}
+
+/// Decodes UTF-8 from an `io::Read` one character at a time.
+///
+/// Each invalid sequence, up to the byte that breaks it, becomes one U+FFFD,
+/// which the lexer reports as an Illegal token. The breaking byte starts the
+/// next character, as in `String::from_utf8_lossy`. An I/O error ends the
+/// input and is kept for `Lexer::io_error`.
+#[derive(Debug)]
+pub struct ReadChars<R> {
+    bytes: Bytes<BufReader<R>>,
+    pending: Option<u8>, // 不正な列を途切れさせたバイト (次の文字の先頭)
+    error: Option<io::Error>,
+}
+
+impl<R: Read> ReadChars<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            bytes: BufReader::new(reader).bytes(),
+            pending: None,
+            error: None,
+        }
+    }
+
+    fn next_byte(&mut self) -> Option<u8> {
+        if let Some(b) = self.pending.take() {
+            return Some(b);
+        }
+        match self.bytes.next()? {
+            Ok(b) => Some(b),
+            Err(err) => {
+                self.error = Some(err);
+                None
+            }
+        }
+    }
+}
+
+impl<R: Read> CharSource for ReadChars<R> {
+    fn next_char(&mut self) -> Option<(char, usize)> {
+        if self.error.is_some() {
+            return None;
+        }
+        let first = self.next_byte()?;
+        // 2バイト目の範囲は冗長な表現、サロゲート、U+10FFFより上を除く
+        let (width, second) = match first {
+            0x00..=0x7f => return Some((char::from(first), 1)),
+            0xc2..=0xdf => (2, 0x80..=0xbf),
+            0xe0 => (3, 0xa0..=0xbf),
+            0xed => (3, 0x80..=0x9f),
+            0xe1..=0xef => (3, 0x80..=0xbf),
+            0xf0 => (4, 0x90..=0xbf),
+            0xf4 => (4, 0x80..=0x8f),
+            0xf1..=0xf3 => (4, 0x80..=0xbf),
+            _ => return Some((char::REPLACEMENT_CHARACTER, 1)),
+        };
+        let mut buf = [first, 0, 0, 0];
+        for (i, slot) in buf.iter_mut().enumerate().take(width).skip(1) {
+            let valid = if i == 1 { second.clone() } else { 0x80..=0xbf };
+            match self.next_byte() {
+                Some(b) if valid.contains(&b) => *slot = b,
+                Some(b) => {
+                    self.pending = Some(b);
+                    return Some((char::REPLACEMENT_CHARACTER, i));
+                }
+                None => return Some((char::REPLACEMENT_CHARACTER, i)),
+            }
+        }
+        let ch = std::str::from_utf8(&buf[..width])
+            .ok()
+            .and_then(|s| s.chars().next())
+            .unwrap_or(char::REPLACEMENT_CHARACTER);
+        Some((ch, width))
     }
 }
 
-fn is_letter(ch: char) -> bool {
-    ch.is_ascii_alphabetic() || ch == '_'
+impl<R: Read> Iterator for ReadChars<R> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        self.next_char().map(|(ch, _)| ch)
+    }
 }
 
+/// The error for an Illegal token: an unknown character, or a string literal
+/// that runs to the end of the input.
+pub(crate) fn illegal_token_error(literal: &str, span: token::Span) -> Diagnostic {
+    if literal.starts_with('"') {
+        Diagnostic::error(
+            "unterminated string literal",
+            token::Span::new(span.start, span.start + 1),
+        )
+        .with_code(ErrorCode::UnterminatedString)
+        .with_help("add a closing `\"`")
+    } else {
+        Diagnostic::error(format!("unknown character `{}`", literal), span)
+            .with_code(ErrorCode::UnknownCharacter)
+    }
+}
+
+/// Identifiers follow Unicode UAX #31: XID_Start or `_`, then XID_Continue.
+pub(crate) fn is_identifer_start(ch: char) -> bool {
+    ch.is_xid_start() || ch == '_'
+}
+
+pub(crate) fn is_identifer_continue(ch: char) -> bool {
+    ch.is_xid_continue()
+}
 
 #[cfg(test)]
 mod tests {
@@ -265,4 +534,300 @@ if (5 < 10) {
             );
         }
     }
+
+    #[test]
+    fn token_span() {
+        let input = "let five = 5;\n10 != 9;";
+        let tests = [
+            (0, 3),
+            (4, 8),
+            (9, 10),
+            (11, 12),
+            (12, 13),
+            (14, 16),
+            (17, 19),
+            (20, 21),
+            (21, 22),
+            (22, 22),
+        ];
+
+        let mut l = Lexer::new(input);
+        for (i, (start, end)) in tests.iter().enumerate() {
+            let tok = l.next_token();
+            assert_eq!(
+                tok.span,
+                token::Span::new(*start, *end),
+                "tests[{}] - span wrong",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn illegal_token_error() {
+        let mut l = Lexer::new("let x = 5 @ 3;");
+        while l.next_token().token_type != token::TokenType::Eof {}
+
+        let errors = l.errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, Some(ErrorCode::UnknownCharacter));
+        assert_eq!(errors[0].message, "unknown character `@`");
+        assert_eq!(errors[0].span, token::Span::new(10, 11));
+    }
+
+    #[test]
+    fn unicode_identifer() {
+        use super::token::TokenType::*;
+
+        let input = "let 変数 = x1 + café_2;\u{3000}Ñu;";
+        let tests = [
+            (Let, "let", 0, 3),
+            (Ident, "変数", 4, 10),
+            (Assign, "=", 11, 12),
+            (Ident, "x1", 13, 15),
+            (Plus, "+", 16, 17),
+            (Ident, "café_2", 18, 25),
+            (Semicolon, ";", 25, 26),
+            (Ident, "Ñu", 29, 32),
+            (Semicolon, ";", 32, 33),
+            (Eof, "", 33, 33),
+        ];
+
+        let mut l = Lexer::new(input);
+        for (i, (token_type, literal, start, end)) in tests.iter().enumerate() {
+            let tok = l.next_token();
+            assert_eq!(
+                tok.token_type, *token_type,
+                "tests[{}] - tokentype wrong",
+                i
+            );
+            assert_eq!(tok.literal, *literal, "tests[{}] - literal wrong", i);
+            assert_eq!(
+                tok.span,
+                token::Span::new(*start, *end),
+                "tests[{}] - span wrong",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn integer_literal() {
+        let input = "0x1F 0o17 0b1010 1_000_000 0b102 12ab 0x";
+        let tests = ["0x1F", "0o17", "0b1010", "1_000_000", "0b102", "12ab", "0x"];
+
+        let mut l = Lexer::new(input);
+        for (i, literal) in tests.iter().enumerate() {
+            let tok = l.next_token();
+            assert_eq!(
+                tok.token_type,
+                token::TokenType::Int,
+                "tests[{}] - tokentype wrong",
+                i
+            );
+            assert_eq!(tok.literal, *literal, "tests[{}] - literal wrong", i);
+        }
+    }
+
+    #[test]
+    fn float_literal() {
+        use super::token::TokenType::*;
+
+        let input = "3.14 1e-9 .5 2.5E+3 1_000.000_1 1. 0.x 1e";
+        let tests = [
+            (Float, "3.14"),
+            (Float, "1e-9"),
+            (Float, ".5"),
+            (Float, "2.5E+3"),
+            (Float, "1_000.000_1"),
+            (Int, "1"),
+            (Illegal, "."),
+            (Int, "0"),
+            (Illegal, "."),
+            (Ident, "x"),
+            (Float, "1e"),
+            (Eof, ""),
+        ];
+
+        let mut l = Lexer::new(input);
+        for (i, (token_type, literal)) in tests.iter().enumerate() {
+            let tok = l.next_token();
+            assert_eq!(
+                tok.token_type, *token_type,
+                "tests[{}] - tokentype wrong",
+                i
+            );
+            assert_eq!(tok.literal, *literal, "tests[{}] - literal wrong", i);
+        }
+    }
+
+    #[test]
+    fn comments() {
+        use super::token::TokenType::*;
+
+        let input = "// header\nlet x = 1; // trailing\n10 / 2 //";
+        let tokens: Vec<(token::TokenType, token::Span)> = Lexer::new(input)
+            .map(|tok| (tok.token_type, tok.span))
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                (Let, token::Span::new(10, 13)),
+                (Ident, token::Span::new(14, 15)),
+                (Assign, token::Span::new(16, 17)),
+                (Int, token::Span::new(18, 19)),
+                (Semicolon, token::Span::new(19, 20)),
+                (Int, token::Span::new(33, 35)),
+                (Slash, token::Span::new(36, 37)),
+                (Int, token::Span::new(38, 39)),
+                (Eof, token::Span::new(42, 42)),
+            ]
+        );
+    }
+
+    #[test]
+    fn type_annotation() {
+        use super::token::TokenType::{
+            Arrow, Colon, Eof, Function, Ident, Int, Lbrace, Lparen, Minus, Rbrace, Rparen,
+        };
+
+        let tokens: Vec<(token::TokenType, String)> = Lexer::new("fn(a: int) -> bool { a - -1 }")
+            .map(|tok| (tok.token_type, tok.literal))
+            .collect();
+        let expected = [
+            (Function, "fn"),
+            (Lparen, "("),
+            (Ident, "a"),
+            (Colon, ":"),
+            (Ident, "int"),
+            (Rparen, ")"),
+            (Arrow, "->"),
+            (Ident, "bool"),
+            (Lbrace, "{"),
+            (Ident, "a"),
+            (Minus, "-"),
+            (Minus, "-"),
+            (Int, "1"),
+            (Rbrace, "}"),
+            (Eof, ""),
+        ];
+        let expected: Vec<(token::TokenType, String)> = expected
+            .iter()
+            .map(|(token_type, literal)| (*token_type, String::from(*literal)))
+            .collect();
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn strings_and_brackets() {
+        use super::token::TokenType::{
+            Colon, Comma, Eof, Ident, Lbrace, Lbracket, Rbrace, Rbracket,
+        };
+
+        let input = r#"["a", "\"b\\", ""]{"é😀\q": x}["#;
+        let tokens: Vec<(token::TokenType, String)> = Lexer::new(input)
+            .map(|tok| (tok.token_type, tok.literal))
+            .collect();
+        let string = token::TokenType::String;
+        let expected = [
+            (Lbracket, "["),
+            (string, r#""a""#),
+            (Comma, ","),
+            (string, r#""\"b\\""#),
+            (Comma, ","),
+            (string, r#""""#),
+            (Rbracket, "]"),
+            (Lbrace, "{"),
+            (string, r#""é😀\q""#),
+            (Colon, ":"),
+            (Ident, "x"),
+            (Rbrace, "}"),
+            (Lbracket, "["),
+            (Eof, ""),
+        ];
+        let expected: Vec<(token::TokenType, String)> = expected
+            .iter()
+            .map(|(token_type, literal)| (*token_type, String::from(*literal)))
+            .collect();
+        assert_eq!(tokens, expected);
+    }
+
+    #[test]
+    fn unterminated_string() {
+        let mut l = Lexer::new("x + \"abc\\\"\n");
+        let tok = l.nth(2).unwrap();
+        assert_eq!(tok.token_type, token::TokenType::Illegal);
+        assert_eq!(tok.literal, "\"abc\\\"\n");
+        while l.next_token().token_type != token::TokenType::Eof {}
+
+        let errors = l.errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, Some(ErrorCode::UnterminatedString));
+        assert_eq!(errors[0].message, "unterminated string literal");
+        assert_eq!(errors[0].span, token::Span::new(4, 5));
+    }
+
+    #[test]
+    fn iterator() {
+        use super::token::TokenType::*;
+
+        let types: Vec<token::TokenType> =
+            Lexer::new("let x = 5;").map(|tok| tok.token_type).collect();
+        assert_eq!(types, vec![Let, Ident, Assign, Int, Semicolon, Eof]);
+    }
+
+    #[test]
+    fn from_reader() {
+        let input = "let 変数 = x1 + café_2;\u{3000}Ñu @;";
+        let expected: Vec<token::Token> = Lexer::new(input).collect();
+        let mut l = Lexer::from_reader(input.as_bytes());
+        let tokens: Vec<token::Token> = l.by_ref().collect();
+
+        assert_eq!(tokens.len(), expected.len());
+        for (tok, expected) in tokens.iter().zip(expected.iter()) {
+            assert_eq!(tok.token_type, expected.token_type);
+            assert_eq!(tok.literal, expected.literal);
+            assert_eq!(tok.span, expected.span);
+        }
+        assert_eq!(l.errors().len(), 1);
+        assert!(l.io_error().is_none());
+    }
+
+    #[test]
+    fn from_reader_invalid_utf8() {
+        let input: &[u8] = b"x \xff y";
+        let tokens: Vec<token::Token> = Lexer::from_reader(input).collect();
+
+        assert_eq!(tokens[1].token_type, token::TokenType::Illegal);
+        assert_eq!(tokens[1].literal, "\u{fffd}");
+        assert_eq!(tokens[2].literal, "y");
+    }
+
+    #[test]
+    fn from_reader_keeps_bytes_after_invalid_sequences() {
+        let input: &[u8] = b"a \xc3(b) \xe2\x82c \xf0\x9f\x98 d \x80\xc0e";
+        let tokens: Vec<(String, token::Span)> = Lexer::from_reader(input)
+            .map(|tok| (tok.literal, tok.span))
+            .collect();
+        let expected = [
+            ("a", 0, 1),
+            ("\u{fffd}", 2, 3),
+            ("(", 3, 4),
+            ("b", 4, 5),
+            (")", 5, 6),
+            ("\u{fffd}", 7, 9),
+            ("c", 9, 10),
+            ("\u{fffd}", 11, 14),
+            ("d", 15, 16),
+            ("\u{fffd}", 17, 18),
+            ("\u{fffd}", 18, 19),
+            ("e", 19, 20),
+            ("", 20, 20),
+        ];
+        let expected: Vec<(String, token::Span)> = expected
+            .iter()
+            .map(|(literal, start, end)| (literal.to_string(), token::Span::new(*start, *end)))
+            .collect();
+        assert_eq!(tokens, expected);
+    }
 }
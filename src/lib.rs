@@ -0,0 +1,23 @@
+pub mod arena;
+pub mod ast;
+pub mod byte_lexer;
+pub mod cst;
+pub mod diagnostic;
+pub mod error_code;
+pub mod evaluator;
+pub mod formatter;
+pub mod highlight;
+pub mod incremental;
+pub mod interpreter;
+pub mod lexer;
+pub mod lint;
+pub mod lsp;
+pub mod native;
+pub mod object;
+pub mod parser;
+pub mod repl;
+pub mod resolver;
+pub mod serialize;
+pub mod token;
+pub mod typecheck;
+pub mod visit;
@@ -0,0 +1,580 @@
+//! Style and correctness checks for programs that parse and run fine.
+//!
+//! Each `Rule` has a kebab-case name and a `Level`. All rules warn by default;
+//! a `Config`, usually read from a JSON file, can allow or deny them:
+//!
+//! ```text
+//! {"rules": {"empty-block": "allow", "self-comparison": "deny"}}
+//! ```
+//!
+//! A comment `// lint: allow(<rule>, ...)` turns rules off for a single line:
+//! the line it ends, or the next one when the comment is alone on its line.
+
+use super::arena::Ast;
+use super::ast::{
+    BlockStatement, ExpressionType, FunctionLiteral, IfExpression, InfixExpression, Node,
+    PrefixExpression, Program, StatementType,
+};
+use super::diagnostic::{Diagnostic, Severity};
+use super::error_code::ErrorCode;
+use super::resolver::{self, BindingKind};
+use super::token::Span;
+use super::visit::{self, Visitor};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    UnreachableCode,
+    ConstantCondition,
+    SelfComparison,
+    EmptyBlock,
+    DoubleNegation,
+    UnusedParameter,
+}
+
+impl Rule {
+    pub const ALL: &'static [Rule] = &[
+        Rule::UnreachableCode,
+        Rule::ConstantCondition,
+        Rule::SelfComparison,
+        Rule::EmptyBlock,
+        Rule::DoubleNegation,
+        Rule::UnusedParameter,
+    ];
+
+    /// The name used in config files and suppression comments.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rule::UnreachableCode => "unreachable-code",
+            Rule::ConstantCondition => "constant-condition",
+            Rule::SelfComparison => "self-comparison",
+            Rule::EmptyBlock => "empty-block",
+            Rule::DoubleNegation => "double-negation",
+            Rule::UnusedParameter => "unused-parameter",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Rule> {
+        Rule::ALL.iter().find(|rule| rule.name() == name).cloned()
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Rule::UnreachableCode => ErrorCode::UnreachableCode,
+            Rule::ConstantCondition => ErrorCode::ConstantCondition,
+            Rule::SelfComparison => ErrorCode::SelfComparison,
+            Rule::EmptyBlock => ErrorCode::EmptyBlock,
+            Rule::DoubleNegation => ErrorCode::DoubleNegation,
+            Rule::UnusedParameter => ErrorCode::UnusedParameter,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// The rule is not checked.
+    Allow,
+    /// Violations are reported as warnings.
+    Warn,
+    /// Violations are reported as errors.
+    Deny,
+}
+
+impl Level {
+    pub fn from_name(name: &str) -> Option<Level> {
+        match name {
+            "allow" => Some(Level::Allow),
+            "warn" => Some(Level::Warn),
+            "deny" => Some(Level::Deny),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    levels: HashMap<Rule, Level>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            levels: Rule::ALL.iter().map(|rule| (*rule, Level::Warn)).collect(),
+        }
+    }
+}
+
+impl Config {
+    pub fn with_level(mut self, rule: Rule, level: Level) -> Self {
+        self.levels.insert(rule, level);
+        self
+    }
+
+    pub fn level(&self, rule: Rule) -> Level {
+        self.levels[&rule]
+    }
+
+    /// Read a config of the form `{"rules": {"<rule>": "allow|warn|deny"}}`.
+    /// Rules that are not listed keep warning.
+    pub fn from_json(text: &str) -> Result<Config, Diagnostic> {
+        let value: Value = serde_json::from_str(text)
+            .map_err(|err| Diagnostic::error(format!("invalid JSON: {}", err), Span::default()))?;
+        let fields = match &value {
+            Value::Object(fields) => fields,
+            _ => return Err(config_error("config: expected an object")),
+        };
+        let mut config = Config::default();
+        for (key, value) in fields {
+            if key != "rules" {
+                return Err(config_error(format!("config: unknown field `{}`", key)));
+            }
+            let rules = match value {
+                Value::Object(rules) => rules,
+                _ => return Err(config_error("config.rules: expected an object")),
+            };
+            for (name, level) in rules {
+                let rule = Rule::from_name(name).ok_or_else(|| {
+                    config_error(format!("config.rules: unknown rule `{}`", name))
+                })?;
+                let level = level.as_str().and_then(Level::from_name).ok_or_else(|| {
+                    config_error(format!(
+                        "config.rules.{}: expected \"allow\", \"warn\" or \"deny\"",
+                        name
+                    ))
+                })?;
+                config = config.with_level(rule, level);
+            }
+        }
+        Ok(config)
+    }
+}
+
+fn config_error(message: impl Into<String>) -> Diagnostic {
+    Diagnostic::error(message, Span::default())
+}
+
+/// Check a parsed program. `source` is the text it was parsed from, which is
+/// searched for suppression comments. Diagnostics are in source order.
+pub fn lint(program: &Program, source: &str, config: &Config) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        findings: Vec::new(),
+    };
+    linter.visit_program(program);
+    linter.unused_parameters(program);
+
+    let (suppressed, mut diagnostics) = suppressions(source);
+    for (rule, diagnostic) in linter.findings {
+        let line = line_of(source, diagnostic.span.start);
+        let allowed = suppressed
+            .get(&line)
+            .is_some_and(|rules| rules.contains(&rule));
+        if allowed {
+            continue;
+        }
+        let diagnostic = match config.level(rule) {
+            Level::Allow => continue,
+            Level::Warn => diagnostic,
+            Level::Deny => Diagnostic {
+                severity: Severity::Error,
+                ..diagnostic
+            },
+        };
+        diagnostics.push(diagnostic.with_note(format!(
+            "`{}` is enabled; disable it here with `// lint: allow({})`",
+            rule.name(),
+            rule.name()
+        )));
+    }
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+    diagnostics
+}
+
+struct Linter {
+    findings: Vec<(Rule, Diagnostic)>,
+}
+
+impl Linter {
+    fn report(&mut self, rule: Rule, diagnostic: Diagnostic) {
+        self.findings
+            .push((rule, diagnostic.with_code(rule.code())));
+    }
+
+    // return より後の文
+    fn unreachable(&mut self, statements: &[StatementType]) {
+        let position = statements
+            .iter()
+            .position(|statement| matches!(statement, StatementType::ReturnStatement(_)));
+        if let Some(position) = position {
+            if let (Some(first), Some(last)) = (statements.get(position + 1), statements.last()) {
+                self.report(
+                    Rule::UnreachableCode,
+                    Diagnostic::warning("unreachable statement", first.span().to(last.span()))
+                        .with_note("nothing after a `return` is evaluated"),
+                );
+            }
+        }
+    }
+
+    fn empty_block(&mut self, block: &BlockStatement, message: &str) {
+        if block.statements.is_empty() {
+            self.report(Rule::EmptyBlock, Diagnostic::warning(message, block.span));
+        }
+    }
+
+    // 名前の解決は resolver に任せる
+    fn unused_parameters(&mut self, program: &Program) {
+        let ast = Ast::from_program(program);
+        let resolution = resolver::resolve(&ast, &[]);
+        let used: HashSet<_> = resolution
+            .bindings
+            .iter()
+            .map(|(_, binding)| *binding)
+            .collect();
+        for (id, kind) in resolution.definitions.iter() {
+            let name = ast.string(id);
+            if matches!(kind, BindingKind::Parameter(_))
+                && !used.contains(&id)
+                && !name.starts_with('_')
+            {
+                self.report(
+                    Rule::UnusedParameter,
+                    Diagnostic::warning(format!("unused parameter `{}`", name), ast.span(id))
+                        .with_help(format!(
+                            "if this is intentional, prefix it with an underscore: `_{}`",
+                            name
+                        )),
+                );
+            }
+        }
+    }
+}
+
+impl Visitor for Linter {
+    fn visit_program(&mut self, program: &Program) {
+        self.unreachable(program);
+        visit::walk_program(self, program);
+    }
+
+    fn visit_block_statement(&mut self, block: &BlockStatement) {
+        self.unreachable(&block.statements);
+        visit::walk_block_statement(self, block);
+    }
+
+    fn visit_if_expression(&mut self, expression: &IfExpression) {
+        if let Some(value) = constant_truthiness(&expression.condition) {
+            let mut diagnostic = Diagnostic::warning(
+                format!("this condition is always {}", value),
+                expression.condition.span(),
+            );
+            if value && expression.alternative.is_some() {
+                diagnostic = diagnostic.with_note("the `else` block is never evaluated");
+            } else if !value {
+                diagnostic = diagnostic.with_note("the block is never evaluated");
+            }
+            self.report(Rule::ConstantCondition, diagnostic);
+        }
+        self.empty_block(&expression.consequence, "empty block");
+        if let Some(alternative) = &expression.alternative {
+            self.empty_block(alternative, "empty `else` block");
+        }
+        visit::walk_if_expression(self, expression);
+    }
+
+    fn visit_function_literal(&mut self, literal: &FunctionLiteral) {
+        self.empty_block(&literal.body, "empty function body");
+        visit::walk_function_literal(self, literal);
+    }
+
+    fn visit_infix_expression(&mut self, expression: &InfixExpression) {
+        if let (ExpressionType::Identifer(left), ExpressionType::Identifer(right)) =
+            (&*expression.left, &*expression.right)
+        {
+            let always = match expression.operator.as_str() {
+                "==" => Some(true),
+                "!=" | "<" | ">" => Some(false),
+                _ => None,
+            };
+            if let (Some(always), true) = (always, left.value == right.value) {
+                self.report(
+                    Rule::SelfComparison,
+                    Diagnostic::warning(
+                        format!("`{}` is compared with itself", left.value),
+                        expression.span(),
+                    )
+                    .with_note(format!("this is always {}", always)),
+                );
+            }
+        }
+        visit::walk_infix_expression(self, expression);
+    }
+
+    fn visit_prefix_expression(&mut self, expression: &PrefixExpression) {
+        if let ExpressionType::PrefixExpression(inner) = &*expression.right {
+            if expression.operator == "!" && inner.operator == "!" {
+                self.report(
+                    Rule::DoubleNegation,
+                    Diagnostic::warning("redundant double negation", expression.span()).with_help(
+                        "conditions already test truthiness; use the value itself unless a \
+                         `bool` is needed",
+                    ),
+                );
+            }
+        }
+        visit::walk_prefix_expression(self, expression);
+    }
+}
+
+// null と false 以外はすべて真
+fn constant_truthiness(expression: &ExpressionType) -> Option<bool> {
+    match expression {
+        ExpressionType::Boolean(boolean) => Some(boolean.value),
+        ExpressionType::IntegerLiteral(_)
+        | ExpressionType::BigIntegerLiteral(_)
+        | ExpressionType::FloatLiteral(_) => Some(true),
+        ExpressionType::PrefixExpression(prefix) if prefix.operator == "!" => {
+            constant_truthiness(&prefix.right).map(|value| !value)
+        }
+        _ => None,
+    }
+}
+
+// 0 から数えた行番号
+fn line_of(source: &str, offset: usize) -> usize {
+    source[..offset.min(source.len())].matches('\n').count()
+}
+
+// 行ごとの抑制されたルールと、読めなかったコメントの警告
+fn suppressions(source: &str) -> (HashMap<usize, Vec<Rule>>, Vec<Diagnostic>) {
+    let mut suppressed: HashMap<usize, Vec<Rule>> = HashMap::new();
+    let mut diagnostics = Vec::new();
+    let mut offset = 0;
+    for (line, text) in source.split('\n').enumerate() {
+        let start = offset;
+        offset += text.len() + 1;
+        // 文字列リテラルがないので // は必ずコメントの始まり
+        let comment_start = match text.find("//") {
+            Some(position) => position,
+            None => continue,
+        };
+        let comment = text[comment_start + 2..].trim();
+        let rest = match comment.strip_prefix("lint:") {
+            Some(rest) => rest.trim(),
+            None => continue,
+        };
+        let span = Span::new(start + comment_start, start + text.trim_end().len());
+        let names = match rest
+            .strip_prefix("allow(")
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            Some(names) => names,
+            None => {
+                diagnostics.push(
+                    Diagnostic::warning("malformed lint comment", span)
+                        .with_help("write `// lint: allow(<rule>, ...)`"),
+                );
+                continue;
+            }
+        };
+        let target = if text[..comment_start].trim().is_empty() {
+            line + 1
+        } else {
+            line
+        };
+        for name in names.split(',').map(str::trim) {
+            match Rule::from_name(name) {
+                Some(rule) => suppressed.entry(target).or_default().push(rule),
+                None => diagnostics.push(
+                    Diagnostic::warning(format!("unknown lint rule `{}`", name), span).with_note(
+                        format!(
+                            "the rules are {}",
+                            Rule::ALL
+                                .iter()
+                                .map(|rule| format!("`{}`", rule.name()))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ),
+                    ),
+                ),
+            }
+        }
+    }
+    (suppressed, diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::lexer::Lexer;
+    use super::super::parser::Parser;
+    use super::*;
+
+    fn lint_with(input: &str, config: &Config) -> Vec<Diagnostic> {
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
+        assert_eq!(parser.errors(), vec![], "input: {}", input);
+        lint(&program, input, config)
+    }
+
+    fn findings(input: &str) -> Vec<(Option<ErrorCode>, String, Span)> {
+        lint_with(input, &Config::default())
+            .into_iter()
+            .map(|diagnostic| (diagnostic.code, diagnostic.message, diagnostic.span))
+            .collect()
+    }
+
+    #[test]
+    fn clean_programs() {
+        let inputs = [
+            "let fact = fn(n) { if (n < 2) { return 1; } n * fact(n - 1) }; fact(5)",
+            "let f = fn(_x) { 0 }; f(1)",
+            "let x = 1; if (x == 1) { x } else { !x }",
+            "let g = fn(a, b) { a < b }; g(1, 2)",
+        ];
+        for input in inputs.iter() {
+            assert_eq!(findings(input), vec![], "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn rules() {
+        let tests = vec![
+            (
+                "let f = fn() { return 1; 2; 3 }; f()",
+                ErrorCode::UnreachableCode,
+                "unreachable statement",
+                Span::new(25, 29),
+            ),
+            (
+                "return 1; puts(2)",
+                ErrorCode::UnreachableCode,
+                "unreachable statement",
+                Span::new(10, 17),
+            ),
+            (
+                "if (true) { 1 }",
+                ErrorCode::ConstantCondition,
+                "this condition is always true",
+                Span::new(4, 8),
+            ),
+            (
+                "if (!0) { 1 }",
+                ErrorCode::ConstantCondition,
+                "this condition is always false",
+                Span::new(4, 6),
+            ),
+            (
+                "let x = 1; x == x",
+                ErrorCode::SelfComparison,
+                "`x` is compared with itself",
+                Span::new(11, 17),
+            ),
+            (
+                "let x = 1; if (x) { }",
+                ErrorCode::EmptyBlock,
+                "empty block",
+                Span::new(18, 21),
+            ),
+            (
+                "let x = 1; if (x) { 1 } else {}",
+                ErrorCode::EmptyBlock,
+                "empty `else` block",
+                Span::new(29, 31),
+            ),
+            (
+                "let f = fn() {}; f()",
+                ErrorCode::EmptyBlock,
+                "empty function body",
+                Span::new(13, 15),
+            ),
+            (
+                "let x = 1; !!x",
+                ErrorCode::DoubleNegation,
+                "redundant double negation",
+                Span::new(11, 14),
+            ),
+            (
+                "let add = fn(x, y) { x }; add(1, 2)",
+                ErrorCode::UnusedParameter,
+                "unused parameter `y`",
+                Span::new(16, 17),
+            ),
+        ];
+        for (input, code, message, span) in tests {
+            assert_eq!(
+                findings(input),
+                vec![(Some(code), String::from(message), span)],
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn shadowed_parameter_is_unused() {
+        let input = "let f = fn(x) { let g = fn(x) { x }; g(1) }; f(1)";
+        assert_eq!(
+            findings(input),
+            vec![(
+                Some(ErrorCode::UnusedParameter),
+                String::from("unused parameter `x`"),
+                Span::new(11, 12)
+            )]
+        );
+    }
+
+    #[test]
+    fn config() {
+        let input = "let x = 1; if (x) {} x == x";
+        let config =
+            Config::from_json(r#"{"rules": {"empty-block": "allow", "self-comparison": "deny"}}"#)
+                .unwrap();
+        let diagnostics = lint_with(input, &config);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, Some(ErrorCode::SelfComparison));
+        assert_eq!(diagnostics[0].severity, Severity::Error);
+        assert_eq!(config.level(Rule::UnreachableCode), Level::Warn);
+
+        let errors = vec![
+            ("[]", "config: expected an object"),
+            (r#"{"rule": {}}"#, "config: unknown field `rule`"),
+            (
+                r#"{"rules": {"no-loops": "deny"}}"#,
+                "config.rules: unknown rule `no-loops`",
+            ),
+            (
+                r#"{"rules": {"empty-block": "off"}}"#,
+                "config.rules.empty-block: expected \"allow\", \"warn\" or \"deny\"",
+            ),
+        ];
+        for (text, message) in errors {
+            assert_eq!(Config::from_json(text).unwrap_err().message, message);
+        }
+    }
+
+    #[test]
+    fn suppression_comments() {
+        let input = "let x = 1;
+x == x; // lint: allow(self-comparison)
+// lint: allow(empty-block, double-negation)
+if (!!x) {}
+if (x) {}";
+        assert_eq!(
+            findings(input),
+            vec![(
+                Some(ErrorCode::EmptyBlock),
+                String::from("empty block"),
+                Span::new(115, 117)
+            )]
+        );
+
+        let input = "let x = 1; x // lint: allow(loops)\n// lint: deny(empty-block)\nx";
+        let messages: Vec<String> = findings(input)
+            .into_iter()
+            .map(|(_, message, _)| message)
+            .collect();
+        assert_eq!(
+            messages,
+            vec!["unknown lint rule `loops`", "malformed lint comment"]
+        );
+    }
+}
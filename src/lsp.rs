@@ -0,0 +1,853 @@
+//! Language Server Protocol server over stdin and stdout.
+//!
+//! Messages are JSON-RPC 2.0 framed with a `Content-Length` header. Documents
+//! are synchronized in full on every change and analyzed from scratch for each
+//! request, which is fast enough for files of the size Monkey programs have.
+//! Positions are converted between LSP's line and UTF-16 column and the byte
+//! offsets of `Span`.
+//!
+//! `Server::handle` maps one incoming message to the messages to send back,
+//! and `run` only adds the framing, so a session can be tested by feeding it a
+//! scripted transcript.
+
+use super::arena::{Ast, NodeId, NodeKind};
+use super::diagnostic::{Diagnostic, Severity};
+use super::formatter;
+use super::lexer::Lexer;
+use super::resolver::{self, BindingKind, Resolution};
+use super::token::{Span, TokenType};
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, BufRead, Write};
+
+const KEYWORDS: &[&str] = &["fn", "let", "true", "false", "if", "else", "return"];
+
+// JSON-RPC のエラーコード
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+// LSP の列挙値
+const SEVERITY_ERROR: u8 = 1;
+const SEVERITY_WARNING: u8 = 2;
+const SYMBOL_FUNCTION: u8 = 12;
+const SYMBOL_VARIABLE: u8 = 13;
+const COMPLETION_VARIABLE: u8 = 6;
+const COMPLETION_KEYWORD: u8 = 14;
+
+type RequestResult = Result<Value, (i64, String)>;
+
+/// Serve one client until it sends `exit`. Returns the process exit code: 0 if
+/// the client asked for a `shutdown` first, 1 otherwise.
+pub fn run<R: BufRead, W: Write>(mut input: R, mut output: W) -> io::Result<i32> {
+    let mut server = Server::new();
+    while let Some(content) = read_message(&mut input)? {
+        let replies = match serde_json::from_str::<Value>(&content) {
+            Ok(message) if message["method"] == "exit" => {
+                return Ok(if server.shutdown { 0 } else { 1 });
+            }
+            Ok(message) => server.handle(&message),
+            Err(err) => vec![error_response(
+                Value::Null,
+                PARSE_ERROR,
+                format!("invalid JSON: {}", err),
+            )],
+        };
+        for reply in replies {
+            write_message(&mut output, &reply)?;
+        }
+    }
+    // exit の前に入力が終わった
+    Ok(1)
+}
+
+/// Read the content of one message, or `None` at the end of the input.
+pub fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+    })?;
+    let mut content = vec![0; length];
+    input.read_exact(&mut content)?;
+    String::from_utf8(content)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+pub fn write_message<W: Write>(output: &mut W, message: &Value) -> io::Result<()> {
+    let content = message.to_string();
+    write!(
+        output,
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    )?;
+    output.flush()
+}
+
+/// The open documents of one session, keyed by URI.
+#[derive(Debug, Default)]
+pub struct Server {
+    documents: HashMap<String, String>,
+    shutdown: bool,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle a request or notification and return the response and
+    /// notifications to send to the client. `exit` is left to the caller.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = match message["method"].as_str() {
+            Some(method) => method,
+            // クライアントからの応答は使わない
+            None => return Vec::new(),
+        };
+        let params = &message["params"];
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            None => return self.notification(method, params),
+        };
+        let result = if self.shutdown {
+            Err((INVALID_REQUEST, String::from("the server is shutting down")))
+        } else {
+            self.request(method, params)
+        };
+        let response = match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err((code, message)) => error_response(id, code, message),
+        };
+        vec![response]
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.to_string(), text.to_string());
+                vec![self.publish_diagnostics(uri)]
+            }
+            // 同期は常に全文
+            "textDocument/didChange" => {
+                let changes = params["contentChanges"].as_array();
+                match changes.and_then(|changes| changes.last()) {
+                    Some(change) => {
+                        let text = change["text"].as_str().unwrap_or_default();
+                        self.documents.insert(uri.to_string(), text.to_string());
+                        vec![self.publish_diagnostics(uri)]
+                    }
+                    None => Vec::new(),
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                vec![notification(
+                    "textDocument/publishDiagnostics",
+                    json!({"uri": uri, "diagnostics": []}),
+                )]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> RequestResult {
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "hoverProvider": true,
+                    "documentSymbolProvider": true,
+                    "completionProvider": {},
+                    "documentFormattingProvider": true,
+                },
+                "serverInfo": {"name": "ironmonkey", "version": env!("CARGO_PKG_VERSION")},
+            })),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/definition" => self.definition(params),
+            "textDocument/references" => self.references(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/documentSymbol" => self.document_symbols(params),
+            "textDocument/completion" => self.completion(params),
+            "textDocument/formatting" => self.formatting(params),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method {}", method))),
+        }
+    }
+
+    fn document<'a>(&'a self, params: &'a Value) -> Result<(&'a str, &'a str), (i64, String)> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .ok_or_else(|| (INVALID_PARAMS, String::from("missing textDocument.uri")))?;
+        match self.documents.get(uri) {
+            Some(text) => Ok((uri, text)),
+            None => Err((INVALID_PARAMS, format!("{} is not open", uri))),
+        }
+    }
+
+    fn publish_diagnostics(&self, uri: &str) -> Value {
+        let text = &self.documents[uri];
+        let diagnostics: Vec<Value> = match Ast::parse(text, false) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors
+                .iter()
+                .map(|diagnostic| diagnostic_to_json(text, diagnostic))
+                .collect(),
+        };
+        notification(
+            "textDocument/publishDiagnostics",
+            json!({"uri": uri, "diagnostics": diagnostics}),
+        )
+    }
+
+    fn definition(&self, params: &Value) -> RequestResult {
+        let (uri, text) = self.document(params)?;
+        let analysis = match Analysis::new(text) {
+            Some(analysis) => analysis,
+            None => return Ok(Value::Null),
+        };
+        Ok(
+            match analysis.definition_at(position_to_offset(text, &params["position"])) {
+                Some(definition) => location(uri, text, analysis.ast.span(definition)),
+                None => Value::Null,
+            },
+        )
+    }
+
+    fn references(&self, params: &Value) -> RequestResult {
+        let (uri, text) = self.document(params)?;
+        let analysis = match Analysis::new(text) {
+            Some(analysis) => analysis,
+            None => return Ok(Value::Null),
+        };
+        let definition = match analysis.definition_at(position_to_offset(text, &params["position"]))
+        {
+            Some(definition) => definition,
+            None => return Ok(json!([])),
+        };
+        let mut spans: Vec<Span> = analysis
+            .resolution
+            .bindings
+            .iter()
+            .filter(|(_, binding)| **binding == definition)
+            .map(|(id, _)| analysis.ast.span(id))
+            .collect();
+        if params["context"]["includeDeclaration"] == true {
+            spans.push(analysis.ast.span(definition));
+        }
+        spans.sort_by_key(|span| span.start);
+        Ok(spans
+            .into_iter()
+            .map(|span| location(uri, text, span))
+            .collect())
+    }
+
+    fn hover(&self, params: &Value) -> RequestResult {
+        let (_, text) = self.document(params)?;
+        let analysis = match Analysis::new(text) {
+            Some(analysis) => analysis,
+            None => return Ok(Value::Null),
+        };
+        let offset = position_to_offset(text, &params["position"]);
+        let (identifier, definition) = match analysis.identifier_at(offset) {
+            Some(identifier) => match analysis.definition_of(identifier) {
+                Some(definition) => (identifier, definition),
+                None => return Ok(Value::Null),
+            },
+            None => return Ok(Value::Null),
+        };
+        let ast = &analysis.ast;
+        let value = match analysis.resolution.definitions[definition] {
+            BindingKind::Let(statement) => {
+                format!(
+                    "```monkey\n{}\n```",
+                    first_line(&text[range(ast.span(statement))])
+                )
+            }
+            BindingKind::Parameter(function) => {
+                let body = match ast.kind(function) {
+                    NodeKind::Function { body, .. } => *body,
+                    kind => panic!("expected a function, got {:?}", kind),
+                };
+                let header = &text[ast.span(function).start..ast.span(body).start];
+                format!(
+                    "```monkey\n{}\n```\nparameter `{}`",
+                    header.trim_end(),
+                    &text[range(ast.span(definition))]
+                )
+            }
+        };
+        Ok(json!({
+            "contents": {"kind": "markdown", "value": value},
+            "range": range_to_json(text, ast.span(identifier)),
+        }))
+    }
+
+    fn document_symbols(&self, params: &Value) -> RequestResult {
+        let (_, text) = self.document(params)?;
+        Ok(match Ast::parse(text, false) {
+            Ok(ast) => {
+                let mut symbols = Vec::new();
+                for statement in ast.statements() {
+                    collect_symbols(&ast, text, *statement, &mut symbols);
+                }
+                Value::Array(symbols)
+            }
+            Err(_) => Value::Null,
+        })
+    }
+
+    // 構文エラーがあっても補完できるように、識別子はトークンから集める
+    fn completion(&self, params: &Value) -> RequestResult {
+        let (_, text) = self.document(params)?;
+        let offset = position_to_offset(text, &params["position"]);
+        let identifiers: BTreeSet<String> = Lexer::new(text)
+            .filter(|token| token.token_type == TokenType::Ident)
+            .filter(|token| !(token.span.start <= offset && offset <= token.span.end))
+            .map(|token| token.literal)
+            .collect();
+        let keywords = KEYWORDS
+            .iter()
+            .map(|keyword| json!({"label": keyword, "kind": COMPLETION_KEYWORD}));
+        let identifiers = identifiers
+            .into_iter()
+            .map(|name| json!({"label": name, "kind": COMPLETION_VARIABLE}));
+        Ok(keywords.chain(identifiers).collect())
+    }
+
+    fn formatting(&self, params: &Value) -> RequestResult {
+        let (_, text) = self.document(params)?;
+        Ok(match formatter::format(text) {
+            Ok(formatted) if formatted == text => json!([]),
+            Ok(formatted) => json!([{
+                "range": range_to_json(text, Span::new(0, text.len())),
+                "newText": formatted,
+            }]),
+            // 構文エラーがあるときは整形しない
+            Err(_) => Value::Null,
+        })
+    }
+}
+
+/// A document that parses, with its names resolved.
+struct Analysis {
+    ast: Ast,
+    resolution: Resolution,
+}
+
+impl Analysis {
+    fn new(text: &str) -> Option<Self> {
+        let ast = Ast::parse(text, false).ok()?;
+        let resolution = resolver::resolve(&ast, &[]);
+        Some(Self { ast, resolution })
+    }
+
+    // カーソルが識別子の直後にあっても、その識別子とみなす
+    fn identifier_at(&self, offset: usize) -> Option<NodeId> {
+        self.ast.ids().find(|id| {
+            let span = self.ast.span(*id);
+            matches!(self.ast.kind(*id), NodeKind::Identifier(_))
+                && span.start <= offset
+                && offset <= span.end
+        })
+    }
+
+    /// The binding identifier of a use, or the identifier itself if it is one.
+    fn definition_of(&self, identifier: NodeId) -> Option<NodeId> {
+        if self.resolution.definitions.contains(identifier) {
+            Some(identifier)
+        } else {
+            self.resolution.bindings.get(identifier).copied()
+        }
+    }
+
+    fn definition_at(&self, offset: usize) -> Option<NodeId> {
+        self.definition_of(self.identifier_at(offset)?)
+    }
+}
+
+// let をたどって入れ子の DocumentSymbol を作る
+fn collect_symbols(ast: &Ast, text: &str, id: NodeId, symbols: &mut Vec<Value>) {
+    match ast.kind(id) {
+        NodeKind::Let { name, value } => {
+            let mut children = Vec::new();
+            collect_symbols(ast, text, *value, &mut children);
+            let kind = match ast.kind(*value) {
+                NodeKind::Function { .. } => SYMBOL_FUNCTION,
+                _ => SYMBOL_VARIABLE,
+            };
+            symbols.push(json!({
+                "name": ast.string(*name),
+                "kind": kind,
+                "range": range_to_json(text, ast.span(id)),
+                "selectionRange": range_to_json(text, ast.span(*name)),
+                "children": children,
+            }));
+        }
+        NodeKind::Return { value } => collect_symbols(ast, text, *value, symbols),
+        NodeKind::Expression { expression } => collect_symbols(ast, text, *expression, symbols),
+        NodeKind::Block { statements } => {
+            for statement in ast.nodes(*statements) {
+                collect_symbols(ast, text, *statement, symbols);
+            }
+        }
+        NodeKind::Prefix { right, .. } => collect_symbols(ast, text, *right, symbols),
+        NodeKind::Infix { left, right, .. } => {
+            collect_symbols(ast, text, *left, symbols);
+            collect_symbols(ast, text, *right, symbols);
+        }
+        NodeKind::If {
+            condition,
+            consequence,
+            alternative,
+        } => {
+            collect_symbols(ast, text, *condition, symbols);
+            collect_symbols(ast, text, *consequence, symbols);
+            if let Some(alternative) = alternative {
+                collect_symbols(ast, text, *alternative, symbols);
+            }
+        }
+        NodeKind::Function { body, .. } => collect_symbols(ast, text, *body, symbols),
+        NodeKind::Call {
+            function,
+            arguments,
+        } => {
+            collect_symbols(ast, text, *function, symbols);
+            for argument in ast.nodes(*arguments) {
+                collect_symbols(ast, text, *argument, symbols);
+            }
+        }
+        NodeKind::Array { elements: nodes } | NodeKind::Hash { pairs: nodes } => {
+            for node in ast.nodes(*nodes) {
+                collect_symbols(ast, text, *node, symbols);
+            }
+        }
+        NodeKind::Index { left, index } => {
+            collect_symbols(ast, text, *left, symbols);
+            collect_symbols(ast, text, *index, symbols);
+        }
+        _ => {}
+    }
+}
+
+fn diagnostic_to_json(text: &str, diagnostic: &Diagnostic) -> Value {
+    let mut message = diagnostic.message.clone();
+    for note in &diagnostic.notes {
+        message.push_str(&format!("\n{}: {}", note.kind, note.message));
+    }
+    let severity = match diagnostic.severity {
+        Severity::Error => SEVERITY_ERROR,
+        Severity::Warning => SEVERITY_WARNING,
+    };
+    let mut value = json!({
+        "range": range_to_json(text, diagnostic.span),
+        "severity": severity,
+        "source": "ironmonkey",
+        "message": message,
+    });
+    if let Some(code) = diagnostic.code {
+        value["code"] = json!(code.code());
+    }
+    value
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({"jsonrpc": "2.0", "method": method, "params": params})
+}
+
+fn error_response(id: Value, code: i64, message: impl Into<String>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {"code": code, "message": message.into()},
+    })
+}
+
+fn location(uri: &str, text: &str, span: Span) -> Value {
+    json!({"uri": uri, "range": range_to_json(text, span)})
+}
+
+fn range(span: Span) -> std::ops::Range<usize> {
+    span.start..span.end
+}
+
+fn first_line(text: &str) -> String {
+    match text.split_once('\n') {
+        Some((first, _)) => format!("{} ...", first.trim_end()),
+        None => text.to_string(),
+    }
+}
+
+fn range_to_json(text: &str, span: Span) -> Value {
+    json!({
+        "start": offset_to_position(text, span.start),
+        "end": offset_to_position(text, span.end),
+    })
+}
+
+// LSP の列は UTF-16 のコードユニットで数える
+fn offset_to_position(text: &str, offset: usize) -> Value {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let before = &text[..offset];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let character = text[line_start..offset].encode_utf16().count();
+    json!({"line": line, "character": character})
+}
+
+// 行や列が範囲外なら行末や文書の末尾に丸める
+fn position_to_offset(text: &str, position: &Value) -> usize {
+    let line = position["line"].as_u64().unwrap_or(0) as usize;
+    let character = position["character"].as_u64().unwrap_or(0) as usize;
+    let mut line_start = 0;
+    for _ in 0..line {
+        match text[line_start..].find('\n') {
+            Some(i) => line_start += i + 1,
+            None => return text.len(),
+        }
+    }
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(messages: &[Value]) -> Vec<u8> {
+        let mut input = Vec::new();
+        for message in messages {
+            write_message(&mut input, message).unwrap();
+        }
+        input
+    }
+
+    // クライアントのメッセージを順に送り、サーバーの出力と終了コードを返す
+    fn transcript(messages: &[Value]) -> (Vec<Value>, i32) {
+        let mut output = Vec::new();
+        let status = run(&frame(messages)[..], &mut output).unwrap();
+        let mut replies = Vec::new();
+        let mut output = &output[..];
+        while let Some(content) = read_message(&mut output).unwrap() {
+            replies.push(serde_json::from_str(&content).unwrap());
+        }
+        (replies, status)
+    }
+
+    fn request(id: u64, method: &str, params: Value) -> Value {
+        json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params})
+    }
+
+    fn open(text: &str) -> Value {
+        notification(
+            "textDocument/didOpen",
+            json!({"textDocument": {
+                "uri": "file:///main.mk",
+                "languageId": "monkey",
+                "version": 1,
+                "text": text,
+            }}),
+        )
+    }
+
+    fn at(line: u64, character: u64) -> Value {
+        json!({
+            "textDocument": {"uri": "file:///main.mk"},
+            "position": {"line": line, "character": character},
+        })
+    }
+
+    fn range(start: (u64, u64), end: (u64, u64)) -> Value {
+        json!({
+            "start": {"line": start.0, "character": start.1},
+            "end": {"line": end.0, "character": end.1},
+        })
+    }
+
+    // 開いて要求を一つ送り、その結果を返す
+    fn query(text: &str, method: &str, params: Value) -> Value {
+        let (replies, _) = transcript(&[
+            open(text),
+            request(1, method, params),
+            request(2, "shutdown", Value::Null),
+            notification("exit", Value::Null),
+        ]);
+        replies[1]["result"].clone()
+    }
+
+    #[test]
+    fn lifecycle() {
+        let (replies, status) = transcript(&[
+            request(1, "initialize", json!({"capabilities": {}})),
+            notification("initialized", json!({})),
+            request(2, "shutdown", Value::Null),
+            request(3, "textDocument/hover", at(0, 0)),
+            notification("exit", Value::Null),
+        ]);
+        assert_eq!(status, 0);
+        assert_eq!(replies.len(), 3);
+        let capabilities = &replies[0]["result"]["capabilities"];
+        assert_eq!(capabilities["textDocumentSync"], 1);
+        assert_eq!(capabilities["definitionProvider"], true);
+        assert_eq!(capabilities["documentFormattingProvider"], true);
+        assert_eq!(
+            replies[1],
+            json!({"jsonrpc": "2.0", "id": 2, "result": null})
+        );
+        assert_eq!(replies[2]["error"]["code"], INVALID_REQUEST);
+
+        let (_, status) = transcript(&[notification("exit", Value::Null)]);
+        assert_eq!(status, 1);
+    }
+
+    #[test]
+    fn errors() {
+        let mut input = frame(&[request(1, "workspace/symbol", json!({}))]);
+        input.extend(b"Content-Length: 5\r\n\r\n{oops");
+        input.extend(frame(&[
+            request(2, "textDocument/hover", at(0, 0)),
+            notification("$/cancelRequest", json!({"id": 1})),
+        ]));
+        let mut output = Vec::new();
+        assert_eq!(run(&input[..], &mut output).unwrap(), 1);
+        let mut output = &output[..];
+        let mut codes = Vec::new();
+        while let Some(content) = read_message(&mut output).unwrap() {
+            let reply: Value = serde_json::from_str(&content).unwrap();
+            codes.push((reply["id"].clone(), reply["error"]["code"].clone()));
+        }
+        assert_eq!(
+            codes,
+            vec![
+                (json!(1), json!(METHOD_NOT_FOUND)),
+                (Value::Null, json!(PARSE_ERROR)),
+                (json!(2), json!(INVALID_PARAMS)),
+            ]
+        );
+    }
+
+    #[test]
+    fn diagnostics_on_change() {
+        let (replies, _) = transcript(&[
+            open("let x = ;\nlet y 1;"),
+            notification(
+                "textDocument/didChange",
+                json!({
+                    "textDocument": {"uri": "file:///main.mk", "version": 2},
+                    "contentChanges": [{"text": "let x = 1;"}],
+                }),
+            ),
+            notification(
+                "textDocument/didClose",
+                json!({"textDocument": {"uri": "file:///main.mk"}}),
+            ),
+        ]);
+        assert_eq!(replies.len(), 3);
+        assert_eq!(replies[0]["method"], "textDocument/publishDiagnostics");
+        let diagnostics = replies[0]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0]["range"], range((0, 8), (0, 9)));
+        assert_eq!(diagnostics[0]["severity"], SEVERITY_ERROR);
+        assert_eq!(diagnostics[0]["code"], "E0002");
+        assert_eq!(diagnostics[1]["range"], range((1, 6), (1, 7)));
+        assert_eq!(replies[1]["params"]["diagnostics"], json!([]));
+        assert_eq!(replies[2]["params"]["diagnostics"], json!([]));
+    }
+
+    #[test]
+    fn definition_and_references() {
+        let text = "let one = 1;\nlet add = fn(a, b) { a + b };\nadd(one, one)";
+        assert_eq!(
+            query(text, "textDocument/definition", at(2, 10)),
+            json!({"uri": "file:///main.mk", "range": range((0, 4), (0, 7))})
+        );
+        // 定義の上でもよい
+        assert_eq!(
+            query(text, "textDocument/definition", at(1, 13))["range"],
+            range((1, 13), (1, 14))
+        );
+        assert_eq!(
+            query(text, "textDocument/definition", at(1, 0)),
+            Value::Null
+        );
+
+        let mut params = at(0, 5);
+        params["context"] = json!({"includeDeclaration": true});
+        let references = query(text, "textDocument/references", params);
+        let ranges: Vec<Value> = references
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|location| location["range"].clone())
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                range((0, 4), (0, 7)),
+                range((2, 4), (2, 7)),
+                range((2, 9), (2, 12)),
+            ]
+        );
+        let mut params = at(1, 21);
+        params["context"] = json!({"includeDeclaration": false});
+        assert_eq!(
+            query(text, "textDocument/references", params)
+                .as_array()
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn hover() {
+        let text = "let add = fn(a, b) {\n  a + b\n};\nadd(1, 2)";
+        assert_eq!(
+            query(text, "textDocument/hover", at(3, 1)),
+            json!({
+                "contents": {
+                    "kind": "markdown",
+                    "value": "```monkey\nlet add = fn(a, b) { ...\n```",
+                },
+                "range": range((3, 0), (3, 3)),
+            })
+        );
+        assert_eq!(
+            query(text, "textDocument/hover", at(1, 2))["contents"]["value"],
+            "```monkey\nfn(a, b)\n```\nparameter `a`"
+        );
+        assert_eq!(query(text, "textDocument/hover", at(3, 5)), Value::Null);
+    }
+
+    #[test]
+    fn document_symbols() {
+        let text = "let n = 1;\nlet f = fn() { let m = 2; m };";
+        let symbols = query(text, "textDocument/documentSymbol", at(0, 0));
+        assert_eq!(
+            symbols,
+            json!([
+                {
+                    "name": "n",
+                    "kind": SYMBOL_VARIABLE,
+                    "range": range((0, 0), (0, 9)),
+                    "selectionRange": range((0, 4), (0, 5)),
+                    "children": [],
+                },
+                {
+                    "name": "f",
+                    "kind": SYMBOL_FUNCTION,
+                    "range": range((1, 0), (1, 29)),
+                    "selectionRange": range((1, 4), (1, 5)),
+                    "children": [{
+                        "name": "m",
+                        "kind": SYMBOL_VARIABLE,
+                        "range": range((1, 15), (1, 24)),
+                        "selectionRange": range((1, 19), (1, 20)),
+                        "children": [],
+                    }],
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn completion() {
+        // 構文エラーがあっても識別子を集める
+        let items = query(
+            "let total = 1;\nlet count = tot",
+            "textDocument/completion",
+            at(1, 15),
+        );
+        let labels: Vec<&str> = items
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            labels,
+            vec!["fn", "let", "true", "false", "if", "else", "return", "count", "total"]
+        );
+        assert_eq!(items[0]["kind"], COMPLETION_KEYWORD);
+        assert_eq!(items[7]["kind"], COMPLETION_VARIABLE);
+    }
+
+    #[test]
+    fn formatting() {
+        let edits = query(
+            "let x=1;\nx",
+            "textDocument/formatting",
+            json!({"textDocument": {"uri": "file:///main.mk"}, "options": {}}),
+        );
+        assert_eq!(
+            edits,
+            json!([{"range": range((0, 0), (1, 1)), "newText": "let x = 1;\nx;\n"}])
+        );
+        let params = json!({"textDocument": {"uri": "file:///main.mk"}, "options": {}});
+        assert_eq!(
+            query("let x = 1;\n", "textDocument/formatting", params.clone()),
+            json!([])
+        );
+        assert_eq!(
+            query("let x = ;", "textDocument/formatting", params),
+            Value::Null
+        );
+    }
+
+    #[test]
+    fn utf16_positions() {
+        let text = "let é = 1;\nlet 𝔁 = é;";
+        assert_eq!(
+            offset_to_position(text, text.find('=').unwrap()),
+            json!({"line": 0, "character": 6})
+        );
+        let offset = text.rfind('=').unwrap();
+        assert_eq!(
+            offset_to_position(text, offset),
+            json!({"line": 1, "character": 7})
+        );
+        assert_eq!(
+            position_to_offset(text, &json!({"line": 1, "character": 7})),
+            offset
+        );
+        assert_eq!(
+            position_to_offset(text, &json!({"line": 0, "character": 99})),
+            text.find('\n').unwrap()
+        );
+        assert_eq!(
+            position_to_offset(text, &json!({"line": 9, "character": 0})),
+            text.len()
+        );
+    }
+}
@@ -1,11 +1,453 @@
-mod ast;
-mod lexer;
-mod parser;
-mod repl;
-mod token;
+use ironmonkey::diagnostic::{Diagnostic, Renderer, Severity, SourceFile};
+use ironmonkey::error_code::ErrorCode;
+use ironmonkey::evaluator::{IntegerMode, Limits};
+use ironmonkey::formatter;
+use ironmonkey::highlight;
+use ironmonkey::interpreter::Interpreter;
+use ironmonkey::lint::{self, Config};
+use ironmonkey::lsp;
+use ironmonkey::object::Object;
+use ironmonkey::repl;
+use ironmonkey::serialize;
+use ironmonkey::typecheck;
+use std::io::IsTerminal;
+use std::time::Duration;
+use std::{env, fs, io, process};
+
+const USAGE: &str = "usage: ironmonkey [--bignum]
+       ironmonkey check [--types] [--bignum] [--color=auto|always|never] [--error-format=human|json] <file>
+       ironmonkey run [--max-depth=<n>] [--max-steps=<n>] [--max-value-size=<bytes>] [--timeout=<ms>] [--bignum] [--color=auto|always|never] [--error-format=human|json] <file>
+       ironmonkey lint [--config=<file>] [--bignum] [--color=auto|always|never] [--error-format=human|json] <file>
+       ironmonkey ast [--bignum] [--format=json|sexp] <file>
+       ironmonkey fmt [--check] <file>...
+       ironmonkey highlight [--format=ansi|html] <file>
+       ironmonkey explain <code>
+       ironmonkey lsp";
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None => start_repl(IntegerMode::Checked),
+        Some("--bignum") if args.len() == 1 => start_repl(IntegerMode::Big),
+        Some("check") => process::exit(check(&args[1..])),
+        Some("run") => process::exit(run(&args[1..])),
+        Some("lint") => process::exit(lint(&args[1..])),
+        Some("ast") => process::exit(ast(&args[1..])),
+        Some("fmt") => process::exit(fmt(&args[1..])),
+        Some("highlight") => process::exit(highlight(&args[1..])),
+        Some("explain") => process::exit(explain(&args[1..])),
+        Some("lsp") if args.len() == 1 => process::exit(serve()),
+        Some(_) => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    }
+}
+
+fn start_repl(integer_mode: IntegerMode) {
     println!("Hello! This is the Monkey programming language!");
     println!("Feel free to type in commands");
-    repl::start();
+    repl::start(Interpreter::new().with_integer_mode(integer_mode));
+}
+
+// check と run で共通のオプション
+struct Options {
+    color: bool,
+    json: bool,
+    integer_mode: IntegerMode,
+    path: String,
+}
+
+fn parse_options(args: &[String]) -> Option<Options> {
+    let mut color = io::stderr().is_terminal();
+    let mut json = false;
+    let mut integer_mode = IntegerMode::Checked;
+    let mut path = None;
+    for arg in args {
+        match arg.as_str() {
+            "--color=auto" => color = io::stderr().is_terminal(),
+            "--color=always" => color = true,
+            "--color=never" => color = false,
+            "--error-format=human" => json = false,
+            "--error-format=json" => json = true,
+            "--bignum" => integer_mode = IntegerMode::Big,
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg.clone()),
+            _ => return None,
+        }
+    }
+    Some(Options {
+        color,
+        json,
+        integer_mode,
+        path: path?,
+    })
+}
+
+/// Parse a file, resolve its names and report the problems found. With
+/// `--types` the program is also type checked. Returns the process exit code,
+/// which is 1 only if there are errors, not just warnings.
+///
+/// Human readable errors go to stderr. With `--error-format=json` each error is
+/// printed to stdout as one JSON object per line.
+fn check(args: &[String]) -> i32 {
+    let types = args.iter().any(|arg| arg == "--types");
+    let rest: Vec<String> = args
+        .iter()
+        .filter(|arg| *arg != "--types")
+        .cloned()
+        .collect();
+    let options = match parse_options(&rest) {
+        Some(options) => options,
+        None => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+    let text = match read_source(&options.path) {
+        Some(text) => text,
+        None => return 1,
+    };
+
+    let interpreter = Interpreter::new().with_integer_mode(options.integer_mode);
+    let errors = match interpreter.parse(&text) {
+        Ok(program) => {
+            let mut errors = interpreter.resolve(&program).diagnostics;
+            if types {
+                errors.extend(typecheck::typecheck(&program).diagnostics);
+                errors.sort_by_key(|diagnostic| diagnostic.span.start);
+            }
+            errors
+        }
+        Err(errors) => errors,
+    };
+    report(&options, &text, &errors);
+    if errors
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error)
+    {
+        1
+    } else {
+        0
+    }
+}
+
+/// Parse and evaluate a file, printing the value of its last statement.
+/// Parse errors and the runtime error, if any, are reported like `check` does.
+fn run(args: &[String]) -> i32 {
+    let (limits, rest) = match parse_limits(args) {
+        Some(limits) => limits,
+        None => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+    let options = match parse_options(&rest) {
+        Some(options) => options,
+        None => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+    let text = match read_source(&options.path) {
+        Some(text) => text,
+        None => return 1,
+    };
+
+    let interpreter = Interpreter::new()
+        .with_integer_mode(options.integer_mode)
+        .with_limits(limits);
+    let program = match interpreter.parse(&text) {
+        Ok(program) => program,
+        Err(errors) => {
+            report(&options, &text, &errors);
+            return 1;
+        }
+    };
+
+    match interpreter.eval(&program) {
+        Ok(Object::Null) => 0,
+        Ok(value) => {
+            println!("{}", value);
+            0
+        }
+        Err(diagnostic) => {
+            report(&options, &text, &[diagnostic]);
+            1
+        }
+    }
+}
+
+// 制限のオプションを取り除き、残りを返す
+fn parse_limits(args: &[String]) -> Option<(Limits, Vec<String>)> {
+    let mut limits = Limits::default();
+    let mut rest = Vec::new();
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, value),
+            None => {
+                rest.push(arg.clone());
+                continue;
+            }
+        };
+        match name {
+            "--max-depth" => limits.max_depth = Some(value.parse().ok()?),
+            "--max-steps" => limits.max_steps = Some(value.parse().ok()?),
+            "--max-value-size" => limits.max_value_size = Some(value.parse().ok()?),
+            "--timeout" => limits.timeout = Some(Duration::from_millis(value.parse().ok()?)),
+            _ => rest.push(arg.clone()),
+        }
+    }
+    Some((limits, rest))
+}
+
+fn read_source(path: &str) -> Option<String> {
+    match fs::read_to_string(path) {
+        Ok(text) => Some(text),
+        Err(err) => {
+            eprintln!("error: could not read {}: {}", path, err);
+            None
+        }
+    }
+}
+
+fn report(options: &Options, text: &str, errors: &[Diagnostic]) {
+    let renderer = if options.color {
+        Renderer::colored()
+    } else {
+        Renderer::plain()
+    };
+    let source = SourceFile::new(&options.path, text);
+    for diagnostic in errors {
+        if options.json {
+            println!("{}", diagnostic.to_json(&source));
+        } else {
+            eprintln!("{}", renderer.render(diagnostic, &source));
+        }
+    }
+    if !options.json && errors.iter().any(|diagnostic| diagnostic.code.is_some()) {
+        eprintln!("For more information about an error, try `ironmonkey explain <code>`.");
+    }
+}
+
+/// Report style and correctness issues in a file. Rules are configured by the
+/// JSON file given with `--config`; by default every rule warns. Like `check`,
+/// the exit code is 1 only if there are errors, i.e. violations of denied rules.
+fn lint(args: &[String]) -> i32 {
+    let mut config_path = None;
+    let mut rest = Vec::new();
+    for arg in args {
+        match arg.strip_prefix("--config=") {
+            Some(path) => config_path = Some(path.to_string()),
+            None => rest.push(arg.clone()),
+        }
+    }
+    let options = match parse_options(&rest) {
+        Some(options) => options,
+        None => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+    let config = match config_path {
+        Some(path) => {
+            let text = match read_source(&path) {
+                Some(text) => text,
+                None => return 1,
+            };
+            match Config::from_json(&text) {
+                Ok(config) => config,
+                Err(err) => {
+                    eprintln!("error: invalid lint config {}: {}", path, err.message);
+                    return 1;
+                }
+            }
+        }
+        None => Config::default(),
+    };
+    let text = match read_source(&options.path) {
+        Some(text) => text,
+        None => return 1,
+    };
+
+    let interpreter = Interpreter::new().with_integer_mode(options.integer_mode);
+    let errors = match interpreter.parse(&text) {
+        Ok(program) => lint::lint(&program, &text, &config),
+        Err(errors) => errors,
+    };
+    report(&options, &text, &errors);
+    if errors
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error)
+    {
+        1
+    } else {
+        0
+    }
+}
+
+/// Print the syntax tree of a file as JSON (the default) or as an S-expression.
+fn ast(args: &[String]) -> i32 {
+    let mut sexp = false;
+    let mut rest = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--format=json" => sexp = false,
+            "--format=sexp" => sexp = true,
+            _ => rest.push(arg.clone()),
+        }
+    }
+    let options = match parse_options(&rest) {
+        Some(options) => options,
+        None => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+    let text = match read_source(&options.path) {
+        Some(text) => text,
+        None => return 1,
+    };
+
+    let interpreter = Interpreter::new().with_integer_mode(options.integer_mode);
+    match interpreter.parse(&text) {
+        Ok(program) if sexp => {
+            println!("{}", serialize::to_sexp(&program));
+            0
+        }
+        Ok(program) => {
+            println!("{}", serialize::to_json(&program));
+            0
+        }
+        Err(errors) => {
+            report(&options, &text, &errors);
+            1
+        }
+    }
+}
+
+/// Rewrite files in the canonical style. With `--check` nothing is written;
+/// files that would change are listed and the exit code is 1.
+fn fmt(args: &[String]) -> i32 {
+    let mut check = false;
+    let mut paths = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "--check" => check = true,
+            _ if !arg.starts_with("--") => paths.push(arg),
+            _ => {
+                eprintln!("{}", USAGE);
+                return 2;
+            }
+        }
+    }
+    if paths.is_empty() {
+        eprintln!("{}", USAGE);
+        return 2;
+    }
+
+    let mut status = 0;
+    for path in paths {
+        let text = match read_source(path) {
+            Some(text) => text,
+            None => {
+                status = 1;
+                continue;
+            }
+        };
+        match formatter::format(&text) {
+            Ok(formatted) if formatted == text => {}
+            Ok(_) if check => {
+                println!("would reformat {}", path);
+                status = 1;
+            }
+            Ok(formatted) => {
+                if let Err(err) = fs::write(path, formatted) {
+                    eprintln!("error: could not write {}: {}", path, err);
+                    status = 1;
+                }
+            }
+            Err(errors) => {
+                let options = Options {
+                    color: io::stderr().is_terminal(),
+                    json: false,
+                    integer_mode: IntegerMode::Checked,
+                    path: path.clone(),
+                };
+                report(&options, &text, &errors);
+                status = 1;
+            }
+        }
+    }
+    status
+}
+
+/// Print a file with syntax highlighting, as ANSI colours (the default) or as
+/// HTML. Files with syntax errors are highlighted too.
+fn highlight(args: &[String]) -> i32 {
+    let mut html = false;
+    let mut path = None;
+    for arg in args {
+        match arg.as_str() {
+            "--format=ansi" => html = false,
+            "--format=html" => html = true,
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => {
+                eprintln!("{}", USAGE);
+                return 2;
+            }
+        }
+    }
+    let path = match path {
+        Some(path) => path,
+        None => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+    let text = match read_source(path) {
+        Some(text) => text,
+        None => return 1,
+    };
+
+    let highlights = highlight::classify(&text);
+    if html {
+        println!("{}", highlight::to_html(&text, &highlights));
+    } else {
+        print!("{}", highlight::to_ansi(&text, &highlights));
+    }
+    0
+}
+
+/// Print the long description of an error code.
+fn explain(args: &[String]) -> i32 {
+    let code = match args {
+        [code] => code,
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+    match ErrorCode::lookup(code) {
+        Some(code) => {
+            print!("{}", code.explain());
+            0
+        }
+        None => {
+            eprintln!("error: {} is not a valid error code", code);
+            1
+        }
+    }
+}
+
+/// Run the language server on stdin and stdout until the client exits.
+fn serve() -> i32 {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    match lsp::run(stdin.lock(), stdout.lock()) {
+        Ok(status) => status,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            1
+        }
+    }
 }
@@ -0,0 +1,411 @@
+//! Functions written in Rust that Monkey programs can call.
+//!
+//! Any closure whose parameters implement `FromObject` and whose result
+//! implements `NativeResult` can be registered with
+//! `Interpreter::register`. Arguments are converted before the closure runs,
+//! so a Monkey value of the wrong type is reported as a type mismatch at the
+//! argument. A closure that takes `&[Object]` receives the arguments as they
+//! are, in any number.
+
+use super::object::{Array, Object};
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use std::fmt;
+use std::rc::Rc;
+
+/// A Rust function as a Monkey value.
+pub struct Native {
+    pub name: String,
+    /// `None` if any number of arguments is accepted.
+    pub arity: Option<usize>,
+    function: Box<NativeFn>,
+}
+
+type NativeFn = dyn Fn(&[Object]) -> Result<Object, NativeError>;
+
+impl Native {
+    /// Call the function with `arguments`.
+    pub fn call(&self, arguments: &[Object]) -> Result<Object, NativeError> {
+        if let Some(arity) = self.arity {
+            if arguments.len() != arity {
+                return Err(NativeError::ArgumentCount {
+                    expected: arity,
+                    found: arguments.len(),
+                });
+            }
+        }
+        (self.function)(arguments)
+    }
+}
+
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Native({})", self.name)
+    }
+}
+
+/// Why a native function did not return a value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NativeError {
+    /// The argument at `index` could not be converted to the parameter type.
+    Argument {
+        index: usize,
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// The argument at `index` has the parameter's Monkey type, but its
+    /// value does not fit, such as a big integer passed for an `i64`.
+    OutOfRange { index: usize },
+    /// The number of arguments does not match the arity.
+    ArgumentCount { expected: usize, found: usize },
+    /// The function itself returned an error.
+    Failed(String),
+}
+
+/// Conversion of a Monkey value to a Rust parameter type.
+pub trait FromObject: Sized {
+    /// The Monkey type accepted, for error messages.
+    const TYPE_NAME: &'static str;
+
+    fn from_object(object: &Object) -> Option<Self>;
+
+    /// Whether `from_object` rejected `object` for its value rather than
+    /// its type.
+    fn out_of_range(_object: &Object) -> bool {
+        false
+    }
+}
+
+impl FromObject for i64 {
+    const TYPE_NAME: &'static str = "INTEGER";
+
+    fn from_object(object: &Object) -> Option<Self> {
+        match object {
+            Object::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn out_of_range(object: &Object) -> bool {
+        matches!(object, Object::BigInteger(_))
+    }
+}
+
+impl FromObject for BigInt {
+    const TYPE_NAME: &'static str = "INTEGER";
+
+    fn from_object(object: &Object) -> Option<Self> {
+        match object {
+            Object::Integer(value) => Some(BigInt::from(*value)),
+            Object::BigInteger(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+}
+
+// 演算と同じく整数もfloatとして受け取る
+impl FromObject for f64 {
+    const TYPE_NAME: &'static str = "FLOAT";
+
+    fn from_object(object: &Object) -> Option<Self> {
+        match object {
+            Object::Float(value) => Some(*value),
+            Object::Integer(value) => Some(*value as f64),
+            Object::BigInteger(value) => value.to_f64(),
+            _ => None,
+        }
+    }
+}
+
+impl FromObject for bool {
+    const TYPE_NAME: &'static str = "BOOLEAN";
+
+    fn from_object(object: &Object) -> Option<Self> {
+        match object {
+            Object::Boolean(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl FromObject for String {
+    const TYPE_NAME: &'static str = "STRING";
+
+    fn from_object(object: &Object) -> Option<Self> {
+        match object {
+            Object::String(value) => Some(String::from(&**value)),
+            _ => None,
+        }
+    }
+}
+
+/// An array whose elements all convert to `T`.
+impl<T: FromObject> FromObject for Vec<T> {
+    const TYPE_NAME: &'static str = "ARRAY";
+
+    fn from_object(object: &Object) -> Option<Self> {
+        match object {
+            Object::Array(array) => array.elements().iter().map(T::from_object).collect(),
+            _ => None,
+        }
+    }
+
+    // 型の合わない要素がなく、範囲外の要素がある
+    fn out_of_range(object: &Object) -> bool {
+        match object {
+            Object::Array(array) => {
+                let elements = array.elements();
+                elements.iter().any(T::out_of_range)
+                    && elements.iter().all(|element| {
+                        T::from_object(element).is_some() || T::out_of_range(element)
+                    })
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Any value, unconverted.
+impl FromObject for Object {
+    const TYPE_NAME: &'static str = "any value";
+
+    fn from_object(object: &Object) -> Option<Self> {
+        Some(object.clone())
+    }
+}
+
+/// `null` becomes `None`; any other value is converted to `T`.
+impl<T: FromObject> FromObject for Option<T> {
+    const TYPE_NAME: &'static str = T::TYPE_NAME;
+
+    fn from_object(object: &Object) -> Option<Self> {
+        match object {
+            Object::Null => Some(None),
+            object => T::from_object(object).map(Some),
+        }
+    }
+
+    fn out_of_range(object: &Object) -> bool {
+        T::out_of_range(object)
+    }
+}
+
+/// Conversion of a Rust value to a Monkey value.
+pub trait IntoObject {
+    fn into_object(self) -> Object;
+}
+
+impl IntoObject for i64 {
+    fn into_object(self) -> Object {
+        Object::Integer(self)
+    }
+}
+
+// i64に収まる値はIntegerにする
+impl IntoObject for BigInt {
+    fn into_object(self) -> Object {
+        match self.to_i64() {
+            Some(value) => Object::Integer(value),
+            None => Object::BigInteger(self),
+        }
+    }
+}
+
+impl IntoObject for f64 {
+    fn into_object(self) -> Object {
+        Object::Float(self)
+    }
+}
+
+impl IntoObject for bool {
+    fn into_object(self) -> Object {
+        Object::Boolean(self)
+    }
+}
+
+impl IntoObject for String {
+    fn into_object(self) -> Object {
+        Object::String(Rc::from(self))
+    }
+}
+
+impl<T: IntoObject> IntoObject for Vec<T> {
+    fn into_object(self) -> Object {
+        let elements = self.into_iter().map(IntoObject::into_object).collect();
+        Object::Array(Rc::new(Array::new(elements)))
+    }
+}
+
+impl IntoObject for () {
+    fn into_object(self) -> Object {
+        Object::Null
+    }
+}
+
+impl IntoObject for Object {
+    fn into_object(self) -> Object {
+        self
+    }
+}
+
+impl<T: IntoObject> IntoObject for Option<T> {
+    fn into_object(self) -> Object {
+        match self {
+            Some(value) => value.into_object(),
+            None => Object::Null,
+        }
+    }
+}
+
+/// The result of a native function: a value, or a `Result` whose error is
+/// reported as a runtime error with its `Display` text.
+pub trait NativeResult {
+    fn into_result(self) -> Result<Object, NativeError>;
+}
+
+impl<T: IntoObject> NativeResult for T {
+    fn into_result(self) -> Result<Object, NativeError> {
+        Ok(self.into_object())
+    }
+}
+
+impl<T: IntoObject, E: fmt::Display> NativeResult for Result<T, E> {
+    fn into_result(self) -> Result<Object, NativeError> {
+        self.map(IntoObject::into_object)
+            .map_err(|err| NativeError::Failed(err.to_string()))
+    }
+}
+
+/// Closures that can become a `Native`. `Args` is the tuple of parameter
+/// types, which lets one closure type have a single implementation.
+pub trait IntoNative<Args> {
+    fn into_native(self, name: &str) -> Native;
+}
+
+/// The `Args` of closures taking `&[Object]`.
+pub struct Variadic;
+
+impl<F, R> IntoNative<Variadic> for F
+where
+    F: Fn(&[Object]) -> R + 'static,
+    R: NativeResult,
+{
+    fn into_native(self, name: &str) -> Native {
+        Native {
+            name: String::from(name),
+            arity: None,
+            function: Box::new(move |arguments| self(arguments).into_result()),
+        }
+    }
+}
+
+fn argument<T: FromObject>(arguments: &[Object], index: usize) -> Result<T, NativeError> {
+    let object = &arguments[index];
+    T::from_object(object).ok_or_else(|| {
+        if T::out_of_range(object) {
+            NativeError::OutOfRange { index }
+        } else {
+            NativeError::Argument {
+                index,
+                expected: T::TYPE_NAME,
+                found: object.type_name(),
+            }
+        }
+    })
+}
+
+macro_rules! into_native {
+    ($arity:expr; $($arg:ident $index:tt),*) => {
+        impl<F, R, $($arg),*> IntoNative<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: NativeResult,
+            $($arg: FromObject,)*
+        {
+            #[allow(unused_variables)]
+            fn into_native(self, name: &str) -> Native {
+                Native {
+                    name: String::from(name),
+                    arity: Some($arity),
+                    function: Box::new(move |arguments| {
+                        self($(argument::<$arg>(arguments, $index)?),*).into_result()
+                    }),
+                }
+            }
+        }
+    };
+}
+
+into_native!(0;);
+into_native!(1; A 0);
+into_native!(2; A 0, B 1);
+into_native!(3; A 0, B 1, C 2);
+into_native!(4; A 0, B 1, C 2, D 3);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        assert_eq!(i64::from_object(&Object::Integer(3)), Some(3));
+        assert_eq!(i64::from_object(&Object::Float(3.0)), None);
+        assert_eq!(f64::from_object(&Object::Integer(3)), Some(3.0));
+        assert_eq!(
+            Option::<bool>::from_object(&Object::Null),
+            Some(None::<bool>)
+        );
+        assert_eq!(Option::<bool>::from_object(&Object::Integer(1)), None);
+
+        let big: BigInt = "9223372036854775808".parse().unwrap();
+        assert_eq!(big.clone().into_object(), Object::BigInteger(big));
+        assert_eq!(BigInt::from(7).into_object(), Object::Integer(7));
+        assert_eq!(().into_object(), Object::Null);
+        assert_eq!(None::<i64>.into_object(), Object::Null);
+    }
+
+    #[test]
+    fn arguments_are_converted_in_order() {
+        let native = (|a: i64, b: bool| if b { a } else { -a }).into_native("pick");
+        assert_eq!(native.arity, Some(2));
+        assert_eq!(
+            native.call(&[Object::Integer(2), Object::Boolean(false)]),
+            Ok(Object::Integer(-2))
+        );
+        assert_eq!(
+            native.call(&[Object::Integer(2), Object::Null]),
+            Err(NativeError::Argument {
+                index: 1,
+                expected: "BOOLEAN",
+                found: "NULL",
+            })
+        );
+
+        assert_eq!(
+            native.call(&[Object::Integer(2)]),
+            Err(NativeError::ArgumentCount {
+                expected: 2,
+                found: 1,
+            })
+        );
+
+        // 型は合っていても i64 に収まらない
+        let big: BigInt = "9223372036854775808".parse().unwrap();
+        let native = (|a: Option<i64>| a).into_native("maybe");
+        assert_eq!(
+            native.call(&[Object::BigInteger(big.clone())]),
+            Err(NativeError::OutOfRange { index: 0 })
+        );
+        let native = (|a: BigInt| a).into_native("big");
+        assert_eq!(
+            native.call(&[Object::BigInteger(big.clone())]),
+            Ok(Object::BigInteger(big))
+        );
+
+        let native = (|| -> Result<(), &str> { Err("no") }).into_native("fail");
+        assert_eq!(
+            native.call(&[]),
+            Err(NativeError::Failed(String::from("no")))
+        );
+    }
+}
@@ -0,0 +1,564 @@
+use super::ast::{self, BlockStatement, Identifer, Node};
+use super::native::Native;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, Serialize, SerializeMap, SerializeSeq, Serializer};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::rc::Rc;
+
+/// Deepest nesting of arrays and hashes in a value. Printing, comparing,
+/// serializing and dropping a value recurse into it, so the evaluator
+/// rejects a value nested deeper than this when it is made.
+pub const MAX_VALUE_DEPTH: usize = 128;
+
+/// Values produced by the evaluator.
+#[derive(Debug, Clone)]
+pub enum Object {
+    Integer(i64),
+    BigInteger(BigInt), // i64に収まらない整数 (big integerモードのみ)
+    Float(f64),
+    Boolean(bool),
+    String(Rc<str>),
+    Array(Rc<Array>),
+    Hash(Rc<Hash>),
+    ReturnValue(Box<Object>), // return文の値 (関数の外に出るまで包んでおく)
+    Function(Rc<Function>),
+    Native(Rc<Native>), // 埋め込む側が登録したRustの関数
+    Null,
+}
+
+impl Object {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Integer(_) | Object::BigInteger(_) => "INTEGER",
+            Object::Float(_) => "FLOAT",
+            Object::Boolean(_) => "BOOLEAN",
+            Object::String(_) => "STRING",
+            Object::Array(_) => "ARRAY",
+            Object::Hash(_) => "HASH",
+            Object::ReturnValue(_) => "RETURN_VALUE",
+            Object::Function(_) | Object::Native(_) => "FUNCTION",
+            Object::Null => "NULL",
+        }
+    }
+
+    pub fn inspect(&self) -> String {
+        match self {
+            Object::Integer(value) => value.to_string(),
+            Object::BigInteger(value) => value.to_string(),
+            // 必ず . か e を含み、同じ値に読み戻せる形
+            Object::Float(value) => format!("{:?}", value),
+            Object::Boolean(value) => value.to_string(),
+            Object::String(value) => ast::quote(value),
+            Object::Array(array) => {
+                let elements: Vec<String> = array.elements().iter().map(Object::inspect).collect();
+                format!("[{}]", elements.join(", "))
+            }
+            Object::Hash(hash) => {
+                let pairs: Vec<String> = hash
+                    .pairs()
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key, value.inspect()))
+                    .collect();
+                format!("{{{}}}", pairs.join(", "))
+            }
+            Object::ReturnValue(value) => value.inspect(),
+            Object::Function(function) => function.string(),
+            Object::Native(native) => format!("<native fn {}>", native.name),
+            Object::Null => String::from("null"),
+        }
+    }
+
+    /// Bytes the value takes, as `Limits::max_value_size` counts them: the
+    /// magnitude of a big integer, the UTF-8 of a string, and the elements of
+    /// an array or the keys and values of a hash. Other values count as 8.
+    pub fn size(&self) -> usize {
+        match self {
+            Object::BigInteger(value) => value.bits().div_ceil(8) as usize,
+            Object::String(value) => value.len(),
+            Object::Array(array) => array.size,
+            Object::Hash(hash) => hash.size,
+            Object::ReturnValue(value) => value.size(),
+            _ => 8,
+        }
+    }
+
+    /// How deeply arrays and hashes are nested in the value. Other values
+    /// have depth 0.
+    pub fn depth(&self) -> usize {
+        match self {
+            Object::Array(array) => array.depth,
+            Object::Hash(hash) => hash.depth,
+            Object::ReturnValue(value) => value.depth(),
+            _ => 0,
+        }
+    }
+}
+
+/// The elements of an array value. Its size and depth are counted once,
+/// when it is made, so checking a limit never walks the elements.
+#[derive(Debug)]
+pub struct Array {
+    elements: Vec<Object>,
+    size: usize,
+    depth: usize,
+}
+
+impl Array {
+    pub fn new(elements: Vec<Object>) -> Self {
+        let size = elements
+            .iter()
+            .fold(0usize, |size, element| size.saturating_add(element.size()));
+        let depth = elements.iter().map(Object::depth).max().unwrap_or(0) + 1;
+        Self {
+            elements,
+            size,
+            depth,
+        }
+    }
+
+    pub fn elements(&self) -> &[Object] {
+        &self.elements
+    }
+}
+
+/// The pairs of a hash value, ordered by key. Like `Array`, it keeps its
+/// size and depth.
+#[derive(Debug)]
+pub struct Hash {
+    pairs: BTreeMap<HashKey, Object>,
+    size: usize,
+    depth: usize,
+}
+
+impl Hash {
+    pub fn new(pairs: BTreeMap<HashKey, Object>) -> Self {
+        let size = pairs.iter().fold(0usize, |size, (key, value)| {
+            size.saturating_add(key.to_object().size())
+                .saturating_add(value.size())
+        });
+        let depth = pairs.values().map(Object::depth).max().unwrap_or(0) + 1;
+        Self { pairs, size, depth }
+    }
+
+    pub fn pairs(&self) -> &BTreeMap<HashKey, Object> {
+        &self.pairs
+    }
+
+    pub fn get(&self, key: &HashKey) -> Option<&Object> {
+        self.pairs.get(key)
+    }
+}
+
+/// The values that can be hash keys: integers, booleans and strings.
+///
+/// Keys are ordered integers first, by value, then `false` and `true`, then
+/// strings, so a hash prints and serializes the same way every time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HashKey {
+    Integer(i64),
+    BigInteger(BigInt), // Object::BigIntegerと同じくi64に収まらない値だけ
+    Boolean(bool),
+    String(Rc<str>),
+}
+
+impl HashKey {
+    /// The key for `object`, or `None` if its type cannot be a key.
+    pub fn from_object(object: &Object) -> Option<Self> {
+        match object {
+            Object::Integer(value) => Some(HashKey::Integer(*value)),
+            Object::BigInteger(value) => Some(HashKey::BigInteger(value.clone())),
+            Object::Boolean(value) => Some(HashKey::Boolean(*value)),
+            Object::String(value) => Some(HashKey::String(Rc::clone(value))),
+            _ => None,
+        }
+    }
+
+    pub fn to_object(&self) -> Object {
+        match self {
+            HashKey::Integer(value) => Object::Integer(*value),
+            HashKey::BigInteger(value) => Object::BigInteger(value.clone()),
+            HashKey::Boolean(value) => Object::Boolean(*value),
+            HashKey::String(value) => Object::String(Rc::clone(value)),
+        }
+    }
+
+    // 種類ごとの順番
+    fn rank(&self) -> u8 {
+        match self {
+            HashKey::Integer(_) | HashKey::BigInteger(_) => 0,
+            HashKey::Boolean(_) => 1,
+            HashKey::String(_) => 2,
+        }
+    }
+}
+
+impl Ord for HashKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (HashKey::Integer(a), HashKey::Integer(b)) => a.cmp(b),
+            (HashKey::BigInteger(a), HashKey::BigInteger(b)) => a.cmp(b),
+            (HashKey::Integer(a), HashKey::BigInteger(b)) => BigInt::from(*a).cmp(b),
+            (HashKey::BigInteger(a), HashKey::Integer(b)) => a.cmp(&BigInt::from(*b)),
+            (HashKey::Boolean(a), HashKey::Boolean(b)) => a.cmp(b),
+            (HashKey::String(a), HashKey::String(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialOrd for HashKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for HashKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_object().inspect())
+    }
+}
+
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Object::Integer(a), Object::Integer(b)) => a == b,
+            (Object::BigInteger(a), Object::BigInteger(b)) => a == b,
+            (Object::Float(a), Object::Float(b)) => a == b,
+            (Object::Boolean(a), Object::Boolean(b)) => a == b,
+            (Object::String(a), Object::String(b)) => a == b,
+            (Object::Array(a), Object::Array(b)) => a.elements() == b.elements(),
+            (Object::Hash(a), Object::Hash(b)) => a.pairs() == b.pairs(),
+            (Object::ReturnValue(a), Object::ReturnValue(b)) => a == b,
+            (Object::Function(a), Object::Function(b)) => Rc::ptr_eq(a, b),
+            (Object::Native(a), Object::Native(b)) => Rc::ptr_eq(a, b),
+            (Object::Null, Object::Null) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.inspect())
+    }
+}
+
+/// Values map to serde's data model as integers, floats, booleans, strings,
+/// sequences for arrays, maps for hashes and unit for `null`. Functions and
+/// non-finite floats have no data representation and are an error. Formats
+/// whose map keys are strings, such as JSON, write integer and boolean keys
+/// as strings.
+impl Serialize for Object {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Object::Integer(value) => serializer.serialize_i64(*value),
+            Object::BigInteger(value) => match value.to_i128() {
+                Some(value) => serializer.serialize_i128(value),
+                None => Err(ser::Error::custom(format!(
+                    "cannot serialize {}: integers beyond 128 bits are not supported",
+                    value
+                ))),
+            },
+            // JSON などは inf や NaN を null にしてしまうのでエラーにする
+            Object::Float(value) if !value.is_finite() => Err(ser::Error::custom(format!(
+                "cannot serialize {}: non-finite floats are not supported",
+                value
+            ))),
+            Object::Float(value) => serializer.serialize_f64(*value),
+            Object::Boolean(value) => serializer.serialize_bool(*value),
+            Object::String(value) => serializer.serialize_str(value),
+            Object::Array(array) => {
+                let mut seq = serializer.serialize_seq(Some(array.elements().len()))?;
+                for element in array.elements() {
+                    seq.serialize_element(element)?;
+                }
+                seq.end()
+            }
+            Object::Hash(hash) => {
+                let mut map = serializer.serialize_map(Some(hash.pairs().len()))?;
+                for (key, value) in hash.pairs() {
+                    map.serialize_entry(&key.to_object(), value)?;
+                }
+                map.end()
+            }
+            Object::ReturnValue(value) => value.serialize(serializer),
+            Object::Function(_) => Err(ser::Error::custom(
+                "cannot serialize a function: functions have no data representation",
+            )),
+            Object::Native(native) => Err(ser::Error::custom(format!(
+                "cannot serialize native function `{}`: functions have no data representation",
+                native.name
+            ))),
+            Object::Null => serializer.serialize_unit(),
+        }
+    }
+}
+
+/// Sequences become arrays and maps become hashes. A map key that cannot be
+/// a hash key, such as a float, is an error.
+impl<'de> Deserialize<'de> for Object {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ObjectVisitor)
+    }
+}
+
+struct ObjectVisitor;
+
+impl<'de> Visitor<'de> for ObjectVisitor {
+    type Value = Object;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a Monkey value")
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<Object, E> {
+        Ok(Object::Boolean(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Object, E> {
+        Ok(Object::Integer(value))
+    }
+
+    // i64に収まらない値はBigIntegerにする
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Object, E> {
+        self.visit_u128(u128::from(value))
+    }
+
+    fn visit_i128<E: de::Error>(self, value: i128) -> Result<Object, E> {
+        Ok(match value.to_i64() {
+            Some(value) => Object::Integer(value),
+            None => Object::BigInteger(BigInt::from(value)),
+        })
+    }
+
+    fn visit_u128<E: de::Error>(self, value: u128) -> Result<Object, E> {
+        Ok(match value.to_i64() {
+            Some(value) => Object::Integer(value),
+            None => Object::BigInteger(BigInt::from(value)),
+        })
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Object, E> {
+        Ok(Object::Float(value))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Object, E> {
+        Ok(Object::String(Rc::from(value)))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Object, A::Error> {
+        let mut elements = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(element) = seq.next_element()? {
+            elements.push(element);
+        }
+        Ok(Object::Array(Rc::new(Array::new(elements))))
+    }
+
+    // 同じキーが複数あれば後のものを使う
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Object, A::Error> {
+        let mut pairs = BTreeMap::new();
+        while let Some((key, value)) = map.next_entry::<Object, Object>()? {
+            let key = HashKey::from_object(&key).ok_or_else(|| {
+                de::Error::custom(format!("unusable as hash key: {}", key.type_name()))
+            })?;
+            pairs.insert(key, value);
+        }
+        Ok(Object::Hash(Rc::new(Hash::new(pairs))))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Object, E> {
+        Ok(Object::Null)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Object, E> {
+        Ok(Object::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Object, D::Error> {
+        Object::deserialize(deserializer)
+    }
+}
+
+/// A function value together with the environment it closes over.
+pub struct Function {
+    pub parameters: Vec<Identifer>,
+    pub body: BlockStatement,
+    pub env: Env,
+}
+
+impl Function {
+    pub fn string(&self) -> String {
+        let parameters: Vec<String> = self.parameters.iter().map(Node::string).collect();
+        format!(
+            "fn({}) {{\n{}\n}}",
+            parameters.join(", "),
+            self.body.string()
+        )
+    }
+}
+
+// envは自分自身を含みうるので出力しない
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Function({})", self.string())
+    }
+}
+
+pub type Env = Rc<RefCell<Environment>>;
+
+/// Variable bindings of one scope. Lookups fall back to the enclosing scope.
+#[derive(Debug, Default)]
+pub struct Environment {
+    store: HashMap<String, Object>,
+    outer: Option<Env>,
+}
+
+impl Environment {
+    pub fn new() -> Env {
+        Rc::new(RefCell::new(Environment::default()))
+    }
+
+    pub fn new_enclosed(outer: &Env) -> Env {
+        Rc::new(RefCell::new(Environment {
+            store: HashMap::new(),
+            outer: Some(Rc::clone(outer)),
+        }))
+    }
+
+    pub fn get(&self, name: &str) -> Option<Object> {
+        match self.store.get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.outer.as_ref()?.borrow().get(name),
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: Object) {
+        self.store.insert(String::from(name), value);
+    }
+
+    /// Every name visible from this scope, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut names = match &self.outer {
+            Some(outer) => outer.borrow().names(),
+            None => Vec::new(),
+        };
+        names.extend(self.store.keys().cloned());
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::native::IntoNative;
+    use super::*;
+    use serde::de::value::MapDeserializer;
+
+    #[test]
+    fn serde_round_trip() {
+        let values = [
+            (Object::Integer(-3), "-3"),
+            (
+                Object::BigInteger(BigInt::from(9223372036854775808u64)),
+                "9223372036854775808",
+            ),
+            (Object::Float(0.5), "0.5"),
+            (Object::Boolean(true), "true"),
+            (Object::Null, "null"),
+            (string("a\"b\n"), r#""a\"b\n""#),
+            (
+                array(vec![Object::Integer(1), array(vec![]), Object::Null]),
+                "[1,[],null]",
+            ),
+            (
+                hash(vec![
+                    (HashKey::String(Rc::from("b")), Object::Boolean(false)),
+                    (HashKey::String(Rc::from("a")), array(vec![string("x")])),
+                ]),
+                r#"{"a":["x"],"b":false}"#,
+            ),
+        ];
+        for (value, json) in values.iter() {
+            assert_eq!(serde_json::to_string(value).unwrap(), *json);
+            assert_eq!(&serde_json::from_str::<Object>(json).unwrap(), value);
+        }
+        let big: BigInt = "18446744073709551616".parse().unwrap();
+        assert_eq!(
+            serde_json::to_string(&Object::BigInteger(big)).unwrap(),
+            "18446744073709551616"
+        );
+    }
+
+    #[test]
+    fn unrepresentable_values() {
+        let native = Object::Native(Rc::new((|x: i64| x).into_native("id")));
+        assert_eq!(
+            serde_json::to_string(&native).unwrap_err().to_string(),
+            "cannot serialize native function `id`: functions have no data representation"
+        );
+        for value in &[f64::INFINITY, f64::NEG_INFINITY, f64::NAN] {
+            let err = serde_json::to_string(&Object::Float(*value)).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "cannot serialize {}: non-finite floats are not supported",
+                    value
+                )
+            );
+        }
+        let nested = array(vec![Object::Integer(1), native]);
+        assert!(serde_json::to_string(&nested).is_err());
+
+        let pairs = vec![(1.5, 1)].into_iter();
+        let deserializer = MapDeserializer::<_, de::value::Error>::new(pairs);
+        assert_eq!(
+            Object::deserialize(deserializer).unwrap_err().to_string(),
+            "unusable as hash key: FLOAT"
+        );
+    }
+
+    // JSON のキーは文字列なので、整数と真偽値のキーは文字列として書かれる
+    #[test]
+    fn json_hash_keys() {
+        let value = hash(vec![
+            (HashKey::String(Rc::from("s")), Object::Integer(3)),
+            (HashKey::Boolean(true), Object::Integer(2)),
+            (HashKey::Integer(10), Object::Integer(1)),
+            (HashKey::Integer(-1), Object::Integer(0)),
+        ]);
+        assert_eq!(value.inspect(), r#"{-1: 0, 10: 1, true: 2, "s": 3}"#);
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(json, r#"{"-1":0,"10":1,"true":2,"s":3}"#);
+        assert_eq!(
+            serde_json::from_str::<Object>(&json).unwrap().inspect(),
+            r#"{"-1": 0, "10": 1, "s": 3, "true": 2}"#
+        );
+    }
+
+    #[test]
+    fn size_and_depth() {
+        let inner = array(vec![string("abc"), Object::Integer(1)]);
+        assert_eq!((inner.size(), inner.depth()), (11, 1));
+        let outer = hash(vec![(HashKey::String(Rc::from("k")), inner)]);
+        assert_eq!((outer.size(), outer.depth()), (12, 2));
+        assert_eq!((string("").size(), string("").depth()), (0, 0));
+    }
+
+    fn string(value: &str) -> Object {
+        Object::String(Rc::from(value))
+    }
+
+    fn array(elements: Vec<Object>) -> Object {
+        Object::Array(Rc::new(Array::new(elements)))
+    }
+
+    fn hash(pairs: Vec<(HashKey, Object)>) -> Object {
+        Object::Hash(Rc::new(Hash::new(pairs.into_iter().collect())))
+    }
+}
@@ -1,59 +1,168 @@
 use super::ast::{
-    ExpressionStatement, ExpressionType, Identifer, LetStatement, Program, ReturnStatement,
-    StatementType,
+    ArrayLiteral, ArrayType, BigIntegerLiteral, BlockStatement, Boolean, CallExpression,
+    ExpressionStatement, ExpressionType, FloatLiteral, FunctionLiteral, FunctionType, HashLiteral,
+    HashType, Identifer, IfExpression, IndexExpression, InfixExpression, IntegerLiteral,
+    LetStatement, Node, PrefixExpression, Program, ReturnStatement, StatementType, StringLiteral,
+    TypeExpression,
 };
-use super::lexer::Lexer;
-use super::token::{Token, TokenType};
-use crate::ast::Node;
+use super::diagnostic::Diagnostic;
+use super::error_code::ErrorCode;
+use super::lexer::{self, Lexer};
+use super::token::{Span, Token, TokenType};
+use num_bigint::BigInt;
 use std::collections::HashMap;
-use std::mem;
+use std::convert::TryFrom;
+
+/// Deepest nesting of expressions and types the parsers accept. Every pass
+/// over a tree recurses into it, so input nested deeper than this is rejected
+/// before it can exhaust the stack.
+pub const MAX_NESTING_DEPTH: usize = 128;
 
 // type alias
-type PrefixParseFn = fn() -> ExpressionType;
-type InfixParseFn = fn(ExpressionType) -> ExpressionType;
+pub type PrefixParseFn<I> = fn(&mut Parser<I>) -> Option<ExpressionType>;
+pub type InfixParseFn<I> = fn(&mut Parser<I>, ExpressionType) -> Option<ExpressionType>;
 
+/// Parses any token iterator, such as a `Lexer`. An iterator that ends without
+/// an `Eof` token is treated as if it had produced one.
 #[derive(Debug, Clone)]
-struct Parser {
-    lexer: Lexer,
-    errors: Vec<String>,
+pub struct Parser<I = Lexer> {
+    tokens: I,
+    errors: Vec<Diagnostic>,
     cur_token: Option<Box<Token>>,
     peek_token: Option<Box<Token>>,
+    big_integers: bool, // i64に収まらないリテラルをBigIntegerLiteralにする
+    depth: usize,       // 組み立て中の式や型の入れ子の深さ
+    too_deep: bool,     // 入れ子が深すぎて残りを読み飛ばした
 
-    prefix_parse_fns: HashMap<TokenType, PrefixParseFn>,
-    infix_parse_fns: HashMap<TokenType, InfixParseFn>,
+    prefix_parse_fns: HashMap<TokenType, PrefixParseFn<I>>,
+    infix_parse_fns: HashMap<TokenType, InfixParseFn<I>>,
 }
 
-impl Parser {
-    fn new(lexer: Lexer) -> Self {
+impl<I: Iterator<Item = Token>> Parser<I> {
+    pub fn new(tokens: I) -> Self {
         let mut parser = Parser {
-            lexer,
+            tokens,
             errors: Vec::new(),
             cur_token: Default::default(),
             peek_token: Default::default(),
+            big_integers: false,
+            depth: 0,
+            too_deep: false,
             prefix_parse_fns: Default::default(),
             infix_parse_fns: Default::default(),
         };
+        parser.register_prefix(TokenType::Ident, Self::parse_identifer);
+        parser.register_prefix(TokenType::Int, Self::parse_integer_literal);
+        parser.register_prefix(TokenType::Float, Self::parse_float_literal);
+        parser.register_prefix(TokenType::True, Self::parse_boolean);
+        parser.register_prefix(TokenType::False, Self::parse_boolean);
+        parser.register_prefix(TokenType::String, Self::parse_string_literal);
+        parser.register_prefix(TokenType::Lbracket, Self::parse_array_literal);
+        parser.register_prefix(TokenType::Lbrace, Self::parse_hash_literal);
+        parser.register_prefix(TokenType::Bang, Self::parse_prefix_expression);
+        parser.register_prefix(TokenType::Minus, Self::parse_prefix_expression);
+        parser.register_prefix(TokenType::Lparen, Self::parse_grouped_expression);
+        parser.register_prefix(TokenType::If, Self::parse_if_expression);
+        parser.register_prefix(TokenType::Function, Self::parse_function_literal);
+        for token_type in [
+            TokenType::Plus,
+            TokenType::Minus,
+            TokenType::Slash,
+            TokenType::Asterisk,
+            TokenType::Equal,
+            TokenType::NotEqual,
+            TokenType::Lt,
+            TokenType::Gt,
+        ] {
+            parser.register_infix(token_type, Self::parse_infix_expression);
+        }
+        parser.register_infix(TokenType::Lparen, Self::parse_call_expression);
+        parser.register_infix(TokenType::Lbracket, Self::parse_index_expression);
         parser.next_token();
         parser.next_token();
         parser
     }
 
-    fn errors(&self) -> Vec<String> {
-        self.errors.clone()
+    /// Accept integer literals of any size. Literals that do not fit in i64
+    /// become `BigIntegerLiteral`s instead of an overflow error.
+    pub fn with_big_integers(mut self, enabled: bool) -> Self {
+        self.big_integers = enabled;
+        self
+    }
+
+    /// Errors for illegal tokens and for the parser itself, in source order.
+    pub fn errors(&self) -> Vec<Diagnostic> {
+        let mut errors = self.errors.clone();
+        errors.sort_by_key(|diagnostic| diagnostic.span.start);
+        errors
+    }
+
+    fn illegal_token_error(&mut self) {
+        let token = match &self.peek_token {
+            Some(token) => token,
+            None => return,
+        };
+        self.errors
+            .push(lexer::illegal_token_error(&token.literal, token.span));
     }
 
     fn peek_error(&mut self, token_type: &TokenType) {
+        // illegal tokens are already reported by illegal_token_error()
+        if self.peek_token_is(&TokenType::Illegal) {
+            return;
+        }
         let message = format!(
-            "expected next token to be {:?}, got {:?} instead",
+            "expected next token to be {}, got {} instead",
             token_type,
             discover_token_type(&self.peek_token)
         );
-        self.errors.push(message);
+        self.errors.push(
+            Diagnostic::error(message, discover_span(&self.peek_token))
+                .with_code(ErrorCode::UnexpectedToken),
+        );
+    }
+
+    fn no_prefix_parse_fn_error(&mut self) {
+        if self.cur_token_is(TokenType::Illegal) {
+            return;
+        }
+        let message = format!(
+            "expected an expression, got {} instead",
+            discover_token_type(&self.cur_token)
+        );
+        self.errors.push(
+            Diagnostic::error(message, discover_span(&self.cur_token))
+                .with_code(ErrorCode::ExpectedExpression),
+        );
+    }
+
+    // 入れ子を1段深くする。上限を超えたら報告し、残りの入力はすべて読み飛ばす
+    fn enter(&mut self) -> Option<()> {
+        if self.depth < MAX_NESTING_DEPTH {
+            self.depth += 1;
+            return Some(());
+        }
+        self.errors
+            .push(too_deep_error(discover_span(&self.cur_token)));
+        self.too_deep = true;
+        while !self.cur_token_is(TokenType::Eof) {
+            self.next_token();
+        }
+        None
     }
 
     fn next_token(&mut self) {
-        self.cur_token = mem::replace(&mut self.peek_token, None);
-        self.peek_token = Some(Box::from(self.lexer.next_token()));
+        let end = discover_span(&self.peek_token).end;
+        self.cur_token = self.peek_token.take();
+        let token = self.tokens.next().unwrap_or_else(|| Token {
+            token_type: TokenType::Eof,
+            literal: String::new(),
+            span: Span::new(end, end),
+        });
+        self.peek_token = Some(Box::from(token));
+        if self.peek_token_is(&TokenType::Illegal) {
+            self.illegal_token_error();
+        }
     }
 
     fn expect_peek(&mut self, token: TokenType) -> bool {
@@ -75,17 +184,39 @@ impl Parser {
 
     /// Repeat to read by calling next_token() token until reaching TokenType::Eof.
     /// Every time it repeats, call parse_statement() that analysis statement.
-    fn parse_program(&mut self) -> Program {
+    /// Statements that fail to parse are left out and reported through errors().
+    pub fn parse_program(&mut self) -> Program {
         let mut program: Vec<StatementType> = Vec::new();
-        while discover_token_type(&self.cur_token) != TokenType::Eof {
-            let statement = self.parse_statement();
-            program.push(statement);
-            self.next_token();
+        while let Some(statement) = self.parse_top_level_statement() {
+            program.extend(statement);
         }
         program
     }
 
-    fn parse_statement(&mut self) -> StatementType {
+    /// One iteration of `parse_program`: parse the statement at the current
+    /// token and advance to the first token of the next one. Returns `None` at
+    /// the end of input, and `Some(None)` for a statement that failed to parse.
+    pub fn parse_top_level_statement(&mut self) -> Option<Option<StatementType>> {
+        if discover_token_type(&self.cur_token) == TokenType::Eof {
+            return None;
+        }
+        let statement = self.parse_statement();
+        self.next_token();
+        Some(statement)
+    }
+
+    /// Span of the current token. Between top-level statements this is where
+    /// the next statement starts.
+    pub fn current_span(&self) -> Span {
+        discover_span(&self.cur_token)
+    }
+
+    /// Remove and return the errors reported so far, in the order they were found.
+    pub fn take_errors(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn parse_statement(&mut self) -> Option<StatementType> {
         match discover_token_type(&self.cur_token) {
             TokenType::Let => self.parse_let_statement(),
             TokenType::Return => self.parse_return_statement(),
@@ -96,103 +227,862 @@ impl Parser {
     /// Construct LetStatement node based on token(LET) what we focus on.
     /// Provide assertion following tokens and advance a token by calling expect_peek().
     /// First, I expect TokenType::Ident. It is used for constructing Identifer node.
-    /// Then, I expect equal and jump until semicolon.
-    fn parse_let_statement(&mut self) -> StatementType {
-        let first_token = match mem::replace(&mut self.cur_token, None) {
+    /// An optional `: <type>` annotation may follow the name.
+    /// Then, I expect equal and parse the value expression up to the optional semicolon.
+    fn parse_let_statement(&mut self) -> Option<StatementType> {
+        let first_token = match self.cur_token.take() {
             Some(token) => token,
             None => panic!("not found current token"),
         };
 
         if !self.expect_peek(TokenType::Ident) {
-            // TODO: return Result error
+            self.skip_statement();
+            return None;
         }
         self.next_token();
 
-        let second_token = match mem::replace(&mut self.cur_token, None) {
+        let second_token = match self.cur_token.take() {
             Some(token) => token,
             None => panic!("not found current token"),
         };
+        let name = Identifer::new(second_token);
 
-        let ident = Identifer::new(second_token);
-        let statement = LetStatement {
-            token: first_token,
-            name: ident.clone(),
-            value: ExpressionType::Identifer(ident),
+        let annotation = if self.peek_token_is(&TokenType::Colon) {
+            self.next_token();
+            self.next_token();
+            match self.parse_type() {
+                Some(annotation) => Some(annotation),
+                None => {
+                    self.skip_statement();
+                    return None;
+                }
+            }
+        } else {
+            None
         };
 
         if !self.expect_peek(TokenType::Assign) {
-            // TODO: return Result error
+            self.skip_statement();
+            return None;
         }
         self.next_token();
+        self.next_token();
 
-        while !self.cur_token_is(TokenType::Semicolon) {
+        let value = match self.parse_expression(OperatorPriority::Lowest) {
+            Some(value) => value,
+            None => {
+                self.skip_statement();
+                return None;
+            }
+        };
+        if self.peek_token_is(&TokenType::Semicolon) {
             self.next_token();
         }
 
-        StatementType::LetStatement(statement)
+        Some(StatementType::LetStatement(LetStatement {
+            token: first_token,
+            name,
+            annotation,
+            value,
+        }))
     }
 
-    fn parse_return_statement(&mut self) -> StatementType {
-        let token = match mem::replace(&mut self.cur_token, None) {
+    fn parse_return_statement(&mut self) -> Option<StatementType> {
+        let token = match self.cur_token.take() {
             Some(token) => token,
             None => panic!("not found current token"),
         };
-        let statement = ReturnStatement {
-            token,
-            value: ExpressionType::Illegal,
-        };
         self.next_token();
-        while !self.cur_token_is(TokenType::Semicolon) {
-            self.next_token()
+
+        let value = match self.parse_expression(OperatorPriority::Lowest) {
+            Some(value) => value,
+            None => {
+                self.skip_statement();
+                return None;
+            }
+        };
+        if self.peek_token_is(&TokenType::Semicolon) {
+            self.next_token();
+        }
+
+        Some(StatementType::ReturnStatement(ReturnStatement {
+            token,
+            value,
+        }))
+    }
+
+    /// Advance until the semicolon closing the current statement, or the end of input.
+    fn skip_statement(&mut self) {
+        while !self.cur_token_is(TokenType::Semicolon) && !self.cur_token_is(TokenType::Eof) {
+            self.next_token();
         }
-        StatementType::ReturnStatement(statement)
     }
 
-    fn register_prefix(&mut self, token_type: TokenType, func: PrefixParseFn) {
+    pub fn register_prefix(&mut self, token_type: TokenType, func: PrefixParseFn<I>) {
         self.prefix_parse_fns.insert(token_type, func);
     }
 
-    fn register_infix(&mut self, token_type: TokenType, func: InfixParseFn) {
+    pub fn register_infix(&mut self, token_type: TokenType, func: InfixParseFn<I>) {
         self.infix_parse_fns.insert(token_type, func);
     }
 
-    fn parse_expression_statement(&mut self) -> StatementType {
-        let token = match mem::replace(&mut self.cur_token, None) {
+    fn parse_expression_statement(&mut self) -> Option<StatementType> {
+        let token = match self.cur_token.clone() {
             Some(token) => token,
             None => panic!("not found current token"),
         };
-        let statement = ExpressionStatement {
-            token: token,
-            expression: self.parse_expression(),
-        };
+        let expression = self.parse_expression(OperatorPriority::Lowest);
         if self.peek_token_is(&TokenType::Semicolon) {
             self.next_token();
         };
-        StatementType::ExpressionStatement(statement)
+        Some(StatementType::ExpressionStatement(ExpressionStatement {
+            token,
+            expression: expression?,
+        }))
+    }
+
+    fn parse_expression(&mut self, precedence: OperatorPriority) -> Option<ExpressionType> {
+        let depth = self.depth;
+        let expression = self.parse_nested_expression(precedence);
+        self.depth = depth;
+        expression
+    }
+
+    // 左結合の演算子や呼び出しが続くと、読んだ式は左側へ1段ずつ深くなる
+    fn parse_nested_expression(&mut self, precedence: OperatorPriority) -> Option<ExpressionType> {
+        self.enter()?;
+        let prefix = match self
+            .prefix_parse_fns
+            .get(&discover_token_type(&self.cur_token))
+        {
+            Some(prefix) => *prefix,
+            None => {
+                self.no_prefix_parse_fn_error();
+                return None;
+            }
+        };
+        let mut left = prefix(self)?;
+
+        while !self.peek_token_is(&TokenType::Semicolon) && precedence < self.peek_precedence() {
+            let infix = match self
+                .infix_parse_fns
+                .get(&discover_token_type(&self.peek_token))
+            {
+                Some(infix) => *infix,
+                None => return Some(left),
+            };
+            self.next_token();
+            self.enter()?;
+            left = infix(self, left)?;
+        }
+        Some(left)
+    }
+
+    fn peek_precedence(&self) -> OperatorPriority {
+        precedence_of(&discover_token_type(&self.peek_token))
+    }
+
+    fn cur_precedence(&self) -> OperatorPriority {
+        precedence_of(&discover_token_type(&self.cur_token))
+    }
+
+    fn parse_identifer(&mut self) -> Option<ExpressionType> {
+        let token = self.cur_token.clone()?;
+        Some(ExpressionType::Identifer(Identifer::new(token)))
+    }
+
+    /// Convert the literal to i64 here so that a literal out of range is
+    /// reported with its span instead of failing at runtime.
+    fn parse_integer_literal(&mut self) -> Option<ExpressionType> {
+        let token = self.cur_token.clone()?;
+        match integer_literal(token, self.big_integers) {
+            Ok(literal) => Some(literal),
+            Err(err) => {
+                self.errors.push(err);
+                None
+            }
+        }
+    }
+
+    fn parse_float_literal(&mut self) -> Option<ExpressionType> {
+        let token = self.cur_token.clone()?;
+        match float_literal(token) {
+            Ok(literal) => Some(literal),
+            Err(err) => {
+                self.errors.push(err);
+                None
+            }
+        }
+    }
+
+    fn parse_boolean(&mut self) -> Option<ExpressionType> {
+        let token = self.cur_token.clone()?;
+        let value = token.token_type == TokenType::True;
+        Some(ExpressionType::Boolean(Boolean { token, value }))
+    }
+
+    fn parse_string_literal(&mut self) -> Option<ExpressionType> {
+        let token = self.cur_token.clone()?;
+        match string_literal(token) {
+            Ok(literal) => Some(literal),
+            Err(err) => {
+                self.errors.push(err);
+                None
+            }
+        }
+    }
+
+    fn parse_array_literal(&mut self) -> Option<ExpressionType> {
+        let token = self.cur_token.clone()?;
+        let elements = self.parse_expression_list(TokenType::Rbracket)?;
+        Some(ExpressionType::ArrayLiteral(ArrayLiteral {
+            span: token.span.to(discover_span(&self.cur_token)),
+            token,
+            elements,
+        }))
+    }
+
+    fn parse_hash_literal(&mut self) -> Option<ExpressionType> {
+        let token = self.cur_token.clone()?;
+        let mut pairs = Vec::new();
+        if self.peek_token_is(&TokenType::Rbrace) {
+            self.next_token();
+        } else {
+            loop {
+                self.next_token();
+                let key = self.parse_expression(OperatorPriority::Lowest)?;
+                if !self.expect_peek(TokenType::Colon) {
+                    return None;
+                }
+                self.next_token();
+                self.next_token();
+                let value = self.parse_expression(OperatorPriority::Lowest)?;
+                pairs.push((key, value));
+                if !self.peek_token_is(&TokenType::Comma) {
+                    break;
+                }
+                self.next_token();
+            }
+            if !self.expect_peek(TokenType::Rbrace) {
+                return None;
+            }
+            self.next_token();
+        }
+        Some(ExpressionType::HashLiteral(HashLiteral {
+            span: token.span.to(discover_span(&self.cur_token)),
+            token,
+            pairs,
+        }))
+    }
+
+    fn parse_prefix_expression(&mut self) -> Option<ExpressionType> {
+        let token = self.cur_token.clone()?;
+        if token.token_type == TokenType::Minus && self.peek_token_is(&TokenType::Int) {
+            if let Some(literal) = self.parse_min_integer(&token) {
+                return Some(literal);
+            }
+        }
+        self.next_token();
+        let right = self.parse_expression(OperatorPriority::Prefix)?;
+        Some(ExpressionType::PrefixExpression(PrefixExpression {
+            operator: token.literal.clone(),
+            token,
+            right: Box::new(right),
+        }))
+    }
+
+    fn parse_min_integer(&mut self, minus: &Token) -> Option<ExpressionType> {
+        let literal = min_integer(minus, self.peek_token.as_ref()?)?;
+        self.next_token();
+        Some(literal)
+    }
+
+    fn parse_infix_expression(&mut self, left: ExpressionType) -> Option<ExpressionType> {
+        let token = self.cur_token.clone()?;
+        let precedence = self.cur_precedence();
+        self.next_token();
+        let right = self.parse_expression(precedence)?;
+        Some(ExpressionType::InfixExpression(InfixExpression {
+            operator: token.literal.clone(),
+            token,
+            left: Box::new(left),
+            right: Box::new(right),
+        }))
+    }
+
+    fn parse_grouped_expression(&mut self) -> Option<ExpressionType> {
+        self.next_token();
+        let expression = self.parse_expression(OperatorPriority::Lowest)?;
+        if !self.expect_peek(TokenType::Rparen) {
+            return None;
+        }
+        self.next_token();
+        Some(expression)
+    }
+
+    fn parse_if_expression(&mut self) -> Option<ExpressionType> {
+        let token = self.cur_token.clone()?;
+        if !self.expect_peek(TokenType::Lparen) {
+            return None;
+        }
+        self.next_token();
+        self.next_token();
+        let condition = self.parse_expression(OperatorPriority::Lowest)?;
+
+        if !self.expect_peek(TokenType::Rparen) {
+            return None;
+        }
+        self.next_token();
+        if !self.expect_peek(TokenType::Lbrace) {
+            return None;
+        }
+        self.next_token();
+        let consequence = self.parse_block_statement()?;
+
+        let mut alternative = None;
+        if self.peek_token_is(&TokenType::Else) {
+            self.next_token();
+            if !self.expect_peek(TokenType::Lbrace) {
+                return None;
+            }
+            self.next_token();
+            alternative = Some(self.parse_block_statement()?);
+        }
+
+        Some(ExpressionType::IfExpression(IfExpression {
+            token,
+            condition: Box::new(condition),
+            consequence,
+            alternative,
+        }))
+    }
+
+    /// Parse statements from `{` up to the matching `}`, which becomes the current token.
+    fn parse_block_statement(&mut self) -> Option<BlockStatement> {
+        let token = self.cur_token.clone()?;
+        let mut statements = Vec::new();
+        self.next_token();
+
+        while !self.cur_token_is(TokenType::Rbrace) {
+            if self.cur_token_is(TokenType::Eof) {
+                self.errors.push(
+                    Diagnostic::error(
+                        "unclosed block: expected `}` before end of input",
+                        token.span,
+                    )
+                    .with_code(ErrorCode::UnexpectedToken),
+                );
+                return None;
+            }
+            match self.parse_statement() {
+                Some(statement) => statements.push(statement),
+                // 入力の残りはもうない
+                None if self.too_deep => return None,
+                None => {}
+            }
+            self.next_token();
+        }
+
+        Some(BlockStatement {
+            span: token.span.to(discover_span(&self.cur_token)),
+            token,
+            statements,
+        })
+    }
+
+    fn parse_function_literal(&mut self) -> Option<ExpressionType> {
+        let token = self.cur_token.clone()?;
+        if !self.expect_peek(TokenType::Lparen) {
+            return None;
+        }
+        self.next_token();
+        let (parameters, parameter_types) = self.parse_function_parameters()?;
+        let return_type = if self.peek_token_is(&TokenType::Arrow) {
+            self.next_token();
+            self.next_token();
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+
+        if !self.expect_peek(TokenType::Lbrace) {
+            return None;
+        }
+        self.next_token();
+        let body = self.parse_block_statement()?;
+
+        Some(ExpressionType::FunctionLiteral(FunctionLiteral {
+            token,
+            parameters,
+            parameter_types,
+            return_type,
+            body,
+        }))
     }
 
-    fn parse_expression(&mut self) -> ExpressionType {
-        unimplemented!();
+    fn parse_function_parameters(
+        &mut self,
+    ) -> Option<(Vec<Identifer>, Vec<Option<TypeExpression>>)> {
+        let mut parameters = Vec::new();
+        let mut parameter_types = Vec::new();
+        if self.peek_token_is(&TokenType::Rparen) {
+            self.next_token();
+            return Some((parameters, parameter_types));
+        }
+
+        loop {
+            if !self.expect_peek(TokenType::Ident) {
+                return None;
+            }
+            self.next_token();
+            parameters.push(Identifer::new(self.cur_token.clone()?));
+            if self.peek_token_is(&TokenType::Colon) {
+                self.next_token();
+                self.next_token();
+                parameter_types.push(Some(self.parse_type()?));
+            } else {
+                parameter_types.push(None);
+            }
+
+            if !self.peek_token_is(&TokenType::Comma) {
+                break;
+            }
+            self.next_token();
+        }
+
+        if !self.expect_peek(TokenType::Rparen) {
+            return None;
+        }
+        self.next_token();
+        Some((parameters, parameter_types))
+    }
+
+    /// Parse a type annotation starting at the current token: a type name such
+    /// as `int`, `fn(<types>) -> <type>`, `[<type>]` or `{<type>: <type>}`.
+    fn parse_type(&mut self) -> Option<TypeExpression> {
+        let depth = self.depth;
+        let ty = self.parse_nested_type();
+        self.depth = depth;
+        ty
+    }
+
+    fn parse_nested_type(&mut self) -> Option<TypeExpression> {
+        self.enter()?;
+        match discover_token_type(&self.cur_token) {
+            TokenType::Ident => Some(TypeExpression::Named(Identifer::new(
+                self.cur_token.clone()?,
+            ))),
+            TokenType::Function => {
+                let token = self.cur_token.clone()?;
+                if !self.expect_peek(TokenType::Lparen) {
+                    return None;
+                }
+                self.next_token();
+                let mut parameters = Vec::new();
+                if self.peek_token_is(&TokenType::Rparen) {
+                    self.next_token();
+                } else {
+                    loop {
+                        self.next_token();
+                        parameters.push(self.parse_type()?);
+                        if !self.peek_token_is(&TokenType::Comma) {
+                            break;
+                        }
+                        self.next_token();
+                    }
+                    if !self.expect_peek(TokenType::Rparen) {
+                        return None;
+                    }
+                    self.next_token();
+                }
+                if !self.expect_peek(TokenType::Arrow) {
+                    return None;
+                }
+                self.next_token();
+                self.next_token();
+                let result = self.parse_type()?;
+                Some(TypeExpression::Function(FunctionType {
+                    token,
+                    parameters,
+                    result: Box::new(result),
+                }))
+            }
+            TokenType::Lbracket => {
+                let token = self.cur_token.clone()?;
+                self.next_token();
+                let element = self.parse_type()?;
+                if !self.expect_peek(TokenType::Rbracket) {
+                    return None;
+                }
+                self.next_token();
+                Some(TypeExpression::Array(ArrayType {
+                    span: token.span.to(discover_span(&self.cur_token)),
+                    token,
+                    element: Box::new(element),
+                }))
+            }
+            TokenType::Lbrace => {
+                let token = self.cur_token.clone()?;
+                self.next_token();
+                let key = self.parse_type()?;
+                if !self.expect_peek(TokenType::Colon) {
+                    return None;
+                }
+                self.next_token();
+                self.next_token();
+                let value = self.parse_type()?;
+                if !self.expect_peek(TokenType::Rbrace) {
+                    return None;
+                }
+                self.next_token();
+                Some(TypeExpression::Hash(HashType {
+                    span: token.span.to(discover_span(&self.cur_token)),
+                    token,
+                    key: Box::new(key),
+                    value: Box::new(value),
+                }))
+            }
+            TokenType::Illegal => None,
+            token_type => {
+                self.errors.push(
+                    Diagnostic::error(
+                        format!("expected a type, got {} instead", token_type),
+                        discover_span(&self.cur_token),
+                    )
+                    .with_code(ErrorCode::UnexpectedToken),
+                );
+                None
+            }
+        }
+    }
+
+    fn parse_call_expression(&mut self, function: ExpressionType) -> Option<ExpressionType> {
+        let token = self.cur_token.clone()?;
+        let arguments = self.parse_expression_list(TokenType::Rparen)?;
+        Some(ExpressionType::CallExpression(CallExpression {
+            span: function.span().to(discover_span(&self.cur_token)),
+            token,
+            function: Box::new(function),
+            arguments,
+        }))
+    }
+
+    fn parse_index_expression(&mut self, left: ExpressionType) -> Option<ExpressionType> {
+        let token = self.cur_token.clone()?;
+        self.next_token();
+        let index = self.parse_expression(OperatorPriority::Lowest)?;
+        if !self.expect_peek(TokenType::Rbracket) {
+            return None;
+        }
+        self.next_token();
+        Some(ExpressionType::IndexExpression(IndexExpression {
+            span: left.span().to(discover_span(&self.cur_token)),
+            token,
+            left: Box::new(left),
+            index: Box::new(index),
+        }))
+    }
+
+    /// Parse comma separated expressions up to `end`, which becomes the
+    /// current token. Used for call arguments and array elements.
+    fn parse_expression_list(&mut self, end: TokenType) -> Option<Vec<ExpressionType>> {
+        let mut list = Vec::new();
+        if self.peek_token_is(&end) {
+            self.next_token();
+            return Some(list);
+        }
+
+        self.next_token();
+        list.push(self.parse_expression(OperatorPriority::Lowest)?);
+        while self.peek_token_is(&TokenType::Comma) {
+            self.next_token();
+            self.next_token();
+            list.push(self.parse_expression(OperatorPriority::Lowest)?);
+        }
+
+        if !self.expect_peek(end) {
+            return None;
+        }
+        self.next_token();
+        Some(list)
+    }
+}
+
+pub(crate) fn too_deep_error(span: Span) -> Diagnostic {
+    Diagnostic::error(
+        format!(
+            "expression nested too deeply: the limit is {} levels",
+            MAX_NESTING_DEPTH
+        ),
+        span,
+    )
+    .with_code(ErrorCode::NestingTooDeep)
+    .with_help("move inner parts of the expression into `let` bindings")
+}
+
+/// Convert an Int token to i64 when the program is parsed, so that a literal
+/// out of range is reported with its span instead of failing at runtime.
+/// With `big_integers` such a literal becomes a `BigIntegerLiteral` instead.
+pub(crate) fn integer_literal(
+    token: Box<Token>,
+    big_integers: bool,
+) -> Result<ExpressionType, Diagnostic> {
+    let value = parse_int(&token.literal)
+        .and_then(|value| i64::try_from(value).map_err(|_| IntLiteralError::Overflow));
+    match value {
+        Ok(value) => Ok(ExpressionType::IntegerLiteral(IntegerLiteral {
+            token,
+            value,
+        })),
+        Err(IntLiteralError::Overflow) if big_integers => {
+            let value = parse_big_int(&token.literal);
+            Ok(ExpressionType::BigIntegerLiteral(BigIntegerLiteral {
+                token,
+                value,
+            }))
+        }
+        Err(err) => Err(err.diagnostic(&token)),
+    }
+}
+
+pub(crate) fn float_literal(token: Box<Token>) -> Result<ExpressionType, Diagnostic> {
+    match token.literal.replace('_', "").parse::<f64>() {
+        Ok(value) if value.is_finite() => {
+            Ok(ExpressionType::FloatLiteral(FloatLiteral { token, value }))
+        }
+        Ok(_) => Err(Diagnostic::error(
+            format!("float literal `{}` is out of range for f64", token.literal),
+            token.span,
+        )
+        .with_code(ErrorCode::FloatOverflow)
+        .with_help(format!("the largest float is {:e}", f64::MAX))),
+        Err(_) => Err(Diagnostic::error(
+            format!("invalid float literal `{}`", token.literal),
+            token.span,
+        )
+        .with_code(ErrorCode::InvalidFloatLiteral)),
     }
 }
 
-enum OperatorPriority {
-    LOWEST = 1,
-    EQUALS = 2,      // ==
-    LESSGREATER = 3, // > or <
-    SUM = 4,         // +
-    PRODUCT = 5,     // *
-    PREFIX = 6,      // -X or !X
-    CALL = 7,        // myFunction(X)
+/// Decode the escapes of a String token: `\"`, `\\`, `\n`, `\r`, `\t` and
+/// `\u{...}` with one to six hex digits.
+pub(crate) fn string_literal(token: Box<Token>) -> Result<ExpressionType, Diagnostic> {
+    let literal = &token.literal;
+    let body = &literal[1..literal.len() - 1];
+    let mut value = String::with_capacity(body.len());
+    let mut chars = body.char_indices().peekable();
+    while let Some((offset, ch)) = chars.next() {
+        if ch != '\\' {
+            value.push(ch);
+            continue;
+        }
+        let decoded = match chars.next() {
+            Some((_, '"')) => Some('"'),
+            Some((_, '\\')) => Some('\\'),
+            Some((_, 'n')) => Some('\n'),
+            Some((_, 'r')) => Some('\r'),
+            Some((_, 't')) => Some('\t'),
+            Some((_, 'u')) if chars.peek().map(|(_, c)| *c) == Some('{') => {
+                chars.next();
+                let mut digits = String::new();
+                while let Some((_, c)) = chars.next_if(|(_, c)| c.is_ascii_hexdigit()) {
+                    digits.push(c);
+                }
+                match chars.next() {
+                    Some((_, '}')) if (1..=6).contains(&digits.len()) => {
+                        u32::from_str_radix(&digits, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        match decoded {
+            Some(ch) => value.push(ch),
+            None => {
+                // エスケープの \ から、読んだところまで
+                let end = chars.peek().map_or(body.len(), |(end, _)| *end);
+                let start = token.span.start + 1 + offset;
+                return Err(Diagnostic::error(
+                    format!("invalid escape `{}` in string literal", &body[offset..end]),
+                    Span::new(start, start + (end - offset)),
+                )
+                .with_code(ErrorCode::InvalidEscape)
+                .with_help(
+                    "the escapes are `\\\"`, `\\\\`, `\\n`, `\\r`, `\\t` and `\\u{...}`; \
+                     write `\\\\` for a backslash",
+                ));
+            }
+        }
+    }
+    Ok(ExpressionType::StringLiteral(StringLiteral {
+        token,
+        value,
+    }))
+}
+
+// 9223372036854775808 は -がついた時だけ i64::MIN として表せるので、
+// - と Int token を1つの IntegerLiteral にまとめる
+pub(crate) fn min_integer(minus: &Token, literal: &Token) -> Option<ExpressionType> {
+    if parse_int(&literal.literal) != Ok(i64::MIN.unsigned_abs()) {
+        return None;
+    }
+    let token = Token {
+        token_type: TokenType::Int,
+        literal: format!("-{}", literal.literal),
+        span: minus.span.to(literal.span),
+    };
+    Some(ExpressionType::IntegerLiteral(IntegerLiteral {
+        token: Box::new(token),
+        value: i64::MIN,
+    }))
+}
+
+/// Why the literal of an Int token is not a valid integer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IntLiteralError {
+    Overflow,
+    // 不正な数字の、リテラル中でのバイト位置
+    InvalidDigit {
+        offset: usize,
+        digit: char,
+        radix: u32,
+    },
+    NoDigits {
+        prefix_len: usize,
+    },
+}
+
+impl IntLiteralError {
+    fn diagnostic(&self, token: &Token) -> Diagnostic {
+        let span = token.span;
+        match *self {
+            IntLiteralError::Overflow => Diagnostic::error(
+                format!("integer literal `{}` is too large for i64", token.literal),
+                span,
+            )
+            .with_code(ErrorCode::IntegerOverflow)
+            .with_help(format!("the largest integer is {}", i64::MAX)),
+            IntLiteralError::InvalidDigit {
+                offset,
+                digit,
+                radix,
+            } => {
+                let start = span.start + offset;
+                Diagnostic::error(
+                    format!("invalid digit `{}` in {} literal", digit, radix_name(radix)),
+                    Span::new(start, start + digit.len_utf8()),
+                )
+                .with_code(ErrorCode::InvalidIntegerLiteral)
+            }
+            IntLiteralError::NoDigits { prefix_len } => Diagnostic::error(
+                format!(
+                    "missing digits after the integer prefix `{}`",
+                    &token.literal[..prefix_len]
+                ),
+                span,
+            )
+            .with_code(ErrorCode::InvalidIntegerLiteral),
+        }
+    }
+}
+
+fn radix_name(radix: u32) -> &'static str {
+    match radix {
+        2 => "binary",
+        8 => "octal",
+        16 => "hexadecimal",
+        _ => "decimal",
+    }
+}
+
+/// Parse a decimal, `0x`, `0o` or `0b` literal whose digits may be separated by `_`.
+/// The magnitude is returned as u64 so that `-9223372036854775808` can be read.
+fn parse_int(literal: &str) -> Result<u64, IntLiteralError> {
+    let (radix, prefix_len) = match literal.get(..2) {
+        Some("0x") => (16, 2),
+        Some("0o") => (8, 2),
+        Some("0b") => (2, 2),
+        _ => (10, 0),
+    };
+    let digits = &literal[prefix_len..];
+
+    // 桁あふれより先に、不正な数字を報告する
+    if let Some((offset, digit)) = digits
+        .char_indices()
+        .find(|(_, c)| *c != '_' && !c.is_digit(radix))
+    {
+        return Err(IntLiteralError::InvalidDigit {
+            offset: prefix_len + offset,
+            digit,
+            radix,
+        });
+    }
+    if !digits.chars().any(|c| c != '_') {
+        return Err(IntLiteralError::NoDigits { prefix_len });
+    }
+
+    digits
+        .chars()
+        .filter_map(|c| c.to_digit(radix))
+        .try_fold(0u64, |value, digit| {
+            value
+                .checked_mul(u64::from(radix))?
+                .checked_add(u64::from(digit))
+        })
+        .ok_or(IntLiteralError::Overflow)
+}
+
+// parse_intがOverflowを返した、数字の正しいリテラルだけを受け取る
+fn parse_big_int(literal: &str) -> BigInt {
+    let (radix, digits) = match literal.get(..2) {
+        Some("0x") => (16, &literal[2..]),
+        Some("0o") => (8, &literal[2..]),
+        Some("0b") => (2, &literal[2..]),
+        _ => (10, literal),
+    };
+    let digits = digits.replace('_', "");
+    BigInt::parse_bytes(digits.as_bytes(), radix).expect("digits are validated by parse_int")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OperatorPriority {
+    Lowest = 1,
+    Equals = 2,      // ==
+    LessGreater = 3, // > or <
+    Sum = 4,         // +
+    Product = 5,     // *
+    Prefix = 6,      // -X or !X
+    Call = 7,        // myFunction(X)
+    Index = 8,       // array[index]
+}
+
+pub(crate) fn precedence_of(token_type: &TokenType) -> OperatorPriority {
+    match token_type {
+        TokenType::Equal | TokenType::NotEqual => OperatorPriority::Equals,
+        TokenType::Lt | TokenType::Gt => OperatorPriority::LessGreater,
+        TokenType::Plus | TokenType::Minus => OperatorPriority::Sum,
+        TokenType::Slash | TokenType::Asterisk => OperatorPriority::Product,
+        TokenType::Lparen => OperatorPriority::Call,
+        TokenType::Lbracket => OperatorPriority::Index,
+        _ => OperatorPriority::Lowest,
+    }
 }
 
 fn discover_token_type(token: &Option<Box<Token>>) -> TokenType {
-    token.clone().map_or(TokenType::Illegal, |v| v.token_type)
+    token.as_ref().map_or(TokenType::Illegal, |v| v.token_type)
+}
+
+fn discover_span(token: &Option<Box<Token>>) -> Span {
+    token.as_ref().map_or(Span::default(), |v| v.span)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::super::ast::StatementType;
+    use super::super::ast::{Node, StatementType};
+    use super::super::diagnostic::{Renderer, SourceFile};
     use super::super::lexer::Lexer;
     use super::*;
 
@@ -207,7 +1097,7 @@ let foobar = 838383;
         let mut parser = Parser::new(lexer);
 
         let program: Program = parser.parse_program();
-        check_parse_errors(&parser);
+        check_parse_errors(&parser, input);
 
         assert_eq!(
             program.len(),
@@ -234,15 +1124,17 @@ let foobar = 838383;
     }
 
     // check errors stored in the parser struct
-    fn check_parse_errors(parser: &Parser) {
+    fn check_parse_errors<I: Iterator<Item = Token>>(parser: &Parser<I>, input: &str) {
         let errors = parser.errors();
-        if errors.len() == 0 {
+        if errors.is_empty() {
             return;
         }
+        let source = SourceFile::new("test.mk", input);
         println!("parser has {} errors", errors.len());
-        for message in errors {
-            println!("parser error: {}", message);
+        for diagnostic in &errors {
+            print!("{}", Renderer::plain().render(diagnostic, &source));
         }
+        panic!("parser has {} errors", errors.len());
     }
 
     #[test]
@@ -256,7 +1148,7 @@ return 993322;
         let mut parser = Parser::new(lexer);
 
         let program = parser.parse_program();
-        check_parse_errors(&parser);
+        check_parse_errors(&parser, input);
 
         assert_eq!(
             program.len(),
@@ -281,15 +1173,15 @@ return 993322;
         let lexer = Lexer::new(input);
         let mut parser = Parser::new(lexer);
         let program = parser.parse_program();
-        check_parse_errors(&parser);
+        check_parse_errors(&parser, input);
 
-        assert_ne!(
+        assert_eq!(
             program.len(),
             1,
-            "program has enough statements. got={}",
+            "program has not enough statements. got={}",
             program.len()
         );
-        let expression = match program.get(0).unwrap() {
+        let expression = match program.first().unwrap() {
             StatementType::ExpressionStatement(statement) => &statement.expression,
             _ => std::process::exit(1),
         };
@@ -300,4 +1192,426 @@ return 993322;
         assert_eq!(&ident.value, "foobar");
         assert_eq!(ident.token_literal(), "foobar");
     }
+
+    #[test]
+    fn illegal_token_errors() {
+        let input = "let x @ 5;\n@;";
+
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        parser.parse_program();
+
+        let errors = parser.errors();
+        let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec!["unknown character `@`", "unknown character `@`"]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let input = "let x 5;\nlet = 10;\nlet 838383;";
+
+        let lexer = Lexer::new(input);
+        let mut parser = Parser::new(lexer);
+        parser.parse_program();
+
+        let errors = parser.errors();
+        let tests = vec![
+            (
+                "expected next token to be `=`, got integer literal instead",
+                Span::new(6, 7),
+            ),
+            (
+                "expected next token to be identifier, got `=` instead",
+                Span::new(13, 14),
+            ),
+            (
+                "expected next token to be identifier, got integer literal instead",
+                Span::new(23, 29),
+            ),
+        ];
+        assert_eq!(errors.len(), tests.len(), "errors: {:?}", errors);
+        for ((message, span), diagnostic) in tests.into_iter().zip(errors.iter()) {
+            assert_eq!(diagnostic.code, Some(ErrorCode::UnexpectedToken));
+            assert_eq!(diagnostic.message, message);
+            assert_eq!(diagnostic.span, span);
+        }
+    }
+
+    #[test]
+    fn any_token_iterator() {
+        use super::super::byte_lexer::ByteLexer;
+
+        let input = "let x = 5;\nfoobar;";
+        let tokens = ByteLexer::new(input)
+            .filter(|tok| tok.token_type != TokenType::Eof)
+            .map(|tok| tok.to_token());
+
+        // no Eof token at the end of the iterator
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse_program();
+        check_parse_errors(&parser, input);
+
+        assert_eq!(program.len(), 2);
+        assert_eq!(program[0].token_literal(), "let");
+        assert_eq!(program[1].token_literal(), "foobar");
+    }
+
+    #[test]
+    fn integer_literal_expression() {
+        let tests = vec![
+            ("5;", 5),
+            ("1_000_000;", 1_000_000),
+            ("0x1F;", 0x1F),
+            ("0xff_ff;", 0xffff),
+            ("0o17;", 0o17),
+            ("0b1010;", 0b1010),
+            ("9223372036854775807;", i64::MAX),
+        ];
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input);
+            let mut parser = Parser::new(lexer);
+            let program = parser.parse_program();
+            check_parse_errors(&parser, input);
+
+            assert_eq!(program.len(), 1, "input: {}", input);
+            let literal = match &program[0] {
+                StatementType::ExpressionStatement(ExpressionStatement {
+                    expression: ExpressionType::IntegerLiteral(literal),
+                    ..
+                }) => literal,
+                _ => panic!("not an integer literal. input: {}", input),
+            };
+            assert_eq!(literal.value, expected, "input: {}", input);
+            assert_eq!(literal.token_literal(), &input[..input.len() - 1]);
+        }
+    }
+
+    #[test]
+    fn integer_literal_errors() {
+        let tests = vec![
+            (
+                "99999999999999999999;",
+                ErrorCode::IntegerOverflow,
+                "integer literal `99999999999999999999` is too large for i64",
+                Span::new(0, 20),
+            ),
+            (
+                "0x1_0000_0000_0000_0000;",
+                ErrorCode::IntegerOverflow,
+                "integer literal `0x1_0000_0000_0000_0000` is too large for i64",
+                Span::new(0, 23),
+            ),
+            (
+                "let mask = 0b1021;",
+                ErrorCode::InvalidIntegerLiteral,
+                "invalid digit `2` in binary literal",
+                Span::new(15, 16),
+            ),
+            (
+                "12ab;",
+                ErrorCode::InvalidIntegerLiteral,
+                "invalid digit `a` in decimal literal",
+                Span::new(2, 3),
+            ),
+            (
+                "0x;",
+                ErrorCode::InvalidIntegerLiteral,
+                "missing digits after the integer prefix `0x`",
+                Span::new(0, 2),
+            ),
+        ];
+        for (input, code, message, span) in tests {
+            let lexer = Lexer::new(input);
+            let mut parser = Parser::new(lexer);
+            parser.parse_program();
+
+            let errors = parser.errors();
+            assert_eq!(errors.len(), 1, "input: {}, errors: {:?}", input, errors);
+            assert_eq!(errors[0].code, Some(code));
+            assert_eq!(errors[0].message, message);
+            assert_eq!(errors[0].span, span);
+        }
+    }
+
+    fn parse(input: &str) -> Program {
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
+        assert_eq!(parser.errors(), vec![], "input: {:?}", input);
+        program
+    }
+
+    #[test]
+    fn operator_precedence() {
+        let tests = vec![
+            ("-a * b", "((-a) * b)"),
+            ("!-a", "(!(-a))"),
+            ("a + b - c", "((a + b) - c)"),
+            ("a * b / c", "((a * b) / c)"),
+            ("a + b * c + d / e - f", "(((a + (b * c)) + (d / e)) - f)"),
+            ("5 > 4 == 3 < 4", "((5 > 4) == (3 < 4))"),
+            (
+                "3 + 4 * 5 == 3 * 1 + 4 * 5",
+                "((3 + (4 * 5)) == ((3 * 1) + (4 * 5)))",
+            ),
+            ("1 + (2 + 3) + 4", "((1 + (2 + 3)) + 4)"),
+            ("-(5 + 5)", "(-(5 + 5))"),
+            ("!(true == true)", "(!(true == true))"),
+            ("a + add(b * c) + d", "((a + add((b * c))) + d)"),
+            (
+                "add(a, b, 1, 2 * 3, add(6, 7 * 8))",
+                "add(a, b, 1, (2 * 3), add(6, (7 * 8)))",
+            ),
+            ("1.5 * 2 + .5", "((1.5 * 2) + 0.5)"),
+            ("-9223372036854775808", "-9223372036854775808"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(parse(input).string(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn if_function_and_call() {
+        let program = parse("if (x < y) { x } else { y }");
+        let expression = match &program[0] {
+            StatementType::ExpressionStatement(statement) => &statement.expression,
+            _ => panic!("not an expression statement"),
+        };
+        match expression {
+            ExpressionType::IfExpression(expression) => {
+                assert_eq!(expression.condition.string(), "(x < y)");
+                assert_eq!(expression.consequence.string(), "x");
+                assert_eq!(expression.alternative.as_ref().unwrap().string(), "y");
+            }
+            _ => panic!("not an if expression"),
+        }
+        assert_eq!(expression.span(), Span::new(0, 27));
+
+        let program = parse("fn(x, y) { x + y; }(1, 2)");
+        match &program[0] {
+            StatementType::ExpressionStatement(ExpressionStatement {
+                expression: ExpressionType::CallExpression(call),
+                ..
+            }) => {
+                assert_eq!(call.function.string(), "fn(x, y) (x + y)");
+                assert_eq!(call.arguments.len(), 2);
+                assert_eq!(call.span(), Span::new(0, 25));
+            }
+            _ => panic!("not a call expression"),
+        }
+    }
+
+    #[test]
+    fn type_annotations() {
+        let input = "let f: fn(int, fn() -> bool) -> int = fn(a: int, b) -> int { a };";
+        let program = parse(input);
+        assert_eq!(program.string(), input.replace("{ a }", "a"));
+        let statement = match &program[0] {
+            StatementType::LetStatement(statement) => statement,
+            _ => panic!("not a let statement"),
+        };
+        assert_eq!(
+            statement.annotation.as_ref().unwrap().span(),
+            Span::new(7, 35)
+        );
+        match &statement.value {
+            ExpressionType::FunctionLiteral(literal) => {
+                assert_eq!(literal.parameter_types.len(), 2);
+                assert_eq!(literal.parameter_types[0].as_ref().unwrap().string(), "int");
+                assert!(literal.parameter_types[1].is_none());
+                assert_eq!(
+                    literal.return_type.as_ref().unwrap().span(),
+                    Span::new(55, 58)
+                );
+            }
+            _ => panic!("not a function literal"),
+        }
+
+        let tests = vec![
+            (
+                "let x: = 1;",
+                "expected a type, got `=` instead",
+                Span::new(7, 8),
+            ),
+            (
+                "fn(a: fn(int) int) { a }",
+                "expected next token to be `->`, got identifier instead",
+                Span::new(14, 17),
+            ),
+        ];
+        for (input, message, span) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            let errors = parser.errors();
+            assert_eq!(errors[0].message, message, "input: {}", input);
+            assert_eq!(errors[0].span, span, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn float_literal_expression() {
+        let tests = vec![
+            ("2.75", 2.75, "2.75"),
+            (".5", 0.5, "0.5"),
+            ("1e-9", 1e-9, "1e-9"),
+            ("2.5E+3", 2500.0, "2500.0"),
+            ("1_000.000_1", 1000.0001, "1000.0001"),
+            ("0.1", 0.1, "0.1"),
+            ("1e300", 1e300, "1e300"),
+        ];
+        for (input, expected, string) in tests {
+            let program = parse(input);
+            let literal = match &program[0] {
+                StatementType::ExpressionStatement(ExpressionStatement {
+                    expression: ExpressionType::FloatLiteral(literal),
+                    ..
+                }) => literal,
+                _ => panic!("not a float literal. input: {}", input),
+            };
+            assert_eq!(literal.value, expected, "input: {}", input);
+            assert_eq!(literal.token_literal(), input);
+            // 文字列化したものは同じ値に読み戻せる
+            assert_eq!(literal.string(), string, "input: {}", input);
+            match &parse(&literal.string())[0] {
+                StatementType::ExpressionStatement(ExpressionStatement {
+                    expression: ExpressionType::FloatLiteral(again),
+                    ..
+                }) => assert_eq!(again.value.to_bits(), expected.to_bits()),
+                _ => panic!("does not round-trip. input: {}", input),
+            }
+        }
+    }
+
+    #[test]
+    fn float_literal_errors() {
+        let tests = vec![
+            ("1e;", ErrorCode::InvalidFloatLiteral, Span::new(0, 2)),
+            ("1.5x;", ErrorCode::InvalidFloatLiteral, Span::new(0, 4)),
+            ("1e400;", ErrorCode::FloatOverflow, Span::new(0, 5)),
+        ];
+        for (input, code, span) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+
+            let errors = parser.errors();
+            assert_eq!(errors.len(), 1, "input: {}, errors: {:?}", input, errors);
+            assert_eq!(errors[0].code, Some(code), "input: {}", input);
+            assert_eq!(errors[0].span, span, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn string_literal_expression() {
+        let tests = vec![
+            (r#""hello world""#, "hello world"),
+            (r#""""#, ""),
+            (r#""a\"b\\c\n\r\t""#, "a\"b\\c\n\r\t"),
+            (r#""\u{1F600}\u{e9}""#, "😀é"),
+            (r#""改行\u{0}""#, "改行\0"),
+        ];
+        for (input, expected) in tests {
+            let program = parse(input);
+            let literal = match &program[0] {
+                StatementType::ExpressionStatement(ExpressionStatement {
+                    expression: ExpressionType::StringLiteral(literal),
+                    ..
+                }) => literal,
+                _ => panic!("not a string literal. input: {}", input),
+            };
+            assert_eq!(literal.value, expected, "input: {}", input);
+            assert_eq!(literal.token_literal(), input);
+            // 文字列化したものは同じ値に読み戻せる
+            match &parse(&literal.string())[0] {
+                StatementType::ExpressionStatement(ExpressionStatement {
+                    expression: ExpressionType::StringLiteral(again),
+                    ..
+                }) => assert_eq!(again.value, expected),
+                _ => panic!("does not round-trip. input: {}", input),
+            }
+        }
+    }
+
+    #[test]
+    fn arrays_hashes_and_index() {
+        let tests = vec![
+            ("[1, 2 * 2, a]", "[1, (2 * 2), a]"),
+            ("[]", "[]"),
+            (
+                "a * [1, 2, 3, 4][b * c] * d",
+                "((a * ([1, 2, 3, 4][(b * c)])) * d)",
+            ),
+            (
+                "add(a * b[2], b[1], 2 * [1, 2][1])",
+                "add((a * (b[2])), (b[1]), (2 * ([1, 2][1])))",
+            ),
+            ("-a[0]", "(-(a[0]))"),
+            ("f(x)[0](y)", "(f(x)[0])(y)"),
+            (r#"{"one": 1, true: 2 + 3}"#, r#"{"one": 1, true: (2 + 3)}"#),
+            ("{}", "{}"),
+            ("{}[k]", "({}[k])"),
+            (
+                "let x: [{string: int}] = []",
+                "let x: [{string: int}] = [];",
+            ),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(parse(input).string(), expected, "input: {}", input);
+        }
+
+        let program = parse(r#"{"a": [1, 2]}["a"][1]"#);
+        match &program[0] {
+            StatementType::ExpressionStatement(ExpressionStatement {
+                expression: ExpressionType::IndexExpression(index),
+                ..
+            }) => {
+                assert_eq!(index.span(), Span::new(0, 21));
+                assert_eq!(index.left.span(), Span::new(0, 18));
+                assert_eq!(index.index.span(), Span::new(19, 20));
+            }
+            _ => panic!("not an index expression"),
+        }
+    }
+
+    #[test]
+    fn string_and_collection_errors() {
+        let tests = vec![
+            (r#""a\qb""#, ErrorCode::InvalidEscape, Span::new(2, 4)),
+            (
+                r#""\u{110000}""#,
+                ErrorCode::InvalidEscape,
+                Span::new(1, 11),
+            ),
+            (r#""\u{}""#, ErrorCode::InvalidEscape, Span::new(1, 5)),
+            (
+                r#"x + "abc"#,
+                ErrorCode::UnterminatedString,
+                Span::new(4, 5),
+            ),
+            ("[1, 2", ErrorCode::UnexpectedToken, Span::new(5, 5)),
+            ("[1, 2,]", ErrorCode::ExpectedExpression, Span::new(6, 7)),
+            ("{1 2}", ErrorCode::UnexpectedToken, Span::new(3, 4)),
+            ("a[1", ErrorCode::UnexpectedToken, Span::new(3, 3)),
+            (
+                "let x: [int = 1;",
+                ErrorCode::UnexpectedToken,
+                Span::new(12, 13),
+            ),
+        ];
+        for (input, code, span) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+
+            let errors = parser.errors();
+            assert_eq!(
+                errors[0].code,
+                Some(code),
+                "input: {}, errors: {:?}",
+                input,
+                errors
+            );
+            assert_eq!(errors[0].span, span, "input: {}", input);
+        }
+    }
 }
@@ -1,8 +1,11 @@
-use std::io::{self, Write, stdout};
+use super::diagnostic::{Renderer, SourceFile};
+use super::interpreter::Interpreter;
+use super::object::Object;
+use std::io::{self, stdout, Write};
 
 const PROMPT: &str = ">> ";
 
-pub fn start() {
+pub fn start(interpreter: Interpreter) {
     loop {
         print!("{}", PROMPT);
         stdout().flush().unwrap();
@@ -12,11 +15,25 @@ pub fn start() {
             return;
         }
 
-        let mut lexer = super::lexer::Lexer::new(&buffer);
-        let mut tok = lexer.next_token();
-        while tok.token_type.value() != super::token::TokenType::Eof.value() {
-            println!("{:?}", tok);
-            tok = lexer.next_token();
+        if buffer.is_empty() {
+            return; // EOF
+        }
+
+        let source = SourceFile::new("<repl>", &buffer);
+        let program = match interpreter.parse(&buffer) {
+            Ok(program) => program,
+            Err(errors) => {
+                for diagnostic in &errors {
+                    eprintln!("{}", Renderer::plain().render(diagnostic, &source));
+                }
+                continue;
+            }
+        };
+
+        match interpreter.eval(&program) {
+            Ok(Object::Null) => {}
+            Ok(value) => println!("{}", value),
+            Err(diagnostic) => eprintln!("{}", Renderer::plain().render(&diagnostic, &source)),
         }
     }
 }
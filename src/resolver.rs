@@ -0,0 +1,543 @@
+//! Static name resolution over the arena AST.
+//!
+//! Every identifier use is bound to the `let` name or parameter it refers to at
+//! runtime. Only function bodies open a scope; the blocks of `if` share the
+//! scope they appear in, as in the evaluator. Inside a function body names of
+//! the enclosing scopes may be defined after the function, because the body
+//! runs only when it is called, which is what makes recursion work.
+
+use super::arena::{Ast, NodeId, NodeKind, SideTable, Symbol};
+use super::diagnostic::Diagnostic;
+use super::error_code::ErrorCode;
+use std::collections::HashSet;
+
+/// What kind of node introduces a binding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingKind {
+    /// The `let` statement whose name it is.
+    Let(NodeId),
+    /// The function literal whose parameter it is.
+    Parameter(NodeId),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Resolution {
+    /// For each identifier use, the identifier node that binds it. Uses of
+    /// builtins and undefined names have no entry.
+    pub bindings: SideTable<NodeId>,
+    /// For each binding identifier, where it comes from.
+    pub definitions: SideTable<BindingKind>,
+    /// Errors and warnings in source order.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Resolve the names of a program. `builtins` are the names that are bound
+/// before the program starts.
+pub fn resolve<'a>(ast: &'a Ast, builtins: &[&'a str]) -> Resolution {
+    let mut resolver = Resolver {
+        ast,
+        builtins: builtins.iter().copied().collect(),
+        scopes: Vec::new(),
+        resolution: Resolution::default(),
+    };
+    resolver.enter_scope(ast.statements());
+    for statement in ast.statements() {
+        resolver.statement(*statement);
+    }
+    resolver.exit_scope();
+    resolver
+        .resolution
+        .diagnostics
+        .sort_by_key(|diagnostic| diagnostic.span.start);
+    resolver.resolution
+}
+
+struct Binding {
+    symbol: Symbol,
+    id: NodeId,
+    defined: bool, // let の値を評価し終えたか
+    used: bool,
+}
+
+#[derive(Default)]
+struct Scope {
+    bindings: Vec<Binding>,
+}
+
+struct Resolver<'a> {
+    ast: &'a Ast,
+    builtins: HashSet<&'a str>,
+    scopes: Vec<Scope>,
+    resolution: Resolution,
+}
+
+impl<'a> Resolver<'a> {
+    // スコープ内のすべての let を先に宣言しておき、定義前の使用を見分ける
+    fn enter_scope(&mut self, statements: &[NodeId]) {
+        self.scopes.push(Scope::default());
+        for statement in statements {
+            self.declare(*statement);
+        }
+    }
+
+    fn declare(&mut self, id: NodeId) {
+        let ast = self.ast;
+        match ast.kind(id) {
+            NodeKind::Let { name, value } => {
+                self.bind(*name, BindingKind::Let(id), false);
+                self.declare(*value);
+            }
+            NodeKind::Return { value } => self.declare(*value),
+            NodeKind::Expression { expression } => self.declare(*expression),
+            NodeKind::Block { statements } => {
+                for statement in ast.nodes(*statements) {
+                    self.declare(*statement);
+                }
+            }
+            NodeKind::Prefix { right, .. } => self.declare(*right),
+            NodeKind::Infix { left, right, .. } => {
+                self.declare(*left);
+                self.declare(*right);
+            }
+            NodeKind::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.declare(*condition);
+                self.declare(*consequence);
+                if let Some(alternative) = alternative {
+                    self.declare(*alternative);
+                }
+            }
+            NodeKind::Call {
+                function,
+                arguments,
+            } => {
+                self.declare(*function);
+                for argument in ast.nodes(*arguments) {
+                    self.declare(*argument);
+                }
+            }
+            NodeKind::Array { elements: nodes } | NodeKind::Hash { pairs: nodes } => {
+                for node in ast.nodes(*nodes) {
+                    self.declare(*node);
+                }
+            }
+            NodeKind::Index { left, index } => {
+                self.declare(*left);
+                self.declare(*index);
+            }
+            // 関数の本体は別のスコープ
+            NodeKind::Function { .. }
+            | NodeKind::Identifier(_)
+            | NodeKind::Integer(_)
+            | NodeKind::BigInteger(_)
+            | NodeKind::Float(_)
+            | NodeKind::Boolean(_)
+            | NodeKind::String(_) => {}
+            kind => panic!("expected a statement or an expression, got {:?}", kind),
+        }
+    }
+
+    fn bind(&mut self, id: NodeId, kind: BindingKind, defined: bool) {
+        let symbol = self.identifier(id);
+        let name = self.ast.name(symbol);
+        if self.builtins.contains(name) {
+            self.resolution.diagnostics.push(
+                Diagnostic::warning(
+                    format!("`{}` shadows a builtin function", name),
+                    self.ast.span(id),
+                )
+                .with_code(ErrorCode::ShadowedBuiltin),
+            );
+        }
+        self.resolution.definitions.insert(id, kind);
+        let scope = self.scopes.last_mut().expect("inside a scope");
+        scope.bindings.push(Binding {
+            symbol,
+            id,
+            defined,
+            used: false,
+        });
+    }
+
+    fn exit_scope(&mut self) {
+        let scope = self.scopes.pop().expect("inside a scope");
+        for binding in scope.bindings {
+            let name = self.ast.name(binding.symbol);
+            // 使われない引数は lint の unused-parameter が報告する
+            let is_let = matches!(
+                self.resolution.definitions.get(binding.id),
+                Some(BindingKind::Let(_))
+            );
+            if is_let && !binding.used && !name.starts_with('_') {
+                self.resolution.diagnostics.push(
+                    Diagnostic::warning(
+                        format!("unused variable `{}`", name),
+                        self.ast.span(binding.id),
+                    )
+                    .with_code(ErrorCode::UnusedVariable)
+                    .with_help(format!(
+                        "if this is intentional, prefix it with an underscore: `_{}`",
+                        name
+                    )),
+                );
+            }
+        }
+    }
+
+    fn identifier(&self, id: NodeId) -> Symbol {
+        match self.ast.kind(id) {
+            NodeKind::Identifier(symbol) => *symbol,
+            kind => panic!("expected an identifier, got {:?}", kind),
+        }
+    }
+
+    fn statement(&mut self, id: NodeId) {
+        let ast = self.ast;
+        match ast.kind(id) {
+            NodeKind::Let { name, value } => {
+                self.expression(*value);
+                let scope = self.scopes.last_mut().expect("inside a scope");
+                if let Some(binding) = scope.bindings.iter_mut().find(|b| b.id == *name) {
+                    binding.defined = true;
+                }
+            }
+            NodeKind::Return { value } => self.expression(*value),
+            NodeKind::Expression { expression } => self.expression(*expression),
+            kind => panic!("expected a statement, got {:?}", kind),
+        }
+    }
+
+    fn block(&mut self, id: NodeId) {
+        let ast = self.ast;
+        if let NodeKind::Block { statements } = ast.kind(id) {
+            for statement in ast.nodes(*statements) {
+                self.statement(*statement);
+            }
+        }
+    }
+
+    fn expression(&mut self, id: NodeId) {
+        let ast = self.ast;
+        match ast.kind(id) {
+            NodeKind::Identifier(symbol) => self.reference(id, *symbol),
+            NodeKind::Integer(_)
+            | NodeKind::BigInteger(_)
+            | NodeKind::Float(_)
+            | NodeKind::Boolean(_)
+            | NodeKind::String(_) => {}
+            NodeKind::Array { elements: nodes } | NodeKind::Hash { pairs: nodes } => {
+                for node in ast.nodes(*nodes) {
+                    self.expression(*node);
+                }
+            }
+            NodeKind::Index { left, index } => {
+                self.expression(*left);
+                self.expression(*index);
+            }
+            NodeKind::Prefix { right, .. } => self.expression(*right),
+            NodeKind::Infix { left, right, .. } => {
+                self.expression(*left);
+                self.expression(*right);
+            }
+            NodeKind::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.expression(*condition);
+                self.block(*consequence);
+                if let Some(alternative) = alternative {
+                    self.block(*alternative);
+                }
+            }
+            NodeKind::Function { parameters, body } => {
+                let statements = match ast.kind(*body) {
+                    NodeKind::Block { statements } => ast.nodes(*statements),
+                    kind => panic!("expected a block, got {:?}", kind),
+                };
+                self.enter_scope(&[]);
+                for parameter in ast.nodes(*parameters) {
+                    self.bind(*parameter, BindingKind::Parameter(id), true);
+                }
+                for statement in statements {
+                    self.declare(*statement);
+                }
+                self.block(*body);
+                self.exit_scope();
+            }
+            NodeKind::Call {
+                function,
+                arguments,
+            } => {
+                self.expression(*function);
+                for argument in ast.nodes(*arguments) {
+                    self.expression(*argument);
+                }
+            }
+            kind => panic!("expected an expression, got {:?}", kind),
+        }
+    }
+
+    fn reference(&mut self, id: NodeId, symbol: Symbol) {
+        let name = self.ast.name(symbol);
+        let span = self.ast.span(id);
+        let diagnostic = match self.lookup(symbol) {
+            Lookup::Found(scope, index) => {
+                let binding = &mut self.scopes[scope].bindings[index];
+                binding.used = true;
+                self.resolution.bindings.insert(id, binding.id);
+                return;
+            }
+            Lookup::Builtin => return,
+            Lookup::BeforeDefinition(index) => {
+                // 定義があるので、未使用とは報告しない
+                let current = self.scopes.len() - 1;
+                self.scopes[current].bindings[index].used = true;
+                Diagnostic::error(format!("`{}` is used before its definition", name), span)
+                    .with_code(ErrorCode::UseBeforeDefinition)
+                    .with_note(format!("`{}` is defined later in the same scope", name))
+            }
+            Lookup::Undefined => {
+                let diagnostic = Diagnostic::error(format!("undefined variable `{}`", name), span)
+                    .with_code(ErrorCode::UndefinedVariable);
+                match self.similar_name(name) {
+                    Some(similar) => diagnostic.with_help(format!(
+                        "a variable with a similar name exists: `{}`",
+                        similar
+                    )),
+                    None => diagnostic,
+                }
+            }
+        };
+        self.resolution.diagnostics.push(diagnostic);
+    }
+
+    fn lookup(&self, symbol: Symbol) -> Lookup {
+        let current = self.scopes.len() - 1;
+        // 同じスコープでは、すでに定義された最後の束縛
+        if let Some(index) = self.scopes[current]
+            .bindings
+            .iter()
+            .rposition(|b| b.symbol == symbol && b.defined)
+        {
+            return Lookup::Found(current, index);
+        }
+        // 外側のスコープでは、後で定義されるものも見える
+        for (depth, scope) in self.scopes[..current].iter().enumerate().rev() {
+            let index = scope
+                .bindings
+                .iter()
+                .rposition(|b| b.symbol == symbol && b.defined)
+                .or_else(|| scope.bindings.iter().position(|b| b.symbol == symbol));
+            if let Some(index) = index {
+                return Lookup::Found(depth, index);
+            }
+        }
+        if let Some(index) = self.scopes[current]
+            .bindings
+            .iter()
+            .position(|b| b.symbol == symbol)
+        {
+            return Lookup::BeforeDefinition(index);
+        }
+        if self.builtins.contains(self.ast.name(symbol)) {
+            Lookup::Builtin
+        } else {
+            Lookup::Undefined
+        }
+    }
+
+    // 打ち間違いらしい名前。見えている束縛とbuiltinから編集距離の近いものを探す
+    fn similar_name(&self, name: &str) -> Option<&'a str> {
+        let ast = self.ast;
+        let limit = ((name.chars().count() + 1) / 3).max(1);
+        self.scopes
+            .iter()
+            .flat_map(|scope| scope.bindings.iter().map(|b| ast.name(b.symbol)))
+            .chain(self.builtins.iter().copied())
+            .map(|candidate| (edit_distance(name, candidate), candidate))
+            .filter(|(distance, _)| *distance <= limit)
+            .min()
+            .map(|(_, candidate)| candidate)
+    }
+}
+
+enum Lookup {
+    Found(usize, usize), // scopes[.0].bindings[.1]
+    Builtin,
+    BeforeDefinition(usize), // 現在のスコープの束縛
+    Undefined,
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::token::Span;
+    use super::*;
+
+    fn resolve_source(input: &str) -> (Ast, Resolution) {
+        let ast = Ast::parse(input, false).unwrap();
+        let resolution = resolve(&ast, &["len", "puts"]);
+        (ast, resolution)
+    }
+
+    fn diagnostics(input: &str) -> Vec<(ErrorCode, String, Span)> {
+        resolve_source(input)
+            .1
+            .diagnostics
+            .into_iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.code.unwrap(),
+                    diagnostic.message,
+                    diagnostic.span,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn binds_uses_to_definitions() {
+        let input = "let x = 1; let f = fn(x, y) { x + y }; f(x, 2)";
+        let (ast, resolution) = resolve_source(input);
+        assert!(resolution.diagnostics.is_empty());
+
+        // (使用位置, 束縛位置)
+        let mut bound: Vec<(usize, usize)> = resolution
+            .bindings
+            .iter()
+            .map(|(id, binding)| (ast.span(id).start, ast.span(*binding).start))
+            .collect();
+        bound.sort();
+        assert_eq!(bound, vec![(30, 22), (34, 25), (39, 15), (41, 4)]);
+
+        let kinds: Vec<BindingKind> = resolution
+            .definitions
+            .iter()
+            .map(|(_, kind)| *kind)
+            .collect();
+        let parameters = kinds
+            .iter()
+            .filter(|kind| matches!(kind, BindingKind::Parameter(_)))
+            .count();
+        assert_eq!((kinds.len(), parameters), (4, 2));
+    }
+
+    #[test]
+    fn clean_programs() {
+        let inputs = [
+            // 再帰と相互再帰
+            "let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } }; fact(5)",
+            "let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } };
+             let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } };
+             even(10)",
+            // if のブロックはスコープを作らない
+            "if (true) { let y = 1; } y",
+            "let _unused = 1; fn(_x) { 0 }",
+            "let x = 1; let x = x + 1; x",
+            "len(puts)",
+            "let add = fn(a) { fn(b) { a + b } }; add(1)(2)",
+        ];
+        for input in inputs.iter() {
+            assert_eq!(diagnostics(input), vec![], "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn undefined_variables() {
+        assert_eq!(
+            diagnostics("let count = 1; if (count > 0) { cuont + 1 }"),
+            vec![(
+                ErrorCode::UndefinedVariable,
+                String::from("undefined variable `cuont`"),
+                Span::new(32, 37)
+            )]
+        );
+        let (_, resolution) = resolve_source("let count = 1; count + cuont");
+        assert_eq!(
+            resolution.diagnostics[0].notes[0].message,
+            "a variable with a similar name exists: `count`"
+        );
+        // 関数の中の、まれにしか実行されない分岐
+        assert_eq!(
+            diagnostics("let f = fn(n) { if (n > 100) { return m; } n }; f(1)")[0].0,
+            ErrorCode::UndefinedVariable
+        );
+    }
+
+    #[test]
+    fn use_before_definition() {
+        assert_eq!(
+            diagnostics("let total = price * 2; let price = 5; total"),
+            vec![(
+                ErrorCode::UseBeforeDefinition,
+                String::from("`price` is used before its definition"),
+                Span::new(12, 17)
+            )]
+        );
+        assert_eq!(
+            diagnostics("let x = x + 1; x")[0].0,
+            ErrorCode::UseBeforeDefinition
+        );
+        // 外側に同じ名前があればそちらを指す
+        let input = "let x = 1; let f = fn() { let y = x; let x = 2; x + y }; f()";
+        assert_eq!(diagnostics(input), vec![]);
+    }
+
+    #[test]
+    fn unused_bindings() {
+        assert_eq!(
+            diagnostics("let a = 1; let add = fn(x, y) { x }; add(2, 3)"),
+            vec![(
+                ErrorCode::UnusedVariable,
+                String::from("unused variable `a`"),
+                Span::new(4, 5)
+            ),]
+        );
+    }
+
+    #[test]
+    fn shadowed_builtins() {
+        assert_eq!(
+            diagnostics("let len = fn(puts) { puts }; len(1)"),
+            vec![
+                (
+                    ErrorCode::ShadowedBuiltin,
+                    String::from("`len` shadows a builtin function"),
+                    Span::new(4, 7)
+                ),
+                (
+                    ErrorCode::ShadowedBuiltin,
+                    String::from("`puts` shadows a builtin function"),
+                    Span::new(13, 17)
+                ),
+            ]
+        );
+        let (ast, resolution) = resolve_source("let len = 1; len");
+        assert_eq!(resolution.bindings.iter().count(), 1);
+        assert_eq!(ast.statements().len(), 2);
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("count", "cuont"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+}
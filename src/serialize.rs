@@ -0,0 +1,1120 @@
+//! External representations of the AST, used by `ironmonkey ast`.
+//!
+//! `to_json` writes every node as an object with its `kind`, its fields and
+//! its `span`. `from_json` reads that form back, so that programs generated
+//! outside Rust can be evaluated. `to_sexp` is a compact form without spans
+//! for reading and diffing trees.
+//!
+//! Type annotations are written as an `annotation` field on the annotated
+//! name and a `return_type` field on functions. Both are left out when there
+//! is no annotation, and may be missing when reading.
+
+use super::ast::{
+    self, ArrayLiteral, ArrayType, BigIntegerLiteral, BlockStatement, Boolean, CallExpression,
+    ExpressionStatement, ExpressionType, FloatLiteral, FunctionLiteral, FunctionType, HashLiteral,
+    HashType, Identifer, IfExpression, IndexExpression, InfixExpression, IntegerLiteral,
+    LetStatement, Node, PrefixExpression, Program, ReturnStatement, StatementType, StringLiteral,
+    TypeExpression,
+};
+use super::diagnostic::Diagnostic;
+use super::error_code::ErrorCode;
+use super::lexer::{is_identifer_continue, is_identifer_start};
+use super::token::{Span, Token, TokenType};
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use serde_json::{json, Map, Value};
+
+pub fn to_json(program: &Program) -> Value {
+    json!({
+        "kind": "Program",
+        "statements": program.iter().map(statement_to_json).collect::<Vec<_>>(),
+        "span": program.span(),
+    })
+}
+
+fn statement_to_json(statement: &StatementType) -> Value {
+    match statement {
+        StatementType::LetStatement(statement) => json!({
+            "kind": "LetStatement",
+            "name": annotated_to_json(&statement.name, statement.annotation.as_ref()),
+            "value": expression_to_json(&statement.value),
+            "span": statement.span(),
+        }),
+        StatementType::ReturnStatement(statement) => json!({
+            "kind": "ReturnStatement",
+            "value": expression_to_json(&statement.value),
+            "span": statement.span(),
+        }),
+        StatementType::ExpressionStatement(statement) => json!({
+            "kind": "ExpressionStatement",
+            "expression": expression_to_json(&statement.expression),
+            "span": statement.span(),
+        }),
+    }
+}
+
+fn block_to_json(block: &BlockStatement) -> Value {
+    json!({
+        "kind": "Block",
+        "statements": block.statements.iter().map(statement_to_json).collect::<Vec<_>>(),
+        "span": block.span,
+    })
+}
+
+fn identifer_to_json(identifer: &Identifer) -> Value {
+    json!({
+        "kind": "Identifier",
+        "value": identifer.value,
+        "span": identifer.span(),
+    })
+}
+
+// 型注釈のある名前は annotation を持つ
+fn annotated_to_json(identifer: &Identifer, annotation: Option<&TypeExpression>) -> Value {
+    let mut value = identifer_to_json(identifer);
+    if let (Value::Object(fields), Some(annotation)) = (&mut value, annotation) {
+        fields.insert(String::from("annotation"), type_to_json(annotation));
+    }
+    value
+}
+
+fn type_to_json(ty: &TypeExpression) -> Value {
+    match ty {
+        TypeExpression::Named(name) => json!({
+            "kind": "NamedType",
+            "name": name.value,
+            "span": name.span(),
+        }),
+        TypeExpression::Function(ty) => json!({
+            "kind": "FunctionType",
+            "parameters": ty.parameters.iter().map(type_to_json).collect::<Vec<_>>(),
+            "result": type_to_json(&ty.result),
+            "span": ty.span(),
+        }),
+        TypeExpression::Array(ty) => json!({
+            "kind": "ArrayType",
+            "element": type_to_json(&ty.element),
+            "span": ty.span(),
+        }),
+        TypeExpression::Hash(ty) => json!({
+            "kind": "HashType",
+            "key": type_to_json(&ty.key),
+            "value": type_to_json(&ty.value),
+            "span": ty.span(),
+        }),
+    }
+}
+
+fn expression_to_json(expression: &ExpressionType) -> Value {
+    match expression {
+        ExpressionType::Identifer(identifer) => identifer_to_json(identifer),
+        ExpressionType::IntegerLiteral(literal) => json!({
+            "kind": "IntegerLiteral",
+            "value": literal.value,
+            "span": literal.span(),
+        }),
+        // JSONの数値はi64を超えると精度が落ちるので文字列にする
+        ExpressionType::BigIntegerLiteral(literal) => json!({
+            "kind": "IntegerLiteral",
+            "value": literal.value.to_string(),
+            "span": literal.span(),
+        }),
+        ExpressionType::FloatLiteral(literal) => json!({
+            "kind": "FloatLiteral",
+            "value": literal.value,
+            "span": literal.span(),
+        }),
+        ExpressionType::Boolean(boolean) => json!({
+            "kind": "Boolean",
+            "value": boolean.value,
+            "span": boolean.span(),
+        }),
+        ExpressionType::StringLiteral(literal) => json!({
+            "kind": "StringLiteral",
+            "value": literal.value,
+            "span": literal.span(),
+        }),
+        ExpressionType::ArrayLiteral(literal) => json!({
+            "kind": "ArrayLiteral",
+            "elements": literal.elements.iter().map(expression_to_json).collect::<Vec<_>>(),
+            "span": literal.span(),
+        }),
+        ExpressionType::HashLiteral(literal) => json!({
+            "kind": "HashLiteral",
+            "pairs": literal
+                .pairs
+                .iter()
+                .map(|(key, value)| json!({
+                    "key": expression_to_json(key),
+                    "value": expression_to_json(value),
+                }))
+                .collect::<Vec<_>>(),
+            "span": literal.span(),
+        }),
+        ExpressionType::PrefixExpression(expression) => json!({
+            "kind": "PrefixExpression",
+            "operator": expression.operator,
+            "right": expression_to_json(&expression.right),
+            "span": expression.span(),
+        }),
+        ExpressionType::InfixExpression(expression) => json!({
+            "kind": "InfixExpression",
+            "left": expression_to_json(&expression.left),
+            "operator": expression.operator,
+            "right": expression_to_json(&expression.right),
+            "span": expression.span(),
+        }),
+        ExpressionType::IfExpression(expression) => json!({
+            "kind": "IfExpression",
+            "condition": expression_to_json(&expression.condition),
+            "consequence": block_to_json(&expression.consequence),
+            "alternative": expression.alternative.as_ref().map(block_to_json),
+            "span": expression.span(),
+        }),
+        ExpressionType::FunctionLiteral(literal) => {
+            let parameters: Vec<Value> = literal
+                .parameters
+                .iter()
+                .zip(&literal.parameter_types)
+                .map(|(parameter, annotation)| annotated_to_json(parameter, annotation.as_ref()))
+                .collect();
+            let mut value = json!({
+                "kind": "FunctionLiteral",
+                "parameters": parameters,
+                "body": block_to_json(&literal.body),
+                "span": literal.span(),
+            });
+            if let (Value::Object(fields), Some(return_type)) = (&mut value, &literal.return_type) {
+                fields.insert(String::from("return_type"), type_to_json(return_type));
+            }
+            value
+        }
+        ExpressionType::CallExpression(expression) => json!({
+            "kind": "CallExpression",
+            "function": expression_to_json(&expression.function),
+            "arguments": expression.arguments.iter().map(expression_to_json).collect::<Vec<_>>(),
+            "span": expression.span(),
+        }),
+        ExpressionType::IndexExpression(expression) => json!({
+            "kind": "IndexExpression",
+            "left": expression_to_json(&expression.left),
+            "index": expression_to_json(&expression.index),
+            "span": expression.span(),
+        }),
+        ExpressionType::Illegal => json!({ "kind": "Illegal" }),
+    }
+}
+
+/// Read a program in the form written by `to_json`. Spans are optional; the
+/// tokens inside the nodes are rebuilt from the node spans, so a loaded tree
+/// prints and evaluates like a parsed one but its tokens keep no source text
+/// such as radix prefixes. Integers beyond i64 are only accepted with
+/// `big_integers`, as with `Parser::with_big_integers`.
+pub fn from_json(text: &str, big_integers: bool) -> Result<Program, Diagnostic> {
+    let value: Value = serde_json::from_str(text)
+        .map_err(|err| Diagnostic::error(format!("invalid JSON: {}", err), Span::default()))?;
+    let node = Object::new(&value, "program")?;
+    node.expect_kind("Program")?;
+    node.array("statements")?
+        .iter()
+        .enumerate()
+        .map(|(i, statement)| {
+            statement_from_json(
+                statement,
+                &format!("program.statements[{}]", i),
+                big_integers,
+            )
+        })
+        .collect()
+}
+
+fn load_error(path: &str, message: impl std::fmt::Display) -> Diagnostic {
+    Diagnostic::error(format!("{}: {}", path, message), Span::default())
+}
+
+// JSONのobjectとエラーメッセージ用のパス
+struct Object<'a> {
+    fields: &'a Map<String, Value>,
+    path: &'a str,
+}
+
+impl<'a> Object<'a> {
+    fn new(value: &'a Value, path: &'a str) -> Result<Self, Diagnostic> {
+        match value {
+            Value::Object(fields) => Ok(Self { fields, path }),
+            _ => Err(load_error(path, "expected an object")),
+        }
+    }
+
+    fn kind(&self) -> Result<&'a str, Diagnostic> {
+        self.field("kind")?
+            .as_str()
+            .ok_or_else(|| load_error(self.path, "`kind` must be a string"))
+    }
+
+    fn expect_kind(&self, expected: &str) -> Result<(), Diagnostic> {
+        let kind = self.kind()?;
+        if kind == expected {
+            Ok(())
+        } else {
+            Err(load_error(
+                self.path,
+                format!("expected a {} node, found {}", expected, kind),
+            ))
+        }
+    }
+
+    fn field(&self, name: &str) -> Result<&'a Value, Diagnostic> {
+        self.fields
+            .get(name)
+            .ok_or_else(|| load_error(self.path, format!("missing field `{}`", name)))
+    }
+
+    fn child_path(&self, name: &str) -> String {
+        format!("{}.{}", self.path, name)
+    }
+
+    fn string(&self, name: &str) -> Result<&'a str, Diagnostic> {
+        self.field(name)?
+            .as_str()
+            .ok_or_else(|| load_error(self.path, format!("`{}` must be a string", name)))
+    }
+
+    fn array(&self, name: &str) -> Result<&'a Vec<Value>, Diagnostic> {
+        self.field(name)?
+            .as_array()
+            .ok_or_else(|| load_error(self.path, format!("`{}` must be an array", name)))
+    }
+
+    fn span(&self) -> Result<Span, Diagnostic> {
+        let value = match self.fields.get("span") {
+            Some(value) => value,
+            None => return Ok(Span::default()),
+        };
+        let offset = |name: &str| {
+            value
+                .get(name)
+                .and_then(Value::as_u64)
+                .map(|offset| offset as usize)
+        };
+        match (offset("start"), offset("end")) {
+            (Some(start), Some(end)) if start <= end => Ok(Span::new(start, end)),
+            _ => Err(load_error(
+                self.path,
+                "`span` must be {\"start\": n, \"end\": m} with n <= m",
+            )),
+        }
+    }
+}
+
+// 位置 start から始まる token を作る
+fn token(token_type: TokenType, literal: &str, start: usize) -> Box<Token> {
+    Box::new(Token {
+        token_type,
+        literal: String::from(literal),
+        span: Span::new(start, start + literal.len()),
+    })
+}
+
+fn statement_from_json(
+    value: &Value,
+    path: &str,
+    big_integers: bool,
+) -> Result<StatementType, Diagnostic> {
+    let node = Object::new(value, path)?;
+    let span = node.span()?;
+    match node.kind()? {
+        "LetStatement" => {
+            let name = node.field("name")?;
+            Ok(StatementType::LetStatement(LetStatement {
+                token: token(TokenType::Let, "let", span.start),
+                name: identifer_from_json(name, &node.child_path("name"))?,
+                annotation: annotation_from_json(name, &node.child_path("name"))?,
+                value: expression_from_json(
+                    node.field("value")?,
+                    &node.child_path("value"),
+                    big_integers,
+                )?,
+            }))
+        }
+        "ReturnStatement" => Ok(StatementType::ReturnStatement(ReturnStatement {
+            token: token(TokenType::Return, "return", span.start),
+            value: expression_from_json(
+                node.field("value")?,
+                &node.child_path("value"),
+                big_integers,
+            )?,
+        })),
+        "ExpressionStatement" => {
+            let expression = expression_from_json(
+                node.field("expression")?,
+                &node.child_path("expression"),
+                big_integers,
+            )?;
+            Ok(StatementType::ExpressionStatement(ExpressionStatement {
+                token: first_token(&expression),
+                expression,
+            }))
+        }
+        kind => Err(load_error(path, format!("unknown statement kind {}", kind))),
+    }
+}
+
+// 式の先頭の token。ExpressionStatement の token になる
+fn first_token(expression: &ExpressionType) -> Box<Token> {
+    match expression {
+        ExpressionType::Identifer(identifer) => identifer.token.clone(),
+        ExpressionType::IntegerLiteral(literal) => literal.token.clone(),
+        ExpressionType::BigIntegerLiteral(literal) => literal.token.clone(),
+        ExpressionType::FloatLiteral(literal) => literal.token.clone(),
+        ExpressionType::Boolean(boolean) => boolean.token.clone(),
+        ExpressionType::StringLiteral(literal) => literal.token.clone(),
+        ExpressionType::ArrayLiteral(literal) => literal.token.clone(),
+        ExpressionType::HashLiteral(literal) => literal.token.clone(),
+        ExpressionType::PrefixExpression(expression) => expression.token.clone(),
+        ExpressionType::InfixExpression(expression) => first_token(&expression.left),
+        ExpressionType::IfExpression(expression) => expression.token.clone(),
+        ExpressionType::FunctionLiteral(literal) => literal.token.clone(),
+        ExpressionType::CallExpression(expression) => first_token(&expression.function),
+        ExpressionType::IndexExpression(expression) => first_token(&expression.left),
+        ExpressionType::Illegal => Box::new(Token::default()),
+    }
+}
+
+fn block_from_json(
+    value: &Value,
+    path: &str,
+    big_integers: bool,
+) -> Result<BlockStatement, Diagnostic> {
+    let node = Object::new(value, path)?;
+    node.expect_kind("Block")?;
+    let span = node.span()?;
+    let statements = node
+        .array("statements")?
+        .iter()
+        .enumerate()
+        .map(|(i, statement)| {
+            statement_from_json(
+                statement,
+                &format!("{}.statements[{}]", path, i),
+                big_integers,
+            )
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(BlockStatement {
+        token: token(TokenType::Lbrace, "{", span.start),
+        statements,
+        span,
+    })
+}
+
+fn identifer_from_json(value: &Value, path: &str) -> Result<Identifer, Diagnostic> {
+    let node = Object::new(value, path)?;
+    node.expect_kind("Identifier")?;
+    name_from_json(&node, "value")
+}
+
+fn name_from_json(node: &Object, field: &str) -> Result<Identifer, Diagnostic> {
+    let path = node.path;
+    let name = node.string(field)?;
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(is_identifer_start)
+        && chars.all(is_identifer_continue)
+        && TokenType::lookup_iden(name) == TokenType::Ident;
+    if !valid {
+        return Err(load_error(
+            path,
+            format!("`{}` is not a valid identifier", name),
+        ));
+    }
+    Ok(Identifer::new(token(
+        TokenType::Ident,
+        name,
+        node.span()?.start,
+    )))
+}
+
+// 名前の node の annotation。なければ None
+fn annotation_from_json(value: &Value, path: &str) -> Result<Option<TypeExpression>, Diagnostic> {
+    let node = Object::new(value, path)?;
+    optional_type_from_json(&node, "annotation")
+}
+
+fn optional_type_from_json(
+    node: &Object,
+    name: &str,
+) -> Result<Option<TypeExpression>, Diagnostic> {
+    match node.fields.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => type_from_json(value, &node.child_path(name)).map(Some),
+    }
+}
+
+fn type_from_json(value: &Value, path: &str) -> Result<TypeExpression, Diagnostic> {
+    let node = Object::new(value, path)?;
+    match node.kind()? {
+        "NamedType" => Ok(TypeExpression::Named(name_from_json(&node, "name")?)),
+        "FunctionType" => {
+            let parameters = node
+                .array("parameters")?
+                .iter()
+                .enumerate()
+                .map(|(i, parameter)| {
+                    type_from_json(parameter, &format!("{}.parameters[{}]", path, i))
+                })
+                .collect::<Result<_, _>>()?;
+            Ok(TypeExpression::Function(FunctionType {
+                token: token(TokenType::Function, "fn", node.span()?.start),
+                parameters,
+                result: Box::new(type_from_json(
+                    node.field("result")?,
+                    &node.child_path("result"),
+                )?),
+            }))
+        }
+        "ArrayType" => Ok(TypeExpression::Array(ArrayType {
+            token: token(TokenType::Lbracket, "[", node.span()?.start),
+            element: Box::new(type_from_json(
+                node.field("element")?,
+                &node.child_path("element"),
+            )?),
+            span: node.span()?,
+        })),
+        "HashType" => Ok(TypeExpression::Hash(HashType {
+            token: token(TokenType::Lbrace, "{", node.span()?.start),
+            key: Box::new(type_from_json(node.field("key")?, &node.child_path("key"))?),
+            value: Box::new(type_from_json(
+                node.field("value")?,
+                &node.child_path("value"),
+            )?),
+            span: node.span()?,
+        })),
+        kind => Err(load_error(path, format!("unknown type kind {}", kind))),
+    }
+}
+
+fn expression_from_json(
+    value: &Value,
+    path: &str,
+    big_integers: bool,
+) -> Result<ExpressionType, Diagnostic> {
+    let node = Object::new(value, path)?;
+    let span = node.span()?;
+    let expression = match node.kind()? {
+        "Identifier" => ExpressionType::Identifer(identifer_from_json(value, path)?),
+        "IntegerLiteral" => integer_from_json(&node, span.start, big_integers)?,
+        "FloatLiteral" => {
+            let value = node
+                .field("value")?
+                .as_f64()
+                .ok_or_else(|| load_error(path, "`value` must be a number"))?;
+            ExpressionType::FloatLiteral(FloatLiteral {
+                token: token(TokenType::Float, &format!("{:?}", value), span.start),
+                value,
+            })
+        }
+        "Boolean" => {
+            let value = node
+                .field("value")?
+                .as_bool()
+                .ok_or_else(|| load_error(path, "`value` must be a boolean"))?;
+            let (token_type, literal) = if value {
+                (TokenType::True, "true")
+            } else {
+                (TokenType::False, "false")
+            };
+            ExpressionType::Boolean(Boolean {
+                token: token(token_type, literal, span.start),
+                value,
+            })
+        }
+        "StringLiteral" => {
+            let value = node.string("value")?;
+            // 元のリテラルとはエスケープの書き方が違いうるので、位置は span に従う
+            let mut token = token(TokenType::String, &ast::quote(value), span.start);
+            if span.end > span.start {
+                token.span = span;
+            }
+            ExpressionType::StringLiteral(StringLiteral {
+                token,
+                value: String::from(value),
+            })
+        }
+        "ArrayLiteral" => {
+            let elements = node
+                .array("elements")?
+                .iter()
+                .enumerate()
+                .map(|(i, element)| {
+                    expression_from_json(
+                        element,
+                        &format!("{}.elements[{}]", path, i),
+                        big_integers,
+                    )
+                })
+                .collect::<Result<_, _>>()?;
+            ExpressionType::ArrayLiteral(ArrayLiteral {
+                token: token(TokenType::Lbracket, "[", span.start),
+                elements,
+                span,
+            })
+        }
+        "HashLiteral" => {
+            let mut pairs = Vec::new();
+            for (i, pair) in node.array("pairs")?.iter().enumerate() {
+                let path = format!("{}.pairs[{}]", path, i);
+                let pair = Object::new(pair, &path)?;
+                pairs.push((
+                    expression_from_json(
+                        pair.field("key")?,
+                        &pair.child_path("key"),
+                        big_integers,
+                    )?,
+                    expression_from_json(
+                        pair.field("value")?,
+                        &pair.child_path("value"),
+                        big_integers,
+                    )?,
+                ));
+            }
+            ExpressionType::HashLiteral(HashLiteral {
+                token: token(TokenType::Lbrace, "{", span.start),
+                pairs,
+                span,
+            })
+        }
+        "PrefixExpression" => {
+            let operator = node.string("operator")?;
+            let token_type = match operator {
+                "!" => TokenType::Bang,
+                "-" => TokenType::Minus,
+                _ => {
+                    return Err(load_error(
+                        path,
+                        format!("unknown prefix operator `{}`", operator),
+                    ))
+                }
+            };
+            ExpressionType::PrefixExpression(PrefixExpression {
+                token: token(token_type, operator, span.start),
+                operator: String::from(operator),
+                right: Box::new(expression_from_json(
+                    node.field("right")?,
+                    &node.child_path("right"),
+                    big_integers,
+                )?),
+            })
+        }
+        "InfixExpression" => {
+            let operator = node.string("operator")?;
+            let token_type = match operator {
+                "+" => TokenType::Plus,
+                "-" => TokenType::Minus,
+                "*" => TokenType::Asterisk,
+                "/" => TokenType::Slash,
+                "<" => TokenType::Lt,
+                ">" => TokenType::Gt,
+                "==" => TokenType::Equal,
+                "!=" => TokenType::NotEqual,
+                _ => {
+                    return Err(load_error(
+                        path,
+                        format!("unknown infix operator `{}`", operator),
+                    ))
+                }
+            };
+            let left =
+                expression_from_json(node.field("left")?, &node.child_path("left"), big_integers)?;
+            let right = expression_from_json(
+                node.field("right")?,
+                &node.child_path("right"),
+                big_integers,
+            )?;
+            ExpressionType::InfixExpression(InfixExpression {
+                token: token(token_type, operator, left.span().end),
+                left: Box::new(left),
+                operator: String::from(operator),
+                right: Box::new(right),
+            })
+        }
+        "IfExpression" => {
+            let alternative = match node.field("alternative")? {
+                Value::Null => None,
+                alternative => Some(block_from_json(
+                    alternative,
+                    &node.child_path("alternative"),
+                    big_integers,
+                )?),
+            };
+            ExpressionType::IfExpression(IfExpression {
+                token: token(TokenType::If, "if", span.start),
+                condition: Box::new(expression_from_json(
+                    node.field("condition")?,
+                    &node.child_path("condition"),
+                    big_integers,
+                )?),
+                consequence: block_from_json(
+                    node.field("consequence")?,
+                    &node.child_path("consequence"),
+                    big_integers,
+                )?,
+                alternative,
+            })
+        }
+        "FunctionLiteral" => {
+            let mut parameters = Vec::new();
+            let mut parameter_types = Vec::new();
+            for (i, parameter) in node.array("parameters")?.iter().enumerate() {
+                let path = format!("{}.parameters[{}]", path, i);
+                parameters.push(identifer_from_json(parameter, &path)?);
+                parameter_types.push(annotation_from_json(parameter, &path)?);
+            }
+            ExpressionType::FunctionLiteral(FunctionLiteral {
+                token: token(TokenType::Function, "fn", span.start),
+                parameters,
+                parameter_types,
+                return_type: optional_type_from_json(&node, "return_type")?,
+                body: block_from_json(node.field("body")?, &node.child_path("body"), big_integers)?,
+            })
+        }
+        "CallExpression" => {
+            let function = expression_from_json(
+                node.field("function")?,
+                &node.child_path("function"),
+                big_integers,
+            )?;
+            let arguments = node
+                .array("arguments")?
+                .iter()
+                .enumerate()
+                .map(|(i, argument)| {
+                    expression_from_json(
+                        argument,
+                        &format!("{}.arguments[{}]", path, i),
+                        big_integers,
+                    )
+                })
+                .collect::<Result<_, _>>()?;
+            ExpressionType::CallExpression(CallExpression {
+                token: token(TokenType::Lparen, "(", function.span().end),
+                function: Box::new(function),
+                arguments,
+                span,
+            })
+        }
+        "IndexExpression" => {
+            let left =
+                expression_from_json(node.field("left")?, &node.child_path("left"), big_integers)?;
+            ExpressionType::IndexExpression(IndexExpression {
+                token: token(TokenType::Lbracket, "[", left.span().end),
+                left: Box::new(left),
+                index: Box::new(expression_from_json(
+                    node.field("index")?,
+                    &node.child_path("index"),
+                    big_integers,
+                )?),
+                span,
+            })
+        }
+        kind => {
+            return Err(load_error(
+                path,
+                format!("unknown expression kind {}", kind),
+            ))
+        }
+    };
+    Ok(expression)
+}
+
+// i64に収まる値は数値で、収まらない値は10進の文字列で書かれている
+fn integer_from_json(
+    node: &Object,
+    start: usize,
+    big_integers: bool,
+) -> Result<ExpressionType, Diagnostic> {
+    let value = match node.field("value")? {
+        Value::Number(number) => number.as_i64().map(BigInt::from),
+        Value::String(digits) => digits.parse::<BigInt>().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| load_error(node.path, "`value` must be an integer"))?;
+    let literal = value.to_string();
+    Ok(match value.to_i64() {
+        Some(value) => ExpressionType::IntegerLiteral(IntegerLiteral {
+            token: token(TokenType::Int, &literal, start),
+            value,
+        }),
+        None if big_integers => ExpressionType::BigIntegerLiteral(BigIntegerLiteral {
+            token: token(TokenType::Int, &literal, start),
+            value,
+        }),
+        None => {
+            return Err(load_error(
+                node.path,
+                format!("integer `{}` is too large for i64", literal),
+            )
+            .with_code(ErrorCode::IntegerOverflow)
+            .with_help(format!("the largest integer is {}", i64::MAX)))
+        }
+    })
+}
+
+/// e.g. `(let x (+ 1 (call f y)))` or `(index (array 1 2) 0)`
+pub fn to_sexp(program: &Program) -> String {
+    let mut buf = String::from("(program");
+    for statement in program {
+        buf.push(' ');
+        statement_to_sexp(statement, &mut buf);
+    }
+    buf.push(')');
+    buf
+}
+
+fn statement_to_sexp(statement: &StatementType, buf: &mut String) {
+    match statement {
+        StatementType::LetStatement(statement) => {
+            buf.push_str("(let ");
+            annotated_to_sexp(&statement.name, statement.annotation.as_ref(), buf);
+            buf.push(' ');
+            expression_to_sexp(&statement.value, buf);
+            buf.push(')');
+        }
+        StatementType::ReturnStatement(statement) => {
+            buf.push_str("(return ");
+            expression_to_sexp(&statement.value, buf);
+            buf.push(')');
+        }
+        StatementType::ExpressionStatement(statement) => {
+            expression_to_sexp(&statement.expression, buf)
+        }
+    }
+}
+
+// x か (x : int)
+fn annotated_to_sexp(identifer: &Identifer, annotation: Option<&TypeExpression>, buf: &mut String) {
+    match annotation {
+        Some(annotation) => {
+            buf.push_str(&format!("({} : ", identifer.value));
+            type_to_sexp(annotation, buf);
+            buf.push(')');
+        }
+        None => buf.push_str(&identifer.value),
+    }
+}
+
+// int や (-> (int int) bool)
+fn type_to_sexp(ty: &TypeExpression, buf: &mut String) {
+    match ty {
+        TypeExpression::Named(name) => buf.push_str(&name.value),
+        TypeExpression::Function(ty) => {
+            buf.push_str("(-> (");
+            for (i, parameter) in ty.parameters.iter().enumerate() {
+                if i > 0 {
+                    buf.push(' ');
+                }
+                type_to_sexp(parameter, buf);
+            }
+            buf.push_str(") ");
+            type_to_sexp(&ty.result, buf);
+            buf.push(')');
+        }
+        TypeExpression::Array(ty) => {
+            buf.push_str("(array ");
+            type_to_sexp(&ty.element, buf);
+            buf.push(')');
+        }
+        TypeExpression::Hash(ty) => {
+            buf.push_str("(hash ");
+            type_to_sexp(&ty.key, buf);
+            buf.push(' ');
+            type_to_sexp(&ty.value, buf);
+            buf.push(')');
+        }
+    }
+}
+
+fn block_to_sexp(block: &BlockStatement, buf: &mut String) {
+    buf.push_str("(block");
+    for statement in &block.statements {
+        buf.push(' ');
+        statement_to_sexp(statement, buf);
+    }
+    buf.push(')');
+}
+
+fn expression_to_sexp(expression: &ExpressionType, buf: &mut String) {
+    match expression {
+        ExpressionType::Identifer(identifer) => buf.push_str(&identifer.value),
+        ExpressionType::IntegerLiteral(literal) => buf.push_str(&literal.value.to_string()),
+        ExpressionType::BigIntegerLiteral(literal) => buf.push_str(&literal.value.to_string()),
+        ExpressionType::FloatLiteral(literal) => buf.push_str(&literal.string()),
+        ExpressionType::Boolean(boolean) => buf.push_str(&boolean.value.to_string()),
+        ExpressionType::StringLiteral(literal) => buf.push_str(&literal.string()),
+        ExpressionType::ArrayLiteral(literal) => {
+            buf.push_str("(array");
+            for element in &literal.elements {
+                buf.push(' ');
+                expression_to_sexp(element, buf);
+            }
+            buf.push(')');
+        }
+        ExpressionType::HashLiteral(literal) => {
+            buf.push_str("(hash");
+            for (key, value) in &literal.pairs {
+                buf.push_str(" (");
+                expression_to_sexp(key, buf);
+                buf.push(' ');
+                expression_to_sexp(value, buf);
+                buf.push(')');
+            }
+            buf.push(')');
+        }
+        ExpressionType::PrefixExpression(expression) => {
+            buf.push('(');
+            buf.push_str(&expression.operator);
+            buf.push(' ');
+            expression_to_sexp(&expression.right, buf);
+            buf.push(')');
+        }
+        ExpressionType::InfixExpression(expression) => {
+            buf.push('(');
+            buf.push_str(&expression.operator);
+            buf.push(' ');
+            expression_to_sexp(&expression.left, buf);
+            buf.push(' ');
+            expression_to_sexp(&expression.right, buf);
+            buf.push(')');
+        }
+        ExpressionType::IfExpression(expression) => {
+            buf.push_str("(if ");
+            expression_to_sexp(&expression.condition, buf);
+            buf.push(' ');
+            block_to_sexp(&expression.consequence, buf);
+            if let Some(alternative) = &expression.alternative {
+                buf.push(' ');
+                block_to_sexp(alternative, buf);
+            }
+            buf.push(')');
+        }
+        ExpressionType::FunctionLiteral(literal) => {
+            buf.push_str("(fn (");
+            for (i, (parameter, annotation)) in literal
+                .parameters
+                .iter()
+                .zip(&literal.parameter_types)
+                .enumerate()
+            {
+                if i > 0 {
+                    buf.push(' ');
+                }
+                annotated_to_sexp(parameter, annotation.as_ref(), buf);
+            }
+            buf.push_str(") ");
+            if let Some(return_type) = &literal.return_type {
+                buf.push_str(": ");
+                type_to_sexp(return_type, buf);
+                buf.push(' ');
+            }
+            block_to_sexp(&literal.body, buf);
+            buf.push(')');
+        }
+        ExpressionType::CallExpression(expression) => {
+            buf.push_str("(call ");
+            expression_to_sexp(&expression.function, buf);
+            for argument in &expression.arguments {
+                buf.push(' ');
+                expression_to_sexp(argument, buf);
+            }
+            buf.push(')');
+        }
+        ExpressionType::IndexExpression(expression) => {
+            buf.push_str("(index ");
+            expression_to_sexp(&expression.left, buf);
+            buf.push(' ');
+            expression_to_sexp(&expression.index, buf);
+            buf.push(')');
+        }
+        ExpressionType::Illegal => buf.push_str("(illegal)"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::evaluator::{Evaluator, IntegerMode};
+    use super::super::lexer::Lexer;
+    use super::super::object::Environment;
+    use super::super::parser::Parser;
+    use super::*;
+
+    fn parse(input: &str) -> Program {
+        let mut parser = Parser::new(Lexer::new(input)).with_big_integers(true);
+        let program = parser.parse_program();
+        assert_eq!(parser.errors(), vec![]);
+        program
+    }
+
+    #[test]
+    fn json_has_kind_fields_and_span() {
+        let program = parse("let x = -1 + y;");
+        let expected = json!({
+            "kind": "Program",
+            "span": {"start": 0, "end": 14},
+            "statements": [{
+                "kind": "LetStatement",
+                "span": {"start": 0, "end": 14},
+                "name": {"kind": "Identifier", "value": "x", "span": {"start": 4, "end": 5}},
+                "value": {
+                    "kind": "InfixExpression",
+                    "span": {"start": 8, "end": 14},
+                    "operator": "+",
+                    "left": {
+                        "kind": "PrefixExpression",
+                        "span": {"start": 8, "end": 10},
+                        "operator": "-",
+                        "right": {"kind": "IntegerLiteral", "value": 1, "span": {"start": 9, "end": 10}},
+                    },
+                    "right": {"kind": "Identifier", "value": "y", "span": {"start": 13, "end": 14}},
+                },
+            }],
+        });
+        assert_eq!(to_json(&program), expected);
+    }
+
+    #[test]
+    fn sexp() {
+        let tests = vec![
+            ("let x = 1 + 2 * 3;", "(program (let x (+ 1 (* 2 3))))"),
+            ("return -x;", "(program (return (- x)))"),
+            (
+                "if (a < b) { 1.5 } else { !true }",
+                "(program (if (< a b) (block 1.5) (block (! true))))",
+            ),
+            (
+                "fn(x, y) { x }(0x10, 18446744073709551616)",
+                "(program (call (fn (x y) (block x)) 16 18446744073709551616))",
+            ),
+            ("fn() {}", "(program (fn () (block)))"),
+            (
+                "let f: fn(int) -> bool = fn(a: int, b) -> bool { true };",
+                "(program (let (f : (-> (int) bool)) (fn ((a : int) b) : bool (block true))))",
+            ),
+            (
+                r#"let h: {string: [int]} = {"a\n": [1, 2]}; h["a\n"][0]"#,
+                r#"(program (let (h : (hash string (array int))) (hash ("a\n" (array 1 2)))) (index (index h "a\n") 0))"#,
+            ),
+            ("", "(program)"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(to_sexp(&parse(input)), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let inputs = [
+            "let add = fn(a, b) { a + b }; add(1, 2.5);",
+            "let f = fn(n) { if (n < 2) { return n; } else { f(n - 1) * 2 } }; f(10)",
+            "!(-9223372036854775808 == 18446744073709551616)",
+            "if (true) { 1 }",
+            "let twice: fn(fn() -> int) -> int = fn(f: fn() -> int, x) -> int { f() };",
+            r#"let h: {string: [float]} = {"a\"\u{1F600}": [1.5], "b": []}; h["b"] == [] + [h["a"][0]]"#,
+            r#"{1: "x", true: {}}[1 + 0]"#,
+        ];
+        for input in inputs.iter() {
+            let program = parse(input);
+            let json = to_json(&program).to_string();
+            let loaded = from_json(&json, true).unwrap();
+            assert_eq!(loaded.string(), program.string(), "input: {}", input);
+            assert_eq!(to_json(&loaded).to_string(), json, "input: {}", input);
+
+            let evaluator = Evaluator::new(IntegerMode::Big);
+            assert_eq!(
+                evaluator.eval_program(&loaded, &Environment::new()),
+                evaluator.eval_program(&program, &Environment::new()),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn spans_are_optional() {
+        let json = r#"{"kind": "Program", "statements": [{"kind": "ExpressionStatement",
+            "expression": {"kind": "CallExpression",
+                "function": {"kind": "FunctionLiteral",
+                    "parameters": [{"kind": "Identifier", "value": "x"}],
+                    "body": {"kind": "Block", "statements": [{"kind": "ExpressionStatement",
+                        "expression": {"kind": "InfixExpression", "operator": "*",
+                            "left": {"kind": "Identifier", "value": "x"},
+                            "right": {"kind": "IntegerLiteral", "value": 2}}}]}},
+                "arguments": [{"kind": "IntegerLiteral", "value": 21}]}}]}"#;
+        let program = from_json(json, false).unwrap();
+        assert_eq!(program.string(), "fn(x) (x * 2)(21)");
+        let result = Evaluator::default().eval_program(&program, &Environment::new());
+        assert_eq!(result.unwrap().inspect(), "42");
+    }
+
+    #[test]
+    fn load_errors() {
+        let tests = vec![
+            ("[]", "program: expected an object"),
+            (
+                "{\"kind\": \"Program\"}",
+                "program: missing field `statements`",
+            ),
+            (
+                r#"{"kind": "Program", "statements": [{"kind": "Loop"}]}"#,
+                "program.statements[0]: unknown statement kind Loop",
+            ),
+            (
+                r#"{"kind": "Program", "statements": [{"kind": "ReturnStatement",
+                    "value": {"kind": "InfixExpression", "operator": "%",
+                        "left": {"kind": "Boolean", "value": true},
+                        "right": {"kind": "Boolean", "value": true}}}]}"#,
+                "program.statements[0].value: unknown infix operator `%`",
+            ),
+            (
+                r#"{"kind": "Program", "statements": [{"kind": "LetStatement",
+                    "name": {"kind": "Identifier", "value": "let"},
+                    "value": {"kind": "IntegerLiteral", "value": 1.5}}]}"#,
+                "program.statements[0].name: `let` is not a valid identifier",
+            ),
+            (
+                r#"{"kind": "Program", "statements": [{"kind": "LetStatement",
+                    "name": {"kind": "Identifier", "value": "x",
+                        "annotation": {"kind": "FunctionType", "parameters": [{"kind": "Tuple"}]}},
+                    "value": {"kind": "IntegerLiteral", "value": 1}}]}"#,
+                "program.statements[0].name.annotation.parameters[0]: unknown type kind Tuple",
+            ),
+        ];
+        for (input, expected) in tests {
+            let err = from_json(input, false).unwrap_err();
+            assert_eq!(err.message, expected, "input: {}", input);
+        }
+        assert!(from_json("{", false)
+            .unwrap_err()
+            .message
+            .starts_with("invalid JSON"));
+    }
+
+    #[test]
+    fn big_integers_need_the_flag() {
+        let json = r#"{"kind": "Program", "statements": [{"kind": "ExpressionStatement",
+            "expression": {"kind": "IntegerLiteral", "value": "18446744073709551616"}}]}"#;
+        let err = from_json(json, false).unwrap_err();
+        assert_eq!(
+            err.message,
+            "program.statements[0].expression: integer `18446744073709551616` is too large for i64"
+        );
+        assert_eq!(err.code, Some(ErrorCode::IntegerOverflow));
+
+        let program = from_json(json, true).unwrap();
+        assert_eq!(program.string(), "18446744073709551616");
+        // i64 に収まる値は文字列で書かれていても読める
+        let json = json.replace("18446744073709551616", "-9223372036854775808");
+        assert_eq!(
+            from_json(&json, false).unwrap().string(),
+            "-9223372036854775808"
+        );
+    }
+}
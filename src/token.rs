@@ -1,9 +1,32 @@
+use serde::Serialize;
 use std::fmt;
 
+/// Byte range of a token in the source text. `end` is exclusive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// Smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Self {
+        Self {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Token {
     pub token_type: TokenType,
     pub literal: String,
+    pub span: Span,
 }
 
 impl Token {
@@ -11,6 +34,7 @@ impl Token {
         Self {
             token_type,
             literal: literal.map_or("".to_string(), |v| v.to_string()),
+            span: Span::default(),
         }
     }
 
@@ -18,6 +42,7 @@ impl Token {
         Self {
             token_type,
             literal: String::from(literal),
+            span: Span::default(),
         }
     }
 }
@@ -27,11 +52,12 @@ impl Default for Token {
         Token {
             token_type: TokenType::Illegal,
             literal: String::new(),
+            span: Span::default(),
         }
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TokenType {
     Illegal, // UNKNOWN TOKEN OR STRING
     Eof,     // END OF FILE
@@ -39,6 +65,8 @@ pub enum TokenType {
     // 識別子(Identifer) + literal
     Ident,
     Int,
+    Float,
+    String, // 引用符も含めたソースのまま
 
     // 演算子(operator)
     Assign,
@@ -57,11 +85,15 @@ pub enum TokenType {
     // delimiter
     Comma,
     Semicolon,
+    Colon,
+    Arrow, // 型注釈の戻り値
 
     Lparen,
     Rparen,
     Lbrace,
     Rbrace,
+    Lbracket,
+    Rbracket,
 
     // keyword
     Function,
@@ -81,6 +113,8 @@ impl TokenType {
             Eof => "EOF",
             Ident => "IDENT",
             Int => "INT",
+            Float => "FLOAT",
+            String => "STRING",
             Assign => "=",
             Plus => "+",
             Minus => "-",
@@ -93,10 +127,14 @@ impl TokenType {
             NotEqual => "!=",
             Comma => ",",
             Semicolon => ";",
+            Colon => ":",
+            Arrow => "->",
             Lparen => "(",
             Rparen => ")",
             Lbrace => "{",
             Rbrace => "}",
+            Lbracket => "[",
+            Rbracket => "]",
             Function => "FUNCTION",
             Let => "LET",
             True => "TRUE",
@@ -121,8 +159,22 @@ impl TokenType {
     }
 }
 
+/// Human readable form used in diagnostics, e.g. `=` or `identifier`.
 impl fmt::Display for TokenType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "({})", self)
+        use TokenType::*;
+        match self {
+            Illegal => write!(f, "illegal token"),
+            Eof => write!(f, "end of input"),
+            Ident => write!(f, "identifier"),
+            Int => write!(f, "integer literal"),
+            Float => write!(f, "float literal"),
+            String => write!(f, "string literal"),
+            Function => write!(f, "`fn`"),
+            Let | True | False | If | Else | Return => {
+                write!(f, "`{}`", self.value().to_lowercase())
+            }
+            _ => write!(f, "`{}`", self.value()),
+        }
     }
 }
@@ -0,0 +1,1232 @@
+//! Static type inference (Hindley–Milner with let-polymorphism).
+//!
+//! The pass runs on the arena AST and uses the resolver to find the binding of
+//! each name. Monkey is dynamically typed, so the rules follow what the
+//! evaluator accepts: integers and floats mix freely in arithmetic, `==` and
+//! `!=` compare values of any types, and any value can be a condition. Outside
+//! arithmetic `int` and `float` are different types, so an `int` annotation
+//! does not accept a float. `+` also joins two strings. The elements of an
+//! array share one type, as do the keys and the values of a hash; indexing
+//! past the end or with a missing key gives `null` at run time, which the
+//! types do not track.
+//!
+//! Type annotations (`let x: int`, `fn(a: int) -> bool`) are checked where
+//! they are written: an annotated name has exactly the annotated type, which
+//! also makes it monomorphic.
+
+use super::arena::{Ast, NodeId, NodeKind, SideTable};
+use super::ast::Program;
+use super::diagnostic::Diagnostic;
+use super::error_code::ErrorCode;
+use super::resolver::{self, Resolution};
+use super::token::{Span, TokenType};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    Int,
+    Float,
+    Bool,
+    String,
+    Array(Box<Type>),
+    /// Key and value types.
+    Hash(Box<Type>, Box<Type>),
+    /// The value of a block that ends with a `let`, or an empty one.
+    Null,
+    Function(Vec<Type>, Box<Type>),
+    Var(TypeVar),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TypeVar(u32);
+
+/// Type variables are printed as `'a`, `'b`, ... in order of appearance.
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = HashMap::new();
+        write!(f, "{}", self.display(&mut names))
+    }
+}
+
+impl Type {
+    fn display(&self, names: &mut HashMap<TypeVar, String>) -> String {
+        match self {
+            Type::Int => String::from("int"),
+            Type::Float => String::from("float"),
+            Type::Bool => String::from("bool"),
+            Type::String => String::from("string"),
+            Type::Array(element) => format!("[{}]", element.display(names)),
+            Type::Hash(key, value) => {
+                format!("{{{}: {}}}", key.display(names), value.display(names))
+            }
+            Type::Null => String::from("null"),
+            Type::Function(parameters, result) => {
+                let parameters: Vec<String> = parameters
+                    .iter()
+                    .map(|parameter| parameter.display(names))
+                    .collect();
+                format!("fn({}) -> {}", parameters.join(", "), result.display(names))
+            }
+            Type::Var(var) => {
+                let next = names.len();
+                names.entry(*var).or_insert_with(|| var_name(next)).clone()
+            }
+        }
+    }
+}
+
+fn var_name(index: usize) -> String {
+    let letter = (b'a' + (index % 26) as u8) as char;
+    match index / 26 {
+        0 => format!("'{}", letter),
+        n => format!("'{}{}", letter, n),
+    }
+}
+
+/// The result of `typecheck`.
+#[derive(Debug, Clone)]
+pub struct Typing {
+    pub ast: Ast,
+    /// The type of every expression node, with variables resolved as far as
+    /// inference could.
+    pub types: SideTable<Type>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Typing {
+    /// The type of the expression of a top-level expression statement.
+    pub fn statement_type(&self, index: usize) -> Option<&Type> {
+        match self.ast.kind(*self.ast.statements().get(index)?) {
+            NodeKind::Expression { expression } => self.types.get(*expression),
+            _ => None,
+        }
+    }
+}
+
+/// Infer the types of a program. Names the resolver cannot bind, such as
+/// builtins, are given a fresh unknown type.
+pub fn typecheck(program: &Program) -> Typing {
+    let ast = Ast::from_program(program);
+    let resolution = resolver::resolve(&ast, &[]);
+    let mut inference = Inference {
+        ast: &ast,
+        resolution: &resolution,
+        vars: Vec::new(),
+        level: 0,
+        schemes: SideTable::new(),
+        returns: Vec::new(),
+        types: SideTable::new(),
+        diagnostics: Vec::new(),
+    };
+    for statement in ast.statements() {
+        inference.statement(*statement);
+    }
+
+    let mut types = SideTable::new();
+    for (id, ty) in inference.types.iter() {
+        types.insert(id, inference.resolve(ty));
+    }
+    let mut diagnostics = inference.diagnostics;
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+    Typing {
+        ast,
+        types,
+        diagnostics,
+    }
+}
+
+// 型変数の状態。levelはletの入れ子の深さで、これより深い変数だけを一般化する
+#[derive(Debug, Clone)]
+struct VarState {
+    bound: Option<Type>,
+    level: u32,
+    class: Class,
+}
+
+// 型変数に入れられる型の範囲。後のものほど狭い
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Class {
+    Any,
+    Addable, // int か float か string (+ の被演算子)
+    Numeric, // int か float
+}
+
+// ∀vars. ty
+#[derive(Debug, Clone)]
+struct Scheme {
+    vars: Vec<TypeVar>,
+    ty: Type,
+}
+
+enum UnifyError {
+    Mismatch,
+    NotNumeric,
+    NotAddable,
+    Infinite,
+}
+
+struct Inference<'a> {
+    ast: &'a Ast,
+    resolution: &'a Resolution,
+    vars: Vec<VarState>,
+    level: u32,
+    schemes: SideTable<Scheme>, // 束縛のidごと
+    returns: Vec<Type>,         // 関数の戻り値の型。returnと本体の値を合わせる
+    types: SideTable<Type>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Inference<'a> {
+    fn fresh(&mut self) -> Type {
+        self.fresh_at(self.level)
+    }
+
+    fn fresh_at(&mut self, level: u32) -> Type {
+        let var = TypeVar(self.vars.len() as u32);
+        self.vars.push(VarState {
+            bound: None,
+            level,
+            class: Class::Any,
+        });
+        Type::Var(var)
+    }
+
+    fn state(&self, var: TypeVar) -> &VarState {
+        &self.vars[var.0 as usize]
+    }
+
+    fn state_mut(&mut self, var: TypeVar) -> &mut VarState {
+        &mut self.vars[var.0 as usize]
+    }
+
+    // 束縛された変数を外側だけたどる
+    fn shallow(&self, ty: &Type) -> Type {
+        let mut ty = ty.clone();
+        while let Type::Var(var) = ty {
+            match &self.state(var).bound {
+                Some(bound) => ty = bound.clone(),
+                None => break,
+            }
+        }
+        ty
+    }
+
+    fn resolve(&self, ty: &Type) -> Type {
+        match self.shallow(ty) {
+            Type::Array(element) => Type::Array(Box::new(self.resolve(&element))),
+            Type::Hash(key, value) => {
+                Type::Hash(Box::new(self.resolve(&key)), Box::new(self.resolve(&value)))
+            }
+            Type::Function(parameters, result) => Type::Function(
+                parameters
+                    .iter()
+                    .map(|parameter| self.resolve(parameter))
+                    .collect(),
+                Box::new(self.resolve(&result)),
+            ),
+            ty => ty,
+        }
+    }
+
+    fn unify(&mut self, left: &Type, right: &Type) -> Result<(), UnifyError> {
+        match (self.shallow(left), self.shallow(right)) {
+            (Type::Var(a), Type::Var(b)) if a == b => Ok(()),
+            (Type::Var(var), ty) | (ty, Type::Var(var)) => self.bind(var, ty),
+            (Type::Int, Type::Int)
+            | (Type::Float, Type::Float)
+            | (Type::Bool, Type::Bool)
+            | (Type::String, Type::String)
+            | (Type::Null, Type::Null) => Ok(()),
+            (Type::Array(l), Type::Array(r)) => self.unify(&l, &r),
+            (Type::Hash(lk, lv), Type::Hash(rk, rv)) => {
+                self.unify(&lk, &rk)?;
+                self.unify(&lv, &rv)
+            }
+            (Type::Function(lp, lr), Type::Function(rp, rr)) if lp.len() == rp.len() => {
+                for (l, r) in lp.iter().zip(rp.iter()) {
+                    self.unify(l, r)?;
+                }
+                self.unify(&lr, &rr)
+            }
+            _ => Err(UnifyError::Mismatch),
+        }
+    }
+
+    fn bind(&mut self, var: TypeVar, ty: Type) -> Result<(), UnifyError> {
+        let VarState { level, class, .. } = *self.state(var);
+        if let Type::Var(other) = ty {
+            let other = self.state_mut(other);
+            other.level = other.level.min(level);
+            other.class = other.class.max(class);
+        } else {
+            match class {
+                Class::Numeric if !matches!(ty, Type::Int | Type::Float) => {
+                    return Err(UnifyError::NotNumeric)
+                }
+                Class::Addable if !matches!(ty, Type::Int | Type::Float | Type::String) => {
+                    return Err(UnifyError::NotAddable)
+                }
+                _ => {}
+            }
+            if self.occurs(var, &ty, level) {
+                return Err(UnifyError::Infinite);
+            }
+        }
+        self.state_mut(var).bound = Some(ty);
+        Ok(())
+    }
+
+    // var が ty に現れるか調べながら、ty の変数のlevelを下げる
+    fn occurs(&mut self, var: TypeVar, ty: &Type, level: u32) -> bool {
+        match self.shallow(ty) {
+            Type::Var(other) => {
+                let state = self.state_mut(other);
+                state.level = state.level.min(level);
+                other == var
+            }
+            Type::Function(parameters, result) => {
+                let mut found = false;
+                for parameter in parameters.iter().chain(std::iter::once(&*result)) {
+                    found |= self.occurs(var, parameter, level);
+                }
+                found
+            }
+            Type::Array(element) => self.occurs(var, &element, level),
+            Type::Hash(key, value) => {
+                let found = self.occurs(var, &key, level);
+                self.occurs(var, &value, level) || found
+            }
+            _ => false,
+        }
+    }
+
+    fn generalize(&mut self, ty: &Type) -> Scheme {
+        let ty = self.resolve(ty);
+        let mut vars = Vec::new();
+        self.free_vars(&ty, &mut vars);
+        vars.retain(|var| self.state(*var).level > self.level);
+        Scheme { vars, ty }
+    }
+
+    fn free_vars(&self, ty: &Type, vars: &mut Vec<TypeVar>) {
+        match ty {
+            Type::Var(var) if !vars.contains(var) => vars.push(*var),
+            Type::Function(parameters, result) => {
+                for parameter in parameters {
+                    self.free_vars(parameter, vars);
+                }
+                self.free_vars(result, vars);
+            }
+            Type::Array(element) => self.free_vars(element, vars),
+            Type::Hash(key, value) => {
+                self.free_vars(key, vars);
+                self.free_vars(value, vars);
+            }
+            _ => {}
+        }
+    }
+
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let mut fresh = HashMap::new();
+        for var in &scheme.vars {
+            let class = self.state(*var).class;
+            let ty = self.fresh();
+            if let Type::Var(new) = ty {
+                self.state_mut(new).class = class;
+            }
+            fresh.insert(*var, ty);
+        }
+        substitute(&scheme.ty, &fresh)
+    }
+
+    fn mismatch(&mut self, error: UnifyError, expected: &Type, found: &Type, span: Span) {
+        let expected = self.resolve(expected);
+        let found = self.resolve(found);
+        let diagnostic = match error {
+            UnifyError::Infinite => {
+                let (var, ty) = match expected {
+                    Type::Var(_) => (&expected, &found),
+                    _ => (&found, &expected),
+                };
+                let mut names = HashMap::new();
+                Diagnostic::error(
+                    format!(
+                        "infinite type: `{} = {}`",
+                        var.display(&mut names),
+                        ty.display(&mut names)
+                    ),
+                    span,
+                )
+                .with_code(ErrorCode::InfiniteType)
+            }
+            // 数の型変数はどちらの側にもありうる
+            UnifyError::NotNumeric => {
+                let message = match found {
+                    Type::Var(_) => {
+                        format!("mismatched types: expected `{}`, found a number", expected)
+                    }
+                    _ => format!("mismatched types: expected a number, found `{}`", found),
+                };
+                Diagnostic::error(message, span).with_code(ErrorCode::TypeMismatch)
+            }
+            UnifyError::NotAddable => {
+                let message = match found {
+                    Type::Var(_) => format!(
+                        "mismatched types: expected `{}`, found a number or a string",
+                        expected
+                    ),
+                    _ => format!(
+                        "mismatched types: expected a number or a string, found `{}`",
+                        found
+                    ),
+                };
+                Diagnostic::error(message, span).with_code(ErrorCode::TypeMismatch)
+            }
+            UnifyError::Mismatch => {
+                // 両方の型で同じ変数名を使う
+                let mut names = HashMap::new();
+                Diagnostic::error(
+                    format!(
+                        "mismatched types: expected `{}`, found `{}`",
+                        expected.display(&mut names),
+                        found.display(&mut names)
+                    ),
+                    span,
+                )
+                .with_code(ErrorCode::TypeMismatch)
+            }
+        };
+        self.diagnostics.push(diagnostic);
+    }
+
+    // 型注釈の型。注釈がなければNone
+    fn annotation(&mut self, id: NodeId) -> Option<Type> {
+        let annotation = self.ast.annotation(id)?;
+        Some(self.annotation_type(annotation))
+    }
+
+    fn annotation_type(&mut self, id: NodeId) -> Type {
+        let ast = self.ast;
+        match ast.kind(id) {
+            NodeKind::NamedType(symbol) => {
+                match ast.name(*symbol) {
+                    "int" => Type::Int,
+                    "float" => Type::Float,
+                    "bool" => Type::Bool,
+                    "string" => Type::String,
+                    "null" => Type::Null,
+                    name => {
+                        self.diagnostics.push(
+                        Diagnostic::error(format!("unknown type `{}`", name), ast.span(id))
+                            .with_code(ErrorCode::UnknownType)
+                            .with_note("the types are `int`, `float`, `bool`, `string`, `null`, `[T]`, `{K: V}` and `fn(...) -> ...`"),
+                    );
+                        // 続くエラーを出さないように何にでも合わせる
+                        self.fresh()
+                    }
+                }
+            }
+            NodeKind::FunctionType { parameters, result } => {
+                let parameters = ast
+                    .nodes(*parameters)
+                    .iter()
+                    .map(|parameter| self.annotation_type(*parameter))
+                    .collect();
+                Type::Function(parameters, Box::new(self.annotation_type(*result)))
+            }
+            NodeKind::ArrayType { element } => {
+                Type::Array(Box::new(self.annotation_type(*element)))
+            }
+            NodeKind::HashType { key, value } => {
+                let key_ty = self.annotation_type(*key);
+                self.hash_key(&key_ty, ast.span(*key));
+                Type::Hash(Box::new(key_ty), Box::new(self.annotation_type(*value)))
+            }
+            kind => panic!("expected a type, got {:?}", kind),
+        }
+    }
+
+    // スコープの外から参照された束縛は、letを見る前に単相の型を与えておく
+    fn binding_type(&mut self, binding: NodeId) -> Type {
+        match self.schemes.get(binding).cloned() {
+            Some(scheme) => self.instantiate(&scheme),
+            None => {
+                let ty = self.fresh_at(0);
+                self.schemes.insert(
+                    binding,
+                    Scheme {
+                        vars: Vec::new(),
+                        ty: ty.clone(),
+                    },
+                );
+                ty
+            }
+        }
+    }
+
+    fn statement(&mut self, id: NodeId) -> Type {
+        let ast = self.ast;
+        match ast.kind(id) {
+            NodeKind::Let { name, value } => {
+                self.level += 1;
+                let placeholder = match self.schemes.get(*name) {
+                    Some(scheme) => scheme.ty.clone(),
+                    None => {
+                        let ty = self.fresh();
+                        self.schemes.insert(
+                            *name,
+                            Scheme {
+                                vars: Vec::new(),
+                                ty: ty.clone(),
+                            },
+                        );
+                        ty
+                    }
+                };
+                if let Some(annotation) = self.annotation(*name) {
+                    if let Err(error) = self.unify(&annotation, &placeholder) {
+                        let span = ast.span(ast.annotation(*name).expect("annotated"));
+                        self.mismatch(error, &annotation, &placeholder, span);
+                    }
+                }
+                let ty = self.expression(*value);
+                if let Err(error) = self.unify(&placeholder, &ty) {
+                    self.mismatch(error, &placeholder, &ty, ast.span(*value));
+                }
+                self.level -= 1;
+                // 注釈があればその型になる
+                let scheme = self.generalize(&placeholder);
+                self.schemes.insert(*name, scheme);
+                Type::Null
+            }
+            NodeKind::Return { value } => {
+                let ty = self.expression(*value);
+                if let Some(expected) = self.returns.last().cloned() {
+                    if let Err(error) = self.unify(&expected, &ty) {
+                        self.mismatch(error, &expected, &ty, ast.span(*value));
+                    }
+                }
+                // return の後には何も続かない
+                self.fresh()
+            }
+            NodeKind::Expression { expression } => self.expression(*expression),
+            kind => panic!("expected a statement, got {:?}", kind),
+        }
+    }
+
+    fn block(&mut self, id: NodeId) -> Type {
+        let ast = self.ast;
+        let statements = match ast.kind(id) {
+            NodeKind::Block { statements } => ast.nodes(*statements),
+            kind => panic!("expected a block, got {:?}", kind),
+        };
+        let mut ty = Type::Null;
+        for statement in statements {
+            ty = self.statement(*statement);
+        }
+        ty
+    }
+
+    fn expression(&mut self, id: NodeId) -> Type {
+        let ty = self.infer(id);
+        self.types.insert(id, ty.clone());
+        ty
+    }
+
+    fn infer(&mut self, id: NodeId) -> Type {
+        let ast = self.ast;
+        match ast.kind(id) {
+            NodeKind::Identifier(_) => match self.resolution.bindings.get(id) {
+                Some(binding) => self.binding_type(*binding),
+                None => self.fresh(),
+            },
+            NodeKind::Integer(_) | NodeKind::BigInteger(_) => Type::Int,
+            NodeKind::Float(_) => Type::Float,
+            NodeKind::Boolean(_) => Type::Bool,
+            NodeKind::String(_) => Type::String,
+            NodeKind::Array { elements } => {
+                let element_ty = self.fresh();
+                for element in ast.nodes(*elements) {
+                    let ty = self.expression(*element);
+                    if let Err(error) = self.unify(&element_ty, &ty) {
+                        self.mismatch(error, &element_ty, &ty, ast.span(*element));
+                    }
+                }
+                Type::Array(Box::new(element_ty))
+            }
+            // キーと値が交互に並んでいる
+            NodeKind::Hash { pairs } => {
+                let (key_ty, value_ty) = (self.fresh(), self.fresh());
+                let nodes = ast.nodes(*pairs);
+                for pair in nodes.chunks(2) {
+                    for (node, expected) in pair.iter().zip([&key_ty, &value_ty]) {
+                        let ty = self.expression(*node);
+                        if let Err(error) = self.unify(expected, &ty) {
+                            self.mismatch(error, expected, &ty, ast.span(*node));
+                        }
+                    }
+                }
+                if let Some(first) = nodes.first() {
+                    self.hash_key(&key_ty, ast.span(*first));
+                }
+                Type::Hash(Box::new(key_ty), Box::new(value_ty))
+            }
+            NodeKind::Index { left, index } => {
+                let left_ty = self.expression(*left);
+                let index_ty = self.expression(*index);
+                let (expected, result) = match self.shallow(&left_ty) {
+                    Type::Array(element) => (Type::Int, *element),
+                    Type::Hash(key, value) => (*key, *value),
+                    // 配列かハッシュかまだ分からない
+                    Type::Var(_) => return self.fresh(),
+                    ty => {
+                        self.diagnostics.push(
+                            Diagnostic::error(
+                                format!("cannot index into `{}`", self.resolve(&ty)),
+                                ast.span(*left),
+                            )
+                            .with_code(ErrorCode::UnknownOperator)
+                            .with_note("only arrays and hashes can be indexed"),
+                        );
+                        return self.fresh();
+                    }
+                };
+                if let Err(error) = self.unify(&expected, &index_ty) {
+                    self.mismatch(error, &expected, &index_ty, ast.span(*index));
+                }
+                result
+            }
+            NodeKind::Prefix { operator, right } => {
+                let ty = self.expression(*right);
+                match operator {
+                    TokenType::Bang => Type::Bool,
+                    _ => {
+                        let code = ErrorCode::UnknownOperator;
+                        self.number(&ty, *operator, ast.span(*right), code);
+                        ty
+                    }
+                }
+            }
+            NodeKind::Infix {
+                operator,
+                left,
+                right,
+            } => {
+                let left_ty = self.expression(*left);
+                let right_ty = self.expression(*right);
+                let code = self.operand_error(&left_ty, &right_ty);
+                match operator {
+                    TokenType::Equal | TokenType::NotEqual => Type::Bool,
+                    TokenType::Plus => self.plus(&left_ty, &right_ty, *left, *right, code),
+                    TokenType::Lt | TokenType::Gt => {
+                        self.number(&left_ty, *operator, ast.span(*left), code);
+                        self.number(&right_ty, *operator, ast.span(*right), code);
+                        Type::Bool
+                    }
+                    _ => self.arithmetic(&left_ty, &right_ty, *operator, *left, *right, code),
+                }
+            }
+            NodeKind::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.expression(*condition);
+                let consequence_ty = self.block(*consequence);
+                match alternative {
+                    Some(alternative) => {
+                        let alternative_ty = self.block(*alternative);
+                        if let Err(error) = self.unify(&consequence_ty, &alternative_ty) {
+                            self.mismatch(
+                                error,
+                                &consequence_ty,
+                                &alternative_ty,
+                                ast.span(*alternative),
+                            );
+                        }
+                        consequence_ty
+                    }
+                    // 条件が偽ならnullになるが、文として使われることが多いので縛らない
+                    None => self.fresh(),
+                }
+            }
+            NodeKind::Function { parameters, body } => {
+                let parameter_types: Vec<Type> = ast
+                    .nodes(*parameters)
+                    .iter()
+                    .map(|parameter| {
+                        let ty = match self.annotation(*parameter) {
+                            Some(annotation) => annotation,
+                            None => self.fresh(),
+                        };
+                        self.schemes.insert(
+                            *parameter,
+                            Scheme {
+                                vars: Vec::new(),
+                                ty: ty.clone(),
+                            },
+                        );
+                        self.types.insert(*parameter, ty.clone());
+                        ty
+                    })
+                    .collect();
+                let result = match self.annotation(id) {
+                    Some(annotation) => annotation,
+                    None => self.fresh(),
+                };
+                self.returns.push(result.clone());
+                let body_ty = self.block(*body);
+                self.returns.pop();
+                if let Err(error) = self.unify(&result, &body_ty) {
+                    self.mismatch(error, &result, &body_ty, ast.span(*body));
+                }
+                Type::Function(parameter_types, Box::new(result))
+            }
+            NodeKind::Call {
+                function,
+                arguments,
+            } => {
+                let function_ty = self.expression(*function);
+                let arguments = ast.nodes(*arguments);
+                let argument_types: Vec<Type> = arguments
+                    .iter()
+                    .map(|argument| self.expression(*argument))
+                    .collect();
+                let result = self.fresh();
+                match self.shallow(&function_ty) {
+                    Type::Function(parameters, function_result) => {
+                        if parameters.len() != arguments.len() {
+                            self.diagnostics.push(
+                                Diagnostic::error(
+                                    format!(
+                                        "this function takes {} argument{} but {} {} given",
+                                        parameters.len(),
+                                        if parameters.len() == 1 { "" } else { "s" },
+                                        arguments.len(),
+                                        if arguments.len() == 1 { "was" } else { "were" }
+                                    ),
+                                    ast.span(id),
+                                )
+                                .with_code(ErrorCode::WrongArgumentCount),
+                            );
+                            return result;
+                        }
+                        for ((parameter, argument), ty) in
+                            parameters.iter().zip(arguments).zip(&argument_types)
+                        {
+                            if let Err(error) = self.unify(parameter, ty) {
+                                self.mismatch(error, parameter, ty, ast.span(*argument));
+                            }
+                        }
+                        *function_result
+                    }
+                    Type::Var(_) => {
+                        let expected = Type::Function(argument_types, Box::new(result.clone()));
+                        if let Err(error) = self.unify(&function_ty, &expected) {
+                            self.mismatch(error, &expected, &function_ty, ast.span(*function));
+                        }
+                        result
+                    }
+                    ty => {
+                        self.diagnostics.push(
+                            Diagnostic::error(
+                                format!("`{}` is not a function", self.resolve(&ty)),
+                                ast.span(*function),
+                            )
+                            .with_code(ErrorCode::NotAFunction),
+                        );
+                        result
+                    }
+                }
+            }
+            kind => panic!("expected an expression, got {:?}", kind),
+        }
+    }
+
+    // 実行時と同じく、両辺が同じ型なら演算子がその型にないという誤り、
+    // 違う型なら型の不一致として報告する
+    fn operand_error(&mut self, left: &Type, right: &Type) -> ErrorCode {
+        match (self.shallow(left), self.shallow(right)) {
+            (Type::Var(_), _) | (_, Type::Var(_)) => ErrorCode::TypeMismatch,
+            (left, right) if left == right => ErrorCode::UnknownOperator,
+            _ => ErrorCode::TypeMismatch,
+        }
+    }
+
+    // + は数の足し算か文字列の連結
+    fn plus(
+        &mut self,
+        left_ty: &Type,
+        right_ty: &Type,
+        left: NodeId,
+        right: NodeId,
+        code: ErrorCode,
+    ) -> Type {
+        let ast = self.ast;
+        match (self.shallow(left_ty), self.shallow(right_ty)) {
+            (Type::String, _) | (_, Type::String) => {
+                for (ty, node) in [(left_ty, left), (right_ty, right)] {
+                    if let Err(error) = self.unify(&Type::String, ty) {
+                        self.mismatch(error, &Type::String, ty, ast.span(node));
+                    }
+                }
+                Type::String
+            }
+            // どちらか分かるまで、数か文字列の同じ型にしておく
+            (Type::Var(l), Type::Var(r)) => {
+                for var in [l, r] {
+                    let state = self.state_mut(var);
+                    state.class = state.class.max(Class::Addable);
+                }
+                let _ = self.unify(left_ty, right_ty);
+                left_ty.clone()
+            }
+            _ => self.arithmetic(left_ty, right_ty, TokenType::Plus, left, right, code),
+        }
+    }
+
+    fn arithmetic(
+        &mut self,
+        left_ty: &Type,
+        right_ty: &Type,
+        operator: TokenType,
+        left: NodeId,
+        right: NodeId,
+        code: ErrorCode,
+    ) -> Type {
+        let ast = self.ast;
+        let left_ok = self.number(left_ty, operator, ast.span(left), code);
+        let right_ok = self.number(right_ty, operator, ast.span(right), code);
+        if !(left_ok && right_ok) {
+            return self.fresh();
+        }
+        // intとfloatを混ぜるとfloatになる。型の分からない側は
+        // 数の型変数のまま残し、結果はその変数にする
+        match (self.shallow(left_ty), self.shallow(right_ty)) {
+            (Type::Float, _) | (_, Type::Float) => Type::Float,
+            (Type::Int, Type::Int) => Type::Int,
+            (Type::Var(_), Type::Int) => left_ty.clone(),
+            (Type::Int, Type::Var(_)) => right_ty.clone(),
+            // 両方分からなければ同じ型にする
+            _ => {
+                let _ = self.unify(left_ty, right_ty);
+                left_ty.clone()
+            }
+        }
+    }
+
+    // ハッシュのキーになれる型か。分からない型はそのまま通す
+    fn hash_key(&mut self, ty: &Type, span: Span) {
+        let ty = self.resolve(ty);
+        if let Type::Float | Type::Null | Type::Function(..) | Type::Array(_) | Type::Hash(..) = ty
+        {
+            self.diagnostics.push(
+                Diagnostic::error(format!("`{}` cannot be a hash key", ty), span)
+                    .with_code(ErrorCode::UnusableHashKey)
+                    .with_note("hash keys are `int`, `bool` or `string`"),
+            );
+        }
+    }
+
+    // 算術と比較の被演算子は数でなければならない
+    fn number(&mut self, ty: &Type, operator: TokenType, span: Span, code: ErrorCode) -> bool {
+        match self.shallow(ty) {
+            Type::Int | Type::Float => true,
+            // 数か文字列と決まっていた変数も数に絞る
+            Type::Var(var) => {
+                self.state_mut(var).class = Class::Numeric;
+                true
+            }
+            ty => {
+                self.diagnostics.push(
+                    Diagnostic::error(
+                        format!(
+                            "cannot apply `{}` to `{}`",
+                            operator.value(),
+                            self.resolve(&ty)
+                        ),
+                        span,
+                    )
+                    .with_code(code)
+                    .with_note("arithmetic and comparison operators take int or float operands"),
+                );
+                false
+            }
+        }
+    }
+}
+
+fn substitute(ty: &Type, fresh: &HashMap<TypeVar, Type>) -> Type {
+    match ty {
+        Type::Var(var) => fresh.get(var).cloned().unwrap_or_else(|| ty.clone()),
+        Type::Array(element) => Type::Array(Box::new(substitute(element, fresh))),
+        Type::Hash(key, value) => Type::Hash(
+            Box::new(substitute(key, fresh)),
+            Box::new(substitute(value, fresh)),
+        ),
+        Type::Function(parameters, result) => Type::Function(
+            parameters
+                .iter()
+                .map(|parameter| substitute(parameter, fresh))
+                .collect(),
+            Box::new(substitute(result, fresh)),
+        ),
+        ty => ty.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::lexer::Lexer;
+    use super::super::parser::Parser;
+    use super::*;
+
+    fn typing(input: &str) -> Typing {
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
+        assert_eq!(parser.errors(), vec![], "input: {}", input);
+        typecheck(&program)
+    }
+
+    // 最後の式文の型
+    fn type_of(input: &str) -> String {
+        let typing = typing(input);
+        assert_eq!(typing.diagnostics, vec![], "input: {}", input);
+        let last = typing.ast.statements().len() - 1;
+        typing.statement_type(last).unwrap().to_string()
+    }
+
+    fn errors(input: &str) -> Vec<(ErrorCode, String, Span)> {
+        typing(input)
+            .diagnostics
+            .into_iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.code.unwrap(),
+                    diagnostic.message,
+                    diagnostic.span,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn operator_errors_match_runtime() {
+        use super::super::evaluator::Evaluator;
+        use super::super::object::Environment;
+
+        for input in [
+            "1 + true",
+            "true + false",
+            "-true",
+            "true < 1.5",
+            "2 * (1 < 2)",
+        ]
+        .iter()
+        {
+            let program = Parser::new(Lexer::new(input)).parse_program();
+            let runtime = Evaluator::default()
+                .eval_program(&program, &Environment::new())
+                .unwrap_err();
+            assert_eq!(
+                errors(input)[0].0,
+                runtime.code.unwrap(),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn infers_types() {
+        let tests = vec![
+            ("1 + 2 * 3", "int"),
+            ("1 + 2.5", "float"),
+            ("-9223372036854775808 < 2", "bool"),
+            ("!5", "bool"),
+            ("1 == true", "bool"),
+            ("if (1) { true } else { false }", "bool"),
+            ("fn(x) { x }", "fn('a) -> 'a"),
+            ("fn(a, b) { a + b }", "fn('a, 'a) -> 'a"),
+            ("fn(f, x) { f(f(x)) }", "fn(fn('a) -> 'a, 'a) -> 'a"),
+            ("fn() { let x = 1; }", "fn() -> null"),
+            ("fn(n) { if (n < 0) { return 0; } n }", "fn(int) -> int"),
+            (
+                "let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } }; fact",
+                "fn(int) -> int",
+            ),
+            (
+                "let add = fn(a) { fn(b) { a + b } }; add(1)",
+                "fn(int) -> int",
+            ),
+            // 型の分からない数は、混ぜる相手に合わせて結果の型が決まる
+            ("let inc = fn(x) { x + 1 }; inc(2.5)", "float"),
+            ("let inc = fn(x) { x + 1 }; inc(2)", "int"),
+            ("let half = fn(x) { x / 2.0 }; half(3)", "float"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(type_of(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn let_polymorphism() {
+        let input = "let id = fn(x) { x }; let a = id(1); let b = id(true); id(id)(b)";
+        assert_eq!(type_of(input), "bool");
+
+        // 引数は単相なので、関数の中で別の型に使うとエラー
+        let input = "fn(id) { id(1); id(true) }";
+        assert_eq!(errors(input)[0].0, ErrorCode::TypeMismatch);
+    }
+
+    #[test]
+    fn mutual_recursion() {
+        let input = "let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } };
+let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } };
+even";
+        assert_eq!(type_of(input), "fn('a) -> bool");
+    }
+
+    #[test]
+    fn strings_arrays_and_hashes() {
+        let tests = [
+            (r#""a" + "b""#, "string"),
+            ("[1, 2][0]", "int"),
+            ("[]", "['a]"),
+            (r#"{"a": [1.5], "b": []}"#, "{string: [float]}"),
+            (r#"let h = {true: "yes"}; h[false]"#, "string"),
+            // + の被演算子は数か文字列のどちらか
+            ("fn(a, b) { a + b }", "fn('a, 'a) -> 'a"),
+            (r#"let join = fn(a, b) { a + b }; join("x", "y")"#, "string"),
+            ("let join = fn(a, b) { a + b }; join(1, 2)", "int"),
+            ("fn(s) { s + \"!\" }", "fn(string) -> string"),
+            ("fn(a, i) { a[i] }", "fn('a, 'b) -> 'c"),
+            ("let xs: [int] = []; xs", "[int]"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(type_of(input), expected, "input: {}", input);
+        }
+
+        let tests = vec![
+            (
+                r#""a" + 1"#,
+                ErrorCode::TypeMismatch,
+                "mismatched types: expected `string`, found `int`",
+                Span::new(6, 7),
+            ),
+            (
+                r#"[1, "two"]"#,
+                ErrorCode::TypeMismatch,
+                "mismatched types: expected `int`, found `string`",
+                Span::new(4, 9),
+            ),
+            (
+                r#"{1.5: "small"}"#,
+                ErrorCode::UnusableHashKey,
+                "`float` cannot be a hash key",
+                Span::new(1, 4),
+            ),
+            (
+                r#"[1, 2]["0"]"#,
+                ErrorCode::TypeMismatch,
+                "mismatched types: expected `int`, found `string`",
+                Span::new(7, 10),
+            ),
+            (
+                "let n = 5; n[0]",
+                ErrorCode::UnknownOperator,
+                "cannot index into `int`",
+                Span::new(11, 12),
+            ),
+            (
+                "let join = fn(a, b) { a + b }; join(true, 1)",
+                ErrorCode::TypeMismatch,
+                "mismatched types: expected a number or a string, found `bool`",
+                Span::new(36, 40),
+            ),
+            (
+                "let h: {fn() -> int: int} = {}; h",
+                ErrorCode::UnusableHashKey,
+                "`fn() -> int` cannot be a hash key",
+                Span::new(8, 19),
+            ),
+        ];
+        for (input, code, message, span) in tests {
+            assert_eq!(
+                errors(input),
+                vec![(code, String::from(message), span)],
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn type_errors() {
+        let tests = vec![
+            (
+                "1 + true",
+                ErrorCode::TypeMismatch,
+                "cannot apply `+` to `bool`",
+                Span::new(4, 8),
+            ),
+            (
+                "-true",
+                ErrorCode::UnknownOperator,
+                "cannot apply `-` to `bool`",
+                Span::new(1, 5),
+            ),
+            (
+                "let five = 5; five(1)",
+                ErrorCode::NotAFunction,
+                "`int` is not a function",
+                Span::new(14, 18),
+            ),
+            (
+                "let f = fn(x) { x }; f(1, 2)",
+                ErrorCode::WrongArgumentCount,
+                "this function takes 1 argument but 2 were given",
+                Span::new(21, 28),
+            ),
+            (
+                "let neg = fn(x) { -x }; neg(true)",
+                ErrorCode::TypeMismatch,
+                "mismatched types: expected a number, found `bool`",
+                Span::new(28, 32),
+            ),
+            (
+                "let inc = fn(x) { x + 1 }; inc(true)",
+                ErrorCode::TypeMismatch,
+                "mismatched types: expected a number, found `bool`",
+                Span::new(31, 35),
+            ),
+            (
+                "if (true) { 1 } else { false }",
+                ErrorCode::TypeMismatch,
+                "mismatched types: expected `int`, found `bool`",
+                Span::new(21, 30),
+            ),
+            (
+                "let apply = fn(f) { f(1) }; apply(fn(b) { !b }); apply(fn(x, y) { x })",
+                ErrorCode::TypeMismatch,
+                "mismatched types: expected `fn(int) -> 'a`, found `fn('b, 'c) -> 'b`",
+                Span::new(55, 69),
+            ),
+            (
+                "fn(x) { x(x) }",
+                ErrorCode::InfiniteType,
+                "infinite type: `'a = fn('a) -> 'b`",
+                Span::new(8, 9),
+            ),
+            (
+                "let f = fn() { let y = 1; y; let z = y; }; f() - 1",
+                ErrorCode::TypeMismatch,
+                "cannot apply `-` to `null`",
+                Span::new(43, 46),
+            ),
+            // まれにしか通らない分岐
+            (
+                "let f = fn(n) { if (n > 100) { return n + true; } n }",
+                ErrorCode::TypeMismatch,
+                "cannot apply `+` to `bool`",
+                Span::new(42, 46),
+            ),
+        ];
+        for (input, code, message, span) in tests {
+            assert_eq!(
+                errors(input),
+                vec![(code, String::from(message), span)],
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn annotations() {
+        assert_eq!(type_of("let x: float = 1.0; x"), "float");
+        assert_eq!(
+            type_of("fn(a: int, b) -> bool { a < b }"),
+            "fn(int, 'a) -> bool"
+        );
+        assert_eq!(
+            type_of("fn(f: fn(int) -> int, x) { f(x) }"),
+            "fn(fn(int) -> int, int) -> int"
+        );
+        // 注釈された関数は多相にならない
+        assert_eq!(
+            errors("let id: fn(int) -> int = fn(x) { x }; id(true)"),
+            vec![(
+                ErrorCode::TypeMismatch,
+                String::from("mismatched types: expected `int`, found `bool`"),
+                Span::new(41, 45),
+            )]
+        );
+
+        let tests = vec![
+            (
+                "let x: int = true;",
+                ErrorCode::TypeMismatch,
+                "mismatched types: expected `int`, found `bool`",
+                Span::new(13, 17),
+            ),
+            (
+                "let f = fn(a: int, b) -> bool { a + b };",
+                ErrorCode::TypeMismatch,
+                "mismatched types: expected `bool`, found a number",
+                Span::new(30, 39),
+            ),
+            (
+                "let apply: fn(fn(int) -> int, int) -> int = fn(f, x) { f(x) }; apply(fn(b) { !b }, 1)",
+                ErrorCode::TypeMismatch,
+                "mismatched types: expected `fn(int) -> int`, found `fn(int) -> bool`",
+                Span::new(69, 81),
+            ),
+            (
+                "let name: str = 1; name + 1",
+                ErrorCode::UnknownType,
+                "unknown type `str`",
+                Span::new(10, 13),
+            ),
+            // intとfloatは算術演算の中でしか混ざらない
+            (
+                "let x: int = 2.5; x",
+                ErrorCode::TypeMismatch,
+                "mismatched types: expected `int`, found `float`",
+                Span::new(13, 16),
+            ),
+            (
+                "let f = fn(a: int) -> int { a * 1.5 }; f(2)",
+                ErrorCode::TypeMismatch,
+                "mismatched types: expected `int`, found `float`",
+                Span::new(26, 37),
+            ),
+            (
+                "let x: float = 1; x",
+                ErrorCode::TypeMismatch,
+                "mismatched types: expected `float`, found `int`",
+                Span::new(15, 16),
+            ),
+            (
+                "fn(f: fn(bool) -> int) { f(1) }",
+                ErrorCode::TypeMismatch,
+                "mismatched types: expected `bool`, found `int`",
+                Span::new(27, 28),
+            ),
+        ];
+        for (input, code, message, span) in tests {
+            assert_eq!(
+                errors(input),
+                vec![(code, String::from(message), span)],
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_names_are_not_errors() {
+        assert_eq!(errors("puts(1) + undefined"), vec![]);
+    }
+}
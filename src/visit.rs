@@ -0,0 +1,797 @@
+//! Traversal of the AST.
+//!
+//! `Visitor` and `VisitorMut` have a method per node type whose default
+//! implementation calls the matching `walk_*` function to visit the children.
+//! A pass overrides only the methods it cares about, and calls `walk_*` from
+//! the override when it still wants to descend. `Folder` does the same for
+//! rewriting: each method takes a node by value and returns its replacement,
+//! with `noop_fold_*` rebuilding the node from folded children.
+//!
+//! Type annotations are leaves: their names are not identifiers of the
+//! program, so the visitors do not descend into them.
+
+use super::ast::{
+    ArrayLiteral, BigIntegerLiteral, BlockStatement, Boolean, CallExpression, ExpressionStatement,
+    ExpressionType, FloatLiteral, FunctionLiteral, HashLiteral, Identifer, IfExpression,
+    IndexExpression, InfixExpression, IntegerLiteral, LetStatement, PrefixExpression, Program,
+    ReturnStatement, StatementType, StringLiteral, TypeExpression,
+};
+
+pub trait Visitor {
+    fn visit_program(&mut self, program: &Program) {
+        walk_program(self, program)
+    }
+    fn visit_statement(&mut self, statement: &StatementType) {
+        walk_statement(self, statement)
+    }
+    fn visit_let_statement(&mut self, statement: &LetStatement) {
+        walk_let_statement(self, statement)
+    }
+    fn visit_return_statement(&mut self, statement: &ReturnStatement) {
+        walk_return_statement(self, statement)
+    }
+    fn visit_expression_statement(&mut self, statement: &ExpressionStatement) {
+        walk_expression_statement(self, statement)
+    }
+    fn visit_block_statement(&mut self, block: &BlockStatement) {
+        walk_block_statement(self, block)
+    }
+    fn visit_expression(&mut self, expression: &ExpressionType) {
+        walk_expression(self, expression)
+    }
+    fn visit_identifer(&mut self, _identifer: &Identifer) {}
+    fn visit_integer_literal(&mut self, _literal: &IntegerLiteral) {}
+    fn visit_big_integer_literal(&mut self, _literal: &BigIntegerLiteral) {}
+    fn visit_float_literal(&mut self, _literal: &FloatLiteral) {}
+    fn visit_boolean(&mut self, _boolean: &Boolean) {}
+    fn visit_string_literal(&mut self, _literal: &StringLiteral) {}
+    fn visit_array_literal(&mut self, literal: &ArrayLiteral) {
+        walk_array_literal(self, literal)
+    }
+    fn visit_hash_literal(&mut self, literal: &HashLiteral) {
+        walk_hash_literal(self, literal)
+    }
+    fn visit_type_expression(&mut self, _ty: &TypeExpression) {}
+    fn visit_prefix_expression(&mut self, expression: &PrefixExpression) {
+        walk_prefix_expression(self, expression)
+    }
+    fn visit_infix_expression(&mut self, expression: &InfixExpression) {
+        walk_infix_expression(self, expression)
+    }
+    fn visit_if_expression(&mut self, expression: &IfExpression) {
+        walk_if_expression(self, expression)
+    }
+    fn visit_function_literal(&mut self, literal: &FunctionLiteral) {
+        walk_function_literal(self, literal)
+    }
+    fn visit_call_expression(&mut self, expression: &CallExpression) {
+        walk_call_expression(self, expression)
+    }
+    fn visit_index_expression(&mut self, expression: &IndexExpression) {
+        walk_index_expression(self, expression)
+    }
+}
+
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, program: &Program) {
+    for statement in program {
+        visitor.visit_statement(statement);
+    }
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &StatementType) {
+    match statement {
+        StatementType::LetStatement(statement) => visitor.visit_let_statement(statement),
+        StatementType::ReturnStatement(statement) => visitor.visit_return_statement(statement),
+        StatementType::ExpressionStatement(statement) => {
+            visitor.visit_expression_statement(statement)
+        }
+    }
+}
+
+pub fn walk_let_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &LetStatement) {
+    visitor.visit_identifer(&statement.name);
+    if let Some(annotation) = &statement.annotation {
+        visitor.visit_type_expression(annotation);
+    }
+    visitor.visit_expression(&statement.value);
+}
+
+pub fn walk_return_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &ReturnStatement) {
+    visitor.visit_expression(&statement.value);
+}
+
+pub fn walk_expression_statement<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    statement: &ExpressionStatement,
+) {
+    visitor.visit_expression(&statement.expression);
+}
+
+pub fn walk_block_statement<V: Visitor + ?Sized>(visitor: &mut V, block: &BlockStatement) {
+    for statement in &block.statements {
+        visitor.visit_statement(statement);
+    }
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expression: &ExpressionType) {
+    match expression {
+        ExpressionType::Identifer(identifer) => visitor.visit_identifer(identifer),
+        ExpressionType::IntegerLiteral(literal) => visitor.visit_integer_literal(literal),
+        ExpressionType::BigIntegerLiteral(literal) => visitor.visit_big_integer_literal(literal),
+        ExpressionType::FloatLiteral(literal) => visitor.visit_float_literal(literal),
+        ExpressionType::Boolean(boolean) => visitor.visit_boolean(boolean),
+        ExpressionType::StringLiteral(literal) => visitor.visit_string_literal(literal),
+        ExpressionType::ArrayLiteral(literal) => visitor.visit_array_literal(literal),
+        ExpressionType::HashLiteral(literal) => visitor.visit_hash_literal(literal),
+        ExpressionType::PrefixExpression(expression) => visitor.visit_prefix_expression(expression),
+        ExpressionType::InfixExpression(expression) => visitor.visit_infix_expression(expression),
+        ExpressionType::IfExpression(expression) => visitor.visit_if_expression(expression),
+        ExpressionType::FunctionLiteral(literal) => visitor.visit_function_literal(literal),
+        ExpressionType::CallExpression(expression) => visitor.visit_call_expression(expression),
+        ExpressionType::IndexExpression(expression) => visitor.visit_index_expression(expression),
+        ExpressionType::Illegal => {}
+    }
+}
+
+pub fn walk_array_literal<V: Visitor + ?Sized>(visitor: &mut V, literal: &ArrayLiteral) {
+    for element in &literal.elements {
+        visitor.visit_expression(element);
+    }
+}
+
+pub fn walk_hash_literal<V: Visitor + ?Sized>(visitor: &mut V, literal: &HashLiteral) {
+    for (key, value) in &literal.pairs {
+        visitor.visit_expression(key);
+        visitor.visit_expression(value);
+    }
+}
+
+pub fn walk_prefix_expression<V: Visitor + ?Sized>(visitor: &mut V, expression: &PrefixExpression) {
+    visitor.visit_expression(&expression.right);
+}
+
+pub fn walk_infix_expression<V: Visitor + ?Sized>(visitor: &mut V, expression: &InfixExpression) {
+    visitor.visit_expression(&expression.left);
+    visitor.visit_expression(&expression.right);
+}
+
+pub fn walk_if_expression<V: Visitor + ?Sized>(visitor: &mut V, expression: &IfExpression) {
+    visitor.visit_expression(&expression.condition);
+    visitor.visit_block_statement(&expression.consequence);
+    if let Some(alternative) = &expression.alternative {
+        visitor.visit_block_statement(alternative);
+    }
+}
+
+pub fn walk_function_literal<V: Visitor + ?Sized>(visitor: &mut V, literal: &FunctionLiteral) {
+    for (parameter, annotation) in literal.parameters.iter().zip(&literal.parameter_types) {
+        visitor.visit_identifer(parameter);
+        if let Some(annotation) = annotation {
+            visitor.visit_type_expression(annotation);
+        }
+    }
+    if let Some(return_type) = &literal.return_type {
+        visitor.visit_type_expression(return_type);
+    }
+    visitor.visit_block_statement(&literal.body);
+}
+
+pub fn walk_call_expression<V: Visitor + ?Sized>(visitor: &mut V, expression: &CallExpression) {
+    visitor.visit_expression(&expression.function);
+    for argument in &expression.arguments {
+        visitor.visit_expression(argument);
+    }
+}
+
+pub fn walk_index_expression<V: Visitor + ?Sized>(visitor: &mut V, expression: &IndexExpression) {
+    visitor.visit_expression(&expression.left);
+    visitor.visit_expression(&expression.index);
+}
+
+/// Like `Visitor`, but with mutable access to the nodes.
+pub trait VisitorMut {
+    fn visit_program_mut(&mut self, program: &mut Program) {
+        walk_program_mut(self, program)
+    }
+    fn visit_statement_mut(&mut self, statement: &mut StatementType) {
+        walk_statement_mut(self, statement)
+    }
+    fn visit_let_statement_mut(&mut self, statement: &mut LetStatement) {
+        walk_let_statement_mut(self, statement)
+    }
+    fn visit_return_statement_mut(&mut self, statement: &mut ReturnStatement) {
+        walk_return_statement_mut(self, statement)
+    }
+    fn visit_expression_statement_mut(&mut self, statement: &mut ExpressionStatement) {
+        walk_expression_statement_mut(self, statement)
+    }
+    fn visit_block_statement_mut(&mut self, block: &mut BlockStatement) {
+        walk_block_statement_mut(self, block)
+    }
+    fn visit_expression_mut(&mut self, expression: &mut ExpressionType) {
+        walk_expression_mut(self, expression)
+    }
+    fn visit_identifer_mut(&mut self, _identifer: &mut Identifer) {}
+    fn visit_integer_literal_mut(&mut self, _literal: &mut IntegerLiteral) {}
+    fn visit_big_integer_literal_mut(&mut self, _literal: &mut BigIntegerLiteral) {}
+    fn visit_float_literal_mut(&mut self, _literal: &mut FloatLiteral) {}
+    fn visit_boolean_mut(&mut self, _boolean: &mut Boolean) {}
+    fn visit_string_literal_mut(&mut self, _literal: &mut StringLiteral) {}
+    fn visit_array_literal_mut(&mut self, literal: &mut ArrayLiteral) {
+        walk_array_literal_mut(self, literal)
+    }
+    fn visit_hash_literal_mut(&mut self, literal: &mut HashLiteral) {
+        walk_hash_literal_mut(self, literal)
+    }
+    fn visit_type_expression_mut(&mut self, _ty: &mut TypeExpression) {}
+    fn visit_prefix_expression_mut(&mut self, expression: &mut PrefixExpression) {
+        walk_prefix_expression_mut(self, expression)
+    }
+    fn visit_infix_expression_mut(&mut self, expression: &mut InfixExpression) {
+        walk_infix_expression_mut(self, expression)
+    }
+    fn visit_if_expression_mut(&mut self, expression: &mut IfExpression) {
+        walk_if_expression_mut(self, expression)
+    }
+    fn visit_function_literal_mut(&mut self, literal: &mut FunctionLiteral) {
+        walk_function_literal_mut(self, literal)
+    }
+    fn visit_call_expression_mut(&mut self, expression: &mut CallExpression) {
+        walk_call_expression_mut(self, expression)
+    }
+    fn visit_index_expression_mut(&mut self, expression: &mut IndexExpression) {
+        walk_index_expression_mut(self, expression)
+    }
+}
+
+pub fn walk_program_mut<V: VisitorMut + ?Sized>(visitor: &mut V, program: &mut Program) {
+    for statement in program {
+        visitor.visit_statement_mut(statement);
+    }
+}
+
+pub fn walk_statement_mut<V: VisitorMut + ?Sized>(visitor: &mut V, statement: &mut StatementType) {
+    match statement {
+        StatementType::LetStatement(statement) => visitor.visit_let_statement_mut(statement),
+        StatementType::ReturnStatement(statement) => visitor.visit_return_statement_mut(statement),
+        StatementType::ExpressionStatement(statement) => {
+            visitor.visit_expression_statement_mut(statement)
+        }
+    }
+}
+
+pub fn walk_let_statement_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    statement: &mut LetStatement,
+) {
+    visitor.visit_identifer_mut(&mut statement.name);
+    if let Some(annotation) = &mut statement.annotation {
+        visitor.visit_type_expression_mut(annotation);
+    }
+    visitor.visit_expression_mut(&mut statement.value);
+}
+
+pub fn walk_return_statement_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    statement: &mut ReturnStatement,
+) {
+    visitor.visit_expression_mut(&mut statement.value);
+}
+
+pub fn walk_expression_statement_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    statement: &mut ExpressionStatement,
+) {
+    visitor.visit_expression_mut(&mut statement.expression);
+}
+
+pub fn walk_block_statement_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    block: &mut BlockStatement,
+) {
+    for statement in &mut block.statements {
+        visitor.visit_statement_mut(statement);
+    }
+}
+
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    expression: &mut ExpressionType,
+) {
+    match expression {
+        ExpressionType::Identifer(identifer) => visitor.visit_identifer_mut(identifer),
+        ExpressionType::IntegerLiteral(literal) => visitor.visit_integer_literal_mut(literal),
+        ExpressionType::BigIntegerLiteral(literal) => {
+            visitor.visit_big_integer_literal_mut(literal)
+        }
+        ExpressionType::FloatLiteral(literal) => visitor.visit_float_literal_mut(literal),
+        ExpressionType::Boolean(boolean) => visitor.visit_boolean_mut(boolean),
+        ExpressionType::StringLiteral(literal) => visitor.visit_string_literal_mut(literal),
+        ExpressionType::ArrayLiteral(literal) => visitor.visit_array_literal_mut(literal),
+        ExpressionType::HashLiteral(literal) => visitor.visit_hash_literal_mut(literal),
+        ExpressionType::PrefixExpression(expression) => {
+            visitor.visit_prefix_expression_mut(expression)
+        }
+        ExpressionType::InfixExpression(expression) => {
+            visitor.visit_infix_expression_mut(expression)
+        }
+        ExpressionType::IfExpression(expression) => visitor.visit_if_expression_mut(expression),
+        ExpressionType::FunctionLiteral(literal) => visitor.visit_function_literal_mut(literal),
+        ExpressionType::CallExpression(expression) => visitor.visit_call_expression_mut(expression),
+        ExpressionType::IndexExpression(expression) => {
+            visitor.visit_index_expression_mut(expression)
+        }
+        ExpressionType::Illegal => {}
+    }
+}
+
+pub fn walk_array_literal_mut<V: VisitorMut + ?Sized>(visitor: &mut V, literal: &mut ArrayLiteral) {
+    for element in &mut literal.elements {
+        visitor.visit_expression_mut(element);
+    }
+}
+
+pub fn walk_hash_literal_mut<V: VisitorMut + ?Sized>(visitor: &mut V, literal: &mut HashLiteral) {
+    for (key, value) in &mut literal.pairs {
+        visitor.visit_expression_mut(key);
+        visitor.visit_expression_mut(value);
+    }
+}
+
+pub fn walk_prefix_expression_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    expression: &mut PrefixExpression,
+) {
+    visitor.visit_expression_mut(&mut expression.right);
+}
+
+pub fn walk_infix_expression_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    expression: &mut InfixExpression,
+) {
+    visitor.visit_expression_mut(&mut expression.left);
+    visitor.visit_expression_mut(&mut expression.right);
+}
+
+pub fn walk_if_expression_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    expression: &mut IfExpression,
+) {
+    visitor.visit_expression_mut(&mut expression.condition);
+    visitor.visit_block_statement_mut(&mut expression.consequence);
+    if let Some(alternative) = &mut expression.alternative {
+        visitor.visit_block_statement_mut(alternative);
+    }
+}
+
+pub fn walk_function_literal_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    literal: &mut FunctionLiteral,
+) {
+    for (parameter, annotation) in literal
+        .parameters
+        .iter_mut()
+        .zip(&mut literal.parameter_types)
+    {
+        visitor.visit_identifer_mut(parameter);
+        if let Some(annotation) = annotation {
+            visitor.visit_type_expression_mut(annotation);
+        }
+    }
+    if let Some(return_type) = &mut literal.return_type {
+        visitor.visit_type_expression_mut(return_type);
+    }
+    visitor.visit_block_statement_mut(&mut literal.body);
+}
+
+pub fn walk_call_expression_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    expression: &mut CallExpression,
+) {
+    visitor.visit_expression_mut(&mut expression.function);
+    for argument in &mut expression.arguments {
+        visitor.visit_expression_mut(argument);
+    }
+}
+
+pub fn walk_index_expression_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    expression: &mut IndexExpression,
+) {
+    visitor.visit_expression_mut(&mut expression.left);
+    visitor.visit_expression_mut(&mut expression.index);
+}
+
+/// Rewrites a tree by value. Expression methods return an `ExpressionType` so
+/// that a node can be replaced by a node of another kind.
+pub trait Folder {
+    fn fold_program(&mut self, program: Program) -> Program {
+        noop_fold_program(self, program)
+    }
+    fn fold_statement(&mut self, statement: StatementType) -> StatementType {
+        noop_fold_statement(self, statement)
+    }
+    fn fold_let_statement(&mut self, statement: LetStatement) -> StatementType {
+        noop_fold_let_statement(self, statement)
+    }
+    fn fold_return_statement(&mut self, statement: ReturnStatement) -> StatementType {
+        noop_fold_return_statement(self, statement)
+    }
+    fn fold_expression_statement(&mut self, statement: ExpressionStatement) -> StatementType {
+        noop_fold_expression_statement(self, statement)
+    }
+    fn fold_block_statement(&mut self, block: BlockStatement) -> BlockStatement {
+        noop_fold_block_statement(self, block)
+    }
+    fn fold_expression(&mut self, expression: ExpressionType) -> ExpressionType {
+        noop_fold_expression(self, expression)
+    }
+    /// Used for names in `let`, parameters and identifier expressions alike.
+    fn fold_identifer(&mut self, identifer: Identifer) -> Identifer {
+        identifer
+    }
+    fn fold_integer_literal(&mut self, literal: IntegerLiteral) -> ExpressionType {
+        ExpressionType::IntegerLiteral(literal)
+    }
+    fn fold_big_integer_literal(&mut self, literal: BigIntegerLiteral) -> ExpressionType {
+        ExpressionType::BigIntegerLiteral(literal)
+    }
+    fn fold_float_literal(&mut self, literal: FloatLiteral) -> ExpressionType {
+        ExpressionType::FloatLiteral(literal)
+    }
+    fn fold_boolean(&mut self, boolean: Boolean) -> ExpressionType {
+        ExpressionType::Boolean(boolean)
+    }
+    fn fold_string_literal(&mut self, literal: StringLiteral) -> ExpressionType {
+        ExpressionType::StringLiteral(literal)
+    }
+    fn fold_array_literal(&mut self, literal: ArrayLiteral) -> ExpressionType {
+        noop_fold_array_literal(self, literal)
+    }
+    fn fold_hash_literal(&mut self, literal: HashLiteral) -> ExpressionType {
+        noop_fold_hash_literal(self, literal)
+    }
+    fn fold_type_expression(&mut self, ty: TypeExpression) -> TypeExpression {
+        ty
+    }
+    fn fold_prefix_expression(&mut self, expression: PrefixExpression) -> ExpressionType {
+        noop_fold_prefix_expression(self, expression)
+    }
+    fn fold_infix_expression(&mut self, expression: InfixExpression) -> ExpressionType {
+        noop_fold_infix_expression(self, expression)
+    }
+    fn fold_if_expression(&mut self, expression: IfExpression) -> ExpressionType {
+        noop_fold_if_expression(self, expression)
+    }
+    fn fold_function_literal(&mut self, literal: FunctionLiteral) -> ExpressionType {
+        noop_fold_function_literal(self, literal)
+    }
+    fn fold_call_expression(&mut self, expression: CallExpression) -> ExpressionType {
+        noop_fold_call_expression(self, expression)
+    }
+    fn fold_index_expression(&mut self, expression: IndexExpression) -> ExpressionType {
+        noop_fold_index_expression(self, expression)
+    }
+}
+
+pub fn noop_fold_program<F: Folder + ?Sized>(folder: &mut F, program: Program) -> Program {
+    program
+        .into_iter()
+        .map(|statement| folder.fold_statement(statement))
+        .collect()
+}
+
+pub fn noop_fold_statement<F: Folder + ?Sized>(
+    folder: &mut F,
+    statement: StatementType,
+) -> StatementType {
+    match statement {
+        StatementType::LetStatement(statement) => folder.fold_let_statement(statement),
+        StatementType::ReturnStatement(statement) => folder.fold_return_statement(statement),
+        StatementType::ExpressionStatement(statement) => {
+            folder.fold_expression_statement(statement)
+        }
+    }
+}
+
+pub fn noop_fold_let_statement<F: Folder + ?Sized>(
+    folder: &mut F,
+    statement: LetStatement,
+) -> StatementType {
+    StatementType::LetStatement(LetStatement {
+        token: statement.token,
+        name: folder.fold_identifer(statement.name),
+        annotation: statement
+            .annotation
+            .map(|annotation| folder.fold_type_expression(annotation)),
+        value: folder.fold_expression(statement.value),
+    })
+}
+
+pub fn noop_fold_return_statement<F: Folder + ?Sized>(
+    folder: &mut F,
+    statement: ReturnStatement,
+) -> StatementType {
+    StatementType::ReturnStatement(ReturnStatement {
+        token: statement.token,
+        value: folder.fold_expression(statement.value),
+    })
+}
+
+pub fn noop_fold_expression_statement<F: Folder + ?Sized>(
+    folder: &mut F,
+    statement: ExpressionStatement,
+) -> StatementType {
+    StatementType::ExpressionStatement(ExpressionStatement {
+        token: statement.token,
+        expression: folder.fold_expression(statement.expression),
+    })
+}
+
+pub fn noop_fold_block_statement<F: Folder + ?Sized>(
+    folder: &mut F,
+    block: BlockStatement,
+) -> BlockStatement {
+    BlockStatement {
+        token: block.token,
+        statements: block
+            .statements
+            .into_iter()
+            .map(|statement| folder.fold_statement(statement))
+            .collect(),
+        span: block.span,
+    }
+}
+
+pub fn noop_fold_expression<F: Folder + ?Sized>(
+    folder: &mut F,
+    expression: ExpressionType,
+) -> ExpressionType {
+    match expression {
+        ExpressionType::Identifer(identifer) => {
+            ExpressionType::Identifer(folder.fold_identifer(identifer))
+        }
+        ExpressionType::IntegerLiteral(literal) => folder.fold_integer_literal(literal),
+        ExpressionType::BigIntegerLiteral(literal) => folder.fold_big_integer_literal(literal),
+        ExpressionType::FloatLiteral(literal) => folder.fold_float_literal(literal),
+        ExpressionType::Boolean(boolean) => folder.fold_boolean(boolean),
+        ExpressionType::StringLiteral(literal) => folder.fold_string_literal(literal),
+        ExpressionType::ArrayLiteral(literal) => folder.fold_array_literal(literal),
+        ExpressionType::HashLiteral(literal) => folder.fold_hash_literal(literal),
+        ExpressionType::PrefixExpression(expression) => folder.fold_prefix_expression(expression),
+        ExpressionType::InfixExpression(expression) => folder.fold_infix_expression(expression),
+        ExpressionType::IfExpression(expression) => folder.fold_if_expression(expression),
+        ExpressionType::FunctionLiteral(literal) => folder.fold_function_literal(literal),
+        ExpressionType::CallExpression(expression) => folder.fold_call_expression(expression),
+        ExpressionType::IndexExpression(expression) => folder.fold_index_expression(expression),
+        ExpressionType::Illegal => ExpressionType::Illegal,
+    }
+}
+
+pub fn noop_fold_array_literal<F: Folder + ?Sized>(
+    folder: &mut F,
+    literal: ArrayLiteral,
+) -> ExpressionType {
+    ExpressionType::ArrayLiteral(ArrayLiteral {
+        token: literal.token,
+        elements: literal
+            .elements
+            .into_iter()
+            .map(|element| folder.fold_expression(element))
+            .collect(),
+        span: literal.span,
+    })
+}
+
+pub fn noop_fold_hash_literal<F: Folder + ?Sized>(
+    folder: &mut F,
+    literal: HashLiteral,
+) -> ExpressionType {
+    ExpressionType::HashLiteral(HashLiteral {
+        token: literal.token,
+        pairs: literal
+            .pairs
+            .into_iter()
+            .map(|(key, value)| (folder.fold_expression(key), folder.fold_expression(value)))
+            .collect(),
+        span: literal.span,
+    })
+}
+
+pub fn noop_fold_prefix_expression<F: Folder + ?Sized>(
+    folder: &mut F,
+    expression: PrefixExpression,
+) -> ExpressionType {
+    ExpressionType::PrefixExpression(PrefixExpression {
+        token: expression.token,
+        operator: expression.operator,
+        right: Box::new(folder.fold_expression(*expression.right)),
+    })
+}
+
+pub fn noop_fold_infix_expression<F: Folder + ?Sized>(
+    folder: &mut F,
+    expression: InfixExpression,
+) -> ExpressionType {
+    ExpressionType::InfixExpression(InfixExpression {
+        token: expression.token,
+        left: Box::new(folder.fold_expression(*expression.left)),
+        operator: expression.operator,
+        right: Box::new(folder.fold_expression(*expression.right)),
+    })
+}
+
+pub fn noop_fold_if_expression<F: Folder + ?Sized>(
+    folder: &mut F,
+    expression: IfExpression,
+) -> ExpressionType {
+    ExpressionType::IfExpression(IfExpression {
+        token: expression.token,
+        condition: Box::new(folder.fold_expression(*expression.condition)),
+        consequence: folder.fold_block_statement(expression.consequence),
+        alternative: expression
+            .alternative
+            .map(|alternative| folder.fold_block_statement(alternative)),
+    })
+}
+
+pub fn noop_fold_function_literal<F: Folder + ?Sized>(
+    folder: &mut F,
+    literal: FunctionLiteral,
+) -> ExpressionType {
+    ExpressionType::FunctionLiteral(FunctionLiteral {
+        token: literal.token,
+        parameters: literal
+            .parameters
+            .into_iter()
+            .map(|parameter| folder.fold_identifer(parameter))
+            .collect(),
+        parameter_types: literal
+            .parameter_types
+            .into_iter()
+            .map(|annotation| annotation.map(|annotation| folder.fold_type_expression(annotation)))
+            .collect(),
+        return_type: literal
+            .return_type
+            .map(|return_type| folder.fold_type_expression(return_type)),
+        body: folder.fold_block_statement(literal.body),
+    })
+}
+
+pub fn noop_fold_call_expression<F: Folder + ?Sized>(
+    folder: &mut F,
+    expression: CallExpression,
+) -> ExpressionType {
+    ExpressionType::CallExpression(CallExpression {
+        token: expression.token,
+        function: Box::new(folder.fold_expression(*expression.function)),
+        arguments: expression
+            .arguments
+            .into_iter()
+            .map(|argument| folder.fold_expression(argument))
+            .collect(),
+        span: expression.span,
+    })
+}
+
+pub fn noop_fold_index_expression<F: Folder + ?Sized>(
+    folder: &mut F,
+    expression: IndexExpression,
+) -> ExpressionType {
+    ExpressionType::IndexExpression(IndexExpression {
+        token: expression.token,
+        left: Box::new(folder.fold_expression(*expression.left)),
+        index: Box::new(folder.fold_expression(*expression.index)),
+        span: expression.span,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ast::Node;
+    use super::super::lexer::Lexer;
+    use super::super::parser::Parser;
+    use super::*;
+
+    fn parse(input: &str) -> Program {
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
+        assert_eq!(parser.errors(), vec![]);
+        program
+    }
+
+    // 識別子の出現を数えるだけのpass
+    #[derive(Default)]
+    struct CountIdentifers {
+        names: Vec<String>,
+    }
+
+    impl Visitor for CountIdentifers {
+        fn visit_identifer(&mut self, identifer: &Identifer) {
+            self.names.push(identifer.value.clone());
+        }
+    }
+
+    #[test]
+    fn visitor_reaches_every_identifer() {
+        let program = parse(
+            "let f: fn(int, int) -> int = fn(x, y: int) -> int { if (x < y) { g(x) } else { return -y; } }; f(a, b * c);",
+        );
+        let mut visitor = CountIdentifers::default();
+        visitor.visit_program(&program);
+        assert_eq!(
+            visitor.names,
+            vec!["f", "x", "y", "x", "y", "g", "x", "y", "f", "a", "b", "c"]
+        );
+    }
+
+    // 関数の本体には入らないpass
+    #[test]
+    fn override_can_skip_children() {
+        struct TopLevel(usize);
+        impl Visitor for TopLevel {
+            fn visit_identifer(&mut self, _identifer: &Identifer) {
+                self.0 += 1;
+            }
+            fn visit_function_literal(&mut self, _literal: &FunctionLiteral) {}
+        }
+
+        let program = parse("let f = fn(x) { x + y }; f(z);");
+        let mut visitor = TopLevel(0);
+        visitor.visit_program(&program);
+        assert_eq!(visitor.0, 3);
+    }
+
+    #[test]
+    fn visitor_mut_renames() {
+        struct Rename;
+        impl VisitorMut for Rename {
+            fn visit_identifer_mut(&mut self, identifer: &mut Identifer) {
+                if identifer.value == "x" {
+                    identifer.value = String::from("renamed");
+                }
+            }
+        }
+
+        let mut program = parse("let x = 1; fn(x) { x * x }(x);");
+        Rename.visit_program_mut(&mut program);
+        assert_eq!(
+            program.string(),
+            "let renamed = 1;fn(renamed) (renamed * renamed)(renamed)"
+        );
+    }
+
+    #[test]
+    fn folder_rewrites_nodes() {
+        // 整数同士の足し算と掛け算を畳み込む
+        struct ConstantFold;
+        impl Folder for ConstantFold {
+            fn fold_infix_expression(&mut self, expression: InfixExpression) -> ExpressionType {
+                let folded = noop_fold_infix_expression(self, expression);
+                if let ExpressionType::InfixExpression(infix) = &folded {
+                    if let (ExpressionType::IntegerLiteral(l), ExpressionType::IntegerLiteral(r)) =
+                        (infix.left.as_ref(), infix.right.as_ref())
+                    {
+                        let value = match infix.operator.as_str() {
+                            "+" => l.value.checked_add(r.value),
+                            "*" => l.value.checked_mul(r.value),
+                            _ => None,
+                        };
+                        if let Some(value) = value {
+                            let mut token = l.token.clone();
+                            token.literal = value.to_string();
+                            token.span = infix.span();
+                            return ExpressionType::IntegerLiteral(IntegerLiteral { token, value });
+                        }
+                    }
+                }
+                folded
+            }
+        }
+
+        let program = parse("let a = 1 + 2 * 3; f(2 * 2, x + 1);");
+        let program = ConstantFold.fold_program(program);
+        assert_eq!(program.string(), "let a = 7;f(4, (x + 1))");
+        assert_eq!(program[0].span(), crate::token::Span::new(0, 17));
+    }
+}